@@ -0,0 +1,198 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decode and encode throughput of typical messages, through the `Section`
+//! tree alone, through serde on top of it and, for decoding, through serde
+//! straight from the bytes.
+
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use portable_storage::{from_bytes, from_section, to_section, Section};
+use portable_storage_utils::Blob;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct NodeData {
+    local_time: u64,
+    my_port: u32,
+    network_id: Blob,
+    peer_id: u64,
+    support_flags: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CoreSyncData {
+    cumulative_difficulty: u64,
+    current_height: u64,
+    top_id: Blob,
+    top_version: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NetworkAddress {
+    m_ip: u32,
+    m_port: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PeerAddress {
+    addr: NetworkAddress,
+    #[serde(rename = "type")]
+    ty: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PeerlistEntry {
+    adr: PeerAddress,
+    id: u64,
+    last_seen: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Handshake {
+    node_data: NodeData,
+    payload_data: CoreSyncData,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HandshakeResponse {
+    local_peerlist_new: Vec<PeerlistEntry>,
+    node_data: NodeData,
+    payload_data: CoreSyncData,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BlockEntry {
+    block: Blob,
+    txs: Vec<Blob>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GetBlocksResponse {
+    blocks: Vec<BlockEntry>,
+    current_height: u64,
+    start_height: u64,
+}
+
+fn node_data() -> NodeData {
+    NodeData {
+        local_time: 1_600_000_000,
+        my_port: 18080,
+        network_id: Blob(vec![0x12; 16]),
+        peer_id: 0x1122_3344_5566_7788,
+        support_flags: 1,
+    }
+}
+
+fn payload_data() -> CoreSyncData {
+    CoreSyncData {
+        cumulative_difficulty: 0x2c4d_5fd5_c2f1_c7a3,
+        current_height: 2_200_000,
+        top_id: Blob(vec![0x41; 32]),
+        top_version: 14,
+    }
+}
+
+fn handshake() -> Handshake {
+    Handshake {
+        node_data: node_data(),
+        payload_data: payload_data(),
+    }
+}
+
+fn peerlist(peers: usize) -> HandshakeResponse {
+    HandshakeResponse {
+        local_peerlist_new: (0..peers)
+            .map(|i| PeerlistEntry {
+                adr: PeerAddress {
+                    addr: NetworkAddress {
+                        m_ip: 0x0100_007f + i as u32,
+                        m_port: 18080,
+                    },
+                    ty: 1,
+                },
+                id: i as u64,
+                last_seen: 1_600_000_000 - i as i64,
+            })
+            .collect(),
+        node_data: node_data(),
+        payload_data: payload_data(),
+    }
+}
+
+/// About a megabyte of blocks with a few transactions each.
+fn blocks() -> GetBlocksResponse {
+    GetBlocksResponse {
+        blocks: (0..100)
+            .map(|i| BlockEntry {
+                block: Blob(vec![i as u8; 2_000]),
+                txs: (0..4).map(|j| Blob(vec![j as u8; 2_000])).collect(),
+            })
+            .collect(),
+        current_height: 2_200_000,
+        start_height: 2_199_900,
+    }
+}
+
+fn encode(section: &Section) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    portable_storage::write(&mut buf, section);
+    buf.to_vec()
+}
+
+fn bench_message<T>(c: &mut Criterion, name: &str, value: &T)
+where
+    T: Serialize + for<'de> Deserialize<'de>,
+{
+    let section = to_section(value).unwrap();
+    let bytes = encode(&section);
+
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+    group.bench_with_input(BenchmarkId::new("decode", "section"), &bytes, |b, bytes| {
+        b.iter(|| portable_storage::read(&mut &bytes[..]).unwrap())
+    });
+    group.bench_with_input(BenchmarkId::new("decode", "serde"), &bytes, |b, bytes| {
+        b.iter(|| {
+            let section = portable_storage::read(&mut &bytes[..]).unwrap();
+            from_section::<T>(section).unwrap()
+        })
+    });
+    group.bench_with_input(
+        BenchmarkId::new("decode", "from_bytes"),
+        &bytes,
+        |b, bytes| b.iter(|| from_bytes::<T>(bytes).unwrap()),
+    );
+    group.bench_with_input(
+        BenchmarkId::new("encode", "section"),
+        &section,
+        |b, section| b.iter(|| encode(section)),
+    );
+    group.bench_with_input(BenchmarkId::new("encode", "serde"), value, |b, value| {
+        b.iter(|| encode(&to_section(value).unwrap()))
+    });
+
+    group.finish();
+}
+
+fn codec(c: &mut Criterion) {
+    bench_message(c, "handshake", &handshake());
+    bench_message(c, "peerlist_5000", &peerlist(5_000));
+    bench_message(c, "blocks_1mb", &blocks());
+}
+
+criterion_group!(benches, codec);
+criterion_main!(benches);
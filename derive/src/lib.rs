@@ -0,0 +1,288 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `#[derive(StorageSection)]` for `portable_storage::codec`.
+//!
+//! Generates `StorageSection` and `StorageValue` implementations for structs
+//! with named fields, reading and writing every field directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Expr, Field, Fields, Ident, LitByteStr, LitInt,
+    LitStr,
+};
+
+#[proc_macro_derive(StorageSection, attributes(storage))]
+pub fn derive_storage_section(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// How a field is stored.
+enum Kind {
+    /// As a `StorageValue`.
+    Value,
+    /// `pod_as_blob`.
+    Pod,
+    /// `container_pod_as_blob`.
+    PodContainer,
+}
+
+/// The value of a field missing from the section.
+enum Missing {
+    Error,
+    Default,
+    Expr(Expr),
+}
+
+struct FieldSpec {
+    ident: Ident,
+    name: String,
+    kind: Kind,
+    missing: Missing,
+    skip: bool,
+    since: Option<u32>,
+    until: Option<u32>,
+}
+
+impl FieldSpec {
+    fn parse(field: &Field) -> Result<FieldSpec, Error> {
+        let ident = field.ident.clone().unwrap();
+        let name = ident.to_string();
+        let mut spec = FieldSpec {
+            name: name.strip_prefix("r#").unwrap_or(&name).to_owned(),
+            ident,
+            kind: Kind::Value,
+            missing: Missing::Error,
+            skip: false,
+            since: None,
+            until: None,
+        };
+
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("storage")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    spec.name = meta.value()?.parse::<LitStr>()?.value();
+                } else if meta.path.is_ident("default") {
+                    spec.missing = if meta.input.peek(syn::Token![=]) {
+                        Missing::Expr(meta.value()?.parse()?)
+                    } else {
+                        Missing::Default
+                    };
+                } else if meta.path.is_ident("pod_as_blob") {
+                    spec.kind = Kind::Pod;
+                } else if meta.path.is_ident("container_pod_as_blob") {
+                    spec.kind = Kind::PodContainer;
+                } else if meta.path.is_ident("skip") {
+                    spec.skip = true;
+                } else if meta.path.is_ident("since") {
+                    spec.since = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                } else if meta.path.is_ident("until") {
+                    spec.until = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                } else {
+                    return Err(meta.error("unknown `storage` attribute"));
+                }
+                Ok(())
+            })?;
+        }
+
+        if spec.name.len() > 255 {
+            return Err(Error::new_spanned(
+                &spec.ident,
+                "keys are at most 255 bytes long",
+            ));
+        }
+        Ok(spec)
+    }
+
+    fn versioned(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+}
+
+fn option(value: Option<u32>) -> TokenStream2 {
+    match value {
+        Some(value) => quote!(::std::option::Option::Some(#value)),
+        None => quote!(::std::option::Option::None),
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "`StorageSection` needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "`StorageSection` can only be derived for structs",
+            ))
+        }
+    };
+    let specs = fields
+        .iter()
+        .map(FieldSpec::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let codec = quote!(::portable_storage::codec);
+    let stored: Vec<_> = specs.iter().filter(|s| !s.skip).collect();
+    let count = stored.len();
+
+    let presence: Vec<_> = stored
+        .iter()
+        .map(|spec| {
+            let (since, until) = (option(spec.since), option(spec.until));
+            quote!(#codec::present(version, #since, #until))
+        })
+        .collect();
+    let writes = stored.iter().enumerate().map(|(i, spec)| {
+        let ident = &spec.ident;
+        let name = LitStr::new(&spec.name, Span::call_site());
+        let write = match spec.kind {
+            Kind::Value => quote!(#codec::write_field_at(buf, #name, &self.#ident, version);),
+            Kind::Pod => quote!(#codec::write_pod_field(buf, #name, &self.#ident);),
+            Kind::PodContainer => {
+                quote!(#codec::write_pod_container_field(buf, #name, &self.#ident);)
+            }
+        };
+        quote!(if present[#i] { #write })
+    });
+
+    let slots: Vec<_> = stored
+        .iter()
+        .map(|s| format_ident!("__{}", s.ident))
+        .collect();
+    let reads = stored
+        .iter()
+        .zip(&slots)
+        .enumerate()
+        .map(|(i, (spec, slot))| {
+            let key = LitByteStr::new(spec.name.as_bytes(), Span::call_site());
+            let read = match spec.kind {
+                Kind::Value => quote!(#codec::read_field_at(buf, version)?),
+                Kind::Pod => quote!(#codec::read_pod_field(buf)?),
+                Kind::PodContainer => quote!(#codec::read_pod_container_field(buf)?),
+            };
+            quote!(#key if present[#i] => #slot = ::std::option::Option::Some(#read),)
+        });
+
+    let mut index = 0usize..;
+    let inits = specs.iter().map(|spec| {
+        let ident = &spec.ident;
+        if spec.skip {
+            return quote!(#ident: ::std::default::Default::default(),);
+        }
+
+        let slot = format_ident!("__{}", ident);
+        let name = LitStr::new(&spec.name, Span::call_site());
+        let value = match &spec.missing {
+            Missing::Error => quote!(#slot.ok_or_else(|| #codec::missing(#name))?),
+            Missing::Default => quote!(#slot.unwrap_or_default()),
+            Missing::Expr(expr) => quote!(#slot.unwrap_or_else(|| #expr)),
+        };
+        let i = index.next().unwrap();
+        if spec.versioned() {
+            quote! {
+                #ident: if present[#i] { #value } else { ::std::default::Default::default() },
+            }
+        } else {
+            quote!(#ident: #value,)
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #codec::StorageSection for #ident #ty_generics #where_clause {
+            fn write_section(&self, buf: &mut #codec::BytesMut) {
+                self.write_section_at(buf, ::std::option::Option::None)
+            }
+
+            fn read_section<B: #codec::Buf>(buf: &mut B) -> ::portable_storage::Result<Self> {
+                Self::read_section_at(buf, ::std::option::Option::None)
+            }
+
+            fn write_section_at(
+                &self,
+                buf: &mut #codec::BytesMut,
+                version: ::std::option::Option<u32>,
+            ) {
+                let present: [bool; #count] = [#( #presence ),*];
+                let count = present.iter().filter(|present| **present).count();
+                ::portable_storage::raw_size::write(buf, count as u64);
+                #( #writes )*
+            }
+
+            fn read_section_at<B: #codec::Buf>(
+                buf: &mut B,
+                version: ::std::option::Option<u32>,
+            ) -> ::portable_storage::Result<Self> {
+                let present: [bool; #count] = [#( #presence ),*];
+                #( let mut #slots = ::std::option::Option::None; )*
+                let mut scratch = [0u8; 255];
+                for _ in 0..#codec::read_count(buf)? {
+                    match #codec::read_name(buf, &mut scratch)? {
+                        #( #reads )*
+                        _ => #codec::skip_field(buf)?,
+                    }
+                }
+
+                ::std::result::Result::Ok(#ident {
+                    #( #inits )*
+                })
+            }
+        }
+
+        impl #impl_generics #codec::StorageValue for #ident #ty_generics #where_clause {
+            const SERIALIZE_TYPE: u8 = #codec::SECTION;
+
+            fn write_value(&self, buf: &mut #codec::BytesMut) {
+                #codec::StorageSection::write_section(self, buf)
+            }
+
+            fn read_value<B: #codec::Buf>(buf: &mut B) -> ::portable_storage::Result<Self> {
+                #codec::StorageSection::read_section(buf)
+            }
+
+            fn write_value_at(
+                &self,
+                buf: &mut #codec::BytesMut,
+                version: ::std::option::Option<u32>,
+            ) {
+                #codec::StorageSection::write_section_at(self, buf, version)
+            }
+
+            fn read_value_at<B: #codec::Buf>(
+                buf: &mut B,
+                version: ::std::option::Option<u32>,
+            ) -> ::portable_storage::Result<Self> {
+                #codec::StorageSection::read_section_at(buf, version)
+            }
+        }
+
+        impl #impl_generics #codec::StorageElement for #ident #ty_generics #where_clause {}
+    })
+}
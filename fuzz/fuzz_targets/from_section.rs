@@ -0,0 +1,68 @@
+// Copyright 2020 Jean Pierre Dudey <me@jeandudey.tech>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use portable_storage::Section;
+use portable_storage_utils::Blob;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct NetworkAddress {
+    m_ip: u32,
+    m_port: u16,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct PeerAddress {
+    addr: NetworkAddress,
+    #[serde(rename = "type")]
+    ty: u8,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct PeerlistEntry {
+    adr: PeerAddress,
+    id: u64,
+    #[serde(default)]
+    last_seen: i64,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct CoreSyncData {
+    cumulative_difficulty: u64,
+    current_height: u64,
+    top_id: Blob,
+    top_version: u8,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct HandshakeResponse {
+    #[serde(default)]
+    local_peerlist_new: Vec<PeerlistEntry>,
+    payload_data: CoreSyncData,
+    flags: bool,
+    ratio: f64,
+}
+
+fuzz_target!(|section: Section| {
+    portable_storage::from_section::<HandshakeResponse>(section.clone()).ok();
+    portable_storage::from_section::<CoreSyncData>(section).ok();
+});
@@ -0,0 +1,32 @@
+// Copyright 2020 Jean Pierre Dudey <me@jeandudey.tech>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+#![no_main]
+use bytes::{Bytes, BytesMut};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf = Bytes::copy_from_slice(data);
+    if let Ok(value) = portable_storage::raw_size::read(&mut buf) {
+        let consumed = data.len() - buf.len();
+
+        // Writing picks the smallest encoding, so it may be shorter than the
+        // input but must read back to the same value.
+        let mut out = BytesMut::new();
+        portable_storage::raw_size::write(&mut out, value);
+        assert!(out.len() <= consumed);
+        assert_eq!(portable_storage::raw_size::read(&mut out.freeze()).unwrap(), value);
+    }
+});
@@ -0,0 +1,34 @@
+// Copyright 2020 Jean Pierre Dudey <me@jeandudey.tech>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+#![no_main]
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+
+// decode -> encode -> decode -> encode must be stable. Bytes are compared
+// rather than sections since NaN doubles never compare equal.
+fuzz_target!(|data: &[u8]| {
+    let section = match portable_storage::read(&mut &data[..]) {
+        Ok(section) => section,
+        Err(_) => return,
+    };
+
+    let mut first = BytesMut::new();
+    portable_storage::write(&mut first, &section);
+    let decoded = portable_storage::read(&mut &first[..]).expect("re-encoded blob must decode");
+    let mut second = BytesMut::new();
+    portable_storage::write(&mut second, &decoded);
+    assert_eq!(first, second);
+});
@@ -0,0 +1,143 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Allocation counting
+//!
+//! A global allocator that counts the allocations made by the current
+//! thread, so tests can pin how many allocations decoding and encoding
+//! take and catch performance regressions. Install it in the test binary:
+//!
+//! ```rust,ignore
+//! use portable_storage::alloc_counter::{self, CountingAllocator};
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator = CountingAllocator;
+//!
+//! #[test]
+//! fn encoding_is_allocation_free() {
+//!     let section = portable_storage::Section::new();
+//!     let mut buf = bytes::BytesMut::with_capacity(64);
+//!     alloc_counter::assert_max_allocations(0, || {
+//!         portable_storage::to_bytes_into(&section, &mut buf)
+//!     });
+//! }
+//! ```
+//!
+//! Only allocations made while a closure given to [`count`] runs, on its
+//! thread, are counted, so tests running concurrently don't disturb each
+//! other.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+/// Allocations made while counting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Allocations {
+    /// Number of allocations.
+    pub count: usize,
+    /// Number of reallocations, not included in `count`.
+    pub reallocations: usize,
+    /// Bytes requested by allocations and reallocations.
+    pub bytes: usize,
+}
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<Allocations> = const {
+        Cell::new(Allocations {
+            count: 0,
+            reallocations: 0,
+            bytes: 0,
+        })
+    };
+}
+
+fn record(reallocation: bool, size: usize) {
+    // The thread locals may be gone while the thread exits.
+    if COUNTING.try_with(Cell::get).unwrap_or(false) {
+        let _ = ALLOCATIONS.try_with(|allocations| {
+            let mut a = allocations.get();
+            if reallocation {
+                a.reallocations += 1;
+            } else {
+                a.count += 1;
+            }
+            a.bytes += size;
+            allocations.set(a);
+        });
+    }
+}
+
+/// The system allocator, counting allocations.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(false, layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(false, layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(true, new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Runs `f` and returns its result with the allocations it made on this
+/// thread.
+pub fn count<T, F: FnOnce() -> T>(f: F) -> (T, Allocations) {
+    ALLOCATIONS.with(|allocations| allocations.set(Allocations::default()));
+    COUNTING.with(|counting| counting.set(true));
+    let value = f();
+    COUNTING.with(|counting| counting.set(false));
+    (value, ALLOCATIONS.with(Cell::get))
+}
+
+/// Whether [`CountingAllocator`] is the global allocator.
+pub fn is_installed() -> bool {
+    // The read keeps the allocation from being optimized out.
+    let (_, allocations) = count(|| unsafe { std::ptr::read_volatile(&*Box::new(0u64)) });
+    allocations.count > 0
+}
+
+/// Runs `f` and panics if it allocated (or reallocated) more than `max`
+/// times, or if [`CountingAllocator`] isn't installed.
+pub fn assert_max_allocations<T, F: FnOnce() -> T>(max: usize, f: F) -> T {
+    assert!(
+        is_installed(),
+        "`CountingAllocator` isn't the global allocator"
+    );
+    let (value, allocations) = count(f);
+    let total = allocations.count + allocations.reallocations;
+    assert!(
+        total <= max,
+        "expected at most {} allocations, found {:?}",
+        max,
+        allocations
+    );
+    value
+}
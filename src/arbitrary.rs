@@ -0,0 +1,139 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `Arbitrary` implementations for structured fuzzing.
+//!
+//! Generated values are always encodable: keys are at most 255 bytes long,
+//! arrays are homogeneous and typed even when empty, and nesting is bounded.
+
+use crate::{
+    Array, Section, StorageEntry, SERIALIZE_FLAG_ARRAY, SERIALIZE_TYPE_BOOL, SERIALIZE_TYPE_DOUBLE,
+    SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32, SERIALIZE_TYPE_INT64, SERIALIZE_TYPE_INT8,
+    SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING, SERIALIZE_TYPE_UINT16, SERIALIZE_TYPE_UINT32,
+    SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+
+/// How deep sections and arrays may nest.
+const MAX_DEPTH: usize = 4;
+
+const ELEMENT_TYPES: [u8; 12] = [
+    SERIALIZE_TYPE_INT64,
+    SERIALIZE_TYPE_INT32,
+    SERIALIZE_TYPE_INT16,
+    SERIALIZE_TYPE_INT8,
+    SERIALIZE_TYPE_UINT64,
+    SERIALIZE_TYPE_UINT32,
+    SERIALIZE_TYPE_UINT16,
+    SERIALIZE_TYPE_UINT8,
+    SERIALIZE_TYPE_DOUBLE,
+    SERIALIZE_TYPE_STRING,
+    SERIALIZE_TYPE_BOOL,
+    SERIALIZE_TYPE_OBJECT,
+];
+
+impl<'a> Arbitrary<'a> for Section {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        section(u, MAX_DEPTH)
+    }
+}
+
+impl<'a> Arbitrary<'a> for StorageEntry {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        entry(u, MAX_DEPTH)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Array {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        array(u, MAX_DEPTH)
+    }
+}
+
+fn section(u: &mut Unstructured, depth: usize) -> Result<Section> {
+    let len = u.arbitrary_len::<(String, u8)>()?;
+    let mut section = Section::with_capacity(len);
+    for _ in 0..len {
+        let mut name = String::arbitrary(u)?;
+        while name.len() > 255 {
+            name.pop();
+        }
+        section.insert(name, entry(u, depth)?);
+    }
+
+    Ok(section)
+}
+
+fn entry(u: &mut Unstructured, depth: usize) -> Result<StorageEntry> {
+    let kinds = if depth == 0 { 11 } else { 13 };
+    match u.choose_index(kinds)? {
+        12 => array(u, depth - 1).map(StorageEntry::Array),
+        i => value(u, ELEMENT_TYPES[i], depth),
+    }
+}
+
+fn array(u: &mut Unstructured, depth: usize) -> Result<Array> {
+    let kinds = if depth == 0 { 11 } else { 12 };
+    let serialize_type = ELEMENT_TYPES[u.choose_index(kinds)?];
+    let len = u.arbitrary_len::<u64>()?;
+
+    let mut array = Array::with_capacity(len);
+    array.serialize_type = Some(serialize_type | SERIALIZE_FLAG_ARRAY);
+    for _ in 0..len {
+        array.array.push(value(u, serialize_type, depth)?);
+    }
+
+    Ok(array)
+}
+
+fn value(u: &mut Unstructured, serialize_type: u8, depth: usize) -> Result<StorageEntry> {
+    Ok(match serialize_type {
+        SERIALIZE_TYPE_INT64 => StorageEntry::I64(u.arbitrary()?),
+        SERIALIZE_TYPE_INT32 => StorageEntry::I32(u.arbitrary()?),
+        SERIALIZE_TYPE_INT16 => StorageEntry::I16(u.arbitrary()?),
+        SERIALIZE_TYPE_INT8 => StorageEntry::I8(u.arbitrary()?),
+        SERIALIZE_TYPE_UINT64 => StorageEntry::U64(u.arbitrary()?),
+        SERIALIZE_TYPE_UINT32 => StorageEntry::U32(u.arbitrary()?),
+        SERIALIZE_TYPE_UINT16 => StorageEntry::U16(u.arbitrary()?),
+        SERIALIZE_TYPE_UINT8 => StorageEntry::U8(u.arbitrary()?),
+        SERIALIZE_TYPE_DOUBLE => StorageEntry::Double(u.arbitrary()?),
+        SERIALIZE_TYPE_STRING => StorageEntry::Buf(<&[u8]>::arbitrary(u)?.into()),
+        SERIALIZE_TYPE_BOOL => StorageEntry::Bool(u.arbitrary()?),
+        _ => StorageEntry::Section(section(u, depth.saturating_sub(1))?),
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    #[test]
+    fn encodable() {
+        let data: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut u = Unstructured::new(&data);
+
+        while !u.is_empty() {
+            let section = Section::arbitrary(&mut u).unwrap();
+            let mut first = BytesMut::new();
+            crate::write(&mut first, &section);
+
+            let decoded = crate::read(&mut &first[..]).unwrap();
+            let mut second = BytesMut::new();
+            crate::write(&mut second, &decoded);
+            assert_eq!(first, second);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Generates a seed corpus for the fuzz targets.
+//!
+//! Writes valid blobs covering every type tag, every raw size width, nesting
+//! depths and edge lengths into `DIR/de` and `DIR/roundtrip`, and raw size
+//! encodings into `DIR/raw_size`, matching the cargo-fuzz layout when `DIR`
+//! is `fuzz/corpus`.
+
+use bytes::BytesMut;
+use portable_storage::{raw_size, Array, Section, StorageBuf, StorageEntry};
+use std::{env, fs, path::Path, process};
+
+const USAGE: &str = "usage: ps-corpus DIR";
+
+/// The type tag of an array of `u8`.
+const ARRAY_OF_U8: u8 = 0x88;
+
+/// Lengths around the raw size width boundaries.
+const LENGTHS: &[usize] = &[0, 1, 63, 64, 255, 16383, 16384];
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let dir = match args.as_slice() {
+        [dir] if dir != "-h" && dir != "--help" => Path::new(dir),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let blobs = blobs();
+    let sizes = raw_sizes();
+    let result = write_all(&dir.join("de"), &blobs)
+        .and_then(|_| write_all(&dir.join("roundtrip"), &blobs))
+        .and_then(|_| write_all(&dir.join("raw_size"), &sizes));
+    if let Err(e) = result {
+        eprintln!("error: couldn't write the corpus: {}", e);
+        process::exit(1);
+    }
+
+    println!(
+        "wrote {} blobs and {} raw sizes to {}",
+        blobs.len(),
+        sizes.len(),
+        dir.display()
+    );
+}
+
+fn write_all(dir: &Path, files: &[(String, Vec<u8>)]) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (name, bytes) in files {
+        fs::write(dir.join(name), bytes)?;
+    }
+    Ok(())
+}
+
+fn encode(section: &Section) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    portable_storage::write(&mut buf, section);
+    buf.to_vec()
+}
+
+fn single(name: &str, entry: StorageEntry) -> Section {
+    let mut section = Section::new();
+    section.insert(name.to_owned(), entry);
+    section
+}
+
+fn scalars() -> Vec<(&'static str, StorageEntry)> {
+    vec![
+        ("i64", StorageEntry::I64(i64::MIN)),
+        ("i32", StorageEntry::I32(-1)),
+        ("i16", StorageEntry::I16(i16::MAX)),
+        ("i8", StorageEntry::I8(-128)),
+        ("u64", StorageEntry::U64(u64::MAX)),
+        ("u32", StorageEntry::U32(18080)),
+        ("u16", StorageEntry::U16(0)),
+        ("u8", StorageEntry::U8(255)),
+        ("double", StorageEntry::Double(-0.5)),
+        ("string", StorageEntry::Buf(b"monero"[..].into())),
+        ("bool", StorageEntry::Bool(true)),
+        (
+            "section",
+            StorageEntry::Section(single("a", StorageEntry::U8(1))),
+        ),
+    ]
+}
+
+fn blobs() -> Vec<(String, Vec<u8>)> {
+    let mut blobs = vec![("empty".to_owned(), encode(&Section::new()))];
+
+    // Every type tag, alone, as an array element and all together.
+    let mut all = Section::new();
+    for (name, entry) in scalars() {
+        blobs.push((
+            format!("type_{}", name),
+            encode(&single(name, entry.clone())),
+        ));
+
+        let mut array = Array::new();
+        array.push(entry.clone()).unwrap();
+        array.push(entry.clone()).unwrap();
+        blobs.push((
+            format!("array_{}", name),
+            encode(&single(name, StorageEntry::Array(array))),
+        ));
+
+        all.insert(name.to_owned(), entry);
+    }
+    blobs.push(("all_types".to_owned(), encode(&all)));
+
+    // Every raw size width used by string lengths, array lengths and section
+    // entry counts.
+    for &len in LENGTHS {
+        let string = StorageEntry::Buf(StorageBuf::from_elem(0x61, len));
+        blobs.push((format!("string_{}", len), encode(&single("s", string))));
+
+        blobs.push((format!("array_len_{}", len), u8_array(len)));
+
+        if len <= 255 {
+            let mut section = Section::new();
+            for i in 0..len {
+                section.insert(format!("{}", i), StorageEntry::Bool(i % 2 == 0));
+            }
+            blobs.push((format!("entries_{}", len), encode(&section)));
+        }
+    }
+
+    // Key lengths.
+    for &len in &[1, 63, 64, 255] {
+        let key = "k".repeat(len);
+        blobs.push((
+            format!("key_{}", len),
+            encode(&single(&key, StorageEntry::U8(0))),
+        ));
+    }
+
+    // Nesting, through sections and through arrays of sections.
+    for depth in [1, 2, 8, 32] {
+        let mut section = single("leaf", StorageEntry::U32(depth));
+        let mut array_section = section.clone();
+        for _ in 0..depth {
+            section = single("inner", StorageEntry::Section(section));
+
+            let mut array = Array::new();
+            array.push(StorageEntry::Section(array_section)).unwrap();
+            array_section = single("items", StorageEntry::Array(array));
+        }
+        blobs.push((format!("depth_{}", depth), encode(&section)));
+        blobs.push((format!("array_depth_{}", depth), encode(&array_section)));
+    }
+
+    blobs
+}
+
+/// A section holding an array of `len` bytes. Written by hand because an
+/// empty `Array` has no element type and can't be encoded.
+fn u8_array(len: usize) -> Vec<u8> {
+    let mut blob = encode(&Section::new());
+    blob.pop();
+
+    let mut buf = BytesMut::new();
+    raw_size::write(&mut buf, 1);
+    buf.extend_from_slice(&[1, b'a', ARRAY_OF_U8]);
+    raw_size::write(&mut buf, len as u64);
+    buf.extend((0..len).map(|i| i as u8));
+
+    blob.extend_from_slice(&buf);
+    blob
+}
+
+fn raw_sizes() -> Vec<(String, Vec<u8>)> {
+    let values = [
+        0,
+        raw_size::U8_MAX,
+        raw_size::U8_MAX + 1,
+        raw_size::U16_MAX,
+        raw_size::U16_MAX + 1,
+        raw_size::U32_MAX,
+        raw_size::U32_MAX + 1,
+        raw_size::U64_MAX,
+    ];
+
+    values
+        .iter()
+        .map(|&value| {
+            let mut buf = BytesMut::new();
+            raw_size::write(&mut buf, value);
+            (format!("raw_size_{}", value), buf.to_vec())
+        })
+        .collect()
+}
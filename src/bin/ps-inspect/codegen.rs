@@ -0,0 +1,83 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `codegen` subcommand.
+
+use crate::{read_input, usage_error};
+use portable_storage::{header::HeaderValidation, infer::Inference};
+use std::process;
+
+const USAGE: &str = "\
+usage: ps-inspect codegen [options] FILE...
+
+Decodes one or more sample blobs of the same message and prints Rust struct
+definitions matching the observed keys and types. Keys missing from some of
+the samples are marked `#[serde(default)]`.
+
+options:
+    --name NAME         name of the root struct (default: Message)
+    --headerless        the inputs are sections without the storage header
+    --strict            require both header signatures to match
+    -h, --help          print this help
+";
+
+pub fn run<I: Iterator<Item = String>>(mut args: I) {
+    let mut name = "Message".to_owned();
+    let mut headerless = false;
+    let mut validation = HeaderValidation::Lenient;
+    let mut paths = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => {
+                name = args
+                    .next()
+                    .unwrap_or_else(|| usage_error("`--name` requires a value", USAGE))
+            }
+            "--headerless" => headerless = true,
+            "--strict" => validation = HeaderValidation::Strict,
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with("--") => usage_error(&format!("unknown option `{}`", arg), USAGE),
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.is_empty() {
+        usage_error("expected at least one input", USAGE);
+    }
+
+    let mut inference = Inference::new();
+    for path in paths.iter() {
+        let input = read_input(Some(path));
+        let result = if headerless {
+            portable_storage::read_section(&mut &input[..])
+        } else {
+            portable_storage::read_with(&mut &input[..], validation)
+        };
+
+        match result {
+            Ok(section) => inference.add(&section),
+            Err(e) => {
+                eprintln!("error: couldn't decode `{}`: {}", path, e);
+                process::exit(1);
+            }
+        }
+    }
+
+    print!("{}", inference.generate(&name));
+}
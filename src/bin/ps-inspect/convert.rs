@@ -0,0 +1,192 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `convert` subcommand.
+
+use crate::{read_input, usage_error};
+use bytes::BytesMut;
+use portable_storage::{
+    header::HeaderValidation,
+    json::{ByteEncoding, IntegerWidth, JsonConfig},
+    redact::Redactor,
+    Section,
+};
+use std::{
+    fs,
+    io::{self, Write},
+    process,
+};
+
+const USAGE: &str = "\
+usage: ps-inspect convert --to FORMAT [options] [FILE]
+
+Converts a storage blob, JSON or YAML document read from FILE (or stdin)
+into another of those formats. FORMAT is one of `bin`, `json` or `yaml`.
+
+options:
+    --from FORMAT       format of the input (default: bin)
+    --to FORMAT         format of the output
+    --bytes ENCODING    encoding of strings in text formats, `hex` (default)
+                        or `base64`
+    --plain-integers    write integers as plain numbers instead of tagging
+                        them with their width
+    --redact KEY        replace the values of KEY at any depth with a
+                        placeholder in JSON output, may be repeated
+    --redact-path PATH  same for the entry at PATH, keys joined with `.`
+    --headerless        binary input and output don't have a storage header
+    --strict            require both header signatures to match
+    -o FILE             write the output to FILE instead of stdout
+    -h, --help          print this help
+";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Bin,
+    Json,
+    Yaml,
+}
+
+impl Format {
+    fn parse(s: &str) -> Result<Format, String> {
+        match s {
+            "bin" => Ok(Format::Bin),
+            "json" => Ok(Format::Json),
+            "yaml" => Ok(Format::Yaml),
+            _ => Err(format!("unknown format `{}`", s)),
+        }
+    }
+}
+
+struct Options {
+    from: Format,
+    to: Option<Format>,
+    config: JsonConfig,
+    redactor: Redactor,
+    headerless: bool,
+    validation: HeaderValidation,
+    output: Option<String>,
+    path: Option<String>,
+}
+
+pub fn run<I: Iterator<Item = String>>(args: I) {
+    let options = parse_args(args).unwrap_or_else(|e| usage_error(&e, USAGE));
+    let to = options
+        .to
+        .unwrap_or_else(|| usage_error("`--to` is required", USAGE));
+
+    let input = read_input(options.path.as_deref());
+    let output = decode(&input, &options).and_then(|section| encode(&section, to, &options));
+    let output = output.unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    });
+
+    let result = match options.output {
+        Some(ref path) => fs::write(path, &output),
+        None => io::stdout().write_all(&output),
+    };
+    if let Err(e) = result {
+        eprintln!("error: couldn't write output: {}", e);
+        process::exit(2);
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        from: Format::Bin,
+        to: None,
+        config: JsonConfig::default(),
+        redactor: Redactor::new(),
+        headerless: false,
+        validation: HeaderValidation::Lenient,
+        output: None,
+        path: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => options.from = Format::parse(&value(&arg, args.next())?)?,
+            "--to" => options.to = Some(Format::parse(&value(&arg, args.next())?)?),
+            "--bytes" => {
+                options.config.bytes = match value(&arg, args.next())?.as_str() {
+                    "hex" => ByteEncoding::Hex,
+                    "base64" => ByteEncoding::Base64,
+                    other => return Err(format!("unknown byte encoding `{}`", other)),
+                }
+            }
+            "--plain-integers" => options.config.integers = IntegerWidth::Plain,
+            "--redact" => options.redactor = options.redactor.key(value(&arg, args.next())?),
+            "--redact-path" => options.redactor = options.redactor.path(value(&arg, args.next())?),
+            "--headerless" => options.headerless = true,
+            "--strict" => options.validation = HeaderValidation::Strict,
+            "-o" => options.output = Some(value(&arg, args.next())?),
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option `{}`", arg))
+            }
+            _ if options.path.is_none() => options.path = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("`{}` needs a value", option))
+}
+
+fn decode(input: &[u8], options: &Options) -> Result<Section, String> {
+    let text = || std::str::from_utf8(input).map_err(|e| e.to_string());
+    let section = match options.from {
+        Format::Bin if options.headerless => portable_storage::read_section(&mut &input[..]),
+        Format::Bin => portable_storage::read_with(&mut &input[..], options.validation),
+        Format::Json => serde_json::from_str(text()?)
+            .map_err(|e| portable_storage::Error::Conversion(e.to_string()))
+            .and_then(|value| Section::from_json(&value, &options.config)),
+        Format::Yaml => Section::from_yaml(text()?, &options.config),
+    };
+
+    section.map_err(|e| e.to_string())
+}
+
+fn encode(section: &Section, to: Format, options: &Options) -> Result<Vec<u8>, String> {
+    match to {
+        Format::Bin => {
+            let mut buf = BytesMut::new();
+            if options.headerless {
+                portable_storage::write_section(&mut buf, section);
+            } else {
+                portable_storage::write(&mut buf, section);
+            }
+            Ok(buf.to_vec())
+        }
+        Format::Json => {
+            let mut output = serde_json::to_vec_pretty(
+                &section.to_json_redacted(&options.config, &options.redactor),
+            )
+            .map_err(|e| e.to_string())?;
+            output.push(b'\n');
+            Ok(output)
+        }
+        Format::Yaml => section
+            .to_yaml(&options.config)
+            .map(String::into_bytes)
+            .map_err(|e| e.to_string()),
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `diff` subcommand.
+
+use crate::{read_input, usage_error};
+use portable_storage::{header::HeaderValidation, Section};
+use std::process;
+
+const USAGE: &str = "\
+usage: ps-inspect diff [options] OLD NEW
+
+Decodes two storage blobs and prints the paths that were added (+), removed
+(-) or changed (~) from OLD to NEW. Exits with 1 when the blobs differ.
+
+options:
+    --headerless        the inputs are sections without the storage header
+    --strict            require both header signatures to match
+    -h, --help          print this help
+";
+
+pub fn run<I: Iterator<Item = String>>(args: I) {
+    let mut headerless = false;
+    let mut validation = HeaderValidation::Lenient;
+    let mut paths = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "--headerless" => headerless = true,
+            "--strict" => validation = HeaderValidation::Strict,
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with("--") => usage_error(&format!("unknown option `{}`", arg), USAGE),
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.len() != 2 {
+        usage_error("expected two inputs", USAGE);
+    }
+
+    let decode = |path: &str| -> Section {
+        let input = read_input(Some(path));
+        let result = if headerless {
+            portable_storage::read_section(&mut &input[..])
+        } else {
+            portable_storage::read_with(&mut &input[..], validation)
+        };
+
+        result.unwrap_or_else(|e| {
+            eprintln!("error: couldn't decode `{}`: {}", path, e);
+            process::exit(2);
+        })
+    };
+
+    let old = decode(&paths[0]);
+    let new = decode(&paths[1]);
+
+    let differences = portable_storage::diff::diff(&old, &new);
+    for difference in differences.iter() {
+        println!("{}", difference);
+    }
+
+    if !differences.is_empty() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Inspects portable storage blobs.
+//!
+//! Reads a blob from a file (or stdin) and prints the tree of entries with
+//! their types, sizes and offsets. The `convert` subcommand translates blobs
+//! from and to JSON and YAML, `diff` compares two blobs and `codegen`
+//! generates Rust structs from sample blobs.
+
+mod codegen;
+mod convert;
+mod diff;
+
+use portable_storage::{
+    explain::{self, Annotation, AnnotationKind, Explanation},
+    header::HeaderValidation,
+};
+use std::{
+    env, fmt,
+    fs::File,
+    io::{self, Read},
+    process,
+};
+
+const USAGE: &str = "\
+usage: ps-inspect [options] [FILE]
+       ps-inspect convert --to FORMAT [options] [FILE]
+       ps-inspect diff [options] OLD NEW
+       ps-inspect codegen [options] FILE...
+
+Prints the entries of a portable storage blob read from FILE, or from stdin
+when FILE is missing or `-`. Run `ps-inspect SUBCOMMAND --help` for the
+subcommand options.
+
+options:
+    --strict            require both header signatures to match
+    --headerless        the input is a section without the storage header
+    --trace             print the byte-level trace instead of the tree
+    --max-depth N       don't print entries nested deeper than N
+    --max-elements N    print at most N elements of each array
+    -h, --help          print this help
+";
+
+struct Options {
+    validation: HeaderValidation,
+    headerless: bool,
+    trace: bool,
+    max_depth: usize,
+    max_elements: usize,
+    path: Option<String>,
+}
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("convert") => {
+            args.next();
+            convert::run(args)
+        }
+        Some("diff") => {
+            args.next();
+            diff::run(args)
+        }
+        Some("codegen") => {
+            args.next();
+            codegen::run(args)
+        }
+        _ => inspect(args),
+    }
+}
+
+/// Prints `message` followed by `usage` and exits.
+fn usage_error(message: &str, usage: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, usage);
+    process::exit(2);
+}
+
+fn inspect<I: Iterator<Item = String>>(args: I) {
+    let options = parse_args(args).unwrap_or_else(|e| usage_error(&e, USAGE));
+    let input = read_input(options.path.as_deref());
+
+    let explanation = if options.headerless {
+        explain::explain_section(&input)
+    } else {
+        explain::explain_with(&input, options.validation)
+    };
+
+    if options.trace {
+        print!("{}", explanation);
+    } else {
+        print_tree(&explanation, &options);
+    }
+
+    if let Some(ref e) = explanation.error {
+        eprintln!("error at offset {:#x}: {}", explanation.offset, e);
+        process::exit(1);
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        validation: HeaderValidation::Lenient,
+        headerless: false,
+        trace: false,
+        max_depth: usize::MAX,
+        max_elements: usize::MAX,
+        path: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--strict" => options.validation = HeaderValidation::Strict,
+            "--headerless" => options.headerless = true,
+            "--trace" => options.trace = true,
+            "--max-depth" => options.max_depth = number(&arg, args.next())?,
+            "--max-elements" => options.max_elements = number(&arg, args.next())?,
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option `{}`", arg)),
+            _ if options.path.is_none() => options.path = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn number(option: &str, value: Option<String>) -> Result<usize, String> {
+    value
+        .ok_or_else(|| format!("`{}` needs a value", option))?
+        .parse()
+        .map_err(|e| format!("invalid value for `{}`: {}", option, e))
+}
+
+/// Reads the whole input from `path` or stdin, exiting on errors.
+fn read_input(path: Option<&str>) -> Vec<u8> {
+    let mut input = Vec::new();
+    let result = match path {
+        None | Some("-") => io::stdin().read_to_end(&mut input),
+        Some(path) => File::open(path).and_then(|mut f| f.read_to_end(&mut input)),
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: couldn't read input: {}", e);
+        process::exit(2);
+    }
+    input
+}
+
+/// Prints one line per key and array element, annotated with the entry type,
+/// value, size and offset.
+fn print_tree(explanation: &Explanation, options: &Options) {
+    let mut out = String::new();
+    write_tree(&mut out, explanation, options).unwrap();
+    print!("{}", out);
+}
+
+/// The elements seen so far of an array being printed.
+struct ArrayState {
+    /// Depth of the annotations starting its elements.
+    depth: usize,
+    elements: usize,
+}
+
+fn write_tree<W: fmt::Write>(
+    out: &mut W,
+    explanation: &Explanation,
+    options: &Options,
+) -> fmt::Result {
+    let annotations = &explanation.annotations;
+    let ends = entry_ends(explanation);
+
+    let mut arrays: Vec<ArrayState> = Vec::new();
+    // Depth of the elided element whose annotations are being skipped.
+    let mut eliding = None;
+    for (i, annotation) in annotations.iter().enumerate() {
+        while arrays
+            .last()
+            .is_some_and(|array| annotation.depth < array.depth)
+        {
+            arrays.pop();
+        }
+
+        let starts_element = arrays
+            .last()
+            .is_some_and(|array| starts_element(annotations, i, array.depth));
+        if let Some(depth) = eliding {
+            if annotation.depth > depth || (annotation.depth == depth && !starts_element) {
+                continue;
+            }
+            eliding = None;
+        }
+
+        let indent = annotation.depth * 2;
+        if starts_element {
+            let array = arrays.last_mut().unwrap();
+            array.elements += 1;
+            if array.elements > options.max_elements {
+                if array.elements - 1 == options.max_elements
+                    && annotation.depth <= options.max_depth
+                {
+                    writeln!(out, "{:indent$}...", "", indent = indent)?;
+                }
+                eliding = Some(annotation.depth);
+                continue;
+            }
+        }
+
+        if annotation.kind == AnnotationKind::ArrayCount {
+            arrays.push(ArrayState {
+                depth: annotation.depth + 1,
+                elements: 0,
+            });
+        }
+        if annotation.depth > options.max_depth {
+            continue;
+        }
+
+        match annotation.kind {
+            AnnotationKind::Header => writeln!(out, "{}", annotation.description)?,
+            AnnotationKind::Name => {
+                let ty = annotations
+                    .get(i + 1)
+                    .filter(|a| a.kind == AnnotationKind::Type)
+                    .map(|a| a.description.trim_start_matches("type: "))
+                    .unwrap_or("?");
+                let value = annotations[i + 1..]
+                    .iter()
+                    .find(|a| {
+                        a.kind != AnnotationKind::Type && a.kind != AnnotationKind::StringLength
+                    })
+                    .filter(|a| a.kind == AnnotationKind::Value && a.depth == annotation.depth + 1)
+                    .and_then(|a| a.description.split_once(": "))
+                    .map(|(_, v)| format!(" = {}", v))
+                    .unwrap_or_default();
+                let name = annotation.description.trim_start_matches("key ");
+
+                writeln!(
+                    out,
+                    "{:indent$}{}: {}{} ({} bytes @ {:#x})",
+                    "",
+                    name,
+                    ty,
+                    value,
+                    ends[i] - annotation.offset,
+                    annotation.offset,
+                    indent = indent
+                )?;
+            }
+            AnnotationKind::ArrayCount => writeln!(
+                out,
+                "{:indent$}{}",
+                "",
+                annotation.description,
+                indent = indent
+            )?,
+            AnnotationKind::Value if annotation.description.starts_with('[') => writeln!(
+                out,
+                "{:indent$}{} (@ {:#x})",
+                "",
+                annotation.description,
+                annotation.offset,
+                indent = indent
+            )?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Whether annotation `i` is the first one of an element of an array whose
+/// elements start at `depth`. Elements are annotated with their index,
+/// except arrays nested in arrays, which start with their type.
+fn starts_element(annotations: &[Annotation], i: usize, depth: usize) -> bool {
+    let annotation = &annotations[i];
+    if annotation.depth != depth {
+        return false;
+    }
+    match annotation.kind {
+        AnnotationKind::Type => true,
+        AnnotationKind::StringLength => annotation.description.starts_with('['),
+        // The value of a string element follows its length.
+        AnnotationKind::Value => {
+            annotation.description.starts_with('[')
+                && (i == 0 || annotations[i - 1].kind != AnnotationKind::StringLength)
+        }
+        _ => false,
+    }
+}
+
+/// The end offset of the entry starting at each annotation: where the next
+/// annotation at the same or a shallower depth starts.
+fn entry_ends(explanation: &Explanation) -> Vec<usize> {
+    let annotations = &explanation.annotations;
+    let mut ends = vec![explanation.offset; annotations.len()];
+    let mut open: Vec<usize> = Vec::new();
+    for (i, annotation) in annotations.iter().enumerate() {
+        while let Some(&j) = open.last() {
+            if annotations[j].depth < annotation.depth {
+                break;
+            }
+            ends[j] = annotation.offset;
+            open.pop();
+        }
+        open.push(i);
+    }
+    ends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portable_storage::{Array, Section, StorageEntry};
+
+    fn tree(section: &Section, max_elements: usize) -> String {
+        let blob = portable_storage::write_to_vec(section);
+        let options = Options {
+            validation: HeaderValidation::Lenient,
+            headerless: false,
+            trace: false,
+            max_depth: usize::MAX,
+            max_elements,
+            path: None,
+        };
+        let mut out = String::new();
+        write_tree(&mut out, &explain::explain(&blob), &options).unwrap();
+        out
+    }
+
+    #[test]
+    fn max_elements() {
+        let mut peers = Array::new();
+        for i in 0..3 {
+            let mut tags = Array::new();
+            for tag in 0..3u8 {
+                tags.push(StorageEntry::U8(tag)).unwrap();
+            }
+            let mut peer = Section::new();
+            peer.insert("tags".to_owned(), StorageEntry::Array(tags));
+            peer.insert("id".to_owned(), StorageEntry::U64(i));
+            peers.push(StorageEntry::Section(peer)).unwrap();
+        }
+        let mut section = Section::new();
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+
+        let full = tree(&section, usize::MAX);
+        assert_eq!(full.matches("\"id\"").count(), 3);
+        assert_eq!(full.matches("[2] value").count(), 3);
+
+        // Each array has its own budget, and the elided peers are skipped
+        // with their keys.
+        let out = tree(&section, 2);
+        assert_eq!(out.matches("\"id\"").count(), 2, "{}", out);
+        assert_eq!(out.matches("[1] value").count(), 2, "{}", out);
+        assert_eq!(out.matches("[2]").count(), 0, "{}", out);
+        assert_eq!(out.matches("...").count(), 3, "{}", out);
+
+        // Sizes still cover whole entries.
+        let first = full.lines().nth(1).unwrap();
+        let len = portable_storage::write_to_vec(&section).len() - 9 - 1;
+        assert!(
+            first.contains(&format!("({} bytes @ 0xa)", len)),
+            "{}",
+            first
+        );
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Borrowed sections
+//!
+//! A [`Section`] whose keys and strings can borrow from the caller, for the
+//! write path. Building a response out of data that's already at hand
+//! (cached blobs, decoded sections) doesn't need to copy it into an owned
+//! [`crate::Section`] first:
+//!
+//! ```rust
+//! use portable_storage::borrowed::{self, Section, StorageEntry};
+//!
+//! let blocks: Vec<Vec<u8>> = vec![vec![0xaa; 2048], vec![0xbb; 4096]];
+//!
+//! let mut response = Section::new();
+//! response.insert("status", &b"OK"[..]);
+//! response.insert("current_height", StorageEntry::U64(2_200_000));
+//! let mut array = borrowed::Array::new();
+//! for block in &blocks {
+//!     array.push(StorageEntry::from(&block[..])).unwrap();
+//! }
+//! response.insert("blocks", array);
+//!
+//! let blob = borrowed::write_to_vec(&response);
+//! let section = portable_storage::read(&mut &blob[..]).unwrap();
+//! assert_eq!(section, response.into_owned());
+//! ```
+//!
+//! Entries of owned sections can be embedded as they are with
+//! [`StorageEntry::Ref`] and [`StorageEntry::SectionRef`].
+
+use crate::{
+    raw_size, write_buf, write_name, Error, SERIALIZE_FLAG_ARRAY, SERIALIZE_TYPE_ARRAY,
+    SERIALIZE_TYPE_BOOL, SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32,
+    SERIALIZE_TYPE_INT64, SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING,
+    SERIALIZE_TYPE_UINT16, SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use bytes::{BufMut, BytesMut};
+use linked_hash_map::LinkedHashMap;
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageEntry<'a> {
+    U64(u64),
+    U32(u32),
+    U16(u16),
+    U8(u8),
+    I64(i64),
+    I32(i32),
+    I16(i16),
+    I8(i8),
+    Double(f64),
+    Bool(bool),
+    Buf(Cow<'a, [u8]>),
+    Array(Array<'a>),
+    Section(Section<'a>),
+    /// An entry of an owned section.
+    Ref(&'a crate::StorageEntry),
+    /// An owned section.
+    SectionRef(&'a crate::Section),
+}
+
+impl<'a> StorageEntry<'a> {
+    fn serialize_type(&self) -> u8 {
+        match self {
+            StorageEntry::U64(_) => SERIALIZE_TYPE_UINT64,
+            StorageEntry::U32(_) => SERIALIZE_TYPE_UINT32,
+            StorageEntry::U16(_) => SERIALIZE_TYPE_UINT16,
+            StorageEntry::U8(_) => SERIALIZE_TYPE_UINT8,
+            StorageEntry::I64(_) => SERIALIZE_TYPE_INT64,
+            StorageEntry::I32(_) => SERIALIZE_TYPE_INT32,
+            StorageEntry::I16(_) => SERIALIZE_TYPE_INT16,
+            StorageEntry::I8(_) => SERIALIZE_TYPE_INT8,
+            StorageEntry::Double(_) => SERIALIZE_TYPE_DOUBLE,
+            StorageEntry::Bool(_) => SERIALIZE_TYPE_BOOL,
+            StorageEntry::Buf(_) => SERIALIZE_TYPE_STRING,
+            StorageEntry::Array(v) => v
+                .serialize_type
+                .unwrap_or(SERIALIZE_FLAG_ARRAY | SERIALIZE_TYPE_STRING),
+            StorageEntry::Section(_) | StorageEntry::SectionRef(_) => SERIALIZE_TYPE_OBJECT,
+            StorageEntry::Ref(v) => v.serialize_type(),
+        }
+    }
+
+    /// Copies the borrowed data into an owned entry.
+    pub fn into_owned(self) -> crate::StorageEntry {
+        match self {
+            StorageEntry::U64(v) => crate::StorageEntry::U64(v),
+            StorageEntry::U32(v) => crate::StorageEntry::U32(v),
+            StorageEntry::U16(v) => crate::StorageEntry::U16(v),
+            StorageEntry::U8(v) => crate::StorageEntry::U8(v),
+            StorageEntry::I64(v) => crate::StorageEntry::I64(v),
+            StorageEntry::I32(v) => crate::StorageEntry::I32(v),
+            StorageEntry::I16(v) => crate::StorageEntry::I16(v),
+            StorageEntry::I8(v) => crate::StorageEntry::I8(v),
+            StorageEntry::Double(v) => crate::StorageEntry::Double(v),
+            StorageEntry::Bool(v) => crate::StorageEntry::Bool(v),
+            StorageEntry::Buf(v) => crate::StorageEntry::Buf(v[..].into()),
+            StorageEntry::Array(v) => crate::StorageEntry::Array(v.into_owned()),
+            StorageEntry::Section(v) => crate::StorageEntry::Section(v.into_owned()),
+            StorageEntry::Ref(v) => v.clone(),
+            StorageEntry::SectionRef(v) => crate::StorageEntry::Section(v.clone()),
+        }
+    }
+
+    fn write(buf: &mut BytesMut, entry: &Self) {
+        match entry {
+            StorageEntry::Array(v) => Array::write(buf, v),
+            StorageEntry::Ref(v) => crate::StorageEntry::write(buf, v),
+            _ => {
+                buf.put_u8(entry.serialize_type());
+                Self::write_raw(buf, entry);
+            }
+        }
+    }
+
+    fn write_raw(buf: &mut BytesMut, entry: &Self) {
+        match entry {
+            StorageEntry::U64(v) => buf.put_u64_le(*v),
+            StorageEntry::U32(v) => buf.put_u32_le(*v),
+            StorageEntry::U16(v) => buf.put_u16_le(*v),
+            StorageEntry::U8(v) => buf.put_u8(*v),
+            StorageEntry::I64(v) => buf.put_i64_le(*v),
+            StorageEntry::I32(v) => buf.put_i32_le(*v),
+            StorageEntry::I16(v) => buf.put_i16_le(*v),
+            StorageEntry::I8(v) => buf.put_i8(*v),
+            StorageEntry::Double(v) => buf.put_f64_le(*v),
+            StorageEntry::Bool(v) => buf.put_u8(*v as u8),
+            StorageEntry::Buf(v) => write_buf(buf, v),
+            StorageEntry::Array(v) => Array::write(buf, v),
+            StorageEntry::Section(v) => Section::write(buf, v),
+            StorageEntry::Ref(v) => crate::StorageEntry::write_raw(buf, v),
+            StorageEntry::SectionRef(v) => crate::write_section(buf, v),
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for StorageEntry<'a> {
+    fn from(v: &'a [u8]) -> Self {
+        StorageEntry::Buf(Cow::Borrowed(v))
+    }
+}
+
+impl<'a> From<Vec<u8>> for StorageEntry<'a> {
+    fn from(v: Vec<u8>) -> Self {
+        StorageEntry::Buf(Cow::Owned(v))
+    }
+}
+
+impl<'a> From<Array<'a>> for StorageEntry<'a> {
+    fn from(v: Array<'a>) -> Self {
+        StorageEntry::Array(v)
+    }
+}
+
+impl<'a> From<Section<'a>> for StorageEntry<'a> {
+    fn from(v: Section<'a>) -> Self {
+        StorageEntry::Section(v)
+    }
+}
+
+impl<'a> From<&'a crate::StorageEntry> for StorageEntry<'a> {
+    fn from(v: &'a crate::StorageEntry) -> Self {
+        StorageEntry::Ref(v)
+    }
+}
+
+impl<'a> From<&'a crate::Section> for StorageEntry<'a> {
+    fn from(v: &'a crate::Section) -> Self {
+        StorageEntry::SectionRef(v)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Array<'a> {
+    array: Vec<StorageEntry<'a>>,
+    serialize_type: Option<u8>,
+}
+
+impl<'a> Array<'a> {
+    pub fn new() -> Array<'a> {
+        Default::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Array<'a> {
+        Array {
+            array: Vec::with_capacity(capacity),
+            serialize_type: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.array.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.array.is_empty()
+    }
+
+    /// Appends an element, which must have the type of the previous ones.
+    pub fn push(&mut self, entry: StorageEntry<'a>) -> Result<(), Error> {
+        // Arrays of arrays are typed `SERIALIZE_TYPE_ARRAY`, each element
+        // is written with its own flagged type.
+        let entry_type = match entry.serialize_type() {
+            t if t & SERIALIZE_FLAG_ARRAY == SERIALIZE_FLAG_ARRAY => SERIALIZE_TYPE_ARRAY,
+            t => t,
+        };
+
+        match self.serialize_type {
+            Some(serialize_type) if serialize_type & !SERIALIZE_FLAG_ARRAY != entry_type => {
+                return Err(Error::InvalidSerializeType(entry_type))
+            }
+            Some(_) => (),
+            None => self.serialize_type = Some(entry_type | SERIALIZE_FLAG_ARRAY),
+        }
+
+        self.array.push(entry);
+        Ok(())
+    }
+
+    /// Copies the borrowed data into an owned array.
+    pub fn into_owned(self) -> crate::Array {
+        crate::Array {
+            array: self
+                .array
+                .into_iter()
+                .map(StorageEntry::into_owned)
+                .collect(),
+            serialize_type: self.serialize_type,
+        }
+    }
+
+    fn write(buf: &mut BytesMut, array: &Array) {
+        // Empty arrays without a type are written as arrays of strings.
+        buf.put_u8(
+            array
+                .serialize_type
+                .unwrap_or(SERIALIZE_FLAG_ARRAY | SERIALIZE_TYPE_STRING),
+        );
+        raw_size::write(buf, array.array.len() as u64);
+        for entry in array.array.iter() {
+            StorageEntry::write_raw(buf, entry);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Section<'a> {
+    pub entries: LinkedHashMap<Cow<'a, str>, StorageEntry<'a>>,
+}
+
+impl<'a> Section<'a> {
+    pub fn new() -> Section<'a> {
+        Default::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Section<'a> {
+        Section {
+            entries: LinkedHashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Inserts an entry.
+    pub fn insert<K, T>(&mut self, name: K, entry: T)
+    where
+        K: Into<Cow<'a, str>>,
+        T: Into<StorageEntry<'a>>,
+    {
+        self.entries.insert(name.into(), entry.into());
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies the borrowed data into an owned section.
+    pub fn into_owned(self) -> crate::Section {
+        let mut section = crate::Section::with_capacity(self.len());
+        for (name, entry) in self.entries {
+            section.insert(name.into_owned(), entry.into_owned());
+        }
+        section
+    }
+
+    fn write(buf: &mut BytesMut, section: &Self) {
+        raw_size::write(buf, section.entries.len() as u64);
+        for (name, entry) in section.entries.iter() {
+            write_name(buf, name);
+            StorageEntry::write(buf, entry);
+        }
+    }
+}
+
+/// Writes `section` as a storage blob, header included.
+pub fn write(buf: &mut BytesMut, section: &Section) {
+    crate::header::StorageBlockHeader::write(buf);
+    write_section(buf, section);
+}
+
+/// Writes `section` without the storage block header.
+pub fn write_section(buf: &mut BytesMut, section: &Section) {
+    Section::write(buf, section);
+}
+
+/// Writes `section` as a storage blob into a new vector.
+pub fn write_to_vec(section: &Section) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    write(&mut buf, section);
+    buf.to_vec()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_owned() {
+        let mut peer = crate::Section::new();
+        peer.insert("id".to_owned(), crate::StorageEntry::U64(7));
+        let height = crate::StorageEntry::U64(100);
+        let hash = [0x11; 32];
+
+        let mut hashes = Array::new();
+        hashes.push(StorageEntry::from(&hash[..])).unwrap();
+        hashes.push(vec![0x22; 32].into()).unwrap();
+        assert!(matches!(
+            hashes.push(StorageEntry::U8(0)),
+            Err(Error::InvalidSerializeType(SERIALIZE_TYPE_UINT8))
+        ));
+
+        let mut section = Section::new();
+        section.insert("hashes", hashes);
+        section.insert("height", &height);
+        section.insert(String::from("node_data"), &peer);
+        let mut nested = Section::new();
+        nested.insert("flag", StorageEntry::Bool(true));
+        section.insert("nested", nested);
+
+        let blob = write_to_vec(&section);
+        let owned = section.into_owned();
+        assert_eq!(blob, crate::write_to_vec(&owned));
+        assert_eq!(crate::read(&mut &blob[..]).unwrap(), owned);
+    }
+
+    #[test]
+    fn arrays_of_arrays() {
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U32(1)).unwrap();
+        let mut matrix = Array::new();
+        matrix.push(StorageEntry::Array(ids)).unwrap();
+        matrix.push(StorageEntry::Array(Array::new())).unwrap();
+        assert!(matches!(
+            matrix.push(StorageEntry::U32(2)),
+            Err(Error::InvalidSerializeType(SERIALIZE_TYPE_UINT32))
+        ));
+
+        let mut section = Section::new();
+        section.insert("matrix", matrix);
+
+        let blob = write_to_vec(&section);
+        let owned = section.into_owned();
+        assert_eq!(blob, crate::write_to_vec(&owned));
+        let decoded = crate::read(&mut &blob[..]).unwrap();
+        assert_eq!(crate::write_to_vec(&decoded), blob);
+    }
+}
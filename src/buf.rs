@@ -0,0 +1,173 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::INLINE_BUF_LEN;
+use smallvec::SmallVec;
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+/// The bytes of a string entry, stored inline when they're short.
+///
+/// Longer ones are allocated, and may be shared with other entries: reading
+/// with [`read_dedup`](crate::read_dedup) makes identical strings share
+/// their bytes, and cloning shared bytes only bumps a reference count.
+/// Shared bytes are copied the first time they're modified.
+#[derive(Clone)]
+pub struct StorageBuf(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Owned(SmallVec<[u8; INLINE_BUF_LEN]>),
+    Shared(Arc<[u8]>),
+}
+
+impl StorageBuf {
+    pub fn new() -> StorageBuf {
+        StorageBuf(Repr::Owned(SmallVec::new()))
+    }
+
+    pub fn from_slice(data: &[u8]) -> StorageBuf {
+        StorageBuf(Repr::Owned(SmallVec::from_slice(data)))
+    }
+
+    pub fn from_elem(byte: u8, len: usize) -> StorageBuf {
+        StorageBuf(Repr::Owned(SmallVec::from_elem(byte, len)))
+    }
+
+    /// Creates a buffer sharing `data`.
+    pub fn shared(data: Arc<[u8]>) -> StorageBuf {
+        StorageBuf(Repr::Shared(data))
+    }
+
+    /// Whether the bytes are shared with other buffers.
+    pub fn is_shared(&self) -> bool {
+        matches!(self.0, Repr::Shared(_))
+    }
+
+    /// Moves allocated bytes behind a reference count, so clones of the
+    /// buffer share them. Inline bytes are left as they are.
+    pub fn share(&mut self) {
+        if let Repr::Owned(v) = &self.0 {
+            if v.spilled() {
+                self.0 = Repr::Shared(Arc::from(&v[..]));
+            }
+        }
+    }
+
+    /// Whether the bytes are allocated rather than inline.
+    pub fn spilled(&self) -> bool {
+        match &self.0 {
+            Repr::Owned(v) => v.spilled(),
+            Repr::Shared(_) => true,
+        }
+    }
+
+    /// How many bytes the buffer holds without reallocating.
+    pub fn capacity(&self) -> usize {
+        match &self.0 {
+            Repr::Owned(v) => v.capacity(),
+            Repr::Shared(v) => v.len(),
+        }
+    }
+
+    /// Releases the excess capacity, moving the bytes inline when they fit.
+    /// Shared bytes are left as they are.
+    pub fn shrink_to_fit(&mut self) {
+        if let Repr::Owned(v) = &mut self.0 {
+            v.shrink_to_fit();
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.0 {
+            Repr::Owned(v) => v.into_vec(),
+            Repr::Shared(v) => v.to_vec(),
+        }
+    }
+}
+
+impl Default for StorageBuf {
+    fn default() -> StorageBuf {
+        StorageBuf::new()
+    }
+}
+
+impl Deref for StorageBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Owned(v) => v,
+            Repr::Shared(v) => v,
+        }
+    }
+}
+
+impl DerefMut for StorageBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        if let Repr::Shared(v) = &self.0 {
+            self.0 = Repr::Owned(SmallVec::from_slice(v));
+        }
+        match &mut self.0 {
+            Repr::Owned(v) => v,
+            Repr::Shared(_) => unreachable!(),
+        }
+    }
+}
+
+impl PartialEq for StorageBuf {
+    fn eq(&self, other: &StorageBuf) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl Eq for StorageBuf {}
+
+impl fmt::Debug for StorageBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl From<&[u8]> for StorageBuf {
+    fn from(data: &[u8]) -> StorageBuf {
+        StorageBuf::from_slice(data)
+    }
+}
+
+impl From<Vec<u8>> for StorageBuf {
+    fn from(data: Vec<u8>) -> StorageBuf {
+        StorageBuf(Repr::Owned(SmallVec::from_vec(data)))
+    }
+}
+
+impl From<Arc<[u8]>> for StorageBuf {
+    fn from(data: Arc<[u8]>) -> StorageBuf {
+        StorageBuf::shared(data)
+    }
+}
+
+impl std::iter::FromIterator<u8> for StorageBuf {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> StorageBuf {
+        StorageBuf(Repr::Owned(iter.into_iter().collect()))
+    }
+}
@@ -0,0 +1,734 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Direct codec
+//!
+//! Reads and writes structures straight from and to the wire, without going
+//! through [`Section`](crate::Section) or serde. Implementations are meant to
+//! be derived with `#[derive(StorageSection)]` (behind the `derive` feature),
+//! which generates per-field code matching keys against a table built at
+//! compile time.
+//!
+//! ```rust
+//! # #[cfg(feature = "derive")] {
+//! use bytes::BytesMut;
+//! use portable_storage::{codec, StorageSection};
+//!
+//! #[derive(Debug, PartialEq, StorageSection)]
+//! struct SupportFlags {
+//!     support_flags: u32,
+//! }
+//!
+//! let mut buf = BytesMut::new();
+//! codec::write(&mut buf, &SupportFlags { support_flags: 1 });
+//! let flags: SupportFlags = codec::read(&mut &buf[..]).unwrap();
+//! assert_eq!(flags.support_flags, 1);
+//! # }
+//! ```
+//!
+//! Keys are written in declaration order and unknown keys are skipped when
+//! reading. Values must have the exact serialize type of the field.
+//!
+//! Fields take attributes mirroring epee's `KV_SERIALIZE` macros:
+//!
+//! | Attribute | epee | |
+//! |---|---|---|
+//! | `#[storage(rename = "key")]` | `KV_SERIALIZE_N` | Uses another key. |
+//! | `#[storage(default)]` | `KV_SERIALIZE_OPT` | Uses `Default::default()` when missing. |
+//! | `#[storage(default = expr)]` | `KV_SERIALIZE_OPT` | Uses `expr` when missing. |
+//! | `#[storage(pod_as_blob)]` | `KV_SERIALIZE_VAL_POD_AS_BLOB` | Stores a [`Pod`] value as a string of its bytes. |
+//! | `#[storage(container_pod_as_blob)]` | `KV_SERIALIZE_CONTAINER_POD_AS_BLOB` | Stores a `Vec` of [`Pod`] values as one string. |
+//! | `#[storage(skip)]` | | Never written, `Default::default()` when read. |
+//! | `#[storage(since = 2)]` | | Only present from protocol version 2. |
+//! | `#[storage(until = 3)]` | | Only present before protocol version 3. |
+//!
+//! Versioned fields matter when encoding or decoding for a given protocol
+//! version with [`write_version`] and [`read_version`], the version is passed
+//! down to nested structures. Fields absent from that version aren't written,
+//! are skipped when read and take their `Default::default()` value. Without
+//! a version every field is present.
+
+use crate::{
+    header::StorageBlockHeader, raw_size, wire, Error, Result, SERIALIZE_FLAG_ARRAY,
+    SERIALIZE_TYPE_BOOL, SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32,
+    SERIALIZE_TYPE_INT64, SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING,
+    SERIALIZE_TYPE_UINT16, SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use bytes::{BufMut, Bytes};
+
+pub use bytes::{Buf, BytesMut};
+
+/// A value that can be stored in a section field.
+pub trait StorageValue: Sized {
+    /// The serialize type written before the value.
+    const SERIALIZE_TYPE: u8;
+
+    /// Writes the value without its serialize type.
+    fn write_value(&self, buf: &mut BytesMut);
+
+    /// Reads a value whose serialize type was already checked.
+    fn read_value<B: Buf>(buf: &mut B) -> Result<Self>;
+
+    /// Writes the value as present in protocol `version`, every field when
+    /// `None`.
+    fn write_value_at(&self, buf: &mut BytesMut, version: Option<u32>) {
+        let _ = version;
+        self.write_value(buf)
+    }
+
+    /// Reads a value as present in protocol `version`, every field when
+    /// `None`.
+    fn read_value_at<B: Buf>(buf: &mut B, version: Option<u32>) -> Result<Self> {
+        let _ = version;
+        Self::read_value(buf)
+    }
+}
+
+/// A value that can be an array element, that is, any value except arrays.
+pub trait StorageElement: StorageValue {}
+
+/// A plain value that can be stored as its little-endian bytes.
+pub trait Pod: Sized {
+    /// The size of the value in bytes.
+    const SIZE: usize;
+
+    fn write_pod(&self, buf: &mut BytesMut);
+
+    /// Reads a value, `buf` holds at least `SIZE` bytes.
+    fn read_pod<B: Buf>(buf: &mut B) -> Self;
+}
+
+/// A structure stored as a section.
+pub trait StorageSection: Sized {
+    /// Writes the entry count and the entries.
+    fn write_section(&self, buf: &mut BytesMut);
+
+    /// Reads the entry count and the entries.
+    fn read_section<B: Buf>(buf: &mut B) -> Result<Self>;
+
+    /// Writes the entries present in protocol `version`, every entry when
+    /// `None`.
+    fn write_section_at(&self, buf: &mut BytesMut, version: Option<u32>) {
+        let _ = version;
+        self.write_section(buf)
+    }
+
+    /// Reads the entries present in protocol `version`, every entry when
+    /// `None`.
+    fn read_section_at<B: Buf>(buf: &mut B, version: Option<u32>) -> Result<Self> {
+        let _ = version;
+        Self::read_section(buf)
+    }
+}
+
+/// Writes the storage block header followed by `value`.
+pub fn write<T: StorageSection>(buf: &mut BytesMut, value: &T) {
+    StorageBlockHeader::write(buf);
+    value.write_section(buf);
+}
+
+/// Reads the storage block header followed by a `T`.
+pub fn read<T: StorageSection, B: Buf>(buf: &mut B) -> Result<T> {
+    StorageBlockHeader::read(buf)?;
+    T::read_section(buf)
+}
+
+/// Writes the storage block header followed by `value` as present in
+/// protocol `version`.
+pub fn write_version<T: StorageSection>(buf: &mut BytesMut, value: &T, version: u32) {
+    StorageBlockHeader::write(buf);
+    value.write_section_at(buf, Some(version));
+}
+
+/// Reads the storage block header followed by a `T` as present in protocol
+/// `version`.
+pub fn read_version<T: StorageSection, B: Buf>(buf: &mut B, version: u32) -> Result<T> {
+    StorageBlockHeader::read(buf)?;
+    T::read_section_at(buf, Some(version))
+}
+
+/// Whether a field added in `since` and removed in `until` is present in
+/// protocol `version`.
+pub fn present(version: Option<u32>, since: Option<u32>, until: Option<u32>) -> bool {
+    match version {
+        Some(version) => {
+            version >= since.unwrap_or(0) && u64::from(version) < until.map_or(u64::MAX, u64::from)
+        }
+        None => true,
+    }
+}
+
+/// Writes a field: its name, serialize type and value.
+pub fn write_field<T: StorageValue>(buf: &mut BytesMut, name: &str, value: &T) {
+    crate::write_name(buf, name);
+    buf.reserve(1);
+    buf.put_u8(T::SERIALIZE_TYPE);
+    value.write_value(buf);
+}
+
+/// Writes a field as present in protocol `version`.
+pub fn write_field_at<T: StorageValue>(
+    buf: &mut BytesMut,
+    name: &str,
+    value: &T,
+    version: Option<u32>,
+) {
+    crate::write_name(buf, name);
+    buf.reserve(1);
+    buf.put_u8(T::SERIALIZE_TYPE);
+    value.write_value_at(buf, version);
+}
+
+/// Reads the value of a field after its name, checking its serialize type.
+pub fn read_field<T: StorageValue, B: Buf>(buf: &mut B) -> Result<T> {
+    read_field_at(buf, None)
+}
+
+/// Reads the value of a field as present in protocol `version`.
+pub fn read_field_at<T: StorageValue, B: Buf>(buf: &mut B, version: Option<u32>) -> Result<T> {
+    let serialize_type = wire::read_type(buf)?;
+    if serialize_type != T::SERIALIZE_TYPE {
+        return Err(Error::UnexpectedType {
+            expected: T::SERIALIZE_TYPE,
+            found: serialize_type,
+        });
+    }
+
+    T::read_value_at(buf, version)
+}
+
+/// Writes a field holding `value` as a string of its bytes.
+pub fn write_pod_field<T: Pod>(buf: &mut BytesMut, name: &str, value: &T) {
+    crate::write_name(buf, name);
+    buf.reserve(1);
+    buf.put_u8(SERIALIZE_TYPE_STRING);
+    raw_size::write(buf, T::SIZE as u64);
+    value.write_pod(buf);
+}
+
+/// Reads a value stored as a string of its bytes.
+pub fn read_pod_field<T: Pod, B: Buf>(buf: &mut B) -> Result<T> {
+    let length = read_blob_length(buf)?;
+    if length != T::SIZE {
+        return Err(Error::Conversion(format!(
+            "expected a string of {} bytes, found {}",
+            T::SIZE,
+            length
+        )));
+    }
+    ensure_eof!(buf, length);
+    Ok(T::read_pod(buf))
+}
+
+/// Writes a field holding `values` as a single string of their bytes.
+pub fn write_pod_container_field<T: Pod>(buf: &mut BytesMut, name: &str, values: &[T]) {
+    crate::write_name(buf, name);
+    buf.reserve(1);
+    buf.put_u8(SERIALIZE_TYPE_STRING);
+    raw_size::write(buf, (values.len() * T::SIZE) as u64);
+    for value in values {
+        value.write_pod(buf);
+    }
+}
+
+/// Reads values stored as a single string of their bytes.
+pub fn read_pod_container_field<T: Pod, B: Buf>(buf: &mut B) -> Result<Vec<T>> {
+    let length = read_blob_length(buf)?;
+    if T::SIZE == 0 || length % T::SIZE != 0 {
+        return Err(Error::Conversion(format!(
+            "expected a string of a multiple of {} bytes, found {}",
+            T::SIZE,
+            length
+        )));
+    }
+    ensure_eof!(buf, length);
+    Ok((0..length / T::SIZE).map(|_| T::read_pod(buf)).collect())
+}
+
+/// Skips the value of a field after its name.
+pub fn skip_field<B: Buf>(buf: &mut B) -> Result<()> {
+    crate::skip::skip(buf)
+}
+
+/// Reads a section entry count.
+pub fn read_count<B: Buf>(buf: &mut B) -> Result<usize> {
+    read_size(buf)
+}
+
+/// Reads a field name into `scratch` and returns it.
+pub fn read_name<'a, B: Buf>(buf: &mut B, scratch: &'a mut [u8; 255]) -> Result<&'a [u8]> {
+    let length = wire::read_name_len(buf)?;
+    buf.copy_to_slice(&mut scratch[..length]);
+    Ok(&scratch[..length])
+}
+
+/// The error of a required field missing from the section.
+pub fn missing(name: &str) -> Error {
+    Error::Conversion(format!("missing field `{}`", name))
+}
+
+fn read_size<B: Buf>(buf: &mut B) -> Result<usize> {
+    raw_size::read_usize(buf)
+}
+
+/// Checks that the serialize type is a string and reads its length.
+fn read_blob_length<B: Buf>(buf: &mut B) -> Result<usize> {
+    let serialize_type = wire::read_type(buf)?;
+    if serialize_type != SERIALIZE_TYPE_STRING {
+        return Err(Error::UnexpectedType {
+            expected: SERIALIZE_TYPE_STRING,
+            found: serialize_type,
+        });
+    }
+    read_size(buf)
+}
+
+fn read_bytes<B: Buf>(buf: &mut B) -> Result<Bytes> {
+    let length = read_size(buf)?;
+    ensure_eof!(buf, length);
+    Ok(buf.copy_to_bytes(length))
+}
+
+macro_rules! numbers {
+    ($($ty:ty, $serialize_type:ident, $put:ident, $get:ident;)+) => {
+        $(
+        impl StorageValue for $ty {
+            const SERIALIZE_TYPE: u8 = $serialize_type;
+
+            fn write_value(&self, buf: &mut BytesMut) {
+                buf.reserve(std::mem::size_of::<$ty>());
+                buf.$put(*self);
+            }
+
+            fn read_value<B: Buf>(buf: &mut B) -> Result<Self> {
+                ensure_eof!(buf, std::mem::size_of::<$ty>());
+                Ok(buf.$get())
+            }
+        }
+
+        impl StorageElement for $ty {}
+
+        impl Pod for $ty {
+            const SIZE: usize = std::mem::size_of::<$ty>();
+
+            fn write_pod(&self, buf: &mut BytesMut) {
+                self.write_value(buf);
+            }
+
+            fn read_pod<B: Buf>(buf: &mut B) -> Self {
+                buf.$get()
+            }
+        }
+        )+
+    };
+}
+
+numbers! {
+    u64, SERIALIZE_TYPE_UINT64, put_u64_le, get_u64_le;
+    u32, SERIALIZE_TYPE_UINT32, put_u32_le, get_u32_le;
+    u16, SERIALIZE_TYPE_UINT16, put_u16_le, get_u16_le;
+    u8, SERIALIZE_TYPE_UINT8, put_u8, get_u8;
+    i64, SERIALIZE_TYPE_INT64, put_i64_le, get_i64_le;
+    i32, SERIALIZE_TYPE_INT32, put_i32_le, get_i32_le;
+    i16, SERIALIZE_TYPE_INT16, put_i16_le, get_i16_le;
+    i8, SERIALIZE_TYPE_INT8, put_i8, get_i8;
+    f64, SERIALIZE_TYPE_DOUBLE, put_f64_le, get_f64_le;
+}
+
+impl StorageValue for bool {
+    const SERIALIZE_TYPE: u8 = SERIALIZE_TYPE_BOOL;
+
+    fn write_value(&self, buf: &mut BytesMut) {
+        buf.reserve(1);
+        buf.put_u8(*self as u8);
+    }
+
+    fn read_value<B: Buf>(buf: &mut B) -> Result<Self> {
+        ensure_eof!(buf, 1);
+        Ok(buf.get_u8() != 0)
+    }
+}
+
+impl StorageElement for bool {}
+
+impl StorageValue for String {
+    const SERIALIZE_TYPE: u8 = SERIALIZE_TYPE_STRING;
+
+    fn write_value(&self, buf: &mut BytesMut) {
+        crate::write_buf(buf, self.as_bytes());
+    }
+
+    fn read_value<B: Buf>(buf: &mut B) -> Result<Self> {
+        String::from_utf8(read_bytes(buf)?.to_vec()).map_err(|e| Error::Conversion(e.to_string()))
+    }
+}
+
+impl StorageElement for String {}
+
+/// Arbitrary binary data, stored as a string.
+impl StorageValue for Bytes {
+    const SERIALIZE_TYPE: u8 = SERIALIZE_TYPE_STRING;
+
+    fn write_value(&self, buf: &mut BytesMut) {
+        crate::write_buf(buf, self);
+    }
+
+    fn read_value<B: Buf>(buf: &mut B) -> Result<Self> {
+        read_bytes(buf)
+    }
+}
+
+impl StorageElement for Bytes {}
+
+/// Fixed-size binary data such as hashes and keys, stored as a string of
+/// exactly `N` bytes.
+impl<const N: usize> StorageValue for [u8; N] {
+    const SERIALIZE_TYPE: u8 = SERIALIZE_TYPE_STRING;
+
+    fn write_value(&self, buf: &mut BytesMut) {
+        crate::write_buf(buf, self);
+    }
+
+    fn read_value<B: Buf>(buf: &mut B) -> Result<Self> {
+        let length = read_size(buf)?;
+        if length != N {
+            return Err(Error::Conversion(format!(
+                "expected a string of {} bytes, found {}",
+                N, length
+            )));
+        }
+        ensure_eof!(buf, N);
+        let mut value = [0; N];
+        buf.copy_to_slice(&mut value);
+        Ok(value)
+    }
+}
+
+impl<const N: usize> StorageElement for [u8; N] {}
+
+impl<const N: usize> Pod for [u8; N] {
+    const SIZE: usize = N;
+
+    fn write_pod(&self, buf: &mut BytesMut) {
+        buf.reserve(N);
+        buf.put_slice(self);
+    }
+
+    fn read_pod<B: Buf>(buf: &mut B) -> Self {
+        let mut value = [0; N];
+        buf.copy_to_slice(&mut value);
+        value
+    }
+}
+
+impl<T: StorageElement> StorageValue for Vec<T> {
+    const SERIALIZE_TYPE: u8 = T::SERIALIZE_TYPE | SERIALIZE_FLAG_ARRAY;
+
+    fn write_value(&self, buf: &mut BytesMut) {
+        self.write_value_at(buf, None)
+    }
+
+    fn read_value<B: Buf>(buf: &mut B) -> Result<Self> {
+        Self::read_value_at(buf, None)
+    }
+
+    fn write_value_at(&self, buf: &mut BytesMut, version: Option<u32>) {
+        raw_size::write(buf, self.len() as u64);
+        for element in self {
+            element.write_value_at(buf, version);
+        }
+    }
+
+    fn read_value_at<B: Buf>(buf: &mut B, version: Option<u32>) -> Result<Self> {
+        let len = read_size(buf)?;
+        // Every element takes at least a byte, don't trust larger counts.
+        let mut array = Vec::with_capacity(len.min(buf.remaining()));
+        for _ in 0..len {
+            array.push(T::read_value_at(buf, version)?);
+        }
+        Ok(array)
+    }
+}
+
+/// The serialize type of sections, for `StorageValue` implementations of
+/// structures.
+pub const SECTION: u8 = SERIALIZE_TYPE_OBJECT;
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Section, StorageEntry};
+
+    struct Peer {
+        id: u64,
+        last_seen: i64,
+        hashes: Vec<[u8; 2]>,
+    }
+
+    impl StorageSection for Peer {
+        fn write_section(&self, buf: &mut BytesMut) {
+            raw_size::write(buf, 3);
+            write_field(buf, "id", &self.id);
+            write_field(buf, "last_seen", &self.last_seen);
+            write_field(buf, "hashes", &self.hashes);
+        }
+
+        fn read_section<B: Buf>(buf: &mut B) -> Result<Self> {
+            let (mut id, mut last_seen, mut hashes) = (None, None, None);
+            let mut scratch = [0; 255];
+            for _ in 0..read_count(buf)? {
+                match read_name(buf, &mut scratch)? {
+                    b"id" => id = Some(read_field(buf)?),
+                    b"last_seen" => last_seen = Some(read_field(buf)?),
+                    b"hashes" => hashes = Some(read_field(buf)?),
+                    _ => skip_field(buf)?,
+                }
+            }
+
+            Ok(Peer {
+                id: id.ok_or_else(|| missing("id"))?,
+                last_seen: last_seen.ok_or_else(|| missing("last_seen"))?,
+                hashes: hashes.ok_or_else(|| missing("hashes"))?,
+            })
+        }
+    }
+
+    #[test]
+    fn agrees_with_section() {
+        let peer = Peer {
+            id: 7,
+            last_seen: -1,
+            hashes: vec![[1, 2], [3, 4]],
+        };
+        let mut buf = BytesMut::new();
+        write(&mut buf, &peer);
+
+        let section = crate::read(&mut &buf[..]).unwrap();
+        assert_eq!(section["id"], StorageEntry::U64(7));
+        assert_eq!(section["last_seen"], StorageEntry::I64(-1));
+        let mut again = BytesMut::new();
+        crate::write(&mut again, &section);
+        assert_eq!(buf, again);
+
+        let mut section = section;
+        section.insert("unknown".to_owned(), StorageEntry::Bool(true));
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &section);
+        let read: Peer = read(&mut &buf[..]).unwrap();
+        assert_eq!(read.hashes, peer.hashes);
+    }
+
+    #[cfg(feature = "derive")]
+    #[derive(Debug, PartialEq, crate::StorageSection)]
+    struct Handshake {
+        node_data: NodeData,
+        peers: Vec<NodeData>,
+        r#type: u8,
+    }
+
+    #[cfg(feature = "derive")]
+    #[derive(Debug, PartialEq, crate::StorageSection)]
+    struct NodeData {
+        network_id: [u8; 16],
+        my_port: u32,
+        version: String,
+        blob: Bytes,
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derived() {
+        let node_data = NodeData {
+            network_id: [0x12; 16],
+            my_port: 18080,
+            version: "0.17.1.0".to_owned(),
+            blob: Bytes::from_static(b"blob"),
+        };
+        let handshake = Handshake {
+            node_data: NodeData {
+                my_port: 0,
+                ..node_data
+            },
+            peers: vec![NodeData {
+                network_id: [0x34; 16],
+                my_port: 18081,
+                version: String::new(),
+                blob: Bytes::new(),
+            }],
+            r#type: 1,
+        };
+
+        let mut buf = BytesMut::new();
+        write(&mut buf, &handshake);
+        let section = crate::read(&mut &buf[..]).unwrap();
+        assert_eq!(section["type"], StorageEntry::U8(1));
+        assert_eq!(read::<Handshake, _>(&mut &buf[..]).unwrap(), handshake);
+
+        let mut again = BytesMut::new();
+        crate::write(&mut again, &section);
+        assert_eq!(buf, again);
+    }
+
+    #[cfg(feature = "derive")]
+    #[derive(Debug, Default, PartialEq, crate::StorageSection)]
+    struct Attributes {
+        #[storage(rename = "m_height")]
+        height: u64,
+        #[storage(default)]
+        pruning_seed: u32,
+        #[storage(default = 18080)]
+        my_port: u32,
+        #[storage(pod_as_blob)]
+        difficulty: u64,
+        #[storage(container_pod_as_blob)]
+        block_ids: Vec<[u8; 2]>,
+        #[storage(skip)]
+        cached: Option<String>,
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn attributes() {
+        let value = Attributes {
+            height: 10,
+            pruning_seed: 0,
+            my_port: 1,
+            difficulty: 0x0102,
+            block_ids: vec![[1, 2], [3, 4]],
+            cached: Some("not written".to_owned()),
+        };
+        let mut buf = BytesMut::new();
+        write(&mut buf, &value);
+
+        let mut section = crate::read(&mut &buf[..]).unwrap();
+        assert_eq!(section.len(), 5);
+        assert_eq!(section["m_height"], StorageEntry::U64(10));
+        assert_eq!(
+            section["difficulty"],
+            StorageEntry::Buf(vec![2, 1, 0, 0, 0, 0, 0, 0].into())
+        );
+        assert_eq!(
+            section["block_ids"],
+            StorageEntry::Buf(vec![1, 2, 3, 4].into())
+        );
+
+        section.entries.remove("pruning_seed");
+        section.entries.remove("my_port");
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &section);
+        let decoded: Attributes = read(&mut &buf[..]).unwrap();
+        assert_eq!(
+            decoded,
+            Attributes {
+                my_port: 18080,
+                cached: None,
+                ..value
+            }
+        );
+
+        section.insert(
+            "block_ids".to_owned(),
+            StorageEntry::Buf(vec![1, 2, 3].into()),
+        );
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &section);
+        assert!(read::<Attributes, _>(&mut &buf[..]).is_err());
+    }
+
+    #[cfg(feature = "derive")]
+    #[derive(Debug, Default, PartialEq, crate::StorageSection)]
+    struct Versioned {
+        height: u64,
+        #[storage(since = 2)]
+        pruning_seed: u32,
+        #[storage(until = 2)]
+        legacy: bool,
+        nested: Vec<Versioned>,
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn versions() {
+        let value = Versioned {
+            height: 1,
+            pruning_seed: 2,
+            legacy: true,
+            nested: vec![Versioned {
+                height: 3,
+                pruning_seed: 4,
+                legacy: true,
+                nested: Vec::new(),
+            }],
+        };
+
+        let keys_of = |buf: &BytesMut| {
+            let section = crate::read(&mut &buf[..]).unwrap();
+            let nested = match &section["nested"] {
+                StorageEntry::Array(array) => match &array[0] {
+                    StorageEntry::Section(nested) => nested.entries.keys().cloned().collect(),
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            };
+            let keys: Vec<String> = section.entries.keys().cloned().collect();
+            (keys, nested)
+        };
+
+        let mut v1 = BytesMut::new();
+        write_version(&mut v1, &value, 1);
+        let (keys, nested): (_, Vec<String>) = keys_of(&v1);
+        assert_eq!(keys, ["height", "legacy", "nested"]);
+        assert_eq!(nested, ["height", "legacy", "nested"]);
+
+        let mut v2 = BytesMut::new();
+        write_version(&mut v2, &value, 2);
+        assert_eq!(keys_of(&v2).0, ["height", "pruning_seed", "nested"]);
+
+        let mut all = BytesMut::new();
+        write(&mut all, &value);
+        assert_eq!(read::<Versioned, _>(&mut &all[..]).unwrap(), value);
+
+        // Fields absent from the version are ignored even when present.
+        let decoded: Versioned = read_version(&mut &all[..], 2).unwrap();
+        assert!(!decoded.legacy && !decoded.nested[0].legacy);
+        assert_eq!(decoded.nested[0].pruning_seed, 4);
+        assert!(read::<Versioned, _>(&mut &v2[..]).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let mut section = Section::new();
+        section.insert("id".to_owned(), StorageEntry::U32(7));
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &section);
+        assert!(matches!(
+            read::<Peer, _>(&mut &buf[..]),
+            Err(Error::UnexpectedType {
+                expected: SERIALIZE_TYPE_UINT64,
+                found: SERIALIZE_TYPE_UINT32
+            })
+        ));
+
+        let mut section = Section::new();
+        section.insert("id".to_owned(), StorageEntry::U64(7));
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &section);
+        let error = read::<Peer, _>(&mut &buf[..]).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "conversion failed: missing field `last_seen`"
+        );
+    }
+}
@@ -0,0 +1,576 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Code generation from message definitions
+//!
+//! Turns a declarative file describing messages into Rust structs plus
+//! round-trip serialization tests, so protocol definitions can be kept in
+//! one reviewed document. Meant to be called from a build script:
+//!
+//! ```rust,no_run
+//! // build.rs
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("messages.rs");
+//! portable_storage::codegen::generate("messages.idl", out).unwrap();
+//! ```
+//!
+//! and included with `include!(concat!(env!("OUT_DIR"), "/messages.rs"));`.
+//! The generated code uses `serde`, `portable_storage_utils` and, in its
+//! tests, `bytes`.
+//!
+//! The definition file is a list of messages. Each key has a type, keys
+//! marked with `?` are optional and `#` starts a comment:
+//!
+//! ```text
+//! message NodeData {
+//!     network_id: string
+//!     my_port: u32
+//!     peer_id: u64
+//!     support_flags?: u32
+//! }
+//!
+//! message Handshake {
+//!     node_data: NodeData
+//!     peers: [PeerlistEntry]   # arrays of any type
+//! }
+//! ```
+//!
+//! The builtin types are `i64`, `i32`, `i16`, `i8`, `u64`, `u32`, `u16`,
+//! `u8`, `double`, `bool` and `string`. Keys that aren't valid Rust
+//! identifiers can be quoted.
+
+use crate::{
+    infer::identifier,
+    schema::{Schema, SchemaType},
+};
+use std::{fmt::Write as _, fs, io, path::Path};
+
+/// A parsed definition file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Idl {
+    pub messages: Vec<Message>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub name: String,
+    pub fields: Vec<FieldDef>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDef {
+    pub name: String,
+    pub ty: TypeRef,
+    pub optional: bool,
+}
+
+/// The type of a key in a definition file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeRef {
+    /// A scalar or string type, never `SchemaType::Section` or
+    /// `SchemaType::Array`.
+    Builtin(SchemaType),
+    /// Another message of the same file.
+    Message(String),
+    Array(Box<TypeRef>),
+}
+
+/// An error in a definition file.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("line {}: {}", line, message)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Reads the definition file at `input` and writes the generated code to
+/// `output`, telling cargo to rerun the build script when `input` changes.
+pub fn generate<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> io::Result<()> {
+    let input = input.as_ref();
+    println!("cargo:rerun-if-changed={}", input.display());
+
+    let source = fs::read_to_string(input)?;
+    let idl = Idl::parse(&source).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", input.display(), e),
+        )
+    })?;
+    fs::write(output, idl.to_rust())
+}
+
+impl Idl {
+    /// Parses a definition file, checking that every referenced message is
+    /// defined and that messages don't contain themselves.
+    pub fn parse(source: &str) -> Result<Idl, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let mut messages: Vec<Message> = Vec::new();
+        let mut lines = Vec::new();
+
+        while !parser.is_eof() {
+            let line = parser.line();
+            parser.expect_word("message")?;
+            let name = parser.identifier()?;
+            if messages.iter().any(|m| m.name == name) {
+                return Err(error(line, format!("message `{}` is defined twice", name)));
+            }
+            parser.expect(Token::Open)?;
+
+            let mut fields: Vec<FieldDef> = Vec::new();
+            while !parser.eat(&Token::Close) {
+                let line = parser.line();
+                let key = parser.key()?;
+                if fields.iter().any(|f| f.name == key) {
+                    return Err(error(line, format!("key `{}` is defined twice", key)));
+                }
+                let optional = parser.eat(&Token::Question);
+                parser.expect(Token::Colon)?;
+                let ty = parser.type_ref()?;
+                parser.eat(&Token::Comma);
+
+                fields.push(FieldDef {
+                    name: key,
+                    ty,
+                    optional,
+                });
+            }
+
+            messages.push(Message { name, fields });
+            lines.push(line);
+        }
+
+        let idl = Idl { messages };
+        for (message, line) in idl.messages.iter().zip(lines) {
+            for field in message.fields.iter() {
+                let mut referenced = Vec::new();
+                field.ty.messages(&mut referenced);
+                for name in referenced {
+                    if idl.message(name).is_none() {
+                        return Err(error(line, format!("message `{}` isn't defined", name)));
+                    }
+                }
+            }
+            if idl.contains(&message.name, &message.name, &mut Vec::new()) {
+                return Err(error(
+                    line,
+                    format!("message `{}` contains itself", message.name),
+                ));
+            }
+        }
+
+        Ok(idl)
+    }
+
+    pub fn message(&self, name: &str) -> Option<&Message> {
+        self.messages.iter().find(|m| m.name == name)
+    }
+
+    /// Builds the runtime schema of message `name`.
+    pub fn schema(&self, name: &str) -> Option<Schema> {
+        let message = self.message(name)?;
+        let mut schema = Schema::new();
+        for field in message.fields.iter() {
+            let ty = self.schema_type(&field.ty)?;
+            schema = if field.optional {
+                schema.optional(field.name.as_str(), ty)
+            } else {
+                schema.required(field.name.as_str(), ty)
+            };
+        }
+
+        Some(schema)
+    }
+
+    fn schema_type(&self, ty: &TypeRef) -> Option<SchemaType> {
+        match ty {
+            TypeRef::Builtin(ty) => Some(ty.clone()),
+            TypeRef::Message(name) => self.schema(name).map(SchemaType::Section),
+            TypeRef::Array(element) => self.schema_type(element).map(SchemaType::array),
+        }
+    }
+
+    /// Returns `true` if message `name` contains `target`, directly or not.
+    fn contains<'a>(&'a self, name: &str, target: &str, visited: &mut Vec<&'a str>) -> bool {
+        let message = match self.message(name) {
+            Some(message) => message,
+            None => return false,
+        };
+
+        for field in message.fields.iter() {
+            let mut referenced = Vec::new();
+            field.ty.messages(&mut referenced);
+            for inner in referenced {
+                if inner == target {
+                    return true;
+                }
+                if !visited.contains(&inner) {
+                    visited.push(inner);
+                    if self.contains(inner, target, visited) {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Generates the structs and a test module checking that a sample of each
+    /// message survives a write and read.
+    pub fn to_rust(&self) -> String {
+        let mut code = String::new();
+        code.push_str("// Generated by portable_storage::codegen, don't edit.\n");
+
+        for message in self.messages.iter() {
+            code.push('\n');
+            code.push_str(
+                "#[derive(Debug, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize)]\n",
+            );
+            writeln!(code, "pub struct {} {{", message.name).unwrap();
+            for field in message.fields.iter() {
+                let ident = identifier(&field.name);
+                if ident != field.name {
+                    writeln!(code, "    #[serde(rename = {:?})]", field.name).unwrap();
+                }
+                if field.optional {
+                    code.push_str("    #[serde(default)]\n");
+                }
+                writeln!(code, "    pub {}: {},", ident, rust_type(&field.ty)).unwrap();
+            }
+            code.push_str("}\n");
+        }
+
+        code.push_str("\n#[cfg(test)]\nmod portable_storage_codegen_tests {\n");
+        code.push_str("    use super::*;\n");
+        for message in self.messages.iter() {
+            let snake = snake_case(&message.name);
+
+            writeln!(code, "\n    fn sample_{}() -> {} {{", snake, message.name).unwrap();
+            writeln!(code, "        {} {{", message.name).unwrap();
+            for field in message.fields.iter() {
+                writeln!(
+                    code,
+                    "            {}: {},",
+                    identifier(&field.name),
+                    sample(&field.ty)
+                )
+                .unwrap();
+            }
+            code.push_str("        }\n    }\n");
+
+            writeln!(code, "\n    #[test]\n    fn roundtrip_{}() {{", snake).unwrap();
+            writeln!(code, "        let value = sample_{}();", snake).unwrap();
+            code.push_str(concat!(
+                "        let section = ::portable_storage::to_section(&value).unwrap();\n",
+                "        let mut buf = ::bytes::BytesMut::new();\n",
+                "        ::portable_storage::write(&mut buf, &section);\n",
+                "        let section = ::portable_storage::read(&mut buf.freeze()).unwrap();\n",
+                "        assert_eq!(::portable_storage::from_section::<_>(section).ok(), Some(value));\n",
+                "    }\n",
+            ));
+        }
+        code.push_str("}\n");
+
+        code
+    }
+}
+
+impl TypeRef {
+    fn messages<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            TypeRef::Builtin(_) => (),
+            TypeRef::Message(name) => out.push(name),
+            TypeRef::Array(element) => element.messages(out),
+        }
+    }
+}
+
+fn rust_type(ty: &TypeRef) -> String {
+    match ty {
+        TypeRef::Builtin(SchemaType::String) => "::portable_storage_utils::Blob".to_owned(),
+        TypeRef::Builtin(SchemaType::Double) => "f64".to_owned(),
+        TypeRef::Builtin(ty) => ty.to_string(),
+        TypeRef::Message(name) => name.clone(),
+        TypeRef::Array(element) => format!("::std::vec::Vec<{}>", rust_type(element)),
+    }
+}
+
+/// An expression building a sample value of `ty`, arrays get one element so
+/// their type is known when written.
+fn sample(ty: &TypeRef) -> String {
+    match ty {
+        TypeRef::Builtin(SchemaType::String) => {
+            "::portable_storage_utils::Blob(b\"sample\".to_vec())".to_owned()
+        }
+        TypeRef::Builtin(SchemaType::Double) => "0.5".to_owned(),
+        TypeRef::Builtin(SchemaType::Bool) => "true".to_owned(),
+        TypeRef::Builtin(_) => "1".to_owned(),
+        TypeRef::Message(name) => format!("sample_{}()", snake_case(name)),
+        TypeRef::Array(element) => format!("vec![{}]", sample(element)),
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i != 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+fn builtin(name: &str) -> Option<SchemaType> {
+    Some(match name {
+        "i64" => SchemaType::I64,
+        "i32" => SchemaType::I32,
+        "i16" => SchemaType::I16,
+        "i8" => SchemaType::I8,
+        "u64" => SchemaType::U64,
+        "u32" => SchemaType::U32,
+        "u16" => SchemaType::U16,
+        "u8" => SchemaType::U8,
+        "double" => SchemaType::Double,
+        "bool" => SchemaType::Bool,
+        "string" => SchemaType::String,
+        _ => return None,
+    })
+}
+
+fn error(line: usize, message: String) -> ParseError {
+    ParseError { line, message }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Open,
+    Close,
+    Colon,
+    Question,
+    Comma,
+    LeftBracket,
+    RightBracket,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '#' => break,
+                c if c.is_whitespace() => continue,
+                '{' => Token::Open,
+                '}' => Token::Close,
+                ':' => Token::Colon,
+                '?' => Token::Question,
+                ',' => Token::Comma,
+                '[' => Token::LeftBracket,
+                ']' => Token::RightBracket,
+                '"' => {
+                    let mut quoted = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(c) => quoted.push(c),
+                            None => {
+                                return Err(error(line_number, "unterminated string".to_owned()))
+                            }
+                        }
+                    }
+                    Token::Quoted(quoted)
+                }
+                c if c.is_ascii_alphanumeric() || c == '_' => {
+                    let mut word = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if !(c.is_ascii_alphanumeric() || c == '_') {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+                    Token::Word(word)
+                }
+                c => return Err(error(line_number, format!("unexpected character `{}`", c))),
+            };
+            tokens.push((line_number, token));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+}
+
+impl Parser {
+    fn is_eof(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or_else(|| self.tokens.last())
+            .map(|(line, _)| *line)
+            .unwrap_or(1)
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError> {
+        let line = self.line();
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(_, token)| token.clone())
+            .ok_or_else(|| error(line, "unexpected end of file".to_owned()))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.position).map(|(_, t)| t) == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        let line = self.line();
+        let token = self.next()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(error(
+                line,
+                format!("expected {:?}, found {:?}", expected, token),
+            ))
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), ParseError> {
+        self.expect(Token::Word(word.to_owned()))
+    }
+
+    fn identifier(&mut self) -> Result<String, ParseError> {
+        let line = self.line();
+        match self.next()? {
+            Token::Word(word) if !word.starts_with(|c: char| c.is_ascii_digit()) => Ok(word),
+            token => Err(error(
+                line,
+                format!("expected an identifier, found {:?}", token),
+            )),
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ParseError> {
+        let line = self.line();
+        match self.next()? {
+            Token::Word(word) | Token::Quoted(word) if !word.is_empty() && word.len() <= 255 => {
+                Ok(word)
+            }
+            token => Err(error(line, format!("expected a key, found {:?}", token))),
+        }
+    }
+
+    fn type_ref(&mut self) -> Result<TypeRef, ParseError> {
+        if self.eat(&Token::LeftBracket) {
+            let element = self.type_ref()?;
+            self.expect(Token::RightBracket)?;
+            return Ok(TypeRef::Array(Box::new(element)));
+        }
+
+        let name = self.identifier()?;
+        Ok(match builtin(&name) {
+            Some(ty) => TypeRef::Builtin(ty),
+            None => TypeRef::Message(name),
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        # A peer
+        message Peer {
+            "last-seen"?: i64
+            id: u64
+        }
+
+        message Handshake {
+            peers: [Peer]
+            network_id: string,
+        }
+    "#;
+
+    #[test]
+    fn parse_and_generate() {
+        let idl = Idl::parse(SOURCE).unwrap();
+        assert_eq!(idl.messages.len(), 2);
+
+        let schema = idl.schema("Handshake").unwrap();
+        assert_eq!(
+            schema.fields["peers"].ty.to_string(),
+            "array of section".to_owned()
+        );
+        assert!(idl.schema("Peer").unwrap().fields["last-seen"].optional);
+
+        let code = idl.to_rust();
+        assert!(code.contains(
+            "    #[serde(rename = \"last-seen\")]\n    #[serde(default)]\n    pub last_seen: i64,"
+        ));
+        assert!(code.contains("    pub peers: ::std::vec::Vec<Peer>,"));
+        assert!(code.contains("            peers: vec![sample_peer()],"));
+        assert!(code.contains("    fn roundtrip_handshake() {"));
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("message A { b: B }", "line 1: message `B` isn't defined"),
+            (
+                "message A {\n a: [A]\n}",
+                "line 1: message `A` contains itself",
+            ),
+            (
+                "message A {\n a: u8\n a: u8 }",
+                "line 3: key `a` is defined twice",
+            ),
+            (
+                "message A {\n a u8 }",
+                "line 2: expected Colon, found Word(\"u8\")",
+            ),
+            ("message A {", "line 1: unexpected end of file"),
+        ];
+        for (source, message) in cases.iter() {
+            assert_eq!(Idl::parse(source).unwrap_err().to_string(), *message);
+        }
+    }
+}
@@ -13,18 +13,103 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{Section, StorageEntry};
+use crate::{
+    header::StorageBlockHeader, raw_size, wire, Section, StorageEntry, SERIALIZE_TYPE_ARRAY,
+    SERIALIZE_TYPE_BOOL, SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32,
+    SERIALIZE_TYPE_INT64, SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING,
+    SERIALIZE_TYPE_UINT16, SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use bytes::Buf;
 use linked_hash_map::LinkedHashMap;
 use serde::{
     de::{
-        value::Error, DeserializeSeed, Deserializer, Error as ErrorTrait, MapAccess, SeqAccess,
-        Visitor,
+        value::{BorrowedStrDeserializer, Error},
+        DeserializeOwned, DeserializeSeed, Deserializer, Error as ErrorTrait, IntoDeserializer,
+        MapAccess, SeqAccess, Visitor,
     },
     forward_to_deserialize_any, Deserialize,
 };
+use std::{borrow::Cow, convert::TryFrom};
+
+/// How closely the type of an entry must match the type it's deserialized
+/// into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeMatching {
+    /// Whatever the target type accepts, e.g. a `u8` entry for a `u64` field
+    /// or any non-zero byte as `true`. This is the default.
+    Lenient,
+    /// The entry must have the type this crate serializes the target type
+    /// as: no integer widening, no `f32` nor `char`, and booleans encoded as
+    /// 0 or 1. Meant for checking other implementations against the
+    /// canonical encoding.
+    Exact,
+}
+
+impl Default for TypeMatching {
+    fn default() -> Self {
+        TypeMatching::Lenient
+    }
+}
 
 pub fn from_section<'de, T: Deserialize<'de>>(section: Section) -> Result<T, Error> {
-    T::deserialize(SectionDeserializer(section))
+    from_section_with(section, TypeMatching::default())
+}
+
+/// Like [`from_section`], matching the types of the entries as asked.
+pub fn from_section_with<'de, T: Deserialize<'de>>(
+    section: Section,
+    matching: TypeMatching,
+) -> Result<T, Error> {
+    T::deserialize(SectionDeserializer(section, matching))
+}
+
+/// Deserializes a storage blob straight from its bytes, without decoding it
+/// into a [`Section`] first.
+///
+/// Keys are matched against the static names of the fields of the
+/// structures being deserialized, and strings are borrowed from `data`, so
+/// only the deserialized values allocate.
+pub fn from_bytes<'de, T: Deserialize<'de>>(data: &'de [u8]) -> Result<T, Error> {
+    from_bytes_with(data, TypeMatching::default())
+}
+
+/// Like [`from_bytes`], matching the types of the entries as asked.
+pub fn from_bytes_with<'de, T: Deserialize<'de>>(
+    data: &'de [u8],
+    matching: TypeMatching,
+) -> Result<T, Error> {
+    let mut buf = data;
+    StorageBlockHeader::read(&mut buf).map_err(Error::custom)?;
+    from_section_bytes(buf, matching)
+}
+
+/// Deserializes a section that isn't preceded by the storage block header.
+pub(crate) fn from_section_bytes<'de, T: Deserialize<'de>>(
+    mut buf: &'de [u8],
+    matching: TypeMatching,
+) -> Result<T, Error> {
+    T::deserialize(BytesSectionDeserializer(&mut buf, matching))
+}
+
+/// Decodes a storage blob spanning the whole of `data` into a [`Section`]
+/// and deserializes it, in one call.
+///
+/// Unlike [`from_bytes`] the value can't borrow from `data`, and bytes left
+/// after the blob are an error.
+pub fn from_storage_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+    let section = Section::try_from(data).map_err(Error::custom)?;
+    from_section(section)
+}
+
+/// Like [`from_bytes`], deserializing with `seed`, so the value can hand
+/// its parts to the caller while they're decoded.
+pub fn from_bytes_seed<'de, S: DeserializeSeed<'de>>(
+    data: &'de [u8],
+    seed: S,
+) -> Result<S::Value, Error> {
+    let mut buf = data;
+    StorageBlockHeader::read(&mut buf).map_err(Error::custom)?;
+    seed.deserialize(BytesSectionDeserializer(&mut buf, TypeMatching::default()))
 }
 
 macro_rules! unsupported {
@@ -38,18 +123,95 @@ macro_rules! unsupported {
     }
 }
 
-struct SectionDeserializer(Section);
+/// Storage type of the values Rust types are serialized as, `None` for the
+/// ones without one.
+macro_rules! exact {
+    ($($method:ident => $serialize_type:expr)+) => {
+        $(
+        fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where V: Visitor<'de> {
+            self.check(stringify!($method), $serialize_type)?;
+            self.deserialize_any(visitor)
+        }
+        )+
+    }
+}
+
+/// Checks the type of an entry when the types must match exactly.
+fn check_type(
+    matching: TypeMatching,
+    method: &str,
+    expected: Option<u8>,
+    found: Option<u8>,
+) -> Result<(), Error> {
+    if matching == TypeMatching::Lenient {
+        return Ok(());
+    }
+    let expected = expected.ok_or_else(|| {
+        Error::custom(format!(
+            "`{}` has no exact storage type",
+            method.trim_start_matches("deserialize_")
+        ))
+    })?;
+    match found {
+        Some(found) if wire::is_array(found) => {
+            check_type(matching, method, Some(expected), Some(SERIALIZE_TYPE_ARRAY))
+        }
+        Some(found) if found != expected => Err(Error::custom(crate::Error::UnexpectedType {
+            expected,
+            found,
+        })),
+        // A missing type is reported as EOF by `deserialize_any`.
+        _ => Ok(()),
+    }
+}
+
+macro_rules! exact_types {
+    () => {
+        exact! {
+            deserialize_bool => Some(SERIALIZE_TYPE_BOOL)
+            deserialize_i8 => Some(SERIALIZE_TYPE_INT8)
+            deserialize_i16 => Some(SERIALIZE_TYPE_INT16)
+            deserialize_i32 => Some(SERIALIZE_TYPE_INT32)
+            deserialize_i64 => Some(SERIALIZE_TYPE_INT64)
+            deserialize_u8 => Some(SERIALIZE_TYPE_UINT8)
+            deserialize_u16 => Some(SERIALIZE_TYPE_UINT16)
+            deserialize_u32 => Some(SERIALIZE_TYPE_UINT32)
+            deserialize_u64 => Some(SERIALIZE_TYPE_UINT64)
+            deserialize_f32 => None
+            deserialize_f64 => Some(SERIALIZE_TYPE_DOUBLE)
+            deserialize_char => None
+            deserialize_str => Some(SERIALIZE_TYPE_STRING)
+            deserialize_string => Some(SERIALIZE_TYPE_STRING)
+            deserialize_bytes => Some(SERIALIZE_TYPE_STRING)
+            deserialize_byte_buf => Some(SERIALIZE_TYPE_STRING)
+            deserialize_seq => Some(SERIALIZE_TYPE_ARRAY)
+            deserialize_map => Some(SERIALIZE_TYPE_OBJECT)
+        }
+    };
+}
+
+pub(crate) struct SectionDeserializer(pub(crate) Section, pub(crate) TypeMatching);
 
 impl<'de> Deserializer<'de> for SectionDeserializer {
     type Error = Error;
 
     unsupported! {
-        deserialize_any deserialize_bool deserialize_i8 deserialize_i16
+        deserialize_bool deserialize_i8 deserialize_i16
         deserialize_i32 deserialize_i64 deserialize_u8 deserialize_u16
         deserialize_u32 deserialize_u64 deserialize_f32 deserialize_f64
         deserialize_char deserialize_str deserialize_string deserialize_bytes
         deserialize_byte_buf deserialize_option deserialize_unit deserialize_seq
-        deserialize_map deserialize_identifier deserialize_ignored_any
+        deserialize_identifier deserialize_ignored_any
+    }
+
+    /// The root is always a section, values buffering their contents like
+    /// internally tagged enums get it as a map.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
     }
 
     fn deserialize_unit_struct<V>(
@@ -95,17 +257,29 @@ impl<'de> Deserializer<'de> for SectionDeserializer {
         Err(Error::custom("`deserialize_tuple_struct` isn't supported"))
     }
 
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_struct("", &[], visitor)
+    }
+
     fn deserialize_struct<V>(
         self,
         _name: &'static str,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
         let iter = self.0.into_iter();
-        visitor.visit_map(MapDeserializer { iter, value: None })
+        visitor.visit_map(MapDeserializer {
+            iter,
+            value: None,
+            fields,
+            matching: self.1,
+        })
     }
 
     fn deserialize_enum<V>(
@@ -125,7 +299,13 @@ impl<'de> Deserializer<'de> for SectionDeserializer {
     }
 }
 
-pub struct StorageEntryDeserializer(StorageEntry);
+pub struct StorageEntryDeserializer(StorageEntry, TypeMatching);
+
+impl StorageEntryDeserializer {
+    fn check(&self, method: &str, expected: Option<u8>) -> Result<(), Error> {
+        check_type(self.1, method, expected, Some(self.0.serialize_type()))
+    }
+}
 
 impl<'de> Deserializer<'de> for StorageEntryDeserializer {
     type Error = Error;
@@ -145,23 +325,78 @@ impl<'de> Deserializer<'de> for StorageEntryDeserializer {
             StorageEntry::I8(v) => visitor.visit_i8(v),
             StorageEntry::Double(v) => visitor.visit_f64(v),
             StorageEntry::Bool(v) => visitor.visit_bool(v),
-            StorageEntry::Buf(v) => visitor.visit_byte_buf(v),
-            StorageEntry::Array(v) => visitor.visit_seq(ArrayDeserializer(v.into_iter())),
+            StorageEntry::Buf(v) => visitor.visit_byte_buf(v.into_vec()),
+            StorageEntry::Array(v) => visitor.visit_seq(ArrayDeserializer(v.into_iter(), self.1)),
+            StorageEntry::Section(v) => visitor.visit_map(MapDeserializer {
+                iter: v.into_iter(),
+                value: None,
+                fields: &[],
+                matching: self.1,
+            }),
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
             StorageEntry::Section(v) => visitor.visit_map(MapDeserializer {
                 iter: v.into_iter(),
                 value: None,
+                fields,
+                matching: self.1,
             }),
+            entry => {
+                check_type(
+                    self.1,
+                    "deserialize_struct",
+                    Some(SERIALIZE_TYPE_OBJECT),
+                    Some(entry.serialize_type()),
+                )?;
+                StorageEntryDeserializer(entry, self.1).deserialize_any(visitor)
+            }
         }
     }
 
+    /// Strings are read as unit variants, for the tag of adjacently tagged
+    /// enums among others.
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            StorageEntry::Buf(v) => {
+                let variant = String::from_utf8(v.into_vec()).map_err(Error::custom)?;
+                visitor.visit_enum(variant.into_deserializer())
+            }
+            entry => StorageEntryDeserializer(entry, self.1).deserialize_any(visitor),
+        }
+    }
+
+    exact_types!();
+
     forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
-        byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct enum identifier ignored_any
+        option unit unit_struct newtype_struct tuple tuple_struct
+        identifier ignored_any
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
     }
 }
 
-struct ArrayDeserializer(<Vec<StorageEntry> as IntoIterator>::IntoIter);
+struct ArrayDeserializer(<Vec<StorageEntry> as IntoIterator>::IntoIter, TypeMatching);
 
 impl<'de> SeqAccess<'de> for ArrayDeserializer {
     type Error = Error;
@@ -171,7 +406,7 @@ impl<'de> SeqAccess<'de> for ArrayDeserializer {
         T: DeserializeSeed<'de>,
     {
         if let Some(element) = self.0.next() {
-            seed.deserialize(StorageEntryDeserializer(element))
+            seed.deserialize(StorageEntryDeserializer(element, self.1))
                 .map(Some)
         } else {
             Ok(None)
@@ -186,30 +421,49 @@ impl<'de> SeqAccess<'de> for ArrayDeserializer {
     }
 }
 
-struct KeyDeserializer {
-    key: String,
+/// Deserializes keys. Keys naming a field of the structure are passed as the
+/// field's static name, so they're matched by name like with serde's own
+/// deserializers and aliases keep working.
+struct KeyDeserializer<'a> {
+    key: Cow<'a, str>,
+    fields: &'static [&'static str],
 }
 
-impl<'de> Deserializer<'de> for KeyDeserializer {
+impl<'de> Deserializer<'de> for KeyDeserializer<'de> {
     type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_str(self.key.as_str())
+        match self.key {
+            Cow::Borrowed(key) => visitor.visit_borrowed_str(key),
+            Cow::Owned(key) => visitor.visit_string(key),
+        }
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.fields.iter().find(|field| **field == self.key) {
+            Some(field) => visitor.visit_borrowed_str(field),
+            None => self.deserialize_any(visitor),
+        }
     }
 
     forward_to_deserialize_any! {
         bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
         byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct enum identifier ignored_any
+        tuple_struct map struct enum ignored_any
     }
 }
 
 struct MapDeserializer {
     iter: <LinkedHashMap<String, StorageEntry> as IntoIterator>::IntoIter,
     value: Option<StorageEntry>,
+    fields: &'static [&'static str],
+    matching: TypeMatching,
 }
 
 impl<'de> MapAccess<'de> for MapDeserializer {
@@ -221,7 +475,10 @@ impl<'de> MapAccess<'de> for MapDeserializer {
     {
         if let Some((key, value)) = self.iter.next() {
             self.value = Some(value);
-            let key_de = KeyDeserializer { key };
+            let key_de = KeyDeserializer {
+                key: Cow::Owned(key),
+                fields: self.fields,
+            };
             seed.deserialize(key_de).map(Some)
         } else {
             Ok(None)
@@ -236,7 +493,7 @@ impl<'de> MapAccess<'de> for MapDeserializer {
             .value
             .take()
             .ok_or_else(|| Error::custom("seed value is missing"))?;
-        seed.deserialize(StorageEntryDeserializer(value))
+        seed.deserialize(StorageEntryDeserializer(value, self.matching))
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -247,6 +504,362 @@ impl<'de> MapAccess<'de> for MapDeserializer {
     }
 }
 
+/// Deserializes the root section of a blob from its bytes.
+pub(crate) struct BytesSectionDeserializer<'a, 'de>(
+    pub(crate) &'a mut &'de [u8],
+    pub(crate) TypeMatching,
+);
+
+impl<'a, 'de> Deserializer<'de> for BytesSectionDeserializer<'a, 'de> {
+    type Error = Error;
+
+    unsupported! {
+        deserialize_bool deserialize_i8 deserialize_i16
+        deserialize_i32 deserialize_i64 deserialize_u8 deserialize_u16
+        deserialize_u32 deserialize_u64 deserialize_f32 deserialize_f64
+        deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_option deserialize_unit deserialize_seq
+        deserialize_identifier deserialize_ignored_any
+    }
+
+    /// The root is always a section, values buffering their contents like
+    /// internally tagged enums get it as a map.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::custom("`deserialize_unit_struct` isn't supported"))
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::custom(
+            "`deserialize_newtype_struct` isn't supported",
+        ))
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::custom("`deserialize_tuple` isn't supported"))
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::custom("`deserialize_tuple_struct` isn't supported"))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visit_section(self.0, fields, self.1, visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visit_section(self.0, &[], self.1, visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::custom("`deserialize_enum` isn't supported"))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+fn take<'de>(buf: &mut &'de [u8], length: usize) -> Result<&'de [u8], Error> {
+    if buf.len() < length {
+        return Err(Error::custom(crate::Error::eof(length, buf.len())));
+    }
+    let (taken, rest) = buf.split_at(length);
+    *buf = rest;
+    Ok(taken)
+}
+
+fn read_size(buf: &mut &[u8]) -> Result<usize, Error> {
+    raw_size::read_usize(buf).map_err(Error::custom)
+}
+
+fn visit_section<'de, V: Visitor<'de>>(
+    buf: &mut &'de [u8],
+    fields: &'static [&'static str],
+    matching: TypeMatching,
+    visitor: V,
+) -> Result<V::Value, Error> {
+    let remaining = read_size(buf)?;
+    visitor.visit_map(BytesMapDeserializer {
+        buf,
+        remaining,
+        fields,
+        matching,
+    })
+}
+
+/// Deserializes an entry from its bytes, `serialize_type` is known for array
+/// elements and read from `buf` otherwise.
+struct BytesEntryDeserializer<'a, 'de> {
+    buf: &'a mut &'de [u8],
+    serialize_type: Option<u8>,
+    matching: TypeMatching,
+}
+
+impl<'a, 'de> BytesEntryDeserializer<'a, 'de> {
+    fn check(&self, method: &str, expected: Option<u8>) -> Result<(), Error> {
+        let found = self.serialize_type.or_else(|| self.buf.first().copied());
+        check_type(self.matching, method, expected, found)
+    }
+
+    fn visit<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let buf = self.buf;
+        let matching = self.matching;
+        let serialize_type = match self.serialize_type {
+            Some(serialize_type) => serialize_type,
+            None => wire::read_type(buf).map_err(Error::custom)?,
+        };
+        if wire::is_array(serialize_type) {
+            return visit_array(buf, serialize_type, matching, visitor);
+        }
+
+        match serialize_type {
+            SERIALIZE_TYPE_INT64 => visitor.visit_i64(take(buf, 8)?.get_i64_le()),
+            SERIALIZE_TYPE_INT32 => visitor.visit_i32(take(buf, 4)?.get_i32_le()),
+            SERIALIZE_TYPE_INT16 => visitor.visit_i16(take(buf, 2)?.get_i16_le()),
+            SERIALIZE_TYPE_INT8 => visitor.visit_i8(take(buf, 1)?.get_i8()),
+            SERIALIZE_TYPE_UINT64 => visitor.visit_u64(take(buf, 8)?.get_u64_le()),
+            SERIALIZE_TYPE_UINT32 => visitor.visit_u32(take(buf, 4)?.get_u32_le()),
+            SERIALIZE_TYPE_UINT16 => visitor.visit_u16(take(buf, 2)?.get_u16_le()),
+            SERIALIZE_TYPE_UINT8 => visitor.visit_u8(take(buf, 1)?[0]),
+            SERIALIZE_TYPE_DOUBLE => visitor.visit_f64(take(buf, 8)?.get_f64_le()),
+            SERIALIZE_TYPE_BOOL => match take(buf, 1)?[0] {
+                b @ 0..=1 => visitor.visit_bool(b == 1),
+                b if matching == TypeMatching::Lenient => visitor.visit_bool(b != 0),
+                b => Err(Error::custom(format!("{} isn't a valid boolean", b))),
+            },
+            SERIALIZE_TYPE_STRING => {
+                let length = read_size(buf)?;
+                visitor.visit_borrowed_bytes(take(buf, length)?)
+            }
+            SERIALIZE_TYPE_OBJECT => visit_section(buf, fields, matching, visitor),
+            SERIALIZE_TYPE_ARRAY => {
+                let serialize_type = wire::read_array_type(buf).map_err(Error::custom)?;
+                visit_array(buf, serialize_type, matching, visitor)
+            }
+            _ => Err(Error::custom(crate::Error::InvalidSerializeType(
+                serialize_type,
+            ))),
+        }
+    }
+}
+
+fn visit_array<'de, V: Visitor<'de>>(
+    buf: &mut &'de [u8],
+    serialize_type: u8,
+    matching: TypeMatching,
+    visitor: V,
+) -> Result<V::Value, Error> {
+    let remaining = read_size(buf)?;
+    visitor.visit_seq(BytesArrayDeserializer {
+        buf,
+        remaining,
+        serialize_type: wire::element_type(serialize_type),
+        matching,
+    })
+}
+
+impl<'a, 'de> Deserializer<'de> for BytesEntryDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit(&[], visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.check("deserialize_struct", Some(SERIALIZE_TYPE_OBJECT))?;
+        self.visit(fields, visitor)
+    }
+
+    /// Skips the value using the lengths it's encoded with, without visiting
+    /// its contents.
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.serialize_type {
+            Some(serialize_type) => crate::skip::skip_raw(self.buf, serialize_type),
+            None => crate::skip::skip(self.buf),
+        }
+        .map_err(Error::custom)?;
+        visitor.visit_unit()
+    }
+
+    /// Strings are read as unit variants, like with sections.
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let found = self.serialize_type.or_else(|| self.buf.first().copied());
+        if found != Some(SERIALIZE_TYPE_STRING) {
+            return self.visit(&[], visitor);
+        }
+
+        if self.serialize_type.is_none() {
+            take(self.buf, 1)?;
+        }
+        let length = read_size(self.buf)?;
+        let variant = std::str::from_utf8(take(self.buf, length)?).map_err(Error::custom)?;
+        visitor.visit_enum(BorrowedStrDeserializer::new(variant))
+    }
+
+    exact_types!();
+
+    forward_to_deserialize_any! {
+        option unit unit_struct newtype_struct tuple tuple_struct
+        identifier
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+struct BytesArrayDeserializer<'a, 'de> {
+    buf: &'a mut &'de [u8],
+    remaining: usize,
+    serialize_type: u8,
+    matching: TypeMatching,
+}
+
+impl<'a, 'de> SeqAccess<'de> for BytesArrayDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(BytesEntryDeserializer {
+            buf: self.buf,
+            serialize_type: Some(self.serialize_type),
+            matching: self.matching,
+        })
+        .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        // Don't trust the count of elements further than the bytes left.
+        Some(self.remaining.min(self.buf.len()))
+    }
+}
+
+struct BytesMapDeserializer<'a, 'de> {
+    buf: &'a mut &'de [u8],
+    remaining: usize,
+    fields: &'static [&'static str],
+    matching: TypeMatching,
+}
+
+impl<'a, 'de> MapAccess<'de> for BytesMapDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let name = wire::read_name_bytes(self.buf).map_err(Error::custom)?;
+        let key = String::from_utf8_lossy(name);
+        seed.deserialize(KeyDeserializer {
+            key,
+            fields: self.fields,
+        })
+        .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        seed.deserialize(BytesEntryDeserializer {
+            buf: self.buf,
+            serialize_type: None,
+            matching: self.matching,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining.min(self.buf.len()))
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -257,6 +870,23 @@ pub mod tests {
         transaction_proof: u64,
     }
 
+    #[test]
+    fn storage_bytes() {
+        #[derive(Debug, PartialEq, Deserialize, serde::Serialize)]
+        struct Peer {
+            id: u64,
+            port: u32,
+        }
+
+        let peer = Peer { id: 7, port: 18080 };
+        let mut data = crate::to_storage_bytes(&peer).unwrap();
+        assert_eq!(from_storage_bytes::<Peer>(&data).unwrap(), peer);
+
+        data.push(0);
+        assert!(from_storage_bytes::<Peer>(&data).is_err());
+        assert!(from_storage_bytes::<Peer>(&data[..5]).is_err());
+    }
+
     #[test]
     fn test_vector_0() {
         let mut section = Section::with_capacity(2);
@@ -271,4 +901,260 @@ pub mod tests {
         assert_eq!(test_vector_0.id, 56);
         assert_eq!(test_vector_0.transaction_proof, 1337);
     }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Peer<'a> {
+        id: u64,
+        host: &'a [u8],
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Response<'a> {
+        heights: Vec<u32>,
+        #[serde(borrow)]
+        peers: Vec<Peer<'a>>,
+        top: Peer<'a>,
+        flag: bool,
+    }
+
+    #[test]
+    fn from_bytes_matches_from_section() {
+        let peer = |id, host: &[u8]| {
+            let mut peer = Section::new();
+            peer.insert("host".to_owned(), StorageEntry::Buf(host.into()));
+            peer.insert("id".to_owned(), StorageEntry::U64(id));
+            peer.insert("unknown".to_owned(), StorageEntry::Double(0.5));
+            peer
+        };
+        let mut heights = crate::Array::new();
+        heights.push(StorageEntry::U32(1)).unwrap();
+        heights.push(StorageEntry::U32(2)).unwrap();
+        let mut peers = crate::Array::new();
+        peers.push(StorageEntry::Section(peer(1, b"a"))).unwrap();
+        peers.push(StorageEntry::Section(peer(2, b"b"))).unwrap();
+
+        let mut section = Section::new();
+        section.insert("flag".to_owned(), StorageEntry::Bool(true));
+        section.insert("heights".to_owned(), StorageEntry::Array(heights));
+        section.insert("ignored".to_owned(), StorageEntry::Section(peer(9, b"z")));
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+        section.insert("top".to_owned(), StorageEntry::Section(peer(3, b"c")));
+        let blob = crate::write_to_vec(&section);
+
+        let response: Response = from_bytes(&blob).unwrap();
+        assert_eq!(response.heights, [1, 2]);
+        assert_eq!(response.peers[1].host, b"b");
+        assert_eq!(response.top.id, 3);
+        assert!(response.flag);
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Ids {
+            heights: Vec<u32>,
+            flag: bool,
+        }
+        assert_eq!(
+            from_bytes::<Ids>(&blob).unwrap(),
+            from_section::<Ids>(section).unwrap()
+        );
+
+        assert!(from_bytes::<Response>(&blob[..blob.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn aliased_fields() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Aliased {
+            #[serde(alias = "aa")]
+            a: u8,
+            c: u8,
+        }
+
+        for name in ["a", "aa"].iter() {
+            let mut section = Section::new();
+            section.insert((*name).to_owned(), StorageEntry::U8(1));
+            section.insert("c".to_owned(), StorageEntry::U8(2));
+            let expected = Aliased { a: 1, c: 2 };
+
+            let bytes = crate::write_to_vec(&section);
+            assert_eq!(from_bytes::<Aliased>(&bytes).unwrap(), expected);
+            assert_eq!(from_storage_bytes::<Aliased>(&bytes).unwrap(), expected);
+            assert_eq!(from_section::<Aliased>(section).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn skips_ignored_values() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Flag {
+            flag: bool,
+        }
+
+        let mut hashes = crate::Array::new();
+        for i in 0..10_000u64 {
+            hashes.push(StorageEntry::U64(i)).unwrap();
+        }
+        let mut section = Section::new();
+        section.insert("flag".to_owned(), StorageEntry::Bool(true));
+        section.insert("hashes".to_owned(), StorageEntry::Array(hashes));
+        let blob = crate::write_to_vec(&section);
+
+        assert_eq!(from_bytes::<Flag>(&blob).unwrap(), Flag { flag: true });
+        assert!(from_bytes::<Flag>(&blob[..blob.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn exact_types() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Exact {
+            height: u64,
+            flag: bool,
+            ids: Vec<u32>,
+        }
+
+        let section = |height, flag| {
+            let mut ids = crate::Array::new();
+            ids.push(StorageEntry::U32(7)).unwrap();
+            let mut section = Section::new();
+            section.insert("height".to_owned(), height);
+            section.insert("flag".to_owned(), StorageEntry::Bool(flag));
+            section.insert("ids".to_owned(), StorageEntry::Array(ids));
+            section
+        };
+        let expected = Exact {
+            height: 1,
+            flag: true,
+            ids: vec![7],
+        };
+
+        let exact = section(StorageEntry::U64(1), true);
+        let blob = crate::write_to_vec(&exact);
+        assert_eq!(
+            from_bytes_with::<Exact>(&blob, TypeMatching::Exact).unwrap(),
+            expected
+        );
+        assert_eq!(
+            from_section_with::<Exact>(exact, TypeMatching::Exact).unwrap(),
+            expected
+        );
+
+        let widened = section(StorageEntry::U8(1), true);
+        let blob = crate::write_to_vec(&widened);
+        assert_eq!(from_bytes::<Exact>(&blob).unwrap(), expected);
+        assert!(from_bytes_with::<Exact>(&blob, TypeMatching::Exact).is_err());
+        assert_eq!(from_section::<Exact>(widened.clone()).unwrap(), expected);
+        assert!(from_section_with::<Exact>(widened, TypeMatching::Exact).is_err());
+
+        // `true` written as 2.
+        let mut blob = crate::write_to_vec(&section(StorageEntry::U64(1), true));
+        let offset = blob.windows(5).position(|w| w == b"flag\x0b").unwrap() + 5;
+        blob[offset] = 2;
+        assert_eq!(from_bytes::<Exact>(&blob).unwrap(), expected);
+        assert!(from_bytes_with::<Exact>(&blob, TypeMatching::Exact).is_err());
+
+        #[derive(Debug, Deserialize)]
+        struct Float {
+            #[allow(dead_code)]
+            value: f32,
+        }
+        let mut section = Section::new();
+        section.insert("value".to_owned(), StorageEntry::Double(0.5));
+        assert!(from_section::<Float>(section.clone()).is_ok());
+        assert!(from_section_with::<Float>(section, TypeMatching::Exact).is_err());
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(tag = "type")]
+    enum Command {
+        #[serde(rename = "ping")]
+        Ping { nonce: u64 },
+        #[serde(rename = "peers")]
+        Peers { ids: Vec<u32> },
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(tag = "t", content = "c")]
+    enum Adjacent {
+        Height(u64),
+        Peer { id: u64 },
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Envelope {
+        command: Command,
+        payload: Adjacent,
+    }
+
+    #[test]
+    fn tagged_enums() {
+        let mut ids = crate::Array::new();
+        ids.push(StorageEntry::U32(7)).unwrap();
+        let mut peers = Section::new();
+        peers.insert("ids".to_owned(), StorageEntry::Array(ids));
+        // The tag doesn't have to come first.
+        peers.insert("type".to_owned(), StorageEntry::Buf(b"peers"[..].into()));
+
+        let expected = Command::Peers { ids: vec![7] };
+        let blob = crate::write_to_vec(&peers);
+        assert_eq!(from_section::<Command>(peers.clone()).unwrap(), expected);
+        assert_eq!(from_bytes::<Command>(&blob).unwrap(), expected);
+
+        let mut ping = Section::new();
+        ping.insert("type".to_owned(), StorageEntry::Buf(b"ping"[..].into()));
+        ping.insert("nonce".to_owned(), StorageEntry::U64(42));
+        let mut peer = Section::new();
+        peer.insert("id".to_owned(), StorageEntry::U64(9));
+        let mut payload = Section::new();
+        payload.insert("t".to_owned(), StorageEntry::Buf(b"Peer"[..].into()));
+        payload.insert("c".to_owned(), StorageEntry::Section(peer));
+        let mut envelope = Section::new();
+        envelope.insert("command".to_owned(), StorageEntry::Section(ping));
+        envelope.insert("payload".to_owned(), StorageEntry::Section(payload.clone()));
+
+        let expected = Envelope {
+            command: Command::Ping { nonce: 42 },
+            payload: Adjacent::Peer { id: 9 },
+        };
+        let blob = crate::write_to_vec(&envelope);
+        assert_eq!(from_section::<Envelope>(envelope).unwrap(), expected);
+        assert_eq!(from_bytes::<Envelope>(&blob).unwrap(), expected);
+
+        let mut height = Section::new();
+        height.insert("c".to_owned(), StorageEntry::U64(1337));
+        height.insert("t".to_owned(), StorageEntry::Buf(b"Height"[..].into()));
+        let blob = crate::write_to_vec(&height);
+        assert_eq!(
+            from_section::<Adjacent>(height).unwrap(),
+            Adjacent::Height(1337)
+        );
+        assert_eq!(
+            from_bytes::<Adjacent>(&blob).unwrap(),
+            Adjacent::Height(1337)
+        );
+
+        peers.insert("type".to_owned(), StorageEntry::Buf(b"pong"[..].into()));
+        assert!(from_section::<Command>(peers).is_err());
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        enum Network {
+            Mainnet,
+            Testnet,
+        }
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Node {
+            networks: Vec<Network>,
+        }
+        let mut networks = crate::Array::new();
+        networks
+            .push(StorageEntry::Buf(b"Testnet"[..].into()))
+            .unwrap();
+        networks
+            .push(StorageEntry::Buf(b"Mainnet"[..].into()))
+            .unwrap();
+        let mut node = Section::new();
+        node.insert("networks".to_owned(), StorageEntry::Array(networks));
+        let blob = crate::write_to_vec(&node);
+        let expected = vec![Network::Testnet, Network::Mainnet];
+        assert_eq!(from_bytes::<Node>(&blob).unwrap().networks, expected);
+        assert_eq!(from_section::<Node>(node).unwrap().networks, expected);
+    }
 }
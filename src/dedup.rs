@@ -0,0 +1,205 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # String deduplication
+//!
+//! Peer lists and hash-heavy messages often repeat the same strings: hash
+//! lists, transaction extras, addresses. A [`Dedup`] remembers the
+//! strings it has read and makes identical ones share their bytes instead of
+//! allocating a copy each, and can be shared across messages:
+//!
+//! ```rust
+//! use portable_storage::{dedup::Dedup, Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("tx_extra".to_owned(), StorageEntry::Buf(vec![7; 64].into()));
+//! section.insert("prev_extra".to_owned(), StorageEntry::Buf(vec![7; 64].into()));
+//! let blob = portable_storage::write_to_vec(&section);
+//!
+//! let mut dedup = Dedup::new();
+//! let decoded = portable_storage::read_dedup(&mut &blob[..], &mut dedup).unwrap();
+//! assert_eq!(decoded, section);
+//! assert_eq!(dedup.len(), 1);
+//! ```
+//!
+//! Strings that fit inline in a [`StorageBuf`] don't allocate anyway and are
+//! left alone. The deduplicator stops learning new strings once it holds
+//! its limit of strings or of bytes, whichever comes first, so hostile
+//! peers can't grow it without bounds nor pin large strings in it.
+
+use crate::{StorageBuf, INLINE_BUF_LEN};
+use std::{collections::HashSet, sync::Arc};
+
+/// How many strings [`Dedup::new`] remembers.
+pub const DEFAULT_LIMIT: usize = 4096;
+/// How many bytes of strings [`Dedup::new`] remembers.
+pub const DEFAULT_BYTE_LIMIT: usize = 1024 * 1024;
+
+/// A cache of decoded strings.
+#[derive(Debug, Clone)]
+pub struct Dedup {
+    bufs: HashSet<Arc<[u8]>>,
+    limit: usize,
+    bytes: usize,
+    byte_limit: usize,
+}
+
+impl Dedup {
+    pub fn new() -> Dedup {
+        Dedup::with_limit(DEFAULT_LIMIT)
+    }
+
+    /// Creates a deduplicator remembering at most `limit` strings, and at
+    /// most [`DEFAULT_BYTE_LIMIT`] bytes of them.
+    pub fn with_limit(limit: usize) -> Dedup {
+        Dedup::with_limits(limit, DEFAULT_BYTE_LIMIT)
+    }
+
+    /// Creates a deduplicator remembering at most `limit` strings totalling
+    /// at most `byte_limit` bytes.
+    pub fn with_limits(limit: usize, byte_limit: usize) -> Dedup {
+        Dedup {
+            bufs: HashSet::new(),
+            limit,
+            bytes: 0,
+            byte_limit,
+        }
+    }
+
+    /// Number of strings remembered.
+    pub fn len(&self) -> usize {
+        self.bufs.len()
+    }
+
+    /// Total length of the strings remembered.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets every string. Buffers already read keep their bytes.
+    pub fn clear(&mut self) {
+        self.bufs.clear();
+        self.bytes = 0;
+    }
+}
+
+impl Default for Dedup {
+    fn default() -> Dedup {
+        Dedup::new()
+    }
+}
+
+impl crate::Keys for Dedup {
+    fn key(&mut self, name: &[u8]) -> String {
+        String::from_utf8_lossy(name).into_owned()
+    }
+
+    fn buf(&mut self, data: &[u8]) -> StorageBuf {
+        if data.len() <= INLINE_BUF_LEN {
+            return StorageBuf::from_slice(data);
+        }
+
+        if let Some(shared) = self.bufs.get(data) {
+            return StorageBuf::shared(shared.clone());
+        }
+
+        let shared: Arc<[u8]> = data.into();
+        if self.bufs.len() < self.limit && data.len() <= self.byte_limit - self.bytes {
+            self.bufs.insert(shared.clone());
+            self.bytes += data.len();
+        }
+        StorageBuf::shared(shared)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Array, Keys, Section, StorageEntry};
+
+    #[test]
+    fn limit() {
+        let mut dedup = Dedup::with_limit(1);
+        assert!(!dedup.buf(&[1; 8]).is_shared());
+        assert!(dedup.buf(&[1; 40]).is_shared());
+        dedup.buf(&[2; 40]);
+        assert_eq!(dedup.len(), 1);
+
+        dedup.clear();
+        assert!(dedup.is_empty());
+        assert_eq!(dedup.bytes(), 0);
+    }
+
+    #[test]
+    fn byte_limit() {
+        let mut dedup = Dedup::with_limits(DEFAULT_LIMIT, 100);
+        dedup.buf(&[1; 60]);
+        dedup.buf(&[2; 40]);
+        dedup.buf(&[3; 60]);
+        assert_eq!(dedup.len(), 2);
+        assert_eq!(dedup.bytes(), 100);
+        // The string over the budget isn't shared.
+        let (first, second) = (dedup.buf(&[3; 60]), dedup.buf(&[3; 60]));
+        assert_ne!(first.as_ptr(), second.as_ptr());
+        let (first, second) = (dedup.buf(&[2; 40]), dedup.buf(&[2; 40]));
+        assert_eq!(first.as_ptr(), second.as_ptr());
+    }
+
+    #[test]
+    fn shared() {
+        let mut peers = Array::new();
+        for _ in 0..3 {
+            let mut peer = Section::new();
+            peer.insert("id".to_owned(), StorageEntry::Buf(vec![9; 64].into()));
+            peers.push(StorageEntry::Section(peer)).unwrap();
+        }
+        let mut section = Section::new();
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+        let blob = crate::write_to_vec(&section);
+
+        let mut dedup = Dedup::new();
+        let mut decoded = crate::read_dedup(&mut &blob[..], &mut dedup).unwrap();
+        assert_eq!(decoded, crate::read(&mut &blob[..]).unwrap());
+        assert_eq!(dedup.len(), 1);
+
+        let ids: Vec<&[u8]> = match &decoded["peers"] {
+            StorageEntry::Array(peers) => peers
+                .array
+                .iter()
+                .map(|peer| match peer {
+                    StorageEntry::Section(peer) => peer["id"].as_bytes().unwrap(),
+                    _ => unreachable!(),
+                })
+                .collect(),
+            _ => unreachable!(),
+        };
+        assert!(ids.windows(2).all(|w| w[0].as_ptr() == w[1].as_ptr()));
+
+        // Writing to a shared buffer leaves the others untouched.
+        if let StorageEntry::Array(peers) = decoded.entries.get_mut("peers").unwrap() {
+            if let Some(StorageEntry::Section(peer)) = peers.array.first_mut() {
+                if let Some(StorageEntry::Buf(id)) = peer.entries.get_mut("id") {
+                    id[0] = 0;
+                    assert!(!id.is_shared());
+                }
+            }
+        }
+        assert_ne!(crate::write_to_vec(&decoded)[..], blob[..]);
+    }
+}
@@ -0,0 +1,408 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Structural diff
+//!
+//! Compares two sections key by key, recursing into nested sections and
+//! arrays, and reports the paths that were added, removed or changed. Paths
+//! use `.` to separate keys and `[i]` for array elements, e.g.
+//! `local_peerlist_new[3].adr.type`.
+//!
+//! Key order isn't taken into account.
+//!
+//! [`assert_section_eq!`](crate::assert_section_eq) builds on it to print the
+//! differences when two sections, or a section and an encoded blob, don't
+//! match in tests.
+
+use crate::{explain::type_name, Array, Section, StorageEntry, SERIALIZE_FLAG_ARRAY};
+use bytes::{Bytes, BytesMut};
+use std::fmt;
+
+/// Asserts that two sections are equal, printing the structural differences
+/// on failure.
+///
+/// Either side may also be an encoded storage blob (`&[u8]`, `Vec<u8>`,
+/// `Bytes`, ...), anything implementing [`AsOperand`]. When one of them is,
+/// the other side is encoded as well and the bytes must match exactly, so
+/// key order matters too.
+///
+/// ```rust
+/// use portable_storage::{assert_section_eq, Section, StorageEntry};
+///
+/// let mut section = Section::new();
+/// section.insert("height".to_owned(), StorageEntry::U64(5));
+///
+/// let mut buf = bytes::BytesMut::new();
+/// portable_storage::write(&mut buf, &section);
+/// assert_section_eq!(section, buf);
+/// ```
+///
+/// ```rust,should_panic
+/// # use portable_storage::{assert_section_eq, Section, StorageEntry};
+/// let mut old = Section::new();
+/// old.insert("height".to_owned(), StorageEntry::U64(5));
+/// let mut new = Section::new();
+/// new.insert("height".to_owned(), StorageEntry::U64(6));
+///
+/// // panics with:
+/// // sections differ
+/// // ~ height: u64 5 -> u64 6
+/// assert_section_eq!(old, new, "after applying block {}", 1);
+/// ```
+#[macro_export]
+macro_rules! assert_section_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::diff::assert_operands_eq(
+            $crate::diff::AsOperand::as_operand(&$left),
+            $crate::diff::AsOperand::as_operand(&$right),
+            None,
+        )
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::diff::assert_operands_eq(
+            $crate::diff::AsOperand::as_operand(&$left),
+            $crate::diff::AsOperand::as_operand(&$right),
+            Some(format_args!($($arg)+)),
+        )
+    };
+}
+
+/// One side of [`assert_section_eq!`](crate::assert_section_eq).
+#[derive(Debug, Clone, Copy)]
+pub enum Operand<'a> {
+    Section(&'a Section),
+    /// A storage blob, including its header.
+    Bytes(&'a [u8]),
+}
+
+/// Types that can be compared with
+/// [`assert_section_eq!`](crate::assert_section_eq).
+pub trait AsOperand {
+    fn as_operand(&self) -> Operand<'_>;
+}
+
+impl AsOperand for Section {
+    fn as_operand(&self) -> Operand<'_> {
+        Operand::Section(self)
+    }
+}
+
+impl AsOperand for [u8] {
+    fn as_operand(&self) -> Operand<'_> {
+        Operand::Bytes(self)
+    }
+}
+
+impl<const N: usize> AsOperand for [u8; N] {
+    fn as_operand(&self) -> Operand<'_> {
+        Operand::Bytes(self)
+    }
+}
+
+impl AsOperand for Vec<u8> {
+    fn as_operand(&self) -> Operand<'_> {
+        Operand::Bytes(self)
+    }
+}
+
+impl AsOperand for Bytes {
+    fn as_operand(&self) -> Operand<'_> {
+        Operand::Bytes(self)
+    }
+}
+
+impl AsOperand for BytesMut {
+    fn as_operand(&self) -> Operand<'_> {
+        Operand::Bytes(self)
+    }
+}
+
+impl<T: AsOperand + ?Sized> AsOperand for &T {
+    fn as_operand(&self) -> Operand<'_> {
+        (**self).as_operand()
+    }
+}
+
+impl<'a> Operand<'a> {
+    fn section(&self, side: &str) -> Section {
+        match self {
+            Operand::Section(section) => (*section).clone(),
+            Operand::Bytes(bytes) => crate::read(&mut &bytes[..])
+                .unwrap_or_else(|e| panic!("the {} blob doesn't decode: {}", side, e)),
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Operand::Section(section) => {
+                let mut buf = BytesMut::new();
+                crate::write(&mut buf, section);
+                buf.to_vec()
+            }
+            Operand::Bytes(bytes) => bytes.to_vec(),
+        }
+    }
+}
+
+/// Implementation of [`assert_section_eq!`](crate::assert_section_eq).
+#[doc(hidden)]
+pub fn assert_operands_eq(left: Operand, right: Operand, message: Option<fmt::Arguments>) {
+    let differences = diff(&left.section("left"), &right.section("right"));
+
+    let mut report = String::new();
+    if !differences.is_empty() {
+        report.push_str("sections differ");
+        for difference in differences.iter() {
+            report.push_str(&format!("\n{}", difference));
+        }
+    } else if matches!(left, Operand::Bytes(_)) || matches!(right, Operand::Bytes(_)) {
+        let (left, right) = (left.bytes(), right.bytes());
+        if left != right {
+            let offset = left
+                .iter()
+                .zip(right.iter())
+                .position(|(l, r)| l != r)
+                .unwrap_or_else(|| left.len().min(right.len()));
+            report = format!(
+                "sections are equal but their encodings differ at offset {} \
+                 ({} and {} bytes), check the key order",
+                offset,
+                left.len(),
+                right.len()
+            );
+        }
+    }
+
+    if !report.is_empty() {
+        match message {
+            Some(message) => panic!("{}: {}", message, report),
+            None => panic!("{}", report),
+        }
+    }
+}
+
+/// A difference between two sections.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+    /// The path only exists in the second section.
+    Added { path: String, entry: StorageEntry },
+    /// The path only exists in the first section.
+    Removed { path: String, entry: StorageEntry },
+    /// The path exists in both sections but with different values.
+    Changed {
+        path: String,
+        old: StorageEntry,
+        new: StorageEntry,
+    },
+}
+
+impl Difference {
+    pub fn path(&self) -> &str {
+        match self {
+            Difference::Added { path, .. } => path,
+            Difference::Removed { path, .. } => path,
+            Difference::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difference::Added { path, entry } => write!(f, "+ {}: {}", path, Summary(entry)),
+            Difference::Removed { path, entry } => write!(f, "- {}: {}", path, Summary(entry)),
+            Difference::Changed { path, old, new } => {
+                write!(f, "~ {}: {} -> {}", path, Summary(old), Summary(new))
+            }
+        }
+    }
+}
+
+/// Returns the differences between `old` and `new`, in the key order of
+/// `old` followed by the keys only present in `new`.
+pub fn diff(old: &Section, new: &Section) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_sections("", old, new, &mut differences);
+    differences
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+fn diff_sections(prefix: &str, old: &Section, new: &Section, out: &mut Vec<Difference>) {
+    for (name, old_entry) in old.entries.iter() {
+        let path = join(prefix, name);
+        match new.entries.get(name) {
+            Some(new_entry) => diff_entries(path, old_entry, new_entry, out),
+            None => out.push(Difference::Removed {
+                path,
+                entry: old_entry.clone(),
+            }),
+        }
+    }
+
+    for (name, new_entry) in new.entries.iter() {
+        if !old.entries.contains_key(name) {
+            out.push(Difference::Added {
+                path: join(prefix, name),
+                entry: new_entry.clone(),
+            });
+        }
+    }
+}
+
+fn diff_arrays(prefix: &str, old: &Array, new: &Array, out: &mut Vec<Difference>) {
+    for i in 0..old.len().max(new.len()) {
+        let path = format!("{}[{}]", prefix, i);
+        match (old.array.get(i), new.array.get(i)) {
+            (Some(old), Some(new)) => diff_entries(path, old, new, out),
+            (Some(old), None) => out.push(Difference::Removed {
+                path,
+                entry: old.clone(),
+            }),
+            (None, Some(new)) => out.push(Difference::Added {
+                path,
+                entry: new.clone(),
+            }),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+fn diff_entries(path: String, old: &StorageEntry, new: &StorageEntry, out: &mut Vec<Difference>) {
+    match (old, new) {
+        (StorageEntry::Section(old), StorageEntry::Section(new)) => {
+            diff_sections(&path, old, new, out)
+        }
+        (StorageEntry::Array(old_array), StorageEntry::Array(new_array))
+            if old_array.serialize_type == new_array.serialize_type =>
+        {
+            diff_arrays(&path, old_array, new_array, out)
+        }
+        _ if old != new => out.push(Difference::Changed {
+            path,
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// Short, single line rendering of an entry.
+struct Summary<'a>(&'a StorageEntry);
+
+impl<'a> fmt::Display for Summary<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ty = type_name(self.0.serialize_type());
+        match self.0 {
+            StorageEntry::U64(v) => write!(f, "{} {}", ty, v),
+            StorageEntry::U32(v) => write!(f, "{} {}", ty, v),
+            StorageEntry::U16(v) => write!(f, "{} {}", ty, v),
+            StorageEntry::U8(v) => write!(f, "{} {}", ty, v),
+            StorageEntry::I64(v) => write!(f, "{} {}", ty, v),
+            StorageEntry::I32(v) => write!(f, "{} {}", ty, v),
+            StorageEntry::I16(v) => write!(f, "{} {}", ty, v),
+            StorageEntry::I8(v) => write!(f, "{} {}", ty, v),
+            StorageEntry::Double(v) => write!(f, "{} {}", ty, v),
+            StorageEntry::Bool(v) => write!(f, "{} {}", ty, v),
+            StorageEntry::Buf(v) => {
+                write!(f, "{} 0x", ty)?;
+                for b in v.iter() {
+                    write!(f, "{:02x}", b)?;
+                }
+                Ok(())
+            }
+            StorageEntry::Array(v) => match v.serialize_type {
+                Some(t) => write!(
+                    f,
+                    "{} of {} ({} elements)",
+                    ty,
+                    type_name(t & !SERIALIZE_FLAG_ARRAY),
+                    v.len()
+                ),
+                None => write!(f, "empty {}", ty),
+            },
+            StorageEntry::Section(v) => write!(f, "{} of {} entries", ty, v.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn differences() {
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U32(1)).unwrap();
+
+        let mut node_data = Section::new();
+        node_data.insert("my_port".to_owned(), StorageEntry::U32(18080));
+        node_data.insert("peer_id".to_owned(), StorageEntry::U64(1));
+
+        let mut old = Section::new();
+        old.insert(
+            "node_data".to_owned(),
+            StorageEntry::Section(node_data.clone()),
+        );
+        old.insert("ids".to_owned(), StorageEntry::Array(ids.clone()));
+        old.insert("removed".to_owned(), StorageEntry::Bool(true));
+
+        node_data.insert("my_port".to_owned(), StorageEntry::U32(18081));
+        node_data.insert("peer_id".to_owned(), StorageEntry::U16(1));
+        ids.push(StorageEntry::U32(2)).unwrap();
+
+        let mut new = Section::new();
+        new.insert("ids".to_owned(), StorageEntry::Array(ids));
+        new.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+        new.insert("added".to_owned(), StorageEntry::Buf(vec![0xab].into()));
+
+        let differences: Vec<String> = diff(&old, &new).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            differences,
+            vec![
+                "~ node_data.my_port: u32 18080 -> u32 18081",
+                "~ node_data.peer_id: u64 1 -> u16 1",
+                "+ ids[1]: u32 2",
+                "- removed: bool true",
+                "+ added: string 0xab",
+            ]
+        );
+
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "sections are equal but their encodings differ at offset 11")]
+    fn assert_key_order() {
+        let mut left = Section::new();
+        left.insert("a".to_owned(), StorageEntry::U8(1));
+        left.insert("b".to_owned(), StorageEntry::U8(2));
+
+        let mut right = Section::new();
+        right.insert("b".to_owned(), StorageEntry::U8(2));
+        right.insert("a".to_owned(), StorageEntry::U8(1));
+        assert_section_eq!(left, right);
+
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &right);
+        assert_section_eq!(&left, &buf[..]);
+    }
+}
@@ -0,0 +1,379 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Differential testing
+//!
+//! Round-trips randomized sections through a reference implementation and
+//! compares both the bytes and the decoded values with ours, to find
+//! divergences from epee's behavior.
+//!
+//! The reference is anything implementing [`Reference`]: a shim program
+//! linked against epee that reads a blob on stdin and writes it back
+//! re-encoded on stdout ([`Command`]), or input/output pairs recorded from
+//! such a shim earlier ([`Recorded`]).
+//!
+//! ```rust,no_run
+//! use portable_storage::differential::{self, Command};
+//!
+//! let mut epee = Command::new("./epee-roundtrip");
+//! for divergence in differential::run(&mut epee, 42, 1000) {
+//!     println!("{}", divergence);
+//! }
+//! ```
+//!
+//! Generated sections keep their keys in byte order, because epee stores
+//! section entries in a `std::map` and always writes them sorted.
+
+use crate::{
+    diff::{diff, Difference},
+    Array, Section, StorageEntry,
+};
+use bytes::BytesMut;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+/// An implementation to compare against.
+pub trait Reference {
+    /// Decodes `blob` and encodes it back.
+    fn roundtrip(&mut self, blob: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+/// Runs an external program for each blob, writing the blob to its stdin and
+/// reading the re-encoded blob from its stdout. A non-zero exit status means
+/// the program rejected the blob.
+#[derive(Debug, Clone)]
+pub struct Command {
+    program: PathBuf,
+    args: Vec<String>,
+}
+
+impl Command {
+    pub fn new<P: Into<PathBuf>>(program: P) -> Command {
+        Command {
+            program: program.into(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arg<S: Into<String>>(mut self, arg: S) -> Command {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+impl Reference for Command {
+    fn roundtrip(&mut self, blob: &[u8]) -> Result<Vec<u8>, String> {
+        let mut child = std::process::Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("couldn't run `{}`: {}", self.program.display(), e))?;
+
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(blob)
+            .map_err(|e| e.to_string())?;
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).trim().to_owned())
+        }
+    }
+}
+
+/// Input/output pairs recorded from a reference implementation.
+///
+/// Blobs that weren't recorded are reported as rejected by the reference.
+#[derive(Debug, Default, Clone)]
+pub struct Recorded {
+    pairs: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Recorded {
+    pub fn new() -> Recorded {
+        Default::default()
+    }
+
+    /// Loads every `NAME.in` file of `dir` paired with `NAME.out`.
+    pub fn load<P: AsRef<Path>>(dir: P) -> io::Result<Recorded> {
+        let mut recorded = Recorded::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension() == Some(OsStr::new("in")) {
+                let output = fs::read(path.with_extension("out"))?;
+                recorded.insert(fs::read(&path)?, output);
+            }
+        }
+
+        Ok(recorded)
+    }
+
+    pub fn insert(&mut self, input: Vec<u8>, output: Vec<u8>) {
+        self.pairs.insert(input, output);
+    }
+}
+
+impl Reference for Recorded {
+    fn roundtrip(&mut self, blob: &[u8]) -> Result<Vec<u8>, String> {
+        self.pairs
+            .get(blob)
+            .cloned()
+            .ok_or_else(|| "the blob wasn't recorded".to_owned())
+    }
+}
+
+/// A section on which the reference and this crate disagree.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// The section as encoded by this crate.
+    pub input: Vec<u8>,
+    pub kind: DivergenceKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum DivergenceKind {
+    /// The reference couldn't round-trip our encoding.
+    Rejected(String),
+    /// The reference re-encoded the blob differently and this crate can't
+    /// decode its output.
+    Undecodable(Vec<u8>),
+    /// The reference re-encoded the blob to different bytes.
+    Bytes {
+        output: Vec<u8>,
+        differences: Vec<Difference>,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            DivergenceKind::Rejected(reason) => write!(
+                f,
+                "the reference rejected a {} bytes blob: {}",
+                self.input.len(),
+                reason
+            ),
+            DivergenceKind::Undecodable(output) => write!(
+                f,
+                "the reference output ({} bytes) can't be decoded",
+                output.len()
+            ),
+            DivergenceKind::Bytes {
+                output,
+                differences,
+            } => {
+                write!(
+                    f,
+                    "the reference output differs ({} bytes, ours {})",
+                    output.len(),
+                    self.input.len()
+                )?;
+                for difference in differences {
+                    write!(f, "\n  {}", difference)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Round-trips `section` through `reference` and compares the result.
+pub fn check<R: Reference + ?Sized>(reference: &mut R, section: &Section) -> Option<Divergence> {
+    let mut buf = BytesMut::new();
+    crate::write(&mut buf, section);
+    let input = buf.to_vec();
+
+    let kind = match reference.roundtrip(&input) {
+        Err(reason) => DivergenceKind::Rejected(reason),
+        Ok(output) if output == input => return None,
+        Ok(output) => match crate::read(&mut &output[..]) {
+            Ok(decoded) => DivergenceKind::Bytes {
+                differences: diff(section, &decoded),
+                output,
+            },
+            Err(_) => DivergenceKind::Undecodable(output),
+        },
+    };
+
+    Some(Divergence { input, kind })
+}
+
+/// Checks `iterations` random sections generated from `seed` and returns the
+/// divergences found. The same seed always produces the same sections.
+pub fn run<R: Reference + ?Sized>(
+    reference: &mut R,
+    seed: u64,
+    iterations: usize,
+) -> Vec<Divergence> {
+    let mut rng = Rng::new(seed);
+    (0..iterations)
+        .filter_map(|_| check(reference, &rng.section(3)))
+        .collect()
+}
+
+/// Generates a random section from `seed`, nested at most `depth` levels.
+pub fn random_section(seed: u64, depth: usize) -> Section {
+    Rng::new(seed).section(depth)
+}
+
+/// A xorshift generator, good enough to spread the inputs around.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn section(&mut self, depth: usize) -> Section {
+        let mut names: Vec<String> = (0..self.below(6))
+            .map(|_| {
+                let len = 1 + self.below(12) as usize;
+                (0..len)
+                    .map(|_| (b'a' + self.below(26) as u8) as char)
+                    .collect()
+            })
+            .collect();
+        names.sort();
+        names.dedup();
+
+        let mut section = Section::with_capacity(names.len());
+        for name in names {
+            let entry = self.entry(depth, true);
+            section.insert(name, entry);
+        }
+        section
+    }
+
+    fn entry(&mut self, depth: usize, allow_array: bool) -> StorageEntry {
+        let kinds = if depth == 0 { 11 } else { 12 } + allow_array as u64;
+        let kind = self.below(kinds);
+        self.entry_of(kind, depth, allow_array)
+    }
+
+    fn entry_of(&mut self, kind: u64, depth: usize, allow_array: bool) -> StorageEntry {
+        match kind {
+            0 => StorageEntry::U64(self.next()),
+            1 => StorageEntry::U32(self.next() as u32),
+            2 => StorageEntry::U16(self.next() as u16),
+            3 => StorageEntry::U8(self.next() as u8),
+            4 => StorageEntry::I64(self.next() as i64),
+            5 => StorageEntry::I32(self.next() as i32),
+            6 => StorageEntry::I16(self.next() as i16),
+            7 => StorageEntry::I8(self.next() as i8),
+            8 => StorageEntry::Double((self.next() >> 11) as f64 / (1u64 << 53) as f64),
+            9 => StorageEntry::Bool(self.below(2) == 1),
+            10 => {
+                let len = self.below(70) as usize;
+                StorageEntry::Buf((0..len).map(|_| self.next() as u8).collect())
+            }
+            11 if depth > 0 => StorageEntry::Section(self.section(depth - 1)),
+            _ if allow_array => {
+                // Arrays always hold at least one element so their type is
+                // known. Arrays of arrays take a nesting level, like sections.
+                let element = self.below(if depth > 0 { 13 } else { 11 });
+                let mut array = Array::new();
+                for _ in 0..1 + self.below(5) {
+                    let entry = match element {
+                        12 => self.entry_of(element, depth - 1, true),
+                        _ => self.entry_of(element, depth, false),
+                    };
+                    array.push(entry).unwrap();
+                }
+                StorageEntry::Array(array)
+            }
+            _ => StorageEntry::Bool(false),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// This crate as its own reference.
+    struct Native;
+
+    impl Reference for Native {
+        fn roundtrip(&mut self, blob: &[u8]) -> Result<Vec<u8>, String> {
+            let section = crate::read(&mut &blob[..]).map_err(|e| e.to_string())?;
+            let mut buf = BytesMut::new();
+            crate::write(&mut buf, &section);
+            Ok(buf.to_vec())
+        }
+    }
+
+    /// Drops the last key of each blob.
+    struct Lossy;
+
+    impl Reference for Lossy {
+        fn roundtrip(&mut self, blob: &[u8]) -> Result<Vec<u8>, String> {
+            let mut section = crate::read(&mut &blob[..]).map_err(|e| e.to_string())?;
+            section.entries.pop_back();
+            let mut buf = BytesMut::new();
+            crate::write(&mut buf, &section);
+            Ok(buf.to_vec())
+        }
+    }
+
+    #[test]
+    fn native_agrees() {
+        assert!(run(&mut Native, 1, 200).is_empty());
+        assert_eq!(random_section(7, 2), random_section(7, 2));
+    }
+
+    #[test]
+    fn divergences() {
+        let divergences = run(&mut Lossy, 3, 50);
+        assert!(!divergences.is_empty());
+        for divergence in divergences.iter() {
+            match &divergence.kind {
+                DivergenceKind::Bytes { differences, .. } => {
+                    assert!(matches!(differences[..], [Difference::Removed { .. }]))
+                }
+                kind => panic!("unexpected divergence {:?}", kind),
+            }
+        }
+
+        let mut recorded = Recorded::new();
+        let section = random_section(1, 1);
+        assert!(matches!(
+            check(&mut recorded, &section).map(|d| d.kind),
+            Some(DivergenceKind::Rejected(_))
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Text encodings
+//!
+//! Storage blobs as hex strings, to embed them in test vectors and log lines
+//! and load them back, and as base64, for bridges tunneling them through
+//! JSON-RPC and other text protocols.
+//!
+//! ```rust
+//! use portable_storage::{encoding::{self, Base64}, Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("id".to_owned(), StorageEntry::U8(1));
+//!
+//! let hex = section.to_hex();
+//! assert_eq!(hex, "011101010101020101040269640801");
+//! assert_eq!(Section::from_hex(&hex).unwrap(), section);
+//! assert_eq!(encoding::decode_hex(&hex).unwrap(), portable_storage::write_to_vec(&section));
+//!
+//! let base64 = section.to_base64(Base64::UrlSafe);
+//! assert_eq!(Section::from_base64(&base64, Base64::UrlSafe).unwrap(), section);
+//! ```
+
+use crate::{Error, Result, Section, StorageEntry};
+use std::convert::TryFrom;
+
+/// Encodes `data` as lowercase hex.
+pub fn encode_hex(data: &[u8]) -> String {
+    hex::encode(data)
+}
+
+/// Decodes hex in either case. ASCII whitespace is ignored, so wrapped
+/// dumps can be pasted as they are.
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    hex::decode(digits).map_err(|e| Error::Conversion(e.to_string()))
+}
+
+/// The base64 alphabets of RFC 4648.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Base64 {
+    /// `+` and `/`, padded with `=`. This is the default.
+    Standard,
+    /// `-` and `_`, without padding, for URLs and file names.
+    UrlSafe,
+}
+
+impl Default for Base64 {
+    fn default() -> Self {
+        Base64::Standard
+    }
+}
+
+impl Base64 {
+    fn config(self) -> base64::Config {
+        match self {
+            Base64::Standard => base64::STANDARD,
+            Base64::UrlSafe => base64::URL_SAFE_NO_PAD,
+        }
+    }
+}
+
+/// Encodes `data` as base64.
+pub fn encode_base64(data: &[u8], alphabet: Base64) -> String {
+    base64::encode_config(data, alphabet.config())
+}
+
+/// Decodes base64 of the given alphabet, with or without padding.
+pub fn decode_base64(base64: &str, alphabet: Base64) -> Result<Vec<u8>> {
+    base64::decode_config(base64.trim_end_matches('='), alphabet.config())
+        .map_err(|e| Error::Conversion(e.to_string()))
+}
+
+impl Section {
+    /// Encodes the section as a storage blob, header included, in hex.
+    pub fn to_hex(&self) -> String {
+        encode_hex(&crate::write_to_vec(self))
+    }
+
+    /// Decodes a storage blob, header included, from hex. The blob must
+    /// span the whole string.
+    pub fn from_hex(hex: &str) -> Result<Section> {
+        Section::try_from(&decode_hex(hex)?[..])
+    }
+
+    /// Encodes the section as a storage blob, header included, in base64.
+    pub fn to_base64(&self, alphabet: Base64) -> String {
+        encode_base64(&crate::write_to_vec(self), alphabet)
+    }
+
+    /// Decodes a storage blob, header included, from base64. The blob must
+    /// span the whole string.
+    pub fn from_base64(base64: &str, alphabet: Base64) -> Result<Section> {
+        Section::try_from(&decode_base64(base64, alphabet)?[..])
+    }
+}
+
+impl StorageEntry {
+    /// Encodes the bytes of this `StorageEntry::Buf` in base64.
+    pub fn to_base64(&self, alphabet: Base64) -> Result<String> {
+        Ok(encode_base64(self.as_bytes()?, alphabet))
+    }
+
+    /// Creates a `StorageEntry::Buf` from base64.
+    pub fn from_base64(base64: &str, alphabet: Base64) -> Result<StorageEntry> {
+        Ok(StorageEntry::Buf(decode_base64(base64, alphabet)?.into()))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        assert_eq!(encode_hex(&[0x00, 0xab, 0x7f]), "00ab7f");
+        assert_eq!(decode_hex("00AB 7f\n").unwrap(), [0x00, 0xab, 0x7f]);
+        assert!(matches!(decode_hex("0"), Err(Error::Conversion(_))));
+        assert!(matches!(decode_hex("0g"), Err(Error::Conversion(_))));
+
+        let mut section = Section::new();
+        section.insert("blob".to_owned(), StorageEntry::Buf(vec![0xff; 3].into()));
+        let hex = section.to_hex();
+        assert!(hex.ends_with("0a0cffffff"));
+        assert_eq!(Section::from_hex(&hex).unwrap(), section);
+        assert!(Section::from_hex(&hex[..hex.len() - 2])
+            .unwrap_err()
+            .is_eof());
+    }
+
+    #[test]
+    fn base64() {
+        // RFC 4648 test vectors.
+        for (data, encoded) in &[
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode_base64(data.as_bytes(), Base64::Standard), *encoded);
+            assert_eq!(
+                decode_base64(encoded, Base64::Standard).unwrap(),
+                data.as_bytes()
+            );
+            let unpadded = encoded.trim_end_matches('=');
+            assert_eq!(encode_base64(data.as_bytes(), Base64::UrlSafe), unpadded);
+            assert_eq!(
+                decode_base64(unpadded, Base64::Standard).unwrap(),
+                data.as_bytes()
+            );
+        }
+
+        assert_eq!(encode_base64(&[0xfb, 0xff], Base64::Standard), "+/8=");
+        assert_eq!(encode_base64(&[0xfb, 0xff], Base64::UrlSafe), "-_8");
+        assert!(matches!(
+            decode_base64("-_8", Base64::Standard),
+            Err(Error::Conversion(_))
+        ));
+        assert!(matches!(
+            decode_base64("Zm9vY", Base64::Standard),
+            Err(Error::Conversion(_))
+        ));
+
+        let entry = StorageEntry::from_base64("-_8", Base64::UrlSafe).unwrap();
+        assert_eq!(entry, StorageEntry::Buf(vec![0xfb, 0xff].into()));
+        assert_eq!(entry.to_base64(Base64::Standard).unwrap(), "+/8=");
+        assert!(StorageEntry::U8(1).to_base64(Base64::Standard).is_err());
+
+        let mut section = Section::new();
+        section.insert("blob".to_owned(), entry);
+        let base64 = section.to_base64(Base64::Standard);
+        assert_eq!(
+            Section::from_base64(&base64, Base64::Standard).unwrap(),
+            section
+        );
+    }
+}
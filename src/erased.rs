@@ -0,0 +1,138 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Type-erased serialization
+//!
+//! Hands the deserializers of this crate out as `erased_serde` trait
+//! objects, so code that can't be generic over the message type, e.g.
+//! handlers registered by plugins, still reads and writes portable storage.
+//! Serializing needs nothing special: [`to_section`](crate::to_section) and
+//! [`to_storage_bytes`](crate::to_storage_bytes) accept a
+//! `&dyn erased_serde::Serialize` directly.
+//!
+//! ```rust
+//! use portable_storage::erased;
+//! use std::collections::HashMap;
+//!
+//! trait ErasedHandler {
+//!     fn handle(
+//!         &self,
+//!         request: &mut dyn erased_serde::Deserializer,
+//!     ) -> Result<Box<dyn erased_serde::Serialize>, erased_serde::Error>;
+//! }
+//!
+//! #[derive(serde::Deserialize, serde::Serialize)]
+//! struct Ping {
+//!     status: u64,
+//! }
+//!
+//! struct Pong;
+//!
+//! impl ErasedHandler for Pong {
+//!     fn handle(
+//!         &self,
+//!         request: &mut dyn erased_serde::Deserializer,
+//!     ) -> Result<Box<dyn erased_serde::Serialize>, erased_serde::Error> {
+//!         let ping: Ping = erased_serde::deserialize(request)?;
+//!         Ok(Box::new(Ping { status: ping.status + 1 }))
+//!     }
+//! }
+//!
+//! let mut handlers: HashMap<u32, Box<dyn ErasedHandler>> = HashMap::new();
+//! handlers.insert(1003, Box::new(Pong));
+//!
+//! let request = portable_storage::to_storage_bytes(&Ping { status: 1 }).unwrap();
+//! let response = erased::from_bytes(&request, |de| handlers[&1003].handle(de)).unwrap();
+//! let response = portable_storage::to_storage_bytes(&*response).unwrap();
+//!
+//! let pong: Ping = portable_storage::from_bytes(&response).unwrap();
+//! assert_eq!(pong.status, 2);
+//! ```
+
+use crate::{
+    de::{BytesSectionDeserializer, SectionDeserializer, TypeMatching},
+    header::StorageBlockHeader,
+    Section,
+};
+use serde::de::{value::Error, Error as ErrorTrait};
+
+/// Calls `f` with an erased deserializer of the storage blob in `data`,
+/// like [`from_bytes`](crate::from_bytes).
+pub fn from_bytes<'de, F, R>(data: &'de [u8], f: F) -> Result<R, Error>
+where
+    F: FnOnce(&mut dyn erased_serde::Deserializer<'de>) -> Result<R, erased_serde::Error>,
+{
+    let mut buf = data;
+    StorageBlockHeader::read(&mut buf).map_err(Error::custom)?;
+    let deserializer = BytesSectionDeserializer(&mut buf, TypeMatching::default());
+    f(&mut <dyn erased_serde::Deserializer>::erase(deserializer)).map_err(Error::custom)
+}
+
+/// Calls `f` with an erased deserializer of `section`, like
+/// [`from_section`](crate::from_section).
+pub fn from_section<'de, F, R>(section: Section, f: F) -> Result<R, Error>
+where
+    F: FnOnce(&mut dyn erased_serde::Deserializer<'de>) -> Result<R, erased_serde::Error>,
+{
+    let deserializer = SectionDeserializer(section, TypeMatching::default());
+    f(&mut <dyn erased_serde::Deserializer>::erase(deserializer)).map_err(Error::custom)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Peer {
+        id: u64,
+        addr: Vec<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct PeerList {
+        node: u32,
+        peers: Vec<Peer>,
+    }
+
+    #[test]
+    fn roundtrip() {
+        let list = PeerList {
+            node: 3,
+            peers: vec![Peer {
+                id: 7,
+                addr: vec![127, 0, 0, 1],
+            }],
+        };
+        let value: Box<dyn erased_serde::Serialize> = Box::new(Peer {
+            id: 7,
+            addr: vec![127, 0, 0, 1],
+        });
+        let section = crate::to_section(&*value).unwrap();
+        assert_eq!(section, crate::to_section(&list.peers[0]).unwrap());
+
+        let bytes = crate::to_storage_bytes(&list).unwrap();
+        let decoded: PeerList = from_bytes(&bytes, erased_serde::deserialize).unwrap();
+        assert_eq!(decoded, list);
+
+        let section = crate::to_section(&list.peers[0]).unwrap();
+        let peer: Peer = from_section(section, erased_serde::deserialize).unwrap();
+        assert_eq!(peer, list.peers[0]);
+
+        let error = from_bytes(&bytes, erased_serde::deserialize::<Peer>).unwrap_err();
+        assert!(error.to_string().contains("missing field"), "{}", error);
+        assert!(from_bytes(&bytes[..4], erased_serde::deserialize::<Peer>).is_err());
+    }
+}
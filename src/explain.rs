@@ -0,0 +1,512 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Explain
+//!
+//! Decodes a storage blob and produces a byte-level annotated trace of it,
+//! one [`Annotation`] per header, raw size, key, type tag and value. This is
+//! meant for debugging interoperability problems, the trace can be printed
+//! directly:
+//!
+//! ```rust
+//! use bytes::BytesMut;
+//! use portable_storage::{Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("id".to_owned(), StorageEntry::U8(56));
+//!
+//! let mut buf = BytesMut::new();
+//! portable_storage::write(&mut buf, &section);
+//!
+//! let explanation = portable_storage::explain::explain(&buf);
+//! assert!(explanation.error.is_none());
+//! println!("{}", explanation);
+//! ```
+//!
+//! Decoding doesn't stop the trace on errors, the annotations up to the
+//! failing point are kept and the error is reported alongside them. Blobs
+//! nesting deeper than [`MAX_DEPTH`] stop with
+//! [`Error::LimitExceeded`].
+
+use crate::{
+    header::{HeaderValidation, StorageBlockHeader, PORTABLE_STORAGE_BLOCK_HEADER_LENGTH},
+    limits::MAX_DEPTH,
+    raw_size, wire, Error, Result, StorageEntry, SERIALIZE_TYPE_ARRAY, SERIALIZE_TYPE_BOOL,
+    SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32, SERIALIZE_TYPE_INT64,
+    SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING, SERIALIZE_TYPE_UINT16,
+    SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use bytes::Buf;
+use std::{convert::TryFrom, fmt};
+
+/// Maximum number of raw bytes printed per annotation.
+const DISPLAY_BYTES: usize = 12;
+
+/// What a span of bytes stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationKind {
+    /// The storage block header.
+    Header,
+    /// Number of entries of a section.
+    SectionCount,
+    /// Key length and name.
+    Name,
+    /// Serialize type of an entry or array.
+    Type,
+    /// Number of elements of an array.
+    ArrayCount,
+    /// Length of a string value.
+    StringLength,
+    /// A scalar or string value.
+    Value,
+}
+
+/// A span of the input and its interpretation.
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    /// Absolute offset of the span in the input.
+    pub offset: usize,
+    /// The raw bytes of the span.
+    pub bytes: Vec<u8>,
+    /// Nesting depth, 0 is the root section.
+    pub depth: usize,
+    pub kind: AnnotationKind,
+    pub description: String,
+}
+
+/// The result of [`explain`].
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub annotations: Vec<Annotation>,
+    /// The error that stopped decoding, if any.
+    pub error: Option<Error>,
+    /// Offset where decoding stopped.
+    pub offset: usize,
+}
+
+/// Explains a storage blob, including the storage block header.
+pub fn explain(buf: &[u8]) -> Explanation {
+    explain_with(buf, HeaderValidation::default())
+}
+
+/// Explains a storage blob, checking its header with the given mode.
+pub fn explain_with(buf: &[u8], validation: HeaderValidation) -> Explanation {
+    let mut explainer = Explainer::new(buf);
+    let result = explainer
+        .header(validation)
+        .and_then(|_| explainer.section());
+    explainer.finish(result)
+}
+
+/// Explains a section that isn't preceded by the storage block header.
+pub fn explain_section(buf: &[u8]) -> Explanation {
+    let mut explainer = Explainer::new(buf);
+    let result = explainer.section();
+    explainer.finish(result)
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for annotation in self.annotations.iter() {
+            write!(f, "{:08x}  ", annotation.offset)?;
+            for i in 0..DISPLAY_BYTES {
+                match annotation.bytes.get(i) {
+                    Some(b) => write!(f, "{:02x} ", b)?,
+                    None => write!(f, "   ")?,
+                }
+            }
+            let more = if annotation.bytes.len() > DISPLAY_BYTES {
+                '+'
+            } else {
+                ' '
+            };
+            writeln!(
+                f,
+                "{} {:indent$}{}",
+                more,
+                "",
+                annotation.description,
+                indent = annotation.depth * 2
+            )?;
+        }
+
+        if let Some(ref error) = self.error {
+            writeln!(f, "{:08x}  error: {}", self.offset, error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns a human readable name for a serialize type.
+pub(crate) fn type_name(serialize_type: u8) -> &'static str {
+    match serialize_type {
+        SERIALIZE_TYPE_INT64 => "i64",
+        SERIALIZE_TYPE_INT32 => "i32",
+        SERIALIZE_TYPE_INT16 => "i16",
+        SERIALIZE_TYPE_INT8 => "i8",
+        SERIALIZE_TYPE_UINT64 => "u64",
+        SERIALIZE_TYPE_UINT32 => "u32",
+        SERIALIZE_TYPE_UINT16 => "u16",
+        SERIALIZE_TYPE_UINT8 => "u8",
+        SERIALIZE_TYPE_DOUBLE => "double",
+        SERIALIZE_TYPE_STRING => "string",
+        SERIALIZE_TYPE_BOOL => "bool",
+        SERIALIZE_TYPE_OBJECT => "section",
+        SERIALIZE_TYPE_ARRAY => "array",
+        _ => "invalid",
+    }
+}
+
+struct Explainer<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    depth: usize,
+    /// Sections and arrays being explained, bounded by `MAX_DEPTH`.
+    nesting: usize,
+    annotations: Vec<Annotation>,
+}
+
+impl<'a> Explainer<'a> {
+    fn new(buf: &'a [u8]) -> Explainer<'a> {
+        Explainer {
+            buf,
+            pos: 0,
+            depth: 0,
+            nesting: 0,
+            annotations: Vec::new(),
+        }
+    }
+
+    fn finish(self, result: Result<()>) -> Explanation {
+        let pos = self.pos;
+        Explanation {
+            annotations: self.annotations,
+            error: result.err().map(|e| e.at_offset(pos)),
+            offset: self.pos,
+        }
+    }
+
+    fn annotate(&mut self, start: usize, kind: AnnotationKind, description: String) {
+        self.annotations.push(Annotation {
+            offset: start,
+            bytes: self.buf[start..self.pos].to_vec(),
+            depth: self.depth,
+            kind,
+            description,
+        });
+    }
+
+    /// Runs `f` on the remaining input and advances past what it consumed.
+    fn consume<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut &'a [u8]) -> Result<T>,
+    {
+        let mut rest = &self.buf[self.pos..];
+        let before = rest.len();
+        let value = f(&mut rest)?;
+        self.pos += before - rest.len();
+        Ok(value)
+    }
+
+    fn header(&mut self, validation: HeaderValidation) -> Result<()> {
+        let start = self.pos;
+        if self.buf.len() - start < PORTABLE_STORAGE_BLOCK_HEADER_LENGTH {
+            return Err(Error::eof(
+                PORTABLE_STORAGE_BLOCK_HEADER_LENGTH,
+                self.buf.len() - start,
+            ));
+        }
+
+        let result = self.consume(|buf| StorageBlockHeader::read_with(buf, validation));
+        self.pos = start + PORTABLE_STORAGE_BLOCK_HEADER_LENGTH;
+        let description = match result {
+            Ok(ref hdr) => format!(
+                "header: signature_a {:08x}, signature_b {:08x}, version {}",
+                hdr.signature_a, hdr.signature_b, hdr.version
+            ),
+            Err(_) => "header: invalid".to_owned(),
+        };
+        self.annotate(start, AnnotationKind::Header, description);
+        result.map(|_| ())
+    }
+
+    fn size(&mut self, kind: AnnotationKind, what: &str) -> Result<usize> {
+        let start = self.pos;
+        let size = self.consume(raw_size::read)?;
+        self.annotate(start, kind, format!("{}: {}", what, size));
+        usize::try_from(size).map_err(|_| Error::StorageEntryTooBig(size))
+    }
+
+    fn nest(&mut self) -> Result<()> {
+        if self.nesting > MAX_DEPTH {
+            return Err(Error::LimitExceeded {
+                limit: "depth",
+                max: MAX_DEPTH as u64,
+            });
+        }
+        self.nesting += 1;
+        Ok(())
+    }
+
+    fn section(&mut self) -> Result<()> {
+        self.nest()?;
+        let count = self.size(AnnotationKind::SectionCount, "section entries")?;
+        for _ in 0..count {
+            let start = self.pos;
+            let name = self.consume(wire::read_name)?;
+            self.annotate(start, AnnotationKind::Name, format!("key {:?}", name));
+
+            self.depth += 1;
+            let result = self.entry();
+            self.depth -= 1;
+            result?;
+        }
+
+        self.nesting -= 1;
+        Ok(())
+    }
+
+    fn entry(&mut self) -> Result<()> {
+        let start = self.pos;
+        let serialize_type = self.consume(wire::read_type)?;
+
+        if wire::is_array(serialize_type) {
+            self.annotate(start, AnnotationKind::Type, array_type(serialize_type));
+            return self.array(serialize_type);
+        }
+
+        self.annotate(
+            start,
+            AnnotationKind::Type,
+            format!("type: {}", type_name(serialize_type)),
+        );
+        self.value(serialize_type, None)
+    }
+
+    fn array(&mut self, serialize_type: u8) -> Result<()> {
+        self.nest()?;
+        let count = self.size(AnnotationKind::ArrayCount, "array elements")?;
+        let serialize_type = wire::element_type(serialize_type);
+
+        self.depth += 1;
+        for i in 0..count {
+            if let Err(e) = self.value(serialize_type, Some(i)) {
+                self.depth -= 1;
+                return Err(e);
+            }
+        }
+        self.depth -= 1;
+
+        self.nesting -= 1;
+        Ok(())
+    }
+
+    fn value(&mut self, serialize_type: u8, index: Option<usize>) -> Result<()> {
+        let prefix = match index {
+            Some(i) => format!("[{}] ", i),
+            None => String::new(),
+        };
+
+        match serialize_type {
+            SERIALIZE_TYPE_STRING => {
+                let length = self.size(
+                    AnnotationKind::StringLength,
+                    &format!("{}string length", prefix),
+                )?;
+                let start = self.pos;
+                let value = self.consume(|buf| {
+                    ensure_eof!(buf, length);
+                    let value = &buf[..length];
+                    *buf = &buf[length..];
+                    Ok(value)
+                })?;
+                let description = format!("{}string: {}", prefix, describe_bytes(value));
+                self.annotate(start, AnnotationKind::Value, description);
+            }
+            SERIALIZE_TYPE_OBJECT => {
+                if index.is_some() {
+                    let start = self.pos;
+                    self.annotate(start, AnnotationKind::Value, format!("{}section", prefix));
+                }
+                self.section()?;
+            }
+            SERIALIZE_TYPE_ARRAY => {
+                let start = self.pos;
+                let serialize_type = self.consume(wire::read_type)?;
+                self.annotate(start, AnnotationKind::Type, array_type(serialize_type));
+                if !wire::is_array(serialize_type) {
+                    return Err(Error::WrongTypeSequence);
+                }
+                self.array(serialize_type)?;
+            }
+            _ => {
+                let start = self.pos;
+                let entry = self.consume(|buf| {
+                    StorageEntry::read_entry_raw(buf, serialize_type, &mut crate::FreshKeys)
+                })?;
+                let description = format!("{}value: {}", prefix, describe_scalar(&entry));
+                self.annotate(start, AnnotationKind::Value, description);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn array_type(serialize_type: u8) -> String {
+    format!(
+        "type: array of {}",
+        type_name(wire::element_type(serialize_type))
+    )
+}
+
+fn describe_scalar(entry: &StorageEntry) -> String {
+    match entry {
+        StorageEntry::U64(v) => v.to_string(),
+        StorageEntry::U32(v) => v.to_string(),
+        StorageEntry::U16(v) => v.to_string(),
+        StorageEntry::U8(v) => v.to_string(),
+        StorageEntry::I64(v) => v.to_string(),
+        StorageEntry::I32(v) => v.to_string(),
+        StorageEntry::I16(v) => v.to_string(),
+        StorageEntry::I8(v) => v.to_string(),
+        StorageEntry::Double(v) => v.to_string(),
+        StorageEntry::Bool(v) => v.to_string(),
+        StorageEntry::Buf(v) => describe_bytes(v),
+        StorageEntry::Array(_) => "array".to_owned(),
+        StorageEntry::Section(_) => "section".to_owned(),
+    }
+}
+
+fn describe_bytes(v: &[u8]) -> String {
+    if !v.is_empty() && v.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        format!("{:?}", String::from_utf8_lossy(v))
+    } else {
+        let hex: String = v.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{} bytes 0x{}", v.len(), hex)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Array, Section};
+    use bytes::BytesMut;
+
+    #[test]
+    fn explain_roundtrip() {
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U32(1)).unwrap();
+        ids.push(StorageEntry::U32(2)).unwrap();
+
+        let mut node_data = Section::new();
+        node_data.insert("my_port".to_owned(), StorageEntry::U32(18080));
+
+        let mut section = Section::new();
+        section.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+        section.insert("ids".to_owned(), StorageEntry::Array(ids));
+        section.insert("id".to_owned(), StorageEntry::Buf(b"abc"[..].into()));
+
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &section);
+
+        let explanation = explain(&buf);
+        assert!(explanation.error.is_none());
+        assert_eq!(explanation.offset, buf.len());
+
+        let total: usize = explanation.annotations.iter().map(|a| a.bytes.len()).sum();
+        assert_eq!(total, buf.len());
+
+        let descriptions: Vec<&str> = explanation
+            .annotations
+            .iter()
+            .map(|a| a.description.as_str())
+            .collect();
+        assert!(descriptions.contains(&"key \"my_port\""));
+        assert!(descriptions.contains(&"value: 18080"));
+        assert!(descriptions.contains(&"type: array of u32"));
+        assert!(descriptions.contains(&"[1] value: 2"));
+        assert!(descriptions.contains(&"string: \"abc\""));
+    }
+
+    #[test]
+    fn untagged_elements() {
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U16(1)).unwrap();
+        ids.push(StorageEntry::U16(2)).unwrap();
+        let mut section = Section::new();
+        section.insert("ids".to_owned(), StorageEntry::Array(ids));
+
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &section);
+        // Elements follow the count without a serialize type of their own.
+        assert_eq!(
+            &buf[9..],
+            &[0x04, 0x03, b'i', b'd', b's', 0x87, 0x08, 1, 0, 2, 0][..]
+        );
+
+        let explanation = explain(&buf);
+        assert!(explanation.error.is_none());
+        let descriptions: Vec<&str> = explanation
+            .annotations
+            .iter()
+            .map(|a| a.description.as_str())
+            .collect();
+        assert_eq!(
+            descriptions[descriptions.len() - 3..],
+            ["array elements: 2", "[0] value: 1", "[1] value: 2"][..]
+        );
+    }
+
+    #[test]
+    fn explain_deep() {
+        let mut buf = crate::write_to_vec(&Section::new());
+        buf.truncate(9);
+        buf.extend_from_slice(&[0x04, 0x01, b'a', 0x8d]);
+        for _ in 0..100_000 {
+            buf.extend_from_slice(&[0x04, 0x8d]);
+        }
+
+        let explanation = explain(&buf);
+        assert!(matches!(
+            explanation.error,
+            Some(Error::LimitExceeded { limit: "depth", .. })
+        ));
+    }
+
+    #[test]
+    fn explain_truncated() {
+        let mut section = Section::new();
+        section.insert("height".to_owned(), StorageEntry::U64(10));
+
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &section);
+
+        let explanation = explain(&buf[..buf.len() - 1]);
+        assert!(matches!(
+            explanation.error,
+            Some(Error::UnexpectedEof {
+                needed: 8,
+                available: 7,
+                ..
+            })
+        ));
+        assert_eq!(
+            explanation.annotations.last().unwrap().description,
+            "type: u64"
+        );
+    }
+}
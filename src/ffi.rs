@@ -0,0 +1,744 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # C API
+//!
+//! Parses blobs into opaque section handles with typed getters, builds
+//! sections with typed setters and serializes them, so C and C++ tooling
+//! can use this implementation instead of epee. The header can be generated
+//! with cbindgen using the `cbindgen.toml` of the repository, and a C
+//! library built with `cargo rustc --release --features ffi --crate-type
+//! cdylib` (or `staticlib`).
+//!
+//! ```c
+//! ps_section *section;
+//! if (ps_section_parse(blob, blob_len, &section) != PS_STATUS_OK)
+//!     return -1;
+//!
+//! uint32_t port;
+//! ps_section_get_u32(section, "my_port", &port);
+//! ps_section_free(section);
+//! ```
+//!
+//! Arrays, like peer lists and hash lists, have their own handles: they're
+//! borrowed from a section with `ps_section_get_array`, read by index, and
+//! built with `ps_array_new` and the `ps_array_push_*` functions before being
+//! moved into a section.
+//!
+//! ```c
+//! const ps_array *peers;
+//! if (ps_section_get_array(section, "local_peerlist_new", &peers) == PS_STATUS_OK) {
+//!     for (size_t i = 0; i < ps_array_len(peers); i++) {
+//!         const ps_section *peer;
+//!         ps_array_get_section(peers, i, &peer);
+//!     }
+//! }
+//! ```
+//!
+//! Keys are NUL-terminated strings. Values returned by pointer (strings,
+//! nested sections and arrays) are borrowed from the section or array and
+//! are valid until it's modified or freed. Buffers returned by
+//! [`ps_section_write`] must be released with [`ps_buffer_free`].
+
+use crate::{Array, Section, StorageEntry};
+use std::{ffi::CStr, os::raw::c_char, ptr, slice};
+
+/// A section handle.
+#[repr(transparent)]
+#[allow(non_camel_case_types)]
+pub struct ps_section(Section);
+
+/// An array handle.
+#[repr(transparent)]
+#[allow(non_camel_case_types)]
+pub struct ps_array(Array);
+
+/// The outcome of a call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum ps_status {
+    PS_STATUS_OK = 0,
+    /// A required pointer was null.
+    PS_STATUS_NULL_POINTER = 1,
+    /// A key isn't valid UTF-8, or is too long to be encoded when setting
+    /// it.
+    PS_STATUS_INVALID_KEY = 2,
+    /// The section has no such key, or the array no such index.
+    PS_STATUS_NOT_FOUND = 3,
+    /// The entry has another type, or the element doesn't have the type of
+    /// the array.
+    PS_STATUS_WRONG_TYPE = 4,
+    /// The blob couldn't be decoded.
+    PS_STATUS_DECODE_ERROR = 5,
+}
+
+use ps_status::*;
+
+/// Borrows the key of a call, or returns the status to report.
+unsafe fn key<'a>(key: *const c_char) -> Result<&'a str, ps_status> {
+    if key.is_null() {
+        return Err(PS_STATUS_NULL_POINTER);
+    }
+    CStr::from_ptr(key)
+        .to_str()
+        .map_err(|_| PS_STATUS_INVALID_KEY)
+}
+
+/// Looks `key` up in `section`.
+unsafe fn get<'a>(
+    section: *const ps_section,
+    key_ptr: *const c_char,
+) -> Result<&'a StorageEntry, ps_status> {
+    if section.is_null() {
+        return Err(PS_STATUS_NULL_POINTER);
+    }
+    let key = key(key_ptr)?;
+    (*section).0.entries.get(key).ok_or(PS_STATUS_NOT_FOUND)
+}
+
+/// Inserts `entry` under `key` into `section`. Names are encoded with a
+/// length byte, longer keys are rejected.
+unsafe fn set(section: *mut ps_section, key_ptr: *const c_char, entry: StorageEntry) -> ps_status {
+    if section.is_null() {
+        return PS_STATUS_NULL_POINTER;
+    }
+    match key(key_ptr) {
+        Ok(key) if crate::check_name(key).is_err() => PS_STATUS_INVALID_KEY,
+        Ok(key) => {
+            (*section).0.insert(key.to_owned(), entry);
+            PS_STATUS_OK
+        }
+        Err(status) => status,
+    }
+}
+
+/// Looks the element at `index` up in `array`.
+unsafe fn element<'a>(array: *const ps_array, index: usize) -> Result<&'a StorageEntry, ps_status> {
+    if array.is_null() {
+        return Err(PS_STATUS_NULL_POINTER);
+    }
+    let array = &(*array).0;
+    array.array.get(index).ok_or(PS_STATUS_NOT_FOUND)
+}
+
+/// Appends `entry` to `array`.
+unsafe fn push(array: *mut ps_array, entry: StorageEntry) -> ps_status {
+    if array.is_null() {
+        return PS_STATUS_NULL_POINTER;
+    }
+    match (*array).0.push(entry) {
+        Ok(()) => PS_STATUS_OK,
+        Err(_) => PS_STATUS_WRONG_TYPE,
+    }
+}
+
+/// Borrows the bytes of a string entry.
+unsafe fn get_string(
+    entry: Result<&StorageEntry, ps_status>,
+    out_data: *mut *const u8,
+    out_len: *mut usize,
+) -> ps_status {
+    if out_data.is_null() || out_len.is_null() {
+        return PS_STATUS_NULL_POINTER;
+    }
+    status(entry.and_then(|entry| match entry {
+        StorageEntry::Buf(v) => {
+            *out_data = v.as_ptr();
+            *out_len = v.len();
+            Ok(())
+        }
+        _ => Err(PS_STATUS_WRONG_TYPE),
+    }))
+}
+
+/// Copies `len` bytes from `data` into a string entry.
+unsafe fn string(data: *const u8, len: usize) -> Result<StorageEntry, ps_status> {
+    if data.is_null() && len != 0 {
+        return Err(PS_STATUS_NULL_POINTER);
+    }
+    let bytes = if len == 0 {
+        &[][..]
+    } else {
+        slice::from_raw_parts(data, len)
+    };
+    Ok(StorageEntry::Buf(bytes.into()))
+}
+
+/// Borrows a nested section entry.
+unsafe fn get_section(
+    entry: Result<&StorageEntry, ps_status>,
+    out: *mut *const ps_section,
+) -> ps_status {
+    if out.is_null() {
+        return PS_STATUS_NULL_POINTER;
+    }
+    status(entry.and_then(|entry| match entry {
+        StorageEntry::Section(v) => {
+            *out = v as *const Section as *const ps_section;
+            Ok(())
+        }
+        _ => Err(PS_STATUS_WRONG_TYPE),
+    }))
+}
+
+fn status<T>(result: Result<T, ps_status>) -> ps_status {
+    match result {
+        Ok(_) => PS_STATUS_OK,
+        Err(status) => status,
+    }
+}
+
+/// Decodes a blob, header included, into a new section stored in `out`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn ps_section_parse(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut ps_section,
+) -> ps_status {
+    if (data.is_null() && len != 0) || out.is_null() {
+        return PS_STATUS_NULL_POINTER;
+    }
+    let bytes = if len == 0 {
+        &[][..]
+    } else {
+        slice::from_raw_parts(data, len)
+    };
+    match crate::read_from_slice(bytes) {
+        Ok((section, _)) => {
+            *out = Box::into_raw(Box::new(ps_section(section)));
+            PS_STATUS_OK
+        }
+        Err(_) => PS_STATUS_DECODE_ERROR,
+    }
+}
+
+/// Creates an empty section.
+#[no_mangle]
+pub extern "C" fn ps_section_new() -> *mut ps_section {
+    Box::into_raw(Box::new(ps_section(Section::new())))
+}
+
+/// Frees a section created by [`ps_section_parse`] or [`ps_section_new`].
+///
+/// # Safety
+///
+/// `section` must be null or a section owned by the caller, it can't be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ps_section_free(section: *mut ps_section) {
+    if !section.is_null() {
+        drop(Box::from_raw(section));
+    }
+}
+
+/// Number of entries of `section`.
+///
+/// # Safety
+///
+/// `section` must be a valid section.
+#[no_mangle]
+pub unsafe extern "C" fn ps_section_len(section: *const ps_section) -> usize {
+    if section.is_null() {
+        0
+    } else {
+        (*section).0.len()
+    }
+}
+
+macro_rules! scalars {
+    ($($get:ident $set:ident $get_element:ident $push:ident $ty:ty, $variant:ident;)+) => {
+        $(
+        #[doc = concat!("Reads a `", stringify!($ty), "` entry into `out`.")]
+        ///
+        /// # Safety
+        ///
+        /// `section` must be a valid section, `key` a NUL-terminated string
+        /// and `out` writable.
+        #[no_mangle]
+        pub unsafe extern "C" fn $get(
+            section: *const ps_section,
+            key: *const c_char,
+            out: *mut $ty,
+        ) -> ps_status {
+            if out.is_null() {
+                return PS_STATUS_NULL_POINTER;
+            }
+            status(get(section, key).and_then(|entry| match entry {
+                StorageEntry::$variant(v) => {
+                    *out = *v;
+                    Ok(())
+                }
+                _ => Err(PS_STATUS_WRONG_TYPE),
+            }))
+        }
+
+        #[doc = concat!("Inserts a `", stringify!($ty), "` entry.")]
+        ///
+        /// # Safety
+        ///
+        /// `section` must be a valid section and `key` a NUL-terminated
+        /// string.
+        #[no_mangle]
+        pub unsafe extern "C" fn $set(
+            section: *mut ps_section,
+            key: *const c_char,
+            value: $ty,
+        ) -> ps_status {
+            set(section, key, StorageEntry::$variant(value))
+        }
+
+        #[doc = concat!("Reads a `", stringify!($ty), "` element into `out`.")]
+        ///
+        /// # Safety
+        ///
+        /// `array` must be a valid array and `out` writable.
+        #[no_mangle]
+        pub unsafe extern "C" fn $get_element(
+            array: *const ps_array,
+            index: usize,
+            out: *mut $ty,
+        ) -> ps_status {
+            if out.is_null() {
+                return PS_STATUS_NULL_POINTER;
+            }
+            status(element(array, index).and_then(|entry| match entry {
+                StorageEntry::$variant(v) => {
+                    *out = *v;
+                    Ok(())
+                }
+                _ => Err(PS_STATUS_WRONG_TYPE),
+            }))
+        }
+
+        #[doc = concat!("Appends a `", stringify!($ty), "` element.")]
+        ///
+        /// # Safety
+        ///
+        /// `array` must be a valid array.
+        #[no_mangle]
+        pub unsafe extern "C" fn $push(array: *mut ps_array, value: $ty) -> ps_status {
+            push(array, StorageEntry::$variant(value))
+        }
+        )+
+    };
+}
+
+scalars! {
+    ps_section_get_u64 ps_section_set_u64 ps_array_get_u64 ps_array_push_u64 u64, U64;
+    ps_section_get_u32 ps_section_set_u32 ps_array_get_u32 ps_array_push_u32 u32, U32;
+    ps_section_get_u16 ps_section_set_u16 ps_array_get_u16 ps_array_push_u16 u16, U16;
+    ps_section_get_u8 ps_section_set_u8 ps_array_get_u8 ps_array_push_u8 u8, U8;
+    ps_section_get_i64 ps_section_set_i64 ps_array_get_i64 ps_array_push_i64 i64, I64;
+    ps_section_get_i32 ps_section_set_i32 ps_array_get_i32 ps_array_push_i32 i32, I32;
+    ps_section_get_i16 ps_section_set_i16 ps_array_get_i16 ps_array_push_i16 i16, I16;
+    ps_section_get_i8 ps_section_set_i8 ps_array_get_i8 ps_array_push_i8 i8, I8;
+    ps_section_get_double ps_section_set_double ps_array_get_double ps_array_push_double f64, Double;
+    ps_section_get_bool ps_section_set_bool ps_array_get_bool ps_array_push_bool bool, Bool;
+}
+
+/// Borrows a string entry, storing its bytes in `out_data` and `out_len`.
+///
+/// # Safety
+///
+/// `section` must be a valid section, `key` a NUL-terminated string and the
+/// outputs writable.
+#[no_mangle]
+pub unsafe extern "C" fn ps_section_get_string(
+    section: *const ps_section,
+    key: *const c_char,
+    out_data: *mut *const u8,
+    out_len: *mut usize,
+) -> ps_status {
+    get_string(get(section, key), out_data, out_len)
+}
+
+/// Inserts a string entry copying `len` bytes from `data`.
+///
+/// # Safety
+///
+/// `section` must be a valid section, `key` a NUL-terminated string and
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ps_section_set_string(
+    section: *mut ps_section,
+    key: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> ps_status {
+    match string(data, len) {
+        Ok(entry) => set(section, key, entry),
+        Err(status) => status,
+    }
+}
+
+/// Borrows a nested section entry into `out`.
+///
+/// # Safety
+///
+/// `section` must be a valid section, `key` a NUL-terminated string and
+/// `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn ps_section_get_section(
+    section: *const ps_section,
+    key: *const c_char,
+    out: *mut *const ps_section,
+) -> ps_status {
+    get_section(get(section, key), out)
+}
+
+/// Inserts `child` as a nested section, taking ownership of it even on
+/// errors.
+///
+/// # Safety
+///
+/// `section` must be a valid section, `key` a NUL-terminated string and
+/// `child` a section owned by the caller, it can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ps_section_set_section(
+    section: *mut ps_section,
+    key: *const c_char,
+    child: *mut ps_section,
+) -> ps_status {
+    if child.is_null() {
+        return PS_STATUS_NULL_POINTER;
+    }
+    let child = Box::from_raw(child).0;
+    set(section, key, StorageEntry::Section(child))
+}
+
+/// Borrows an array entry into `out`.
+///
+/// # Safety
+///
+/// `section` must be a valid section, `key` a NUL-terminated string and
+/// `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn ps_section_get_array(
+    section: *const ps_section,
+    key: *const c_char,
+    out: *mut *const ps_array,
+) -> ps_status {
+    if out.is_null() {
+        return PS_STATUS_NULL_POINTER;
+    }
+    status(get(section, key).and_then(|entry| match entry {
+        StorageEntry::Array(v) => {
+            *out = v as *const Array as *const ps_array;
+            Ok(())
+        }
+        _ => Err(PS_STATUS_WRONG_TYPE),
+    }))
+}
+
+/// Inserts `array` as an array entry, taking ownership of it even on errors.
+///
+/// # Safety
+///
+/// `section` must be a valid section, `key` a NUL-terminated string and
+/// `array` an array owned by the caller, it can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ps_section_set_array(
+    section: *mut ps_section,
+    key: *const c_char,
+    array: *mut ps_array,
+) -> ps_status {
+    if array.is_null() {
+        return PS_STATUS_NULL_POINTER;
+    }
+    let array = Box::from_raw(array).0;
+    set(section, key, StorageEntry::Array(array))
+}
+
+/// Creates an empty array, typed by its first element.
+#[no_mangle]
+pub extern "C" fn ps_array_new() -> *mut ps_array {
+    Box::into_raw(Box::new(ps_array(Array::new())))
+}
+
+/// Frees an array created by [`ps_array_new`].
+///
+/// # Safety
+///
+/// `array` must be null or an array owned by the caller, it can't be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ps_array_free(array: *mut ps_array) {
+    if !array.is_null() {
+        drop(Box::from_raw(array));
+    }
+}
+
+/// Number of elements of `array`.
+///
+/// # Safety
+///
+/// `array` must be a valid array.
+#[no_mangle]
+pub unsafe extern "C" fn ps_array_len(array: *const ps_array) -> usize {
+    if array.is_null() {
+        0
+    } else {
+        (*array).0.len()
+    }
+}
+
+/// Borrows a string element, storing its bytes in `out_data` and `out_len`.
+///
+/// # Safety
+///
+/// `array` must be a valid array and the outputs writable.
+#[no_mangle]
+pub unsafe extern "C" fn ps_array_get_string(
+    array: *const ps_array,
+    index: usize,
+    out_data: *mut *const u8,
+    out_len: *mut usize,
+) -> ps_status {
+    get_string(element(array, index), out_data, out_len)
+}
+
+/// Appends a string element copying `len` bytes from `data`.
+///
+/// # Safety
+///
+/// `array` must be a valid array and `data` must point to `len` readable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ps_array_push_string(
+    array: *mut ps_array,
+    data: *const u8,
+    len: usize,
+) -> ps_status {
+    match string(data, len) {
+        Ok(entry) => push(array, entry),
+        Err(status) => status,
+    }
+}
+
+/// Borrows a section element into `out`.
+///
+/// # Safety
+///
+/// `array` must be a valid array and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn ps_array_get_section(
+    array: *const ps_array,
+    index: usize,
+    out: *mut *const ps_section,
+) -> ps_status {
+    get_section(element(array, index), out)
+}
+
+/// Appends `child` as a section element, taking ownership of it even on
+/// errors.
+///
+/// # Safety
+///
+/// `array` must be a valid array and `child` a section owned by the caller,
+/// it can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ps_array_push_section(
+    array: *mut ps_array,
+    child: *mut ps_section,
+) -> ps_status {
+    if child.is_null() {
+        return PS_STATUS_NULL_POINTER;
+    }
+    let child = Box::from_raw(child).0;
+    push(array, StorageEntry::Section(child))
+}
+
+/// Encodes `section`, header included, into a new buffer stored in
+/// `out_data` and `out_len`.
+///
+/// # Safety
+///
+/// `section` must be a valid section and the outputs writable.
+#[no_mangle]
+pub unsafe extern "C" fn ps_section_write(
+    section: *const ps_section,
+    out_data: *mut *mut u8,
+    out_len: *mut usize,
+) -> ps_status {
+    if section.is_null() || out_data.is_null() || out_len.is_null() {
+        return PS_STATUS_NULL_POINTER;
+    }
+    let bytes = crate::write_to_vec(&(*section).0).into_boxed_slice();
+    *out_len = bytes.len();
+    *out_data = Box::into_raw(bytes) as *mut u8;
+    PS_STATUS_OK
+}
+
+/// Frees a buffer returned by [`ps_section_write`].
+///
+/// # Safety
+///
+/// `data` and `len` must come from [`ps_section_write`], the buffer can't be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ps_buffer_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn arrays() {
+        unsafe {
+            let peers = ps_array_new();
+            for port in [18080, 28080].iter() {
+                let peer = ps_section_new();
+                ps_section_set_u32(peer, b"port\0".as_ptr() as _, *port);
+                assert_eq!(ps_array_push_section(peers, peer), PS_STATUS_OK);
+            }
+            assert_eq!(ps_array_push_u8(peers, 1), PS_STATUS_WRONG_TYPE);
+            let hashes = ps_array_new();
+            let hash = [0xab; 32];
+            assert_eq!(
+                ps_array_push_string(hashes, hash.as_ptr(), hash.len()),
+                PS_STATUS_OK
+            );
+            let heights = ps_array_new();
+            assert_eq!(ps_array_push_u64(heights, 7), PS_STATUS_OK);
+
+            let root = ps_section_new();
+            let key = b"peers\0".as_ptr() as _;
+            assert_eq!(ps_section_set_array(root, key, peers), PS_STATUS_OK);
+            ps_section_set_array(root, b"hashes\0".as_ptr() as _, hashes);
+            ps_section_set_array(root, b"heights\0".as_ptr() as _, heights);
+
+            let (mut data, mut len) = (ptr::null_mut(), 0);
+            ps_section_write(root, &mut data, &mut len);
+            ps_section_free(root);
+            let mut parsed = ptr::null_mut();
+            assert_eq!(ps_section_parse(data, len, &mut parsed), PS_STATUS_OK);
+            ps_buffer_free(data, len);
+
+            let mut peers = ptr::null();
+            assert_eq!(ps_section_get_array(parsed, key, &mut peers), PS_STATUS_OK);
+            assert_eq!(ps_array_len(peers), 2);
+            let mut peer = ptr::null();
+            assert_eq!(ps_array_get_section(peers, 1, &mut peer), PS_STATUS_OK);
+            let mut port = 0;
+            ps_section_get_u32(peer, b"port\0".as_ptr() as _, &mut port);
+            assert_eq!(port, 28080);
+            assert_eq!(
+                ps_array_get_section(peers, 2, &mut peer),
+                PS_STATUS_NOT_FOUND
+            );
+
+            let mut hashes = ptr::null();
+            ps_section_get_array(parsed, b"hashes\0".as_ptr() as _, &mut hashes);
+            let (mut data, mut len) = (ptr::null(), 0);
+            assert_eq!(
+                ps_array_get_string(hashes, 0, &mut data, &mut len),
+                PS_STATUS_OK
+            );
+            assert_eq!(slice::from_raw_parts(data, len), hash);
+
+            let mut heights = ptr::null();
+            ps_section_get_array(parsed, b"heights\0".as_ptr() as _, &mut heights);
+            let mut height = 0;
+            assert_eq!(ps_array_get_u64(heights, 0, &mut height), PS_STATUS_OK);
+            assert_eq!(height, 7);
+            assert_eq!(ps_array_get_u32(heights, 0, &mut 0), PS_STATUS_WRONG_TYPE);
+            ps_section_free(parsed);
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        unsafe {
+            let child = ps_section_new();
+            assert_eq!(
+                ps_section_set_u32(child, b"my_port\0".as_ptr() as _, 18080),
+                PS_STATUS_OK
+            );
+            let root = ps_section_new();
+            assert_eq!(
+                ps_section_set_section(root, b"node_data\0".as_ptr() as _, child),
+                PS_STATUS_OK
+            );
+            let id = b"id";
+            ps_section_set_string(root, b"top_id\0".as_ptr() as _, id.as_ptr(), id.len());
+
+            let (mut data, mut len) = (ptr::null_mut(), 0);
+            assert_eq!(ps_section_write(root, &mut data, &mut len), PS_STATUS_OK);
+            ps_section_free(root);
+
+            let mut parsed = ptr::null_mut();
+            assert_eq!(ps_section_parse(data, len, &mut parsed), PS_STATUS_OK);
+            ps_buffer_free(data, len);
+            assert_eq!(ps_section_len(parsed), 2);
+
+            let mut node_data = ptr::null();
+            let key = b"node_data\0".as_ptr() as _;
+            assert_eq!(
+                ps_section_get_section(parsed, key, &mut node_data),
+                PS_STATUS_OK
+            );
+            let mut port = 0;
+            let key = b"my_port\0".as_ptr() as _;
+            assert_eq!(ps_section_get_u32(node_data, key, &mut port), PS_STATUS_OK);
+            assert_eq!(port, 18080);
+            let mut wide = 0;
+            assert_eq!(
+                ps_section_get_u64(node_data, key, &mut wide),
+                PS_STATUS_WRONG_TYPE
+            );
+
+            let (mut data, mut len) = (ptr::null(), 0);
+            let key = b"top_id\0".as_ptr() as _;
+            assert_eq!(
+                ps_section_get_string(parsed, key, &mut data, &mut len),
+                PS_STATUS_OK
+            );
+            assert_eq!(slice::from_raw_parts(data, len), id);
+
+            let key = b"missing\0".as_ptr() as _;
+            assert_eq!(ps_section_get_u8(parsed, key, &mut 0), PS_STATUS_NOT_FOUND);
+            ps_section_free(parsed);
+
+            let root = ps_section_new();
+            let mut long = vec![b'k'; 256];
+            long.push(0);
+            assert_eq!(
+                ps_section_set_u8(root, long.as_ptr() as _, 1),
+                PS_STATUS_INVALID_KEY
+            );
+            assert_eq!(
+                ps_section_set_u8(root, long[1..].as_ptr() as _, 1),
+                PS_STATUS_OK
+            );
+            assert_eq!(ps_section_len(root), 1);
+            ps_section_free(root);
+
+            let mut parsed = ptr::null_mut();
+            assert_eq!(
+                ps_section_parse(id.as_ptr(), id.len(), &mut parsed),
+                PS_STATUS_DECODE_ERROR
+            );
+        }
+    }
+}
@@ -0,0 +1,330 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # File container
+//!
+//! Sections persisted to disk (peer stores, caches) are wrapped in a small
+//! container so truncated or corrupted files are detected when loading
+//! instead of decoding to garbage:
+//!
+//! ```rust
+//! use portable_storage::{file, Section, StorageEntry};
+//!
+//! let mut peers = Section::new();
+//! peers.insert("count".to_owned(), StorageEntry::U32(1));
+//!
+//! let path = std::env::temp_dir().join("portable-storage-file-doctest.bin");
+//! file::save_to_path(&path, &peers).unwrap();
+//! assert_eq!(file::load_from_path(&path).unwrap(), peers);
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+//!
+//! The container is [`MAGIC`], the storage blob (header included) and the
+//! CRC-32 (IEEE) of everything before it, little-endian. Corrupted files
+//! fail to load with an [`io::ErrorKind::InvalidData`] error wrapping the
+//! decoding [`Error`].
+//!
+//! Saving is atomic: the container is written to a temporary file next to
+//! the target, synced and renamed over it, so a crash leaves either the old
+//! or the new file and never a torn one. Files that may come from elsewhere
+//! are loaded with [`load_with_limits`], which checks the blob against
+//! [`Limits`] before decoding it.
+
+use crate::{limits::Limits, Error, Result, Section};
+use std::{
+    convert::{TryFrom, TryInto},
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The first bytes of a container, the last one is the format version.
+pub const MAGIC: [u8; 8] = *b"EPEESTO\x01";
+
+/// Length of the CRC-32 trailer.
+const CHECKSUM_LENGTH: usize = 4;
+
+/// Wraps `section` in a container.
+pub fn encode(section: &Section) -> Vec<u8> {
+    let mut data = Vec::with_capacity(
+        MAGIC.len()
+            + crate::header::PORTABLE_STORAGE_BLOCK_HEADER_LENGTH
+            + section.encoded_len()
+            + CHECKSUM_LENGTH,
+    );
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&crate::write_to_vec(section));
+    let checksum = crc32(&data);
+    data.extend_from_slice(&checksum.to_le_bytes());
+    data
+}
+
+/// Reads a section out of a container spanning the whole of `data`.
+pub fn decode(data: &[u8]) -> Result<Section> {
+    Section::try_from(blob(data)?)
+}
+
+/// Reads a section out of a container after checking the blob against
+/// `limits`, see [`read_with_limits`](crate::read_with_limits).
+pub fn decode_with_limits(data: &[u8], limits: &Limits) -> Result<Section> {
+    let blob = blob(data)?;
+    limits.check(blob)?;
+    Section::try_from(blob)
+}
+
+/// Checks the magic and the checksum of a container and returns its blob.
+fn blob(data: &[u8]) -> Result<&[u8]> {
+    if data.len() < MAGIC.len() + CHECKSUM_LENGTH {
+        return Err(Error::eof(MAGIC.len() + CHECKSUM_LENGTH, data.len()));
+    }
+    if data[..MAGIC.len()] != MAGIC {
+        return Err(Error::InvalidHeader);
+    }
+
+    let (contents, trailer) = data.split_at(data.len() - CHECKSUM_LENGTH);
+    let expected = u32::from_le_bytes(trailer.try_into().unwrap());
+    let found = crc32(contents);
+    if expected != found {
+        return Err(Error::ChecksumMismatch { expected, found });
+    }
+
+    Ok(&contents[MAGIC.len()..])
+}
+
+/// Writes `section` to the file at `path` in a container, atomically
+/// replacing it.
+pub fn save_to_path<P: AsRef<Path>>(path: P, section: &Section) -> io::Result<()> {
+    let path = path.as_ref();
+    let temporary = temporary_path(path)?;
+    let result = write_synced(&temporary, &encode(section))
+        .and_then(|_| fs::rename(&temporary, path))
+        .and_then(|_| sync_parent(path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Loads the section saved in the file at `path`.
+pub fn load_from_path<P: AsRef<Path>>(path: P) -> io::Result<Section> {
+    let data = fs::read(path)?;
+    decode(&data).map_err(invalid_data)
+}
+
+/// Loads the section saved in the file at `path`, rejecting files bigger
+/// than the packet size of `limits` without reading them and blobs beyond
+/// the other limits without decoding them.
+pub fn load_with_limits<P: AsRef<Path>>(path: P, limits: &Limits) -> io::Result<Section> {
+    let max = limits
+        .max_packet_size
+        .saturating_add((MAGIC.len() + CHECKSUM_LENGTH) as u64);
+    let mut data = Vec::new();
+    File::open(path)?
+        .take(max.saturating_add(1))
+        .read_to_end(&mut data)?;
+    if data.len() as u64 > max {
+        return Err(invalid_data(Error::LimitExceeded {
+            limit: "packet size",
+            max: limits.max_packet_size,
+        }));
+    }
+
+    decode_with_limits(&data, limits).map_err(invalid_data)
+}
+
+fn invalid_data(e: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// A path next to `path` to write its new contents to before renaming,
+/// unique to the call so concurrent saves don't share it.
+fn temporary_path(path: &Path) -> io::Result<PathBuf> {
+    static SAVES: AtomicUsize = AtomicUsize::new(0);
+
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("`{}` isn't a file path", path.display()),
+        )
+    })?;
+    let mut temporary = name.to_owned();
+    temporary.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        SAVES.fetch_add(1, Ordering::Relaxed)
+    ));
+    Ok(path.with_file_name(temporary))
+}
+
+fn write_synced(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Syncs the directory holding `path` so the rename itself is durable.
+/// Directories can't be opened for that on every platform.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// The CRC-32 (IEEE 802.3, as in zlib) of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::StorageEntry;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn corruption() {
+        let mut section = Section::new();
+        section.insert("height".to_owned(), StorageEntry::U64(1337));
+        let data = encode(&section);
+        assert_eq!(decode(&data).unwrap(), section);
+
+        for i in 0..data.len() {
+            let mut corrupted = data.clone();
+            corrupted[i] ^= 0x10;
+            assert!(decode(&corrupted).is_err(), "flipped a bit of byte {}", i);
+        }
+        assert!(matches!(
+            decode(&data[..data.len() - 1]),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        assert!(decode(&data[..10]).unwrap_err().is_eof());
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(decode(&bad_magic), Err(Error::InvalidHeader)));
+    }
+
+    #[test]
+    fn atomic() {
+        let dir =
+            std::env::temp_dir().join(format!("portable-storage-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("peers.bin");
+
+        let mut section = Section::new();
+        for height in 0..3u64 {
+            section.insert("height".to_owned(), StorageEntry::U64(height));
+            save_to_path(&path, &section).unwrap();
+            assert_eq!(load_from_path(&path).unwrap(), section);
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // Failing to write leaves the previous file alone.
+        assert!(save_to_path(dir.join("missing").join("peers.bin"), &section).is_err());
+        assert!(save_to_path(&dir, &section).is_err());
+        assert_eq!(load_from_path(&path).unwrap(), section);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_saves() {
+        let dir =
+            std::env::temp_dir().join(format!("portable-storage-saves-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("peers.bin");
+
+        let threads: Vec<_> = (0..8u64)
+            .map(|id| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let mut section = Section::new();
+                    section.insert("id".to_owned(), StorageEntry::U64(id));
+                    for _ in 0..20 {
+                        save_to_path(&path, &section).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!(load_from_path(&path).is_ok());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn limits() {
+        let path = std::env::temp_dir().join(format!(
+            "portable-storage-limits-{}.bin",
+            std::process::id()
+        ));
+        let mut section = Section::new();
+        section.insert("blob".to_owned(), StorageEntry::Buf(vec![0; 100].into()));
+        save_to_path(&path, &section).unwrap();
+
+        let limits = Limits::monero_default();
+        assert_eq!(load_with_limits(&path, &limits).unwrap(), section);
+        assert_eq!(
+            load_with_limits(&path, &Limits::unlimited()).unwrap(),
+            section
+        );
+        for limits in &[
+            Limits {
+                max_packet_size: 50,
+                ..limits
+            },
+            Limits {
+                max_strings: 0,
+                ..limits
+            },
+        ] {
+            let error = load_with_limits(&path, limits).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert!(error.to_string().contains("limit"), "{}", error);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn files() {
+        let path =
+            std::env::temp_dir().join(format!("portable-storage-file-{}.bin", std::process::id()));
+        let mut section = Section::new();
+        section.insert("peers".to_owned(), StorageEntry::Section(Section::new()));
+        save_to_path(&path, &section).unwrap();
+        assert_eq!(load_from_path(&path).unwrap(), section);
+
+        fs::write(&path, b"EPEESTO\x01garbage!").unwrap();
+        let error = load_from_path(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
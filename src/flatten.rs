@@ -0,0 +1,152 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Flattening
+//!
+//! Turns a section into `(path, type, value)` rows, one per scalar, ready to
+//! be bulk-loaded into CSV files or analytics databases. Paths use `.` to
+//! separate keys and `[i]` for array elements, like the
+//! [structural diff](crate::diff), and values are rendered like in the
+//! [text format](crate::text): decimal numbers and hex strings.
+//!
+//! ```rust
+//! use portable_storage::{flatten, Section, StorageEntry};
+//!
+//! let mut node_data = Section::new();
+//! node_data.insert("my_port".to_owned(), StorageEntry::U32(18080));
+//! let mut section = Section::new();
+//! section.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+//!
+//! let rows = flatten::flatten(&section);
+//! assert_eq!(rows[0].path, "node_data.my_port");
+//! assert_eq!(rows[0].ty, "u32");
+//! assert_eq!(rows[0].value, "18080");
+//! ```
+//!
+//! Empty sections and arrays produce a row with an empty value, so their
+//! presence isn't lost.
+
+use crate::{explain::type_name, text, Section, StorageEntry};
+use std::io::{self, Write};
+
+/// A single flattened value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub path: String,
+    pub ty: &'static str,
+    pub value: String,
+}
+
+/// Flattens `section` into rows, in key order.
+pub fn flatten(section: &Section) -> Vec<Row> {
+    let mut rows = Vec::new();
+    flatten_section("", section, &mut rows);
+    rows
+}
+
+/// Writes `rows` as CSV with a `path,type,value` header.
+pub fn write_csv<W: Write>(writer: &mut W, rows: &[Row]) -> io::Result<()> {
+    writeln!(writer, "path,type,value")?;
+    for row in rows {
+        writeln!(
+            writer,
+            "{},{},{}",
+            csv_field(&row.path),
+            row.ty,
+            csv_field(&row.value)
+        )?;
+    }
+
+    Ok(())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn flatten_section(prefix: &str, section: &Section, rows: &mut Vec<Row>) {
+    for (name, entry) in section.entries.iter() {
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        flatten_entry(path, entry, rows);
+    }
+}
+
+fn flatten_entry(path: String, entry: &StorageEntry, rows: &mut Vec<Row>) {
+    match entry {
+        StorageEntry::Section(section) if !section.is_empty() => {
+            flatten_section(&path, section, rows)
+        }
+        StorageEntry::Array(array) if !array.is_empty() => {
+            for (i, element) in array.array.iter().enumerate() {
+                flatten_entry(format!("{}[{}]", path, i), element, rows);
+            }
+        }
+        StorageEntry::Section(_) | StorageEntry::Array(_) => rows.push(Row {
+            path,
+            ty: type_name(entry.serialize_type()),
+            value: String::new(),
+        }),
+        _ => {
+            let mut value = String::new();
+            text::write_value(&mut value, entry, 0, Default::default());
+            rows.push(Row {
+                path,
+                ty: type_name(entry.serialize_type()),
+                value,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::Array;
+
+    #[test]
+    fn csv() {
+        let mut peer = Section::new();
+        peer.insert("id".to_owned(), StorageEntry::U64(7));
+        let mut peers = Array::new();
+        peers.push(StorageEntry::Section(peer)).unwrap();
+        peers.push(StorageEntry::Section(Section::new())).unwrap();
+
+        let mut section = Section::new();
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+        section.insert("a,b".to_owned(), StorageEntry::Buf(vec![0x01, 0xff].into()));
+        section.insert("ratio".to_owned(), StorageEntry::Double(0.25));
+        section.insert("none".to_owned(), StorageEntry::Array(Array::new()));
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &flatten(&section)).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "path,type,value\n\
+             peers[0].id,u64,7\n\
+             peers[1],section,\n\
+             \"a,b\",string,0x01ff\n\
+             ratio,double,0.25\n\
+             none,array,\n"
+        );
+    }
+}
@@ -0,0 +1,420 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Push reader
+//!
+//! Walks a blob and reports what it finds to a [`StorageHandler`] instead of
+//! building a [`Section`](crate::Section), so consumers can pick the values
+//! they need without serde and without allocating:
+//!
+//! ```rust
+//! use portable_storage::{handler::StorageHandler, Result, Section, StorageEntry};
+//!
+//! #[derive(Default)]
+//! struct Height {
+//!     depth: usize,
+//!     current: bool,
+//!     height: Option<u64>,
+//! }
+//!
+//! impl StorageHandler for Height {
+//!     fn begin_section(&mut self, _len: usize) -> Result<()> {
+//!         self.depth += 1;
+//!         Ok(())
+//!     }
+//!
+//!     fn end_section(&mut self) -> Result<()> {
+//!         self.depth -= 1;
+//!         Ok(())
+//!     }
+//!
+//!     fn key(&mut self, name: &[u8]) -> Result<()> {
+//!         self.current = self.depth == 1 && name == b"current_height";
+//!         Ok(())
+//!     }
+//!
+//!     fn u64(&mut self, v: u64) -> Result<()> {
+//!         if self.current {
+//!             self.height = Some(v);
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut section = Section::new();
+//! section.insert("current_height".to_owned(), StorageEntry::U64(2_200_000));
+//! let blob = portable_storage::write_to_vec(&section);
+//!
+//! let mut handler = Height::default();
+//! portable_storage::read_with_handler(&mut &blob[..], &mut handler).unwrap();
+//! assert_eq!(handler.height, Some(2_200_000));
+//! ```
+//!
+//! Every callback does nothing by default. Each entry of a section is
+//! reported as its name followed by its value, arrays by their element type
+//! and length followed by their elements. Errors returned by the handler
+//! stop the walk and are returned as is.
+
+use crate::{
+    raw_size, wire, Error, Result, SerializeType, SERIALIZE_TYPE_ARRAY, SERIALIZE_TYPE_BOOL,
+    SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32, SERIALIZE_TYPE_INT64,
+    SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING, SERIALIZE_TYPE_UINT16,
+    SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use bytes::Buf;
+
+/// Receives the contents of a blob, see the [module documentation](self).
+#[allow(unused_variables)]
+pub trait StorageHandler {
+    /// A section with `len` entries starts, the root section included.
+    fn begin_section(&mut self, len: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_section(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The name of the next entry of the current section.
+    fn key(&mut self, name: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// An array of `len` elements of type `element` starts.
+    fn begin_array(&mut self, element: SerializeType, len: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_array(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn u64(&mut self, v: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn u32(&mut self, v: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn u16(&mut self, v: u16) -> Result<()> {
+        Ok(())
+    }
+
+    fn u8(&mut self, v: u8) -> Result<()> {
+        Ok(())
+    }
+
+    fn i64(&mut self, v: i64) -> Result<()> {
+        Ok(())
+    }
+
+    fn i32(&mut self, v: i32) -> Result<()> {
+        Ok(())
+    }
+
+    fn i16(&mut self, v: i16) -> Result<()> {
+        Ok(())
+    }
+
+    fn i8(&mut self, v: i8) -> Result<()> {
+        Ok(())
+    }
+
+    fn double(&mut self, v: f64) -> Result<()> {
+        Ok(())
+    }
+
+    fn bool(&mut self, v: bool) -> Result<()> {
+        Ok(())
+    }
+
+    /// A string, borrowed from the blob.
+    fn bytes(&mut self, v: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<H: StorageHandler + ?Sized> StorageHandler for &mut H {
+    fn begin_section(&mut self, len: usize) -> Result<()> {
+        (**self).begin_section(len)
+    }
+
+    fn end_section(&mut self) -> Result<()> {
+        (**self).end_section()
+    }
+
+    fn key(&mut self, name: &[u8]) -> Result<()> {
+        (**self).key(name)
+    }
+
+    fn begin_array(&mut self, element: SerializeType, len: usize) -> Result<()> {
+        (**self).begin_array(element, len)
+    }
+
+    fn end_array(&mut self) -> Result<()> {
+        (**self).end_array()
+    }
+
+    fn u64(&mut self, v: u64) -> Result<()> {
+        (**self).u64(v)
+    }
+
+    fn u32(&mut self, v: u32) -> Result<()> {
+        (**self).u32(v)
+    }
+
+    fn u16(&mut self, v: u16) -> Result<()> {
+        (**self).u16(v)
+    }
+
+    fn u8(&mut self, v: u8) -> Result<()> {
+        (**self).u8(v)
+    }
+
+    fn i64(&mut self, v: i64) -> Result<()> {
+        (**self).i64(v)
+    }
+
+    fn i32(&mut self, v: i32) -> Result<()> {
+        (**self).i32(v)
+    }
+
+    fn i16(&mut self, v: i16) -> Result<()> {
+        (**self).i16(v)
+    }
+
+    fn i8(&mut self, v: i8) -> Result<()> {
+        (**self).i8(v)
+    }
+
+    fn double(&mut self, v: f64) -> Result<()> {
+        (**self).double(v)
+    }
+
+    fn bool(&mut self, v: bool) -> Result<()> {
+        (**self).bool(v)
+    }
+
+    fn bytes(&mut self, v: &[u8]) -> Result<()> {
+        (**self).bytes(v)
+    }
+}
+
+/// Walks a section that isn't preceded by the storage block header.
+pub(crate) fn section<B: Buf, H: StorageHandler + ?Sized>(
+    buf: &mut B,
+    handler: &mut H,
+) -> Result<()> {
+    let count = raw_size::read_usize(buf)?;
+    handler.begin_section(count)?;
+    for _ in 0..count {
+        let length = wire::read_name_len(buf)?;
+        handler.key(&buf.bytes()[..length])?;
+        buf.advance(length);
+
+        let serialize_type = wire::read_type(buf)?;
+        if wire::is_array(serialize_type) {
+            array(buf, serialize_type, handler)?;
+        } else {
+            value(buf, serialize_type, handler)?;
+        }
+    }
+    handler.end_section()
+}
+
+fn array<B: Buf, H: StorageHandler + ?Sized>(
+    buf: &mut B,
+    serialize_type: u8,
+    handler: &mut H,
+) -> Result<()> {
+    let serialize_type = wire::element_type(serialize_type);
+    let element = SerializeType::from_u8(serialize_type)
+        .ok_or(Error::InvalidSerializeType(serialize_type))?;
+    let count = raw_size::read_usize(buf)?;
+    handler.begin_array(element, count)?;
+    for i in 0..count {
+        value(buf, serialize_type, handler).map_err(|e| e.in_element(i))?;
+    }
+    handler.end_array()
+}
+
+/// Walks a value without its serialize type.
+fn value<B: Buf, H: StorageHandler + ?Sized>(
+    buf: &mut B,
+    serialize_type: u8,
+    handler: &mut H,
+) -> Result<()> {
+    match serialize_type {
+        SERIALIZE_TYPE_INT64 => {
+            ensure_eof!(buf, 8);
+            handler.i64(buf.get_i64_le())
+        }
+        SERIALIZE_TYPE_INT32 => {
+            ensure_eof!(buf, 4);
+            handler.i32(buf.get_i32_le())
+        }
+        SERIALIZE_TYPE_INT16 => {
+            ensure_eof!(buf, 2);
+            handler.i16(buf.get_i16_le())
+        }
+        SERIALIZE_TYPE_INT8 => {
+            ensure_eof!(buf, 1);
+            handler.i8(buf.get_i8())
+        }
+        SERIALIZE_TYPE_UINT64 => {
+            ensure_eof!(buf, 8);
+            handler.u64(buf.get_u64_le())
+        }
+        SERIALIZE_TYPE_UINT32 => {
+            ensure_eof!(buf, 4);
+            handler.u32(buf.get_u32_le())
+        }
+        SERIALIZE_TYPE_UINT16 => {
+            ensure_eof!(buf, 2);
+            handler.u16(buf.get_u16_le())
+        }
+        SERIALIZE_TYPE_UINT8 => {
+            ensure_eof!(buf, 1);
+            handler.u8(buf.get_u8())
+        }
+        SERIALIZE_TYPE_DOUBLE => {
+            ensure_eof!(buf, 8);
+            handler.double(buf.get_f64_le())
+        }
+        SERIALIZE_TYPE_STRING => {
+            let length = raw_size::read_usize(buf)?;
+            ensure_eof!(buf, length);
+            handler.bytes(&buf.bytes()[..length])?;
+            buf.advance(length);
+            Ok(())
+        }
+        SERIALIZE_TYPE_BOOL => {
+            ensure_eof!(buf, 1);
+            handler.bool(buf.get_u8() != 0)
+        }
+        SERIALIZE_TYPE_OBJECT => section(buf, handler),
+        SERIALIZE_TYPE_ARRAY => {
+            let serialize_type = wire::read_array_type(buf)?;
+            array(buf, serialize_type, handler)
+        }
+        _ => Err(Error::InvalidSerializeType(serialize_type)),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Array, Section, StorageEntry};
+
+    /// Writes down every callback.
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl StorageHandler for Log {
+        fn begin_section(&mut self, len: usize) -> Result<()> {
+            self.0.push(format!("{{{}", len));
+            Ok(())
+        }
+
+        fn end_section(&mut self) -> Result<()> {
+            self.0.push("}".to_owned());
+            Ok(())
+        }
+
+        fn key(&mut self, name: &[u8]) -> Result<()> {
+            self.0.push(String::from_utf8_lossy(name).into_owned());
+            Ok(())
+        }
+
+        fn begin_array(&mut self, element: SerializeType, len: usize) -> Result<()> {
+            self.0.push(format!("[{} {}", element, len));
+            Ok(())
+        }
+
+        fn end_array(&mut self) -> Result<()> {
+            self.0.push("]".to_owned());
+            Ok(())
+        }
+
+        fn u32(&mut self, v: u32) -> Result<()> {
+            self.0.push(v.to_string());
+            Ok(())
+        }
+
+        fn bool(&mut self, v: bool) -> Result<()> {
+            self.0.push(v.to_string());
+            Ok(())
+        }
+
+        fn bytes(&mut self, v: &[u8]) -> Result<()> {
+            if v == b"stop" {
+                return Err(Error::Conversion("stopped".to_owned()));
+            }
+            self.0.push(format!("{:?}", v));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn callbacks() {
+        let mut ports = Array::new();
+        ports.push(StorageEntry::U32(18080)).unwrap();
+        ports.push(StorageEntry::U32(18081)).unwrap();
+        let mut matrix = Array::new();
+        matrix.push(StorageEntry::Array(ports.clone())).unwrap();
+        let mut peer = Section::new();
+        peer.insert("ports".to_owned(), StorageEntry::Array(ports));
+        peer.insert("host".to_owned(), StorageEntry::Buf(b"a".to_vec().into()));
+
+        let mut section = Section::new();
+        section.insert("peer".to_owned(), StorageEntry::Section(peer));
+        section.insert("matrix".to_owned(), StorageEntry::Array(matrix));
+        section.insert("ok".to_owned(), StorageEntry::Bool(true));
+        let blob = crate::write_to_vec(&section);
+
+        let mut log = Log::default();
+        crate::read_with_handler(&mut &blob[..], &mut log).unwrap();
+        assert_eq!(
+            log.0,
+            [
+                "{3", "peer", "{2", "ports", "[u32 2", "18080", "18081", "]", "host", "[97]", "}",
+                "matrix", "[array 1", "[u32 2", "18080", "18081", "]", "]", "ok", "true", "}",
+            ]
+        );
+
+        let truncated = &blob[..blob.len() - 1];
+        assert!(
+            crate::read_with_handler(&mut &truncated[..], &mut Log::default())
+                .unwrap_err()
+                .is_eof()
+        );
+    }
+
+    #[test]
+    fn handler_errors() {
+        let mut section = Section::new();
+        section.insert("a".to_owned(), StorageEntry::Buf(b"stop".to_vec().into()));
+        section.insert("b".to_owned(), StorageEntry::Bool(true));
+        let blob = crate::write_to_vec(&section);
+
+        let mut log = Log::default();
+        assert!(matches!(
+            crate::read_with_handler(&mut &blob[..], &mut log),
+            Err(Error::Conversion(_))
+        ));
+        assert_eq!(log.0, ["{2", "a"]);
+    }
+}
@@ -21,6 +21,25 @@ pub const PORTABLE_STORAGE_SIGNATUREB: u32 = 0x0102_0101;
 pub const PORTABLE_STORAGE_FORMAT_VER: u8 = 1;
 pub const PORTABLE_STORAGE_BLOCK_HEADER_LENGTH: usize = 4 + 4 + 1;
 
+/// How strictly the storage block header is checked when reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderValidation {
+    /// Both signatures and the format version must match, this is what
+    /// epee does.
+    Strict,
+    /// At least one of the signatures must match, the format version is
+    /// always checked. This is the default, so a corrupted `signature_b`
+    /// still reads: before these modes only `signature_a` was checked,
+    /// against either signature.
+    Lenient,
+}
+
+impl Default for HeaderValidation {
+    fn default() -> Self {
+        HeaderValidation::Lenient
+    }
+}
+
 #[derive(Debug)]
 pub struct StorageBlockHeader {
     pub signature_a: u32,
@@ -34,14 +53,29 @@ impl StorageBlockHeader {
     }
 
     pub fn is_valid_signature_b(&self) -> bool {
-        self.signature_a == PORTABLE_STORAGE_SIGNATUREB
+        self.signature_b == PORTABLE_STORAGE_SIGNATUREB
     }
 
     pub fn is_valid_version(&self) -> bool {
         self.version == PORTABLE_STORAGE_FORMAT_VER
     }
 
+    /// Checks the header using the given validation mode.
+    pub fn is_valid(&self, validation: HeaderValidation) -> bool {
+        let signatures = match validation {
+            HeaderValidation::Strict => self.is_valid_signature_a() && self.is_valid_signature_b(),
+            HeaderValidation::Lenient => self.is_valid_signature_a() || self.is_valid_signature_b(),
+        };
+
+        signatures && self.is_valid_version()
+    }
+
+    /// Reads the header using the default (lenient) validation mode.
     pub fn read<B: Buf>(buf: &mut B) -> Result<Self, Error> {
+        Self::read_with::<B>(buf, HeaderValidation::default())
+    }
+
+    pub fn read_with<B: Buf>(buf: &mut B, validation: HeaderValidation) -> Result<Self, Error> {
         ensure_eof!(buf, PORTABLE_STORAGE_BLOCK_HEADER_LENGTH);
 
         let hdr = StorageBlockHeader {
@@ -50,7 +84,7 @@ impl StorageBlockHeader {
             version: buf.get_u8(),
         };
 
-        if (hdr.is_valid_signature_a() || hdr.is_valid_signature_b()) && hdr.is_valid_version() {
+        if hdr.is_valid(validation) {
             Ok(hdr)
         } else {
             Err(Error::InvalidHeader)
@@ -64,3 +98,41 @@ impl StorageBlockHeader {
         buf.put_u8(PORTABLE_STORAGE_FORMAT_VER);
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn header(signature_a: u32, signature_b: u32, version: u8) -> BytesMut {
+        let mut buf = BytesMut::new();
+        buf.put_u32_le(signature_a);
+        buf.put_u32_le(signature_b);
+        buf.put_u8(version);
+        buf
+    }
+
+    #[test]
+    fn validation_modes() {
+        let valid = header(
+            PORTABLE_STORAGE_SIGNATUREA,
+            PORTABLE_STORAGE_SIGNATUREB,
+            PORTABLE_STORAGE_FORMAT_VER,
+        );
+        let half = header(PORTABLE_STORAGE_SIGNATUREA, 0, PORTABLE_STORAGE_FORMAT_VER);
+        let version = header(
+            PORTABLE_STORAGE_SIGNATUREA,
+            PORTABLE_STORAGE_SIGNATUREB,
+            PORTABLE_STORAGE_FORMAT_VER + 1,
+        );
+
+        for mode in &[HeaderValidation::Strict, HeaderValidation::Lenient] {
+            assert!(StorageBlockHeader::read_with(&mut valid.clone().freeze(), *mode).is_ok());
+            assert!(StorageBlockHeader::read_with(&mut version.clone().freeze(), *mode).is_err());
+        }
+
+        assert!(StorageBlockHeader::read(&mut half.clone().freeze()).is_ok());
+        assert!(
+            StorageBlockHeader::read_with(&mut half.freeze(), HeaderValidation::Strict).is_err()
+        );
+    }
+}
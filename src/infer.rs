@@ -0,0 +1,368 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Schema inference
+//!
+//! Analyzes one or more decoded sections and generates Rust struct
+//! definitions with serde attributes matching the observed keys and types,
+//! to bootstrap typed bindings for undocumented messages.
+//!
+//! ```rust
+//! use portable_storage::{infer::Inference, Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("height".to_owned(), StorageEntry::U64(1));
+//!
+//! let mut inference = Inference::new();
+//! inference.add(&section);
+//! let code = inference.generate("Message");
+//! assert!(code.contains("pub height: u64,"));
+//! ```
+//!
+//! Nested sections become their own structs, named after their key. Keys
+//! missing from some samples are marked `#[serde(default)]`, strings are
+//! mapped to `portable_storage_utils::Blob`.
+
+use crate::{
+    explain::type_name, Section, StorageEntry, SERIALIZE_FLAG_ARRAY, SERIALIZE_TYPE_BOOL,
+    SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32, SERIALIZE_TYPE_INT64,
+    SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING, SERIALIZE_TYPE_UINT16,
+    SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use linked_hash_map::LinkedHashMap;
+use std::fmt::Write;
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    /// A scalar or string serialize type.
+    Scalar(u8),
+    /// A nested section, index into `Inference::structs`.
+    Struct(usize),
+    Array(Box<Shape>),
+    /// An array whose element type couldn't be determined.
+    Unknown,
+}
+
+#[derive(Debug)]
+struct Field {
+    shape: Shape,
+    /// Number of samples that contained this field.
+    seen: usize,
+    /// Other types the field was observed with.
+    conflicts: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Struct {
+    name: String,
+    path: String,
+    samples: usize,
+    fields: LinkedHashMap<String, Field>,
+}
+
+/// Accumulates observations over sample sections.
+#[derive(Debug, Default)]
+pub struct Inference {
+    structs: Vec<Struct>,
+}
+
+impl Inference {
+    pub fn new() -> Inference {
+        Default::default()
+    }
+
+    /// Adds a sample of the root message.
+    pub fn add(&mut self, section: &Section) {
+        let root = self.struct_for("", "");
+        self.observe(root, section);
+    }
+
+    /// Generates the Rust code for the observed messages, the root struct is
+    /// called `root_name`.
+    pub fn generate(&self, root_name: &str) -> String {
+        let mut code = String::new();
+        code.push_str("use portable_storage_utils::Blob;\n");
+        code.push_str("use serde::{Deserialize, Serialize};\n");
+
+        for (i, s) in self.structs.iter().enumerate() {
+            let name = if i == 0 { root_name } else { s.name.as_str() };
+            code.push('\n');
+            if !s.path.is_empty() {
+                writeln!(code, "/// `{}`", s.path).unwrap();
+            }
+            code.push_str("#[derive(Debug, Clone, Serialize, Deserialize)]\n");
+            writeln!(code, "pub struct {} {{", name).unwrap();
+
+            for (key, field) in s.fields.iter() {
+                for conflict in field.conflicts.iter() {
+                    writeln!(code, "    // FIXME: also seen as {}", conflict).unwrap();
+                }
+                let ident = identifier(key);
+                if ident != *key {
+                    writeln!(code, "    #[serde(rename = {:?})]", key).unwrap();
+                }
+                if field.seen < s.samples {
+                    code.push_str("    #[serde(default)]\n");
+                }
+                writeln!(code, "    pub {}: {},", ident, self.rust_type(&field.shape)).unwrap();
+            }
+
+            code.push_str("}\n");
+        }
+
+        code
+    }
+
+    fn struct_for(&mut self, path: &str, key: &str) -> usize {
+        if let Some(i) = self.structs.iter().position(|s| s.path == path) {
+            return i;
+        }
+
+        let base = camel_case(key);
+        let mut name = base.clone();
+        let mut n = 2;
+        while self.structs.iter().any(|s| s.name == name) {
+            name = format!("{}{}", base, n);
+            n += 1;
+        }
+
+        self.structs.push(Struct {
+            name,
+            path: path.to_owned(),
+            samples: 0,
+            fields: LinkedHashMap::new(),
+        });
+        self.structs.len() - 1
+    }
+
+    fn observe(&mut self, index: usize, section: &Section) {
+        self.structs[index].samples += 1;
+        let path = self.structs[index].path.clone();
+
+        for (key, entry) in section.entries.iter() {
+            let field_path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", path, key)
+            };
+            let shape = self.shape(&field_path, key, entry);
+
+            let fields = &mut self.structs[index].fields;
+            match fields.get_mut(key) {
+                Some(field) => {
+                    field.seen += 1;
+                    if let Some(merged) = merge(&field.shape, &shape) {
+                        field.shape = merged;
+                    } else {
+                        let description = describe(&shape);
+                        if !field.conflicts.contains(&description) {
+                            field.conflicts.push(description);
+                        }
+                    }
+                }
+                None => {
+                    fields.insert(
+                        key.clone(),
+                        Field {
+                            shape,
+                            seen: 1,
+                            conflicts: Vec::new(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    fn shape(&mut self, path: &str, key: &str, entry: &StorageEntry) -> Shape {
+        match entry {
+            StorageEntry::Section(section) => {
+                let index = self.struct_for(path, key);
+                self.observe(index, section);
+                Shape::Struct(index)
+            }
+            StorageEntry::Array(array) => {
+                let mut element = match array.serialize_type {
+                    Some(t) if t & !SERIALIZE_FLAG_ARRAY == SERIALIZE_TYPE_OBJECT => {
+                        Shape::Struct(self.struct_for(path, key))
+                    }
+                    Some(t) => Shape::Scalar(t & !SERIALIZE_FLAG_ARRAY),
+                    None => Shape::Unknown,
+                };
+
+                for entry in array.array.iter() {
+                    let shape = self.shape(path, key, entry);
+                    element = merge(&element, &shape).unwrap_or(element);
+                }
+
+                Shape::Array(Box::new(element))
+            }
+            _ => Shape::Scalar(entry.serialize_type()),
+        }
+    }
+
+    fn rust_type(&self, shape: &Shape) -> String {
+        match shape {
+            Shape::Scalar(t) => match *t {
+                SERIALIZE_TYPE_INT64 => "i64",
+                SERIALIZE_TYPE_INT32 => "i32",
+                SERIALIZE_TYPE_INT16 => "i16",
+                SERIALIZE_TYPE_INT8 => "i8",
+                SERIALIZE_TYPE_UINT64 => "u64",
+                SERIALIZE_TYPE_UINT32 => "u32",
+                SERIALIZE_TYPE_UINT16 => "u16",
+                SERIALIZE_TYPE_UINT8 => "u8",
+                SERIALIZE_TYPE_DOUBLE => "f64",
+                SERIALIZE_TYPE_BOOL => "bool",
+                SERIALIZE_TYPE_STRING => "Blob",
+                _ => "()",
+            }
+            .to_owned(),
+            Shape::Struct(i) => self.structs[*i].name.clone(),
+            Shape::Array(element) => format!("Vec<{}>", self.rust_type(element)),
+            Shape::Unknown => "Blob /* FIXME: unknown element type */".to_owned(),
+        }
+    }
+}
+
+/// Merges two observations of the same field, integers of the same
+/// signedness are widened. Returns `None` if they're incompatible.
+fn merge(a: &Shape, b: &Shape) -> Option<Shape> {
+    match (a, b) {
+        _ if a == b => Some(a.clone()),
+        (Shape::Unknown, other) | (other, Shape::Unknown) => Some(other.clone()),
+        (Shape::Scalar(a), Shape::Scalar(b)) => match (width(*a), width(*b)) {
+            (Some((sa, wa)), Some((sb, wb))) if sa == sb => {
+                Some(Shape::Scalar(if wa >= wb { *a } else { *b }))
+            }
+            _ => None,
+        },
+        (Shape::Array(a), Shape::Array(b)) => merge(a, b).map(|s| Shape::Array(Box::new(s))),
+        _ => None,
+    }
+}
+
+/// Signedness and width in bytes of an integer serialize type.
+fn width(serialize_type: u8) -> Option<(bool, u8)> {
+    match serialize_type {
+        SERIALIZE_TYPE_INT64 => Some((true, 8)),
+        SERIALIZE_TYPE_INT32 => Some((true, 4)),
+        SERIALIZE_TYPE_INT16 => Some((true, 2)),
+        SERIALIZE_TYPE_INT8 => Some((true, 1)),
+        SERIALIZE_TYPE_UINT64 => Some((false, 8)),
+        SERIALIZE_TYPE_UINT32 => Some((false, 4)),
+        SERIALIZE_TYPE_UINT16 => Some((false, 2)),
+        SERIALIZE_TYPE_UINT8 => Some((false, 1)),
+        _ => None,
+    }
+}
+
+fn describe(shape: &Shape) -> String {
+    match shape {
+        Shape::Scalar(t) => type_name(*t).to_owned(),
+        Shape::Struct(_) => "section".to_owned(),
+        Shape::Array(element) => format!("array of {}", describe(element)),
+        Shape::Unknown => "unknown".to_owned(),
+    }
+}
+
+/// Converts a key into a valid Rust field name.
+pub(crate) fn identifier(key: &str) -> String {
+    let mut ident: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if KEYWORDS.contains(&ident.as_str()) {
+        ident.push('_');
+    }
+    ident
+}
+
+fn camel_case(key: &str) -> String {
+    let mut name = String::new();
+    for part in identifier(key).split('_').filter(|p| !p.is_empty()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            name.push(first.to_ascii_uppercase());
+            name.extend(chars);
+        }
+    }
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, 'S');
+    }
+    name
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::Array;
+
+    fn peer(id: u64, last_seen: Option<i64>) -> StorageEntry {
+        let mut adr = Section::new();
+        adr.insert("type".to_owned(), StorageEntry::U8(1));
+
+        let mut peer = Section::new();
+        peer.insert("adr".to_owned(), StorageEntry::Section(adr));
+        peer.insert("id".to_owned(), StorageEntry::U64(id));
+        if let Some(last_seen) = last_seen {
+            peer.insert("last_seen".to_owned(), StorageEntry::I64(last_seen));
+        }
+        StorageEntry::Section(peer)
+    }
+
+    #[test]
+    fn generate() {
+        let mut peers = Array::new();
+        peers.push(peer(1, Some(10))).unwrap();
+        peers.push(peer(2, None)).unwrap();
+
+        let mut first = Section::new();
+        first.insert("local_peerlist_new".to_owned(), StorageEntry::Array(peers));
+        first.insert("height".to_owned(), StorageEntry::U32(5));
+
+        let mut second = Section::new();
+        second.insert("height".to_owned(), StorageEntry::U64(6));
+        second.insert(
+            "network_id".to_owned(),
+            StorageEntry::Buf(vec![0; 16].into()),
+        );
+
+        let mut inference = Inference::new();
+        inference.add(&first);
+        inference.add(&second);
+        let code = inference.generate("Handshake");
+
+        assert!(code.contains("pub struct Handshake {"));
+        assert!(code
+            .contains("    #[serde(default)]\n    pub local_peerlist_new: Vec<LocalPeerlistNew>,"));
+        assert!(code.contains("    pub height: u64,"));
+        assert!(code.contains("    #[serde(default)]\n    pub network_id: Blob,"));
+        assert!(code.contains("pub struct LocalPeerlistNew {"));
+        assert!(code.contains("    #[serde(default)]\n    pub last_seen: i64,"));
+        assert!(code.contains("    pub adr: Adr,"));
+        assert!(code.contains("    #[serde(rename = \"type\")]\n    pub type_: u8,"));
+    }
+}
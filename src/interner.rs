@@ -0,0 +1,139 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Key interning
+//!
+//! P2P and RPC messages use the same small set of keys (`"peer_id"`,
+//! `"adr"`, `"m_ip"`, …) over and over. An [`Interner`] remembers the keys it
+//! has decoded so reading them again is a lookup and a copy instead of
+//! decoding and validating the name, and can be shared across messages:
+//!
+//! ```rust
+//! use portable_storage::{interner::Interner, Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("peer_id".to_owned(), StorageEntry::U64(1));
+//! let blob = portable_storage::write_to_vec(&section);
+//!
+//! let mut interner = Interner::new();
+//! for _ in 0..3 {
+//!     let decoded = portable_storage::read_interned(&mut &blob[..], &mut interner).unwrap();
+//!     assert_eq!(decoded, section);
+//! }
+//! assert_eq!(interner.len(), 1);
+//! ```
+//!
+//! Section keys are owned `String`s, so each decoded key still takes one
+//! allocation. The interner stops learning new keys after its limit to keep
+//! hostile peers from growing it without bounds.
+
+use std::collections::HashMap;
+
+/// How many keys [`Interner::new`] remembers.
+pub const DEFAULT_LIMIT: usize = 1024;
+
+/// A cache of decoded section keys.
+#[derive(Debug, Clone)]
+pub struct Interner {
+    keys: HashMap<Box<[u8]>, String>,
+    limit: usize,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::with_limit(DEFAULT_LIMIT)
+    }
+
+    /// Creates an interner remembering at most `limit` keys.
+    pub fn with_limit(limit: usize) -> Interner {
+        Interner {
+            keys: HashMap::new(),
+            limit,
+        }
+    }
+
+    /// Remembers `key` ahead of decoding, even past the limit.
+    pub fn insert(&mut self, key: &str) {
+        self.keys.insert(key.as_bytes().into(), key.to_owned());
+    }
+
+    /// Number of keys remembered.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets every key.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+}
+
+impl Default for Interner {
+    fn default() -> Interner {
+        Interner::new()
+    }
+}
+
+impl crate::Keys for Interner {
+    fn key(&mut self, name: &[u8]) -> String {
+        if let Some(key) = self.keys.get(name) {
+            return key.clone();
+        }
+
+        let key = String::from_utf8_lossy(name).into_owned();
+        if self.keys.len() < self.limit {
+            self.keys.insert(name.into(), key.clone());
+        }
+        key
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Keys, Section, StorageEntry};
+
+    #[test]
+    fn limit() {
+        let mut interner = Interner::with_limit(2);
+        interner.insert("adr");
+        assert_eq!(interner.key(b"m_ip"), "m_ip");
+        assert_eq!(interner.key(b"m_port"), "m_port");
+        assert_eq!(interner.key(b"m_ip"), "m_ip");
+        assert_eq!(interner.len(), 2);
+
+        interner.clear();
+        assert!(interner.is_empty());
+    }
+
+    #[test]
+    fn nested() {
+        let mut addr = Section::new();
+        addr.insert("m_ip".to_owned(), StorageEntry::U32(1));
+        let mut section = Section::new();
+        section.insert("adr".to_owned(), StorageEntry::Section(addr));
+        let blob = crate::write_to_vec(&section);
+
+        let mut interner = Interner::new();
+        let plain = crate::read(&mut &blob[..]).unwrap();
+        let interned = crate::read_interned(&mut &blob[..], &mut interner).unwrap();
+        assert_eq!(interned, plain);
+        assert_eq!(interner.len(), 2);
+    }
+}
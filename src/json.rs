@@ -0,0 +1,446 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # JSON conversion
+//!
+//! Converts sections and storage entries to and from `serde_json::Value`, so
+//! blobs can be inspected and crafted with ordinary JSON tooling.
+//!
+//! Sections map to JSON objects, arrays to JSON arrays, `Bool` and `Double`
+//! to their JSON counterparts and `Buf` to strings encoded according to
+//! [`ByteEncoding`]. Integers are either written as plain numbers or tagged
+//! with their width, see [`IntegerWidth`]. Doubles JSON can't represent are
+//! tagged too, as `{"$f64": "NaN"}`, `"inf"` or `"-inf"`. Keys starting with
+//! `$` get another `$` in front, so they can't be taken for a tag.
+//!
+//! ```rust
+//! use portable_storage::{json::JsonConfig, Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("id".to_owned(), StorageEntry::U32(5));
+//!
+//! let config = JsonConfig::default();
+//! let value = section.to_json(&config);
+//! assert_eq!(value.to_string(), r#"{"id":{"$u32":5}}"#);
+//!
+//! let section = Section::from_json(&value, &config).unwrap();
+//! assert!(matches!(section["id"], StorageEntry::U32(5)));
+//! ```
+
+use crate::{
+    encoding::{self, Base64},
+    redact::{self, Redactor},
+    Array, Error, Result, Section, StorageEntry,
+};
+use serde_json::{Map, Number, Value};
+
+/// How `StorageEntry::Buf` values are represented as JSON strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteEncoding {
+    /// Lowercase hexadecimal.
+    Hex,
+    /// Standard base64 with padding.
+    Base64,
+}
+
+impl Default for ByteEncoding {
+    fn default() -> Self {
+        ByteEncoding::Hex
+    }
+}
+
+/// How the width of integer entries is preserved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerWidth {
+    /// Integers are wrapped in single-key objects naming their type, e.g.
+    /// `{"$u32": 5}`, so converting back yields exactly the same entries.
+    Tagged,
+    /// Integers are written as plain JSON numbers. When converting back
+    /// non-negative numbers become `U64` and negative ones `I64`.
+    Plain,
+}
+
+impl Default for IntegerWidth {
+    fn default() -> Self {
+        IntegerWidth::Tagged
+    }
+}
+
+/// Options for converting between storage entries and JSON.
+///
+/// Tagged integers are always accepted when converting from JSON, regardless
+/// of the configured [`IntegerWidth`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JsonConfig {
+    pub bytes: ByteEncoding,
+    pub integers: IntegerWidth,
+}
+
+impl JsonConfig {
+    pub fn new(bytes: ByteEncoding, integers: IntegerWidth) -> JsonConfig {
+        JsonConfig { bytes, integers }
+    }
+
+    fn encode_bytes(&self, v: &[u8]) -> String {
+        match self.bytes {
+            ByteEncoding::Hex => encoding::encode_hex(v),
+            ByteEncoding::Base64 => encoding::encode_base64(v, Base64::Standard),
+        }
+    }
+
+    fn decode_bytes(&self, v: &str) -> Result<Vec<u8>> {
+        match self.bytes {
+            ByteEncoding::Hex => encoding::decode_hex(v),
+            ByteEncoding::Base64 => encoding::decode_base64(v, Base64::Standard),
+        }
+    }
+}
+
+const TAG_U64: &str = "$u64";
+const TAG_U32: &str = "$u32";
+const TAG_U16: &str = "$u16";
+const TAG_U8: &str = "$u8";
+const TAG_I64: &str = "$i64";
+const TAG_I32: &str = "$i32";
+const TAG_I16: &str = "$i16";
+const TAG_I8: &str = "$i8";
+const TAG_F64: &str = "$f64";
+
+/// Doubles a leading `$`, tags have a single one.
+fn escape_key(name: &str) -> String {
+    if name.starts_with('$') {
+        format!("${}", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+fn unescape_key(name: &str) -> &str {
+    match name.strip_prefix('$') {
+        Some(rest) if rest.starts_with('$') => rest,
+        _ => name,
+    }
+}
+
+impl Section {
+    /// Converts this section into a JSON object.
+    pub fn to_json(&self, config: &JsonConfig) -> Value {
+        self.json(config, None, "")
+    }
+
+    /// Converts this section into a JSON object, with the values of the
+    /// entries marked by `redactor` replaced by a placeholder string.
+    pub fn to_json_redacted(&self, config: &JsonConfig, redactor: &Redactor) -> Value {
+        self.json(config, Some(redactor), "")
+    }
+
+    fn json(&self, config: &JsonConfig, redactor: Option<&Redactor>, path: &str) -> Value {
+        let mut map = Map::with_capacity(self.len());
+        for (name, entry) in self.entries.iter() {
+            let path = redact::join(path, name);
+            let value = match redactor {
+                Some(redactor) if redactor.is_sensitive(&path) => {
+                    Value::String(redact::PLACEHOLDER.to_owned())
+                }
+                _ => entry.json(config, redactor, &path),
+            };
+            map.insert(escape_key(name), value);
+        }
+
+        Value::Object(map)
+    }
+
+    /// Creates a section from a JSON object.
+    pub fn from_json(value: &Value, config: &JsonConfig) -> Result<Section> {
+        match value {
+            Value::Object(map) => {
+                let mut section = Section::with_capacity(map.len());
+                for (name, value) in map.iter() {
+                    let name = unescape_key(name);
+                    crate::check_name(name)?;
+                    section.insert(name.to_owned(), StorageEntry::from_json(value, config)?);
+                }
+
+                Ok(section)
+            }
+            _ => Err(Error::Conversion(format!(
+                "expected a JSON object, found `{}`",
+                value
+            ))),
+        }
+    }
+}
+
+impl StorageEntry {
+    /// Converts this entry into a JSON value.
+    ///
+    /// Non-finite doubles have no JSON number and are written as tagged
+    /// strings, see the [module documentation](crate::json).
+    pub fn to_json(&self, config: &JsonConfig) -> Value {
+        self.json(config, None, "")
+    }
+
+    fn json(&self, config: &JsonConfig, redactor: Option<&Redactor>, path: &str) -> Value {
+        match self {
+            StorageEntry::U64(v) => integer(config, TAG_U64, *v),
+            StorageEntry::U32(v) => integer(config, TAG_U32, *v),
+            StorageEntry::U16(v) => integer(config, TAG_U16, *v),
+            StorageEntry::U8(v) => integer(config, TAG_U8, *v),
+            StorageEntry::I64(v) => integer(config, TAG_I64, *v),
+            StorageEntry::I32(v) => integer(config, TAG_I32, *v),
+            StorageEntry::I16(v) => integer(config, TAG_I16, *v),
+            StorageEntry::I8(v) => integer(config, TAG_I8, *v),
+            StorageEntry::Double(v) => match Number::from_f64(*v) {
+                Some(number) => Value::Number(number),
+                None => tagged(TAG_F64, Value::String(non_finite(*v).to_owned())),
+            },
+            StorageEntry::Bool(v) => Value::Bool(*v),
+            StorageEntry::Buf(v) => Value::String(config.encode_bytes(v)),
+            StorageEntry::Array(v) => Value::Array(
+                v.array
+                    .iter()
+                    .map(|e| e.json(config, redactor, path))
+                    .collect(),
+            ),
+            StorageEntry::Section(v) => v.json(config, redactor, path),
+        }
+    }
+
+    /// Creates an entry from a JSON value.
+    ///
+    /// Arrays must hold elements of a single storage type and `null` isn't
+    /// supported.
+    pub fn from_json(value: &Value, config: &JsonConfig) -> Result<StorageEntry> {
+        match value {
+            Value::Null => Err(Error::Conversion("`null` isn't supported".to_owned())),
+            Value::Bool(v) => Ok(StorageEntry::Bool(*v)),
+            Value::Number(v) => {
+                if let Some(v) = v.as_u64() {
+                    Ok(StorageEntry::U64(v))
+                } else if let Some(v) = v.as_i64() {
+                    Ok(StorageEntry::I64(v))
+                } else {
+                    Ok(StorageEntry::Double(v.as_f64().unwrap_or_default()))
+                }
+            }
+            Value::String(v) => config.decode_bytes(v).map(|v| StorageEntry::Buf(v.into())),
+            Value::Array(v) => {
+                let mut array = Array::with_capacity(v.len());
+                for value in v.iter() {
+                    array.push(StorageEntry::from_json(value, config)?)?;
+                }
+
+                Ok(StorageEntry::Array(array))
+            }
+            Value::Object(map) => match tagged_entry(map)? {
+                Some(entry) => Ok(entry),
+                None => Section::from_json(value, config).map(StorageEntry::Section),
+            },
+        }
+    }
+}
+
+fn integer<T: Into<Number>>(config: &JsonConfig, tag: &str, v: T) -> Value {
+    match config.integers {
+        IntegerWidth::Tagged => tagged(tag, Value::Number(v.into())),
+        IntegerWidth::Plain => Value::Number(v.into()),
+    }
+}
+
+fn tagged(tag: &str, value: Value) -> Value {
+    let mut map = Map::with_capacity(1);
+    map.insert(tag.to_owned(), value);
+    Value::Object(map)
+}
+
+fn non_finite(v: f64) -> &'static str {
+    if v.is_nan() {
+        "NaN"
+    } else if v > 0.0 {
+        "inf"
+    } else {
+        "-inf"
+    }
+}
+
+fn tagged_entry(map: &Map<String, Value>) -> Result<Option<StorageEntry>> {
+    if map.len() != 1 {
+        return Ok(None);
+    }
+
+    let (tag, value) = map.iter().next().unwrap();
+    if tag == TAG_F64 {
+        return match value {
+            Value::String(v) => match v.as_str() {
+                "NaN" => Ok(Some(StorageEntry::Double(f64::NAN))),
+                "inf" => Ok(Some(StorageEntry::Double(f64::INFINITY))),
+                "-inf" => Ok(Some(StorageEntry::Double(f64::NEG_INFINITY))),
+                _ => Err(Error::Conversion(format!(
+                    "`{}` isn't a value for `{}`",
+                    v, tag
+                ))),
+            },
+            Value::Number(v) => Ok(Some(StorageEntry::Double(v.as_f64().unwrap_or_default()))),
+            _ => Ok(None),
+        };
+    }
+
+    let number = match value {
+        Value::Number(number) => number,
+        _ => return Ok(None),
+    };
+
+    macro_rules! convert {
+        ($get:ident, $variant:path) => {
+            number
+                .$get()
+                .and_then(|v| std::convert::TryFrom::try_from(v).ok())
+                .map($variant)
+                .ok_or_else(|| {
+                    Error::Conversion(format!("`{}` is out of range for `{}`", number, tag))
+                })?
+        };
+    }
+
+    let entry = match tag.as_str() {
+        TAG_U64 => convert!(as_u64, StorageEntry::U64),
+        TAG_U32 => convert!(as_u64, StorageEntry::U32),
+        TAG_U16 => convert!(as_u64, StorageEntry::U16),
+        TAG_U8 => convert!(as_u64, StorageEntry::U8),
+        TAG_I64 => convert!(as_i64, StorageEntry::I64),
+        TAG_I32 => convert!(as_i64, StorageEntry::I32),
+        TAG_I16 => convert!(as_i64, StorageEntry::I16),
+        TAG_I8 => convert!(as_i64, StorageEntry::I8),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(entry))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn section() -> Section {
+        let mut array = Array::new();
+        array.push(StorageEntry::U16(1)).unwrap();
+        array.push(StorageEntry::U16(2)).unwrap();
+
+        let mut inner = Section::new();
+        inner.insert("neg".to_owned(), StorageEntry::I8(-3));
+
+        let mut section = Section::new();
+        section.insert("id".to_owned(), StorageEntry::U32(56));
+        section.insert(
+            "blob".to_owned(),
+            StorageEntry::Buf(vec![0xde, 0xad].into()),
+        );
+        section.insert("ok".to_owned(), StorageEntry::Bool(true));
+        section.insert("ratio".to_owned(), StorageEntry::Double(0.5));
+        section.insert("list".to_owned(), StorageEntry::Array(array));
+        section.insert("inner".to_owned(), StorageEntry::Section(inner));
+        section
+    }
+
+    #[test]
+    fn tagged_roundtrip() {
+        let config = JsonConfig::default();
+        let value = section().to_json(&config);
+        assert_eq!(value["blob"], Value::String("dead".to_owned()));
+
+        let section = Section::from_json(&value, &config).unwrap();
+        assert!(matches!(section["id"], StorageEntry::U32(56)));
+        match (&section["list"], &section["inner"]) {
+            (StorageEntry::Array(list), StorageEntry::Section(inner)) => {
+                assert_eq!(list.len(), 2);
+                assert!(matches!(list[1], StorageEntry::U16(2)));
+                assert!(matches!(inner["neg"], StorageEntry::I8(-3)));
+            }
+            _ => panic!("unexpected entries"),
+        }
+    }
+
+    #[test]
+    fn plain_base64() {
+        let config = JsonConfig::new(ByteEncoding::Base64, IntegerWidth::Plain);
+        let value = section().to_json(&config);
+        assert_eq!(value["id"], Value::from(56));
+        assert_eq!(value["blob"], Value::String("3q0=".to_owned()));
+
+        let section = Section::from_json(&value, &config).unwrap();
+        assert!(matches!(section["id"], StorageEntry::U64(56)));
+        assert!(matches!(section["blob"], StorageEntry::Buf(ref v) if v[..] == [0xde, 0xad]));
+        assert!(matches!(section["ratio"], StorageEntry::Double(v) if v == 0.5));
+    }
+
+    #[test]
+    fn non_finite_doubles() {
+        let config = JsonConfig::default();
+        let mut section = Section::new();
+        for (name, v) in [
+            ("nan", f64::NAN),
+            ("inf", f64::INFINITY),
+            ("ninf", f64::NEG_INFINITY),
+        ]
+        .iter()
+        {
+            section.insert((*name).to_owned(), StorageEntry::Double(*v));
+        }
+
+        let value = section.to_json(&config);
+        assert_eq!(value["ninf"], serde_json::json!({ "$f64": "-inf" }));
+        let section = Section::from_json(&value, &config).unwrap();
+        assert!(matches!(section["nan"], StorageEntry::Double(v) if v.is_nan()));
+        assert!(matches!(section["inf"], StorageEntry::Double(v) if v == f64::INFINITY));
+        assert!(matches!(section["ninf"], StorageEntry::Double(v) if v == f64::NEG_INFINITY));
+
+        let value = serde_json::json!({ "x": { "$f64": "nan" } });
+        assert!(Section::from_json(&value, &config).is_err());
+    }
+
+    #[test]
+    fn dollar_keys() {
+        let mut inner = Section::new();
+        inner.insert("$u32".to_owned(), StorageEntry::U64(5));
+        let mut section = Section::new();
+        section.insert("inner".to_owned(), StorageEntry::Section(inner));
+        section.insert("$$x".to_owned(), StorageEntry::Bool(true));
+
+        for integers in [IntegerWidth::Tagged, IntegerWidth::Plain].iter() {
+            let config = JsonConfig::new(ByteEncoding::Hex, *integers);
+            let value = section.to_json(&config);
+            assert!(value["inner"].get("$$u32").is_some());
+            assert_eq!(Section::from_json(&value, &config).unwrap(), section);
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        let value = serde_json::json!({ "id": { "$u8": 256 } });
+        assert!(matches!(
+            Section::from_json(&value, &JsonConfig::default()),
+            Err(Error::Conversion(_))
+        ));
+    }
+
+    #[test]
+    fn long_keys() {
+        let value = serde_json::json!({ "inner": { "a".repeat(256): true } });
+        assert!(matches!(
+            Section::from_json(&value, &JsonConfig::default()),
+            Err(Error::NameTooLong(256))
+        ));
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Levin buckets
+//!
+//! The framing monerod puts around every storage blob on the wire. Each
+//! bucket starts with a fixed 33 bytes header carrying the command, flags
+//! and the length of the payload that follows.
+//!
+//! ```rust
+//! use bytes::BytesMut;
+//! use portable_storage::{levin::{Bucket, Codec}, Section, StorageEntry};
+//!
+//! let mut payload = Section::new();
+//! payload.insert("support_flags".to_owned(), StorageEntry::U32(1));
+//!
+//! let mut codec = Codec::new();
+//! let mut buf = BytesMut::new();
+//! codec.encode(&Bucket::response(1007, 0, payload), &mut buf);
+//!
+//! let bucket = codec.decode(&mut buf).unwrap().unwrap();
+//! assert_eq!(bucket.header.command, 1007);
+//! assert!(bucket.header.is_response());
+//! assert!(buf.is_empty());
+//! ```
+
+use crate::{limits::Limits, Error, Result, Section};
+use bytes::{Buf, BufMut, BytesMut};
+use std::convert::TryFrom;
+
+pub const LEVIN_SIGNATURE: u64 = 0x0101_0101_0101_2101;
+pub const LEVIN_PROTOCOL_VERSION: u32 = 1;
+pub const LEVIN_PACKET_REQUEST: u32 = 0x01;
+pub const LEVIN_PACKET_RESPONSE: u32 = 0x02;
+pub const LEVIN_PACKET_BEGIN: u32 = 0x04;
+pub const LEVIN_PACKET_END: u32 = 0x08;
+pub const LEVIN_OK: i32 = 0;
+pub const BUCKET_HEADER_LENGTH: usize = 8 + 8 + 1 + 4 + 4 + 4 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketHeader {
+    pub signature: u64,
+    /// Length of the payload following the header.
+    pub cb: u64,
+    pub have_to_return_data: bool,
+    pub command: u32,
+    pub return_code: i32,
+    pub flags: u32,
+    pub protocol_version: u32,
+}
+
+impl BucketHeader {
+    /// The header of a request, `have_to_return_data` is unset for
+    /// notifications.
+    pub fn request(command: u32, cb: u64, have_to_return_data: bool) -> Self {
+        BucketHeader {
+            signature: LEVIN_SIGNATURE,
+            cb,
+            have_to_return_data,
+            command,
+            return_code: LEVIN_OK,
+            flags: LEVIN_PACKET_REQUEST,
+            protocol_version: LEVIN_PROTOCOL_VERSION,
+        }
+    }
+
+    /// The header of a response to `command`.
+    pub fn response(command: u32, cb: u64, return_code: i32) -> Self {
+        BucketHeader {
+            signature: LEVIN_SIGNATURE,
+            cb,
+            have_to_return_data: false,
+            command,
+            return_code,
+            flags: LEVIN_PACKET_RESPONSE,
+            protocol_version: LEVIN_PROTOCOL_VERSION,
+        }
+    }
+
+    pub fn is_request(&self) -> bool {
+        self.flags & LEVIN_PACKET_REQUEST != 0
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & LEVIN_PACKET_RESPONSE != 0
+    }
+
+    /// Reads the header, checking its signature.
+    pub fn read<B: Buf>(buf: &mut B) -> Result<Self> {
+        ensure_eof!(buf, BUCKET_HEADER_LENGTH);
+
+        let header = BucketHeader {
+            signature: buf.get_u64_le(),
+            cb: buf.get_u64_le(),
+            have_to_return_data: buf.get_u8() != 0,
+            command: buf.get_u32_le(),
+            return_code: buf.get_i32_le(),
+            flags: buf.get_u32_le(),
+            protocol_version: buf.get_u32_le(),
+        };
+
+        if header.signature == LEVIN_SIGNATURE {
+            Ok(header)
+        } else {
+            Err(Error::InvalidBucketHeader)
+        }
+    }
+
+    pub fn write(&self, buf: &mut BytesMut) {
+        buf.reserve(BUCKET_HEADER_LENGTH);
+        buf.put_u64_le(self.signature);
+        buf.put_u64_le(self.cb);
+        buf.put_u8(self.have_to_return_data as u8);
+        buf.put_u32_le(self.command);
+        buf.put_i32_le(self.return_code);
+        buf.put_u32_le(self.flags);
+        buf.put_u32_le(self.protocol_version);
+    }
+}
+
+/// A bucket header and the section it carries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bucket {
+    pub header: BucketHeader,
+    pub payload: Section,
+}
+
+impl Bucket {
+    /// A request expecting a response.
+    pub fn request(command: u32, payload: Section) -> Self {
+        Bucket {
+            header: BucketHeader::request(command, payload_len(&payload), true),
+            payload,
+        }
+    }
+
+    /// A request without response.
+    pub fn notification(command: u32, payload: Section) -> Self {
+        Bucket {
+            header: BucketHeader::request(command, payload_len(&payload), false),
+            payload,
+        }
+    }
+
+    pub fn response(command: u32, return_code: i32, payload: Section) -> Self {
+        Bucket {
+            header: BucketHeader::response(command, payload_len(&payload), return_code),
+            payload,
+        }
+    }
+}
+
+fn payload_len(payload: &Section) -> u64 {
+    (crate::header::PORTABLE_STORAGE_BLOCK_HEADER_LENGTH + payload.encoded_len()) as u64
+}
+
+/// Writes `bucket`, setting the header length to the one of the encoded
+/// payload.
+pub fn write(buf: &mut BytesMut, bucket: &Bucket) {
+    let cb = payload_len(&bucket.payload);
+    buf.reserve(BUCKET_HEADER_LENGTH + cb as usize);
+    BucketHeader {
+        cb,
+        ..bucket.header
+    }
+    .write(buf);
+    crate::write(buf, &bucket.payload);
+}
+
+/// Reads a bucket within monerod's default limits, its payload must be
+/// exactly `cb` bytes long.
+pub fn read<B: Buf>(buf: &mut B) -> Result<Bucket> {
+    read_with_limits(buf, &Limits::monero_default())
+}
+
+/// Reads a bucket whose payload is within `limits`.
+pub fn read_with_limits<B: Buf>(buf: &mut B, limits: &Limits) -> Result<Bucket> {
+    let header = BucketHeader::read(buf)?;
+    let cb = match usize::try_from(header.cb) {
+        Ok(cb) if header.cb <= limits.max_packet_size => cb,
+        _ => return Err(Error::BucketTooBig(header.cb)),
+    };
+    ensure_eof!(buf, cb);
+    let payload = buf.copy_to_bytes(cb);
+    limits.check(&payload)?;
+    let (payload, len) = crate::read_from_slice(&payload)?;
+    if len != cb {
+        return Err(Error::InvalidBucketHeader);
+    }
+
+    Ok(Bucket { header, payload })
+}
+
+/// Frames buckets on a stream of bytes.
+///
+/// [`decode`](Codec::decode) takes a whole bucket from the front of the
+/// buffer once it's complete, and leaves the buffer untouched otherwise, so
+/// it fits decoders reading from sockets. Buckets are checked against
+/// [`Limits::monero_initial`], the limits of a connection that hasn't
+/// completed its handshake, unless other limits are given. Raise them with
+/// [`set_limits`](Codec::set_limits) once the handshake is done.
+#[derive(Debug, Clone)]
+pub struct Codec {
+    limits: Limits,
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Codec::new()
+    }
+}
+
+impl Codec {
+    pub fn new() -> Self {
+        Codec::with_limits(Limits::monero_initial())
+    }
+
+    pub fn with_limits(limits: Limits) -> Self {
+        Codec { limits }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Changes the limits of the next buckets, as monerod raises the packet
+    /// size limit once the handshake is done.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Takes the next bucket from `src`, `None` if it isn't complete yet.
+    pub fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Bucket>> {
+        if src.len() < BUCKET_HEADER_LENGTH {
+            return Ok(None);
+        }
+
+        let header = BucketHeader::read(&mut &src[..])?;
+        let len = match usize::try_from(header.cb) {
+            Ok(cb) if header.cb <= self.limits.max_packet_size => BUCKET_HEADER_LENGTH + cb,
+            _ => return Err(Error::BucketTooBig(header.cb)),
+        };
+        // Nothing is reserved for the rest of the bucket, the buffer only
+        // grows as its bytes arrive, so a header alone can't make the
+        // connection hold on to `max_packet_size` bytes.
+        if src.len() < len {
+            return Ok(None);
+        }
+
+        read_with_limits(&mut src.split_to(len), &self.limits).map(Some)
+    }
+
+    pub fn encode(&mut self, bucket: &Bucket, dst: &mut BytesMut) {
+        write(dst, bucket)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::StorageEntry;
+
+    fn ping() -> Bucket {
+        let mut payload = Section::new();
+        payload.insert("status".to_owned(), StorageEntry::Buf(b"OK"[..].into()));
+        payload.insert("peer_id".to_owned(), StorageEntry::U64(42));
+        Bucket::response(1003, LEVIN_OK, payload)
+    }
+
+    #[test]
+    fn header() {
+        let header = BucketHeader::request(1001, 10, true);
+        let mut buf = BytesMut::new();
+        header.write(&mut buf);
+        assert_eq!(buf.len(), BUCKET_HEADER_LENGTH);
+        assert_eq!(buf[..8], [0x01, 0x21, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01]);
+        assert_eq!(BucketHeader::read(&mut &buf[..]).unwrap(), header);
+        assert!(header.is_request() && !header.is_response());
+
+        buf[0] = 0;
+        assert!(matches!(
+            BucketHeader::read(&mut &buf[..]),
+            Err(Error::InvalidBucketHeader)
+        ));
+    }
+
+    #[test]
+    fn codec() {
+        let mut codec = Codec::new();
+        let mut buf = BytesMut::new();
+        codec.encode(&ping(), &mut buf);
+        codec.encode(&ping(), &mut buf);
+        let len = buf.len() / 2;
+
+        let mut stream = BytesMut::new();
+        stream.extend_from_slice(&buf[..len - 1]);
+        assert_eq!(codec.decode(&mut stream).unwrap(), None);
+        stream.extend_from_slice(&buf[len - 1..]);
+        assert_eq!(codec.decode(&mut stream).unwrap(), Some(ping()));
+        assert_eq!(codec.decode(&mut stream).unwrap(), Some(ping()));
+        assert!(stream.is_empty());
+
+        let mut codec = Codec::with_limits(Limits {
+            max_fields: 1,
+            ..Limits::monero_default()
+        });
+        assert!(matches!(
+            codec.decode(&mut buf.clone()),
+            Err(Error::LimitExceeded {
+                limit: "fields",
+                ..
+            })
+        ));
+        codec.set_limits(Limits {
+            max_packet_size: 8,
+            ..Limits::monero_default()
+        });
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(Error::BucketTooBig(_))
+        ));
+    }
+
+    #[test]
+    fn partial_bucket() {
+        let mut codec = Codec::with_limits(Limits::monero_default());
+        let mut buf = BytesMut::new();
+        BucketHeader::request(1001, 50_000_000, true).write(&mut buf);
+
+        let capacity = buf.capacity();
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(buf.capacity(), capacity);
+        assert_eq!(buf.len(), BUCKET_HEADER_LENGTH);
+    }
+
+    #[test]
+    fn initial_limits() {
+        let mut codec = Codec::new();
+        assert_eq!(codec.limits(), &Limits::monero_initial());
+
+        let header = BucketHeader::request(1001, crate::limits::INITIAL_MAX_PACKET_SIZE + 1, true);
+        let mut buf = BytesMut::new();
+        header.write(&mut buf);
+        assert!(matches!(
+            codec.decode(&mut buf.clone()),
+            Err(Error::BucketTooBig(_))
+        ));
+
+        codec.set_limits(Limits::monero_default());
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    }
+}
@@ -15,33 +15,126 @@
 
 use bytes::{Buf, BufMut, BytesMut};
 use linked_hash_map::LinkedHashMap;
-use std::{convert::TryFrom, ops::Index};
+use std::{convert::TryFrom, mem, ops::Index};
 use thiserror::Error;
 
+// Lets the code generated by `portable-storage-derive` refer to this crate
+// by name from within it.
+extern crate self as portable_storage;
+
+#[cfg(feature = "serde")]
 pub mod de;
+#[cfg(feature = "serde")]
 pub mod ser;
 
-pub use de::from_section;
-pub use ser::to_section;
+#[cfg(feature = "serde")]
+pub use de::{
+    from_bytes, from_bytes_seed, from_bytes_with, from_section, from_section_with,
+    from_storage_bytes,
+};
+#[cfg(feature = "derive")]
+pub use portable_storage_derive::StorageSection;
+#[cfg(feature = "serde")]
+pub use ser::{to_section, to_section_with, to_storage_bytes};
+
+pub use buf::StorageBuf;
 
 #[macro_export]
 macro_rules! ensure_eof {
     ($buf:expr, $needed:expr) => {
         if $buf.remaining() < $needed {
-            return Err($crate::Error::UnexpectedEof { needed: $needed });
+            return Err($crate::Error::eof($needed, $buf.remaining()));
         }
     };
 }
 
+#[cfg(feature = "alloc-counter")]
+pub mod alloc_counter;
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+pub mod borrowed;
+mod buf;
+pub mod codec;
+pub mod codegen;
+pub mod dedup;
+pub mod diff;
+#[cfg(feature = "differential")]
+pub mod differential;
+pub mod encoding;
+#[cfg(feature = "erased-serde")]
+pub mod erased;
+pub mod explain;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "file")]
+pub mod file;
+pub mod flatten;
+pub mod handler;
 pub mod header;
+pub mod infer;
+pub mod interner;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "levin")]
+pub mod levin;
+pub mod limits;
+pub mod multimap;
+pub mod numeric;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod patch;
+pub mod pool;
+pub mod profile;
+#[cfg(kani)]
+mod proofs;
+#[cfg(feature = "proptest")]
+pub mod proptest;
 pub mod raw_size;
+pub mod redact;
+pub mod registry;
+pub mod schema;
+pub mod select;
+mod skip;
+pub mod spans;
+#[cfg(feature = "synthetic-vectors")]
+pub mod testvectors;
+pub mod text;
+#[cfg(feature = "toml")]
+pub mod toml;
+pub mod tracked;
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+pub mod transcode;
+#[cfg(feature = "serde")]
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod wire;
+#[cfg(feature = "yaml")]
+pub mod yaml;
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// Errors of this crate, grouped by [`category`](Error::category) so callers
+/// can pick a policy without matching every variant.
 #[derive(Debug, Clone, Error)]
+#[non_exhaustive]
 pub enum Error {
-    #[error("reached EOF, needed {}", needed)]
-    UnexpectedEof { needed: usize },
+    #[error(
+        "reached EOF{}{}, needed {} bytes, {} available",
+        eof_offset(offset),
+        eof_path(path),
+        needed,
+        available
+    )]
+    UnexpectedEof {
+        needed: usize,
+        available: usize,
+        /// Absolute offset of what couldn't be read, when known.
+        offset: Option<usize>,
+        /// Path of the value being read, e.g. `peers[3].id`, empty when
+        /// unknown or at the root.
+        path: String,
+    },
     #[error("the header isn't valid")]
     InvalidHeader,
     #[error("the storage entry serialize type isn't valid ({:X})", _0)]
@@ -50,8 +143,137 @@ pub enum Error {
     InvalidArrayType(u8),
     #[error("the storage entry size is too big for this machine ({})", _0)]
     StorageEntryTooBig(u64),
+    #[error("the length is too big to be stored as a raw size ({})", _0)]
+    LengthOverflow(u64),
     #[error("wrong type sequence")]
     WrongTypeSequence,
+    #[error("expected a storage entry of type {:X}, found {:X}", expected, found)]
+    UnexpectedType { expected: u8, found: u8 },
+    #[error("conversion failed: {}", _0)]
+    Conversion(String),
+    #[error("the levin bucket header isn't valid")]
+    InvalidBucketHeader,
+    #[error("the levin bucket is too big ({})", _0)]
+    BucketTooBig(u64),
+    #[error("the {} limit ({}) was exceeded", limit, max)]
+    LimitExceeded { limit: &'static str, max: u64 },
+    #[error("the raw size {} isn't in its shortest form", _0)]
+    NonCanonicalSize(u64),
+    #[error("the key `{}` is duplicated", _0)]
+    DuplicateKey(String),
+    #[error("the key `{}` wasn't found", _0)]
+    KeyNotFound(String),
+    #[error("the selector `{}` isn't valid", _0)]
+    InvalidSelector(String),
+    #[error("{} isn't supported", _0)]
+    Unsupported(String),
+    #[error("checksum mismatch, expected {:08x}, found {:08x}", expected, found)]
+    ChecksumMismatch { expected: u32, found: u32 },
+    #[error("the entry name is {} bytes long, at most 255 fit", _0)]
+    NameTooLong(usize),
+}
+
+/// Broad kinds of [`Error`](enum@Error)s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// The input ended early, more data may fix it.
+    Eof,
+    /// The input is malformed or doesn't have the expected layout.
+    Format,
+    /// The input is bigger or more nested than allowed.
+    Limits,
+    /// The operation isn't supported for these arguments.
+    Unsupported,
+}
+
+fn eof_offset(offset: &Option<usize>) -> String {
+    match offset {
+        Some(offset) => format!(" at offset {}", offset),
+        None => String::new(),
+    }
+}
+
+fn eof_path(path: &str) -> String {
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!(" while reading `{}`", path)
+    }
+}
+
+impl Error {
+    /// An `UnexpectedEof` error without its offset and path.
+    #[doc(hidden)]
+    pub fn eof(needed: usize, available: usize) -> Error {
+        Error::UnexpectedEof {
+            needed,
+            available,
+            offset: None,
+            path: String::new(),
+        }
+    }
+
+    /// Adds the absolute offset to an `UnexpectedEof` error lacking one.
+    pub(crate) fn at_offset(mut self, at: usize) -> Error {
+        if let Error::UnexpectedEof { ref mut offset, .. } = self {
+            offset.get_or_insert(at);
+        }
+        self
+    }
+
+    /// Prefixes the path of an `UnexpectedEof` error with a key.
+    pub(crate) fn in_key(mut self, key: &str) -> Error {
+        if let Error::UnexpectedEof { ref mut path, .. } = self {
+            *path = match path.chars().next() {
+                None => key.to_owned(),
+                Some('[') => format!("{}{}", key, path),
+                Some(_) => format!("{}.{}", key, path),
+            };
+        }
+        self
+    }
+
+    /// Prefixes the path of an `UnexpectedEof` error with an array index.
+    pub(crate) fn in_element(mut self, index: usize) -> Error {
+        if let Error::UnexpectedEof { ref mut path, .. } = self {
+            *path = match path.chars().next() {
+                None | Some('[') => format!("[{}]{}", index, path),
+                Some(_) => format!("[{}].{}", index, path),
+            };
+        }
+        self
+    }
+
+    /// The category of this error. The category of a variant never changes,
+    /// new variants go in the existing categories when they fit.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::UnexpectedEof { .. } => ErrorCategory::Eof,
+            Error::InvalidHeader
+            | Error::InvalidSerializeType(_)
+            | Error::InvalidArrayType(_)
+            | Error::WrongTypeSequence
+            | Error::UnexpectedType { .. }
+            | Error::Conversion(_)
+            | Error::InvalidBucketHeader
+            | Error::NonCanonicalSize(_)
+            | Error::DuplicateKey(_)
+            | Error::KeyNotFound(_)
+            | Error::ChecksumMismatch { .. } => ErrorCategory::Format,
+            Error::StorageEntryTooBig(_)
+            | Error::LengthOverflow(_)
+            | Error::BucketTooBig(_)
+            | Error::LimitExceeded { .. }
+            | Error::NameTooLong(_) => ErrorCategory::Limits,
+            Error::InvalidSelector(_) | Error::Unsupported(_) => ErrorCategory::Unsupported,
+        }
+    }
+
+    /// Shorthand for checking whether the input was truncated.
+    pub fn is_eof(&self) -> bool {
+        self.category() == ErrorCategory::Eof
+    }
 }
 
 const SERIALIZE_TYPE_INT64: u8 = 1;
@@ -69,7 +291,64 @@ const SERIALIZE_TYPE_OBJECT: u8 = 12;
 const SERIALIZE_TYPE_ARRAY: u8 = 13;
 const SERIALIZE_FLAG_ARRAY: u8 = 0x80;
 
-#[derive(Debug, Clone)]
+/// The type of a storage entry, as written on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum SerializeType {
+    I64 = SERIALIZE_TYPE_INT64,
+    I32 = SERIALIZE_TYPE_INT32,
+    I16 = SERIALIZE_TYPE_INT16,
+    I8 = SERIALIZE_TYPE_INT8,
+    U64 = SERIALIZE_TYPE_UINT64,
+    U32 = SERIALIZE_TYPE_UINT32,
+    U16 = SERIALIZE_TYPE_UINT16,
+    U8 = SERIALIZE_TYPE_UINT8,
+    Double = SERIALIZE_TYPE_DOUBLE,
+    Buf = SERIALIZE_TYPE_STRING,
+    Bool = SERIALIZE_TYPE_BOOL,
+    Section = SERIALIZE_TYPE_OBJECT,
+    Array = SERIALIZE_TYPE_ARRAY,
+}
+
+impl SerializeType {
+    /// Returns the type for a serialize type byte, without the array flag.
+    pub fn from_u8(serialize_type: u8) -> Option<SerializeType> {
+        Some(match serialize_type {
+            SERIALIZE_TYPE_INT64 => SerializeType::I64,
+            SERIALIZE_TYPE_INT32 => SerializeType::I32,
+            SERIALIZE_TYPE_INT16 => SerializeType::I16,
+            SERIALIZE_TYPE_INT8 => SerializeType::I8,
+            SERIALIZE_TYPE_UINT64 => SerializeType::U64,
+            SERIALIZE_TYPE_UINT32 => SerializeType::U32,
+            SERIALIZE_TYPE_UINT16 => SerializeType::U16,
+            SERIALIZE_TYPE_UINT8 => SerializeType::U8,
+            SERIALIZE_TYPE_DOUBLE => SerializeType::Double,
+            SERIALIZE_TYPE_STRING => SerializeType::Buf,
+            SERIALIZE_TYPE_BOOL => SerializeType::Bool,
+            SERIALIZE_TYPE_OBJECT => SerializeType::Section,
+            SERIALIZE_TYPE_ARRAY => SerializeType::Array,
+            _ => return None,
+        })
+    }
+}
+
+impl From<SerializeType> for u8 {
+    fn from(serialize_type: SerializeType) -> u8 {
+        serialize_type as u8
+    }
+}
+
+impl std::fmt::Display for SerializeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(explain::type_name(*self as u8))
+    }
+}
+
+/// How many bytes a [`StorageBuf`] holds without allocating, enough for
+/// hashes, keys and network IDs.
+pub const INLINE_BUF_LEN: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum StorageEntry {
     U64(u64),
     U32(u32),
@@ -81,24 +360,27 @@ pub enum StorageEntry {
     I8(i8),
     Double(f64),
     Bool(bool),
-    Buf(Vec<u8>),
+    Buf(StorageBuf),
     Array(Array),
     Section(Section),
 }
 
 impl StorageEntry {
-    fn read<B: Buf>(buf: &mut B) -> Result<StorageEntry> {
-        ensure_eof!(buf, 1);
-        let serialize_type = buf.get_u8();
-        if serialize_type & SERIALIZE_FLAG_ARRAY == SERIALIZE_FLAG_ARRAY {
-            let arr = Array::read::<B>(buf, serialize_type)?;
+    fn read<B: Buf, K: Keys>(buf: &mut B, keys: &mut K) -> Result<StorageEntry> {
+        let serialize_type = wire::read_type(buf)?;
+        if wire::is_array(serialize_type) {
+            let arr = Array::read::<B, K>(buf, serialize_type, keys)?;
             return Ok(StorageEntry::Array(arr));
         }
 
-        Self::read_entry_raw::<B>(buf, serialize_type)
+        Self::read_entry_raw::<B, K>(buf, serialize_type, keys)
     }
 
-    fn read_entry_raw<B: Buf>(buf: &mut B, serialize_type: u8) -> Result<StorageEntry> {
+    fn read_entry_raw<B: Buf, K: Keys>(
+        buf: &mut B,
+        serialize_type: u8,
+        keys: &mut K,
+    ) -> Result<StorageEntry> {
         let entry = match serialize_type {
             SERIALIZE_TYPE_INT64 => {
                 ensure_eof!(buf, 8);
@@ -137,23 +419,17 @@ impl StorageEntry {
                 StorageEntry::Double(buf.get_f64_le())
             }
             SERIALIZE_TYPE_STRING => {
-                let b = read_buf::<B>(buf)?;
+                let b = read_buf::<B, K>(buf, keys)?;
                 StorageEntry::Buf(b)
             }
             SERIALIZE_TYPE_BOOL => {
                 ensure_eof!(buf, 1);
                 StorageEntry::Bool(buf.get_u8() != 0)
             }
-            SERIALIZE_TYPE_OBJECT => StorageEntry::Section(Section::read::<B>(buf)?),
+            SERIALIZE_TYPE_OBJECT => StorageEntry::Section(Section::read::<B, K>(buf, keys)?),
             SERIALIZE_TYPE_ARRAY => {
-                ensure_eof!(buf, 1);
-
-                let serialize_type = buf.get_u8();
-                if serialize_type & SERIALIZE_FLAG_ARRAY != SERIALIZE_FLAG_ARRAY {
-                    return Err(Error::WrongTypeSequence);
-                }
-
-                let arr = Array::read::<B>(buf, serialize_type)?;
+                let serialize_type = wire::read_array_type(buf)?;
+                let arr = Array::read::<B, K>(buf, serialize_type, keys)?;
                 StorageEntry::Array(arr)
             }
             _ => {
@@ -166,71 +442,207 @@ impl StorageEntry {
 
     fn write(buf: &mut BytesMut, entry: &Self) {
         match entry {
-            StorageEntry::U64(v) => {
-                buf.reserve(9);
-                buf.put_u8(SERIALIZE_TYPE_UINT64);
-                buf.put_u64_le(*v);
-            }
-            StorageEntry::U32(v) => {
-                buf.reserve(5);
-                buf.put_u8(SERIALIZE_TYPE_UINT32);
-                buf.put_u32_le(*v);
-            }
-            StorageEntry::U16(v) => {
-                buf.reserve(3);
-                buf.put_u8(SERIALIZE_TYPE_UINT16);
-                buf.put_u16_le(*v);
-            }
-            StorageEntry::U8(v) => {
-                buf.reserve(2);
-                buf.put_u8(SERIALIZE_TYPE_UINT8);
-                buf.put_u8(*v);
-            }
-            StorageEntry::I64(v) => {
-                buf.reserve(9);
-                buf.put_u8(SERIALIZE_TYPE_INT64);
-                buf.put_i64_le(*v);
-            }
-            StorageEntry::I32(v) => {
-                buf.reserve(5);
-                buf.put_u8(SERIALIZE_TYPE_INT32);
-                buf.put_i32_le(*v);
-            }
-            StorageEntry::I16(v) => {
-                buf.reserve(3);
-                buf.put_u8(SERIALIZE_TYPE_INT16);
-                buf.put_i16_le(*v);
-            }
-            StorageEntry::I8(v) => {
-                buf.reserve(2);
-                buf.put_u8(SERIALIZE_TYPE_INT8);
-                buf.put_i8(*v);
-            }
-            StorageEntry::Double(v) => {
-                buf.reserve(9);
-                buf.put_u8(SERIALIZE_TYPE_DOUBLE);
-                buf.put_f64_le(*v);
-            }
-            StorageEntry::Bool(v) => {
-                buf.reserve(2);
-                buf.put_u8(SERIALIZE_TYPE_BOOL);
-                buf.put_u8(if !v { 0 } else { 1 });
-            }
-            StorageEntry::Buf(v) => {
-                buf.reserve(1);
-                buf.put_u8(SERIALIZE_TYPE_STRING);
-                write_buf(buf, v);
+            // Like epee, arrays are only prefixed with their flagged element
+            // type, `SERIALIZE_TYPE_ARRAY` isn't written.
+            StorageEntry::Array(v) => Array::write(buf, v),
+            _ => {
+                buf.put_u8(entry.serialize_type());
+                Self::write_raw(buf, entry);
             }
+        }
+    }
+
+    /// Length of the entry once written, serialize type included.
+    fn encoded_len(&self) -> usize {
+        match self {
+            StorageEntry::Array(v) => v.encoded_len(),
+            _ => 1 + self.raw_encoded_len(),
+        }
+    }
+
+    /// Length of the entry value once written without its serialize type.
+    fn raw_encoded_len(&self) -> usize {
+        match self {
+            StorageEntry::U64(_) | StorageEntry::I64(_) | StorageEntry::Double(_) => 8,
+            StorageEntry::U32(_) | StorageEntry::I32(_) => 4,
+            StorageEntry::U16(_) | StorageEntry::I16(_) => 2,
+            StorageEntry::U8(_) | StorageEntry::I8(_) | StorageEntry::Bool(_) => 1,
+            StorageEntry::Buf(v) => raw_size::encoded_len(v.len() as u64) + v.len(),
+            StorageEntry::Array(v) => v.encoded_len(),
+            StorageEntry::Section(v) => v.encoded_len(),
+        }
+    }
+
+    /// Writes the entry value without its serialize type, as done for array
+    /// elements.
+    fn write_raw(buf: &mut BytesMut, entry: &Self) {
+        match entry {
+            StorageEntry::U64(v) => buf.put_u64_le(*v),
+            StorageEntry::U32(v) => buf.put_u32_le(*v),
+            StorageEntry::U16(v) => buf.put_u16_le(*v),
+            StorageEntry::U8(v) => buf.put_u8(*v),
+            StorageEntry::I64(v) => buf.put_i64_le(*v),
+            StorageEntry::I32(v) => buf.put_i32_le(*v),
+            StorageEntry::I16(v) => buf.put_i16_le(*v),
+            StorageEntry::I8(v) => buf.put_i8(*v),
+            StorageEntry::Double(v) => buf.put_f64_le(*v),
+            StorageEntry::Bool(v) => buf.put_u8(if !v { 0 } else { 1 }),
+            StorageEntry::Buf(v) => write_buf(buf, v),
+            StorageEntry::Array(v) => Array::write(buf, v),
+            StorageEntry::Section(v) => Section::write(buf, v),
+        }
+    }
+
+    /// Parses this `StorageEntry::Buf` as an embedded section stored
+    /// without the storage block header.
+    pub fn read_embedded_section(&self) -> Result<Section> {
+        let mut buf = self.embedded_buf()?;
+        read_section(&mut buf)
+    }
+
+    /// Parses this `StorageEntry::Buf` as an embedded storage blob, including
+    /// the storage block header.
+    pub fn read_embedded_storage(&self) -> Result<Section> {
+        let mut buf = self.embedded_buf()?;
+        read(&mut buf)
+    }
+
+    /// Creates a `StorageEntry::Buf` holding `section` encoded without the
+    /// storage block header.
+    pub fn embed_section(section: &Section) -> StorageEntry {
+        let mut buf = BytesMut::new();
+        write_section(&mut buf, section);
+        StorageEntry::Buf(StorageBuf::from_slice(&buf))
+    }
+
+    /// Creates a `StorageEntry::Buf` holding `section` encoded as a full
+    /// storage blob, including the storage block header.
+    pub fn embed_storage(section: &Section) -> StorageEntry {
+        let mut buf = BytesMut::new();
+        write(&mut buf, section);
+        StorageEntry::Buf(StorageBuf::from_slice(&buf))
+    }
+
+    /// Returns the value of any integer entry that fits in a `u64`.
+    pub fn as_u64(&self) -> Result<u64> {
+        let v = match *self {
+            StorageEntry::U64(v) => return Ok(v),
+            StorageEntry::U32(v) => return Ok(v.into()),
+            StorageEntry::U16(v) => return Ok(v.into()),
+            StorageEntry::U8(v) => return Ok(v.into()),
+            StorageEntry::I64(v) => v,
+            StorageEntry::I32(v) => v.into(),
+            StorageEntry::I16(v) => v.into(),
+            StorageEntry::I8(v) => v.into(),
+            _ => return Err(self.unexpected(SERIALIZE_TYPE_UINT64)),
+        };
+        u64::try_from(v).map_err(|_| Error::Conversion(format!("{} doesn't fit in a u64", v)))
+    }
+
+    /// Returns the value of any integer entry that fits in an `i64`.
+    pub fn as_i64(&self) -> Result<i64> {
+        match *self {
+            StorageEntry::U64(v) => i64::try_from(v)
+                .map_err(|_| Error::Conversion(format!("{} doesn't fit in an i64", v))),
+            StorageEntry::U32(v) => Ok(v.into()),
+            StorageEntry::U16(v) => Ok(v.into()),
+            StorageEntry::U8(v) => Ok(v.into()),
+            StorageEntry::I64(v) => Ok(v),
+            StorageEntry::I32(v) => Ok(v.into()),
+            StorageEntry::I16(v) => Ok(v.into()),
+            StorageEntry::I8(v) => Ok(v.into()),
+            _ => Err(self.unexpected(SERIALIZE_TYPE_INT64)),
+        }
+    }
+
+    /// Returns the value of a `Double` entry, or of an integer entry that a
+    /// `f64` represents exactly.
+    pub fn as_f64(&self) -> Result<f64> {
+        match *self {
+            StorageEntry::Double(v) => Ok(v),
+            StorageEntry::U32(v) => Ok(v.into()),
+            StorageEntry::U16(v) => Ok(v.into()),
+            StorageEntry::U8(v) => Ok(v.into()),
+            StorageEntry::I32(v) => Ok(v.into()),
+            StorageEntry::I16(v) => Ok(v.into()),
+            StorageEntry::I8(v) => Ok(v.into()),
+            // Integers up to 2^53 in magnitude are exact.
+            StorageEntry::U64(v) if v <= 1 << 53 => Ok(v as f64),
+            StorageEntry::I64(v) if v.unsigned_abs() <= 1 << 53 => Ok(v as f64),
+            StorageEntry::U64(v) => Err(Error::Conversion(format!(
+                "{} can't be represented exactly as a f64",
+                v
+            ))),
+            StorageEntry::I64(v) => Err(Error::Conversion(format!(
+                "{} can't be represented exactly as a f64",
+                v
+            ))),
+            _ => Err(self.unexpected(SERIALIZE_TYPE_DOUBLE)),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool> {
+        match *self {
+            StorageEntry::Bool(v) => Ok(v),
+            _ => Err(self.unexpected(SERIALIZE_TYPE_BOOL)),
+        }
+    }
+
+    /// Returns the bytes of a `Buf` entry.
+    pub fn as_bytes(&self) -> Result<&[u8]> {
+        self.embedded_buf()
+    }
+
+    fn unexpected(&self, expected: u8) -> Error {
+        Error::UnexpectedType {
+            expected,
+            found: self.serialize_type(),
+        }
+    }
+
+    fn embedded_buf(&self) -> Result<&[u8]> {
+        match self {
+            StorageEntry::Buf(v) => Ok(v.as_slice()),
+            _ => Err(self.unexpected(SERIALIZE_TYPE_STRING)),
+        }
+    }
+
+    /// Heap memory held by the entry, see [`Section::memory_usage`].
+    fn heap_usage(&self) -> usize {
+        match self {
+            StorageEntry::Buf(v) if v.spilled() => v.capacity(),
             StorageEntry::Array(v) => {
-                buf.reserve(1);
-                buf.put_u8(SERIALIZE_TYPE_ARRAY);
-                Array::write(buf, v);
-            }
-            StorageEntry::Section(v) => {
-                buf.reserve(1);
-                buf.put_u8(SERIALIZE_TYPE_OBJECT);
-                Section::write(buf, v);
+                v.array.capacity() * mem::size_of::<StorageEntry>()
+                    + v.array.iter().map(Self::heap_usage).sum::<usize>()
             }
+            StorageEntry::Section(v) => v.memory_usage(),
+            _ => 0,
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        match self {
+            StorageEntry::Buf(v) => v.shrink_to_fit(),
+            StorageEntry::Array(v) => v.shrink_to_fit(),
+            StorageEntry::Section(v) => v.shrink_to_fit(),
+            _ => {}
+        }
+    }
+
+    fn share_bufs(&mut self) {
+        match self {
+            StorageEntry::Buf(v) => v.share(),
+            StorageEntry::Array(v) => v.share_bufs(),
+            StorageEntry::Section(v) => v.share_bufs(),
+            _ => {}
+        }
+    }
+
+    fn sort_keys(&mut self) {
+        match self {
+            StorageEntry::Section(section) => section.sort_keys(),
+            StorageEntry::Array(array) => array.array.iter_mut().for_each(Self::sort_keys),
+            _ => {}
         }
     }
 
@@ -253,7 +665,7 @@ impl StorageEntry {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Array {
     array: Vec<StorageEntry>,
     serialize_type: Option<u8>,
@@ -271,6 +683,21 @@ impl Array {
         }
     }
 
+    /// Creates an empty array of `element_kind` elements, so it keeps its
+    /// type even if nothing gets pushed.
+    pub fn new_typed(element_kind: SerializeType) -> Array {
+        Array {
+            array: Vec::new(),
+            serialize_type: Some(element_kind as u8 | SERIALIZE_FLAG_ARRAY),
+        }
+    }
+
+    /// The type of the elements, `None` for untyped empty arrays.
+    pub fn element_kind(&self) -> Option<SerializeType> {
+        self.serialize_type
+            .and_then(|t| SerializeType::from_u8(t & !SERIALIZE_FLAG_ARRAY))
+    }
+
     pub fn len(&self) -> usize {
         self.array.len()
     }
@@ -279,10 +706,49 @@ impl Array {
         self.len() == 0
     }
 
+    /// Splits the array in two at `at`, returning the elements from `at` on
+    /// in an array of the same type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is greater than the length.
+    pub fn split_off(&mut self, at: usize) -> Array {
+        Array {
+            array: self.array.split_off(at),
+            serialize_type: self.serialize_type,
+        }
+    }
+
+    /// Copies the elements into arrays of the same type holding `size`
+    /// elements each, the last one possibly fewer, e.g. to spread them over
+    /// several messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn chunks(&self, size: usize) -> impl Iterator<Item = Array> + '_ {
+        self.array.chunks(size).map(move |chunk| Array {
+            array: chunk.to_vec(),
+            serialize_type: self.serialize_type,
+        })
+    }
+
+    /// Releases the excess capacity of this array and of its elements.
+    pub fn shrink_to_fit(&mut self) {
+        self.array.shrink_to_fit();
+        self.array.iter_mut().for_each(StorageEntry::shrink_to_fit);
+    }
+
+    /// Shares the allocated buffers of the elements, see
+    /// [`Section::share_bufs`].
+    pub fn share_bufs(&mut self) {
+        self.array.iter_mut().for_each(StorageEntry::share_bufs);
+    }
+
     pub fn push(&mut self, entry: StorageEntry) -> std::result::Result<(), Error> {
         if let Some(serialize_type) = self.serialize_type {
             let entry_type = entry.serialize_type();
-            if serialize_type & SERIALIZE_FLAG_ARRAY != entry_type {
+            if serialize_type & !SERIALIZE_FLAG_ARRAY != entry_type {
                 return Err(Error::InvalidSerializeType(entry_type));
             }
         } else {
@@ -293,7 +759,7 @@ impl Array {
         Ok(())
     }
 
-    fn read<B: Buf>(buf: &mut B, mut serialize_type: u8) -> Result<Array> {
+    fn read<B: Buf, K: Keys>(buf: &mut B, mut serialize_type: u8, keys: &mut K) -> Result<Array> {
         let orig_serialize_type = serialize_type;
         if serialize_type & SERIALIZE_FLAG_ARRAY != SERIALIZE_FLAG_ARRAY {
             return Err(Error::InvalidArrayType(serialize_type));
@@ -301,8 +767,7 @@ impl Array {
             serialize_type &= !SERIALIZE_FLAG_ARRAY;
         }
 
-        let size = raw_size::read::<B>(buf)
-            .and_then(|size| usize::try_from(size).map_err(|_| Error::StorageEntryTooBig(size)))?;
+        let size = raw_size::read_usize::<B>(buf)?;
 
         let mut array = Array {
             array: Vec::new(),
@@ -312,21 +777,54 @@ impl Array {
         // uncommenting this, potential DDoS.
         // array.array.reserve(size);
 
-        for _ in 0..size {
-            array
-                .array
-                .push(StorageEntry::read_entry_raw::<B>(buf, serialize_type)?);
+        // Invalid arrays are decoded again sequentially, for the errors to
+        // carry the same offset and path.
+        #[cfg(feature = "rayon")]
+        if size >= parallel::THRESHOLD && buf.bytes().len() == buf.remaining() {
+            if let Some(parallel_keys) = keys.parallel() {
+                if let Some(elements) =
+                    parallel::read_elements::<B>(buf, serialize_type, size, parallel_keys)
+                {
+                    array.array = elements;
+                    return Ok(array);
+                }
+            }
+        }
+
+        for i in 0..size {
+            array.array.push(
+                StorageEntry::read_entry_raw::<B, K>(buf, serialize_type, keys)
+                    .map_err(|e| e.in_element(i))?,
+            );
         }
 
         Ok(array)
     }
 
+    fn encoded_len(&self) -> usize {
+        1 + raw_size::encoded_len(self.array.len() as u64)
+            + self
+                .array
+                .iter()
+                .map(StorageEntry::raw_encoded_len)
+                .sum::<usize>()
+    }
+
     fn write(buf: &mut BytesMut, array: &Array) {
-        buf.reserve(1);
-        buf.put_u8(array.serialize_type.unwrap());
+        // Empty arrays without a type are written as arrays of strings, the
+        // same as borrowed arrays. Nested ones included, every element of an
+        // array of arrays carries its own flagged type like in epee.
+        buf.put_u8(
+            array
+                .serialize_type
+                .unwrap_or(SERIALIZE_FLAG_ARRAY | SERIALIZE_TYPE_STRING),
+        );
         raw_size::write(buf, array.array.len() as u64);
+        if numeric::write_elements(buf, array) {
+            return;
+        }
         for entry in array.array.iter() {
-            StorageEntry::write(buf, &entry);
+            StorageEntry::write_raw(buf, entry);
         }
     }
 }
@@ -349,7 +847,22 @@ impl Index<usize> for Array {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+/// The error of [`Section::try_insert`], handing back what couldn't be
+/// inserted.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("the key `{}` is already in the section", name)]
+pub struct OccupiedError {
+    pub name: String,
+    pub entry: StorageEntry,
+}
+
+impl From<OccupiedError> for Error {
+    fn from(e: OccupiedError) -> Error {
+        Error::DuplicateKey(e.name)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Section {
     pub entries: LinkedHashMap<String, StorageEntry>,
 }
@@ -370,6 +883,23 @@ impl Section {
         self.entries.insert(name, entry.into());
     }
 
+    /// Inserts an entry unless the key is already present, in which case
+    /// the section is left untouched and the key and entry are returned in
+    /// the error. Returns the inserted entry.
+    pub fn try_insert<T: Into<StorageEntry>>(
+        &mut self,
+        name: String,
+        entry: T,
+    ) -> std::result::Result<&mut StorageEntry, OccupiedError> {
+        match self.entries.entry(name) {
+            linked_hash_map::Entry::Occupied(occupied) => Err(OccupiedError {
+                name: occupied.key().clone(),
+                entry: entry.into(),
+            }),
+            linked_hash_map::Entry::Vacant(vacant) => Ok(vacant.insert(entry.into())),
+        }
+    }
+
     /// Length of this section.
     pub fn len(&self) -> usize {
         self.entries.len()
@@ -379,11 +909,96 @@ impl Section {
         self.len() == 0
     }
 
-    fn read<B: Buf>(buf: &mut B) -> Result<Section> {
+    /// The keys starting with `prefix`, in order, e.g. the `m_` fields of a
+    /// section.
+    pub fn keys_with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .keys()
+            .map(String::as_str)
+            .filter(move |name| name.starts_with(prefix))
+    }
+
+    /// The entries for which `predicate` returns `true`, in order.
+    pub fn iter_matching<'a, P>(
+        &'a self,
+        mut predicate: P,
+    ) -> impl Iterator<Item = (&'a str, &'a StorageEntry)> + 'a
+    where
+        P: FnMut(&str, &StorageEntry) -> bool + 'a,
+    {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry))
+            .filter(move |(name, entry)| predicate(name, entry))
+    }
+
+    /// Removes every entry, keeping the allocated storage for the next ones.
+    pub fn clear(&mut self) {
+        self.entries.drain();
+    }
+
+    /// Orders the keys by their bytes, in this section and the nested ones,
+    /// the order epee writes them in.
+    pub fn sort_keys(&mut self) {
+        let mut entries: Vec<_> = self.entries.drain().collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, mut entry) in entries {
+            entry.sort_keys();
+            self.entries.insert(name, entry);
+        }
+    }
+
+    /// Inserts the keys of `template` missing from this section, with their
+    /// values in `template`. Sections present in both are completed the same
+    /// way, other entries are left as they are.
+    pub fn apply_defaults(&mut self, template: &Section) {
+        for (name, default) in template.entries.iter() {
+            match (self.entries.get_mut(name), default) {
+                (Some(StorageEntry::Section(section)), StorageEntry::Section(template)) => {
+                    section.apply_defaults(template)
+                }
+                (Some(_), _) => {}
+                (None, _) => {
+                    self.entries.insert(name.clone(), default.clone());
+                }
+            }
+        }
+    }
+
+    /// Builds a section keeping the order of `entries`. Duplicate keys keep
+    /// the last value, like when reading a blob.
+    pub fn from_entries<I>(entries: I) -> Section
+    where
+        I: IntoIterator<Item = (String, StorageEntry)>,
+    {
+        let entries = entries.into_iter();
+        let mut section = Section::with_capacity(entries.size_hint().0);
+        for (name, entry) in entries {
+            section.entries.insert(name, entry);
+        }
+        section
+    }
+
+    /// Returns the entries in order, as `(key, entry)` pairs.
+    pub fn into_entries(
+        self,
+    ) -> impl ExactSizeIterator<Item = (String, StorageEntry)> + DoubleEndedIterator {
+        self.entries.into_iter()
+    }
+
+    /// Returns the entries in order, as plain `(key, entry)` pairs.
+    pub fn into_vec_pairs(self) -> Vec<(String, StorageEntry)> {
+        self.entries.into_iter().collect()
+    }
+
+    fn read<B: Buf, K: Keys>(buf: &mut B, keys: &mut K) -> Result<Section> {
         let mut section = Section::new();
-        let count = raw_size::read::<B>(buf).and_then(|count| {
-            usize::try_from(count).map_err(|_| Error::StorageEntryTooBig(count))
-        })?;
+        section.read_entries::<B, K>(buf, keys)?;
+        Ok(section)
+    }
+
+    fn read_entries<B: Buf, K: Keys>(&mut self, buf: &mut B, keys: &mut K) -> Result<()> {
+        let count = raw_size::read_usize::<B>(buf)?;
 
         // TODO(jeandudey): this statement gives some performance, but it's
         // disabled since it can be easily abused because we don't have a way
@@ -393,23 +1008,75 @@ impl Section {
         // Gentle reminder: check if Monero suffers from this same problem to
         // avoid a DDoS by triggering OOM errors.
 
-        // section.entries.reserve(count);
+        // self.entries.reserve(count);
 
         for _ in 0..count {
-            let name = read_name::<B>(buf)?;
-            let entry = StorageEntry::read::<B>(buf)?;
-            section.entries.insert(name.clone(), entry);
+            let name = read_key::<B, K>(buf, keys)?;
+            let entry = StorageEntry::read::<B, K>(buf, keys).map_err(|e| e.in_key(&name))?;
+            self.entries.insert(name, entry);
         }
 
-        Ok(section)
+        Ok(())
+    }
+
+    /// Length of the section once written without the storage block header,
+    /// as done by [`write_section`].
+    pub fn encoded_len(&self) -> usize {
+        raw_size::encoded_len(self.entries.len() as u64)
+            + self
+                .entries
+                .iter()
+                .map(|(name, entry)| 1 + name.len() + entry.encoded_len())
+                .sum::<usize>()
+    }
+
+    /// Estimates the heap memory held by this section: the map and its
+    /// entries, keys, buffers and nested arrays and sections. The section
+    /// value itself isn't counted, add `size_of::<Section>()` for a boxed
+    /// one.
+    ///
+    /// Allocators round sizes up and keep their own bookkeeping, so the
+    /// actual usage is somewhat higher.
+    pub fn memory_usage(&self) -> usize {
+        // The map allocates a node per entry, holding the key, the value and
+        // the links, and a table of a key and a node pointer plus a control
+        // byte per bucket.
+        let node = mem::size_of::<(String, StorageEntry)>() + 2 * mem::size_of::<usize>();
+        let table = self.entries.capacity() * (2 * mem::size_of::<usize>() + 1);
+
+        table
+            + self
+                .entries
+                .iter()
+                .map(|(name, entry)| node + name.capacity() + entry.heap_usage())
+                .sum::<usize>()
+    }
+
+    /// Releases the excess capacity of this section and of its buffers,
+    /// arrays and nested sections.
+    pub fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit();
+        for (_, entry) in self.entries.iter_mut() {
+            entry.shrink_to_fit();
+        }
+    }
+
+    /// Moves the allocated buffers of this section and of its arrays and
+    /// nested sections behind reference counts, so cloning the section
+    /// copies its entries but not the bytes of its strings. Useful before
+    /// handing the same large message to several consumers.
+    pub fn share_bufs(&mut self) {
+        for (_, entry) in self.entries.iter_mut() {
+            entry.share_bufs();
+        }
     }
 
     fn write(buf: &mut BytesMut, section: &Self) {
         raw_size::write(buf, section.entries.len() as u64);
 
         for (name, entry) in section.entries.iter() {
-            write_name(buf, &*name);
-            StorageEntry::write(buf, &entry);
+            write_name(buf, name);
+            StorageEntry::write(buf, entry);
         }
     }
 }
@@ -424,6 +1091,38 @@ impl IntoIterator for Section {
     }
 }
 
+/// Builds a section keeping the order of `pairs`. Duplicate keys keep the
+/// last value, like when reading a blob.
+impl From<Vec<(String, StorageEntry)>> for Section {
+    fn from(pairs: Vec<(String, StorageEntry)>) -> Section {
+        Section::from_entries(pairs)
+    }
+}
+
+/// Reads a storage blob, header included. The blob must span the whole
+/// slice, use [`read_from_slice`] to read one followed by other data.
+impl TryFrom<&[u8]> for Section {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Section> {
+        let (section, read) = read_from_slice(data)?;
+        if read != data.len() {
+            return Err(Error::Conversion(format!(
+                "{} bytes left after the storage blob",
+                data.len() - read
+            )));
+        }
+        Ok(section)
+    }
+}
+
+/// Writes a storage blob, header included, like [`write_to_vec`].
+impl From<&Section> for Vec<u8> {
+    fn from(section: &Section) -> Vec<u8> {
+        write_to_vec(section)
+    }
+}
+
 impl Index<&'static str> for Section {
     type Output = StorageEntry;
 
@@ -433,46 +1132,841 @@ impl Index<&'static str> for Section {
 }
 
 pub fn read<B: Buf>(buf: &mut B) -> Result<Section> {
-    header::StorageBlockHeader::read::<B>(buf)?;
-    Section::read::<B>(buf)
+    read_with::<B>(buf, header::HeaderValidation::default())
+}
+
+/// Reads a storage blob checking its header with the given validation mode.
+pub fn read_with<B: Buf>(buf: &mut B, validation: header::HeaderValidation) -> Result<Section> {
+    with_offset(buf, |buf| {
+        header::StorageBlockHeader::read_with::<B>(buf, validation)?;
+        Section::read::<B, _>(buf, &mut FreshKeys)
+    })
 }
 
+/// Writes a storage blob.
+///
+/// # Panics
+///
+/// If an entry name is longer than 255 bytes, the length doesn't fit in its
+/// byte. Sections built from JSON, text or serde reject such names with
+/// [`Error::NameTooLong`] instead.
 pub fn write(buf: &mut BytesMut, section: &Section) {
+    buf.reserve(header::PORTABLE_STORAGE_BLOCK_HEADER_LENGTH + section.encoded_len());
     header::StorageBlockHeader::write(buf);
+    write_section(buf, section);
+}
+
+/// Reads a storage blob taking its keys from `interner`, see the
+/// [`interner`] module.
+pub fn read_interned<B: Buf>(buf: &mut B, interner: &mut interner::Interner) -> Result<Section> {
+    with_offset(buf, |buf| {
+        header::StorageBlockHeader::read::<B>(buf)?;
+        Section::read::<B, _>(buf, interner)
+    })
+}
+
+/// Reads a storage blob sharing the bytes of identical strings through
+/// `dedup`, see the [`dedup`] module.
+pub fn read_dedup<B: Buf>(buf: &mut B, dedup: &mut dedup::Dedup) -> Result<Section> {
+    with_offset(buf, |buf| {
+        header::StorageBlockHeader::read::<B>(buf)?;
+        Section::read::<B, _>(buf, dedup)
+    })
+}
+
+/// Reads a storage blob with its allocated buffers shared, so clones of the
+/// section are cheap, see [`Section::share_bufs`].
+pub fn read_shared<B: Buf>(buf: &mut B) -> Result<Section> {
+    with_offset(buf, |buf| {
+        header::StorageBlockHeader::read::<B>(buf)?;
+        Section::read::<B, _>(buf, &mut SharedBufs)
+    })
+}
+
+/// Reads a storage blob reporting its contents to `handler` instead of
+/// building a section, see the [`handler`] module.
+pub fn read_with_handler<B, H>(buf: &mut B, handler: &mut H) -> Result<()>
+where
+    B: Buf,
+    H: handler::StorageHandler + ?Sized,
+{
+    with_offset(buf, |buf| {
+        header::StorageBlockHeader::read::<B>(buf)?;
+        handler::section(buf, handler)
+    })
+}
+
+/// Reads a storage blob after checking it against `limits`, see the
+/// [`limits`] module.
+pub fn read_with_limits(data: &[u8], limits: &limits::Limits) -> Result<Section> {
+    limits.check(data)?;
+    read(&mut &data[..])
+}
+
+/// Reads a storage blob from the start of `data`, returning the section and
+/// the number of bytes it took.
+pub fn read_from_slice(data: &[u8]) -> Result<(Section, usize)> {
+    let mut buf = data;
+    let section = read(&mut buf)?;
+    Ok((section, data.len() - buf.len()))
+}
+
+/// Writes `section` as a storage blob into a new vector.
+pub fn write_to_vec(section: &Section) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    write(&mut buf, section);
+    buf.to_vec()
+}
+
+/// Writes each section as a storage blob, back to back, reserving the space
+/// for all of them at once. Each message takes
+/// [`PORTABLE_STORAGE_BLOCK_HEADER_LENGTH`](header::PORTABLE_STORAGE_BLOCK_HEADER_LENGTH)
+/// plus [`Section::encoded_len`] bytes.
+pub fn write_batch(buf: &mut BytesMut, sections: &[Section]) {
+    buf.reserve(
+        sections
+            .iter()
+            .map(|section| header::PORTABLE_STORAGE_BLOCK_HEADER_LENGTH + section.encoded_len())
+            .sum(),
+    );
+    for section in sections {
+        header::StorageBlockHeader::write(buf);
+        Section::write(buf, section);
+    }
+}
+
+/// Reads a storage blob into `section`, replacing its entries but reusing
+/// its storage. On errors `section` holds the entries read so far.
+pub fn read_into<B: Buf>(buf: &mut B, section: &mut Section) -> Result<()> {
+    with_offset(buf, |buf| {
+        header::StorageBlockHeader::read::<B>(buf)?;
+        section.clear();
+        section.read_entries::<B, _>(buf, &mut FreshKeys)
+    })
+}
+
+/// Writes `section` as a storage blob into `buf`, replacing its contents but
+/// reusing its capacity.
+pub fn to_bytes_into(section: &Section, buf: &mut BytesMut) {
+    buf.clear();
+    write(buf, section);
+}
+
+/// Reads a section that isn't preceded by the storage block header.
+pub fn read_section<B: Buf>(buf: &mut B) -> Result<Section> {
+    with_offset(buf, |buf| Section::read::<B, _>(buf, &mut FreshKeys))
+}
+
+/// Runs `f` on `buf`, adding the offset reached from the start of `buf` to
+/// `UnexpectedEof` errors.
+fn with_offset<B: Buf, T, F>(buf: &mut B, f: F) -> Result<T>
+where
+    F: FnOnce(&mut B) -> Result<T>,
+{
+    let start = buf.remaining();
+    f(buf).map_err(|e| {
+        let offset = start - buf.remaining();
+        e.at_offset(offset)
+    })
+}
+
+/// Writes a section without the storage block header.
+pub fn write_section(buf: &mut BytesMut, section: &Section) {
+    buf.reserve(section.encoded_len());
     Section::write(buf, section);
 }
 
+/// Turns the names read from a blob into section keys, and the strings into
+/// buffers.
+trait Keys {
+    fn key(&mut self, name: &[u8]) -> String;
+
+    fn buf(&mut self, data: &[u8]) -> StorageBuf {
+        StorageBuf::from_slice(data)
+    }
+
+    /// Keys every thread can use to decode large arrays in parallel, `None`
+    /// for keys with state, which decode them sequentially.
+    #[cfg(feature = "rayon")]
+    fn parallel(&self) -> Option<parallel::StatelessKeys> {
+        None
+    }
+}
+
+/// Decodes every key anew.
+struct FreshKeys;
+
+impl Keys for FreshKeys {
+    fn key(&mut self, name: &[u8]) -> String {
+        String::from_utf8_lossy(name).into_owned()
+    }
+
+    #[cfg(feature = "rayon")]
+    fn parallel(&self) -> Option<parallel::StatelessKeys> {
+        Some(parallel::StatelessKeys::Fresh)
+    }
+}
+
+/// Decodes every key anew and shares every allocated buffer.
+struct SharedBufs;
+
+impl Keys for SharedBufs {
+    fn key(&mut self, name: &[u8]) -> String {
+        FreshKeys.key(name)
+    }
+
+    fn buf(&mut self, data: &[u8]) -> StorageBuf {
+        if data.len() <= INLINE_BUF_LEN {
+            StorageBuf::from_slice(data)
+        } else {
+            StorageBuf::shared(data.into())
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    fn parallel(&self) -> Option<parallel::StatelessKeys> {
+        Some(parallel::StatelessKeys::SharedBufs)
+    }
+}
+
 fn read_name<B: Buf>(buf: &mut B) -> Result<String> {
-    ensure_eof!(buf, 1);
-    let length = buf.get_u8() as usize;
-    ensure_eof!(buf, length);
+    read_key::<B, _>(buf, &mut FreshKeys)
+}
 
-    let s = String::from_utf8_lossy(&buf.bytes()[..length]).into_owned();
+fn read_key<B: Buf, K: Keys>(buf: &mut B, keys: &mut K) -> Result<String> {
+    let length = wire::read_name_len(buf)?;
+    let s = keys.key(&buf.bytes()[..length]);
     buf.advance(length);
     Ok(s)
 }
 
-fn read_buf<B: Buf>(buf: &mut B) -> Result<Vec<u8>> {
-    let length = raw_size::read::<B>(buf).and_then(|length| {
-        usize::try_from(length).map_err(|_| Error::StorageEntryTooBig(length))
-    })?;
+fn read_buf<B: Buf, K: Keys>(buf: &mut B, keys: &mut K) -> Result<StorageBuf> {
+    let length = raw_size::read_usize::<B>(buf)?;
     ensure_eof!(buf, length);
 
-    let mut b = Vec::with_capacity(length);
-    b.extend_from_slice(&buf.bytes()[..length]);
+    let b = keys.buf(&buf.bytes()[..length]);
     buf.advance(length);
     Ok(b)
 }
 
 fn write_buf(buf: &mut BytesMut, b: &[u8]) {
     raw_size::write(buf, b.len() as u64);
-
-    buf.reserve(b.len());
     buf.put(b);
 }
 
+/// Checks that `name` fits in an entry name, whose length is a single byte.
+pub(crate) fn check_name(name: &str) -> Result<()> {
+    if name.len() > u8::MAX as usize {
+        return Err(Error::NameTooLong(name.len()));
+    }
+    Ok(())
+}
+
 fn write_name(buf: &mut BytesMut, name: &str) {
-    buf.reserve(name.as_bytes().len() + 1);
-    buf.put_u8(name.as_bytes().len() as u8);
+    if let Err(error) = check_name(name) {
+        panic!("{}", error);
+    }
+    buf.put_u8(name.len() as u8);
     buf.put(name.as_bytes());
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_section() {
+        let mut inner = Section::new();
+        inner.insert("height".to_owned(), StorageEntry::U64(1337));
+
+        let mut outer = Section::new();
+        outer.insert("blob".to_owned(), StorageEntry::embed_section(&inner));
+        outer.insert("storage".to_owned(), StorageEntry::embed_storage(&inner));
+
+        let mut buf = BytesMut::new();
+        write(&mut buf, &outer);
+        let outer = read(&mut buf.freeze()).unwrap();
+
+        let section = outer["blob"].read_embedded_section().unwrap();
+        assert!(matches!(section["height"], StorageEntry::U64(1337)));
+
+        let storage = outer["storage"].read_embedded_storage().unwrap();
+        assert!(matches!(storage["height"], StorageEntry::U64(1337)));
+
+        assert!(matches!(
+            outer["blob"].read_embedded_storage(),
+            Err(Error::InvalidHeader)
+        ));
+    }
+
+    #[test]
+    fn untagged_array_elements() {
+        let mut array = Array::new();
+        array.push(StorageEntry::U16(0x0201)).unwrap();
+        array.push(StorageEntry::U16(0x0403)).unwrap();
+        let mut buf = BytesMut::new();
+        Array::write(&mut buf, &array);
+        let flagged = SERIALIZE_FLAG_ARRAY | SERIALIZE_TYPE_UINT16;
+        assert_eq!(buf[..], [flagged, 0x08, 0x01, 0x02, 0x03, 0x04][..]);
+
+        let mut array = Array::new();
+        array.push(StorageEntry::Section(Section::new())).unwrap();
+        let mut buf = BytesMut::new();
+        Array::write(&mut buf, &array);
+        let flagged = SERIALIZE_FLAG_ARRAY | SERIALIZE_TYPE_OBJECT;
+        assert_eq!(buf[..], [flagged, 0x04, 0x00][..]);
+    }
+
+    #[test]
+    fn array_entries() {
+        // Array entries start with their flagged element type, like in
+        // epee, without a `SERIALIZE_TYPE_ARRAY` byte in front.
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U8(1)).unwrap();
+        ids.push(StorageEntry::U8(2)).unwrap();
+        let mut section = Section::new();
+        section.insert("ids".to_owned(), StorageEntry::Array(ids));
+
+        let mut buf = BytesMut::new();
+        write(&mut buf, &section);
+        assert_eq!(
+            &buf[9..],
+            &[0x04, 0x03, b'i', b'd', b's', 0x88, 0x08, 1, 2][..]
+        );
+    }
+
+    #[test]
+    fn slices() {
+        let mut section = Section::new();
+        section.insert("height".to_owned(), StorageEntry::U64(1337));
+
+        let mut data = write_to_vec(&section);
+        let len = data.len();
+        data.extend_from_slice(b"trailing");
+        let (decoded, read) = read_from_slice(&data).unwrap();
+        assert_eq!(decoded, section);
+        assert_eq!(read, len);
+
+        assert!(matches!(
+            read_from_slice(&data[..len - 1]),
+            Err(Error::UnexpectedEof { .. })
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "the entry name is 256 bytes long")]
+    fn long_name() {
+        let mut section = Section::new();
+        section.insert("a".repeat(256), StorageEntry::Bool(true));
+        write_to_vec(&section);
+    }
+
+    #[test]
+    fn huge_sizes() {
+        // A string, an array and a section claiming the largest raw size,
+        // they must fail cleanly without trying to allocate.
+        let mut blobs = Vec::new();
+        for entry in &[
+            &[SERIALIZE_TYPE_STRING][..],
+            &[SERIALIZE_FLAG_ARRAY | SERIALIZE_TYPE_UINT8],
+            &[SERIALIZE_TYPE_OBJECT],
+        ] {
+            let mut buf = BytesMut::new();
+            header::StorageBlockHeader::write(&mut buf);
+            raw_size::write(&mut buf, 1);
+            write_name(&mut buf, "huge");
+            buf.put(*entry);
+            raw_size::write(&mut buf, raw_size::U64_MAX);
+            blobs.push(buf);
+        }
+
+        for blob in blobs {
+            let result = read(&mut &blob[..]);
+            if cfg!(target_pointer_width = "32") {
+                assert!(matches!(
+                    result,
+                    Err(Error::StorageEntryTooBig(raw_size::U64_MAX))
+                ));
+            } else {
+                assert!(matches!(result, Err(Error::UnexpectedEof { .. })));
+            }
+        }
+    }
+
+    #[test]
+    fn encoded_len() {
+        let mut inner = Section::new();
+        inner.insert("blob".to_owned(), StorageEntry::Buf(vec![7; 70].into()));
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U32(1)).unwrap();
+        ids.push(StorageEntry::U32(2)).unwrap();
+        let mut sections = Array::new();
+        sections.push(StorageEntry::Section(inner.clone())).unwrap();
+
+        let mut section = Section::new();
+        section.insert("ids".to_owned(), StorageEntry::Array(ids));
+        section.insert("inner".to_owned(), StorageEntry::Section(inner));
+        section.insert("sections".to_owned(), StorageEntry::Array(sections));
+        section.insert("flag".to_owned(), StorageEntry::Bool(true));
+
+        let mut buf = BytesMut::new();
+        write(&mut buf, &section);
+        assert_eq!(buf.len(), 9 + section.encoded_len());
+        assert_eq!(buf.capacity(), buf.len());
+    }
+
+    #[test]
+    fn arrays_of_arrays() {
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U32(1)).unwrap();
+        ids.push(StorageEntry::U32(2)).unwrap();
+        let mut names = Array::new();
+        names.push(StorageEntry::Buf(b"a".to_vec().into())).unwrap();
+        let mut matrix = Array::new();
+        matrix.push(StorageEntry::Array(ids)).unwrap();
+        matrix.push(StorageEntry::Array(names)).unwrap();
+
+        let mut section = Section::new();
+        section.insert("matrix".to_owned(), StorageEntry::Array(matrix));
+        let blob = write_to_vec(&section);
+        assert_eq!(blob.len(), 9 + section.encoded_len());
+        assert_eq!(
+            &blob[9..],
+            &[
+                0x04, 0x06, b'm', b'a', b't', b'r', b'i', b'x', 0x8d, 0x08, 0x86, 0x08, 1, 0, 0, 0,
+                2, 0, 0, 0, 0x8a, 0x04, 0x04, b'a',
+            ][..]
+        );
+        assert_eq!(read(&mut &blob[..]).unwrap(), section);
+
+        // epee also accepts an unflagged `SERIALIZE_TYPE_ARRAY` entry
+        // followed by the flagged element type.
+        let mut unflagged = blob.clone();
+        unflagged.insert(17, SERIALIZE_TYPE_ARRAY);
+        assert_eq!(read(&mut &unflagged[..]).unwrap(), section);
+        unflagged[18] = SERIALIZE_TYPE_UINT32;
+        assert!(matches!(
+            read(&mut &unflagged[..]),
+            Err(Error::WrongTypeSequence)
+        ));
+
+        // Untyped empty arrays are written as arrays of strings.
+        let mut matrix = Array::new();
+        matrix.push(StorageEntry::Array(Array::new())).unwrap();
+        let mut section = Section::new();
+        section.insert("matrix".to_owned(), StorageEntry::Array(matrix));
+        let blob = write_to_vec(&section);
+        assert_eq!(&blob[17..], &[0x8d, 0x04, 0x8a, 0x00][..]);
+        assert_eq!(blob.len(), 9 + section.encoded_len());
+        assert_eq!(write_to_vec(&read(&mut &blob[..]).unwrap()), blob);
+    }
+
+    #[test]
+    fn element_kind() {
+        let mut ids = Array::new_typed(SerializeType::U32);
+        assert_eq!(ids.element_kind(), Some(SerializeType::U32));
+        assert!(matches!(
+            ids.push(StorageEntry::U8(1)),
+            Err(Error::InvalidSerializeType(SERIALIZE_TYPE_UINT8))
+        ));
+        assert_eq!(Array::new().element_kind(), None);
+
+        let mut section = Section::new();
+        section.insert("ids".to_owned(), StorageEntry::Array(ids.clone()));
+        let section = read(&mut &write_to_vec(&section)[..]).unwrap();
+        assert_eq!(section["ids"], StorageEntry::Array(ids));
+
+        let mut matrix = Array::new();
+        matrix.push(StorageEntry::Array(Array::new())).unwrap();
+        assert_eq!(matrix.element_kind(), Some(SerializeType::Array));
+        assert_eq!(SerializeType::from_u8(12), Some(SerializeType::Section));
+        assert_eq!(SerializeType::from_u8(SERIALIZE_FLAG_ARRAY), None);
+        assert_eq!(SerializeType::Buf.to_string(), "string");
+    }
+
+    #[test]
+    fn push_after_first_element() {
+        // The element type is compared without the array flag.
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U16(1)).unwrap();
+        ids.push(StorageEntry::U16(2)).unwrap();
+        assert!(matches!(
+            ids.push(StorageEntry::U32(3)),
+            Err(Error::InvalidSerializeType(SERIALIZE_TYPE_UINT32))
+        ));
+        assert_eq!(ids.len(), 2);
+    }
+
+    #[test]
+    fn vec_pairs() {
+        let pairs = vec![
+            ("z".to_owned(), StorageEntry::U8(1)),
+            ("a".to_owned(), StorageEntry::Bool(true)),
+            ("m".to_owned(), StorageEntry::Section(Section::new())),
+        ];
+        let section = Section::from(pairs.clone());
+        assert_eq!(
+            section.entries.keys().collect::<Vec<_>>(),
+            vec!["z", "a", "m"]
+        );
+        assert_eq!(section.into_vec_pairs(), pairs);
+
+        let section = Section::from(vec![
+            ("id".to_owned(), StorageEntry::U8(1)),
+            ("id".to_owned(), StorageEntry::U8(2)),
+        ]);
+        assert_eq!(section.len(), 1);
+        assert_eq!(section["id"], StorageEntry::U8(2));
+    }
+
+    #[test]
+    fn entries() {
+        let section =
+            Section::from_entries((0..3u8).map(|i| (format!("k{}", 2 - i), StorageEntry::U8(i))));
+        let entries: Vec<_> = section.clone().into_entries().rev().collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("k0".to_owned(), StorageEntry::U8(2)),
+                ("k1".to_owned(), StorageEntry::U8(1)),
+                ("k2".to_owned(), StorageEntry::U8(0)),
+            ]
+        );
+        assert_eq!(section.into_entries().len(), 3);
+        assert_eq!(Section::from_entries(entries.into_iter().rev()).len(), 3);
+    }
+
+    #[test]
+    fn try_insert() {
+        let mut section = Section::new();
+        *section
+            .try_insert("height".to_owned(), StorageEntry::U64(1))
+            .unwrap() = StorageEntry::U64(2);
+        let error = section
+            .try_insert("height".to_owned(), StorageEntry::U64(3))
+            .unwrap_err();
+        assert_eq!(error.name, "height");
+        assert_eq!(error.entry, StorageEntry::U64(3));
+        assert_eq!(section["height"], StorageEntry::U64(2));
+        assert!(matches!(Error::from(error), Error::DuplicateKey(ref name) if name == "height"));
+    }
+
+    #[test]
+    fn share_bufs() {
+        let mut ids = Array::new();
+        ids.push(StorageEntry::Buf(vec![1; 1024].into())).unwrap();
+        let mut inner = Section::new();
+        inner.insert("ids".to_owned(), StorageEntry::Array(ids));
+        inner.insert("short".to_owned(), StorageEntry::Buf(vec![2; 8].into()));
+        let mut section = Section::new();
+        section.insert("inner".to_owned(), StorageEntry::Section(inner));
+        section.insert("blob".to_owned(), StorageEntry::Buf(vec![3; 64].into()));
+
+        fn shared(section: &Section) -> Vec<bool> {
+            let inner = match &section["inner"] {
+                StorageEntry::Section(inner) => inner,
+                _ => unreachable!(),
+            };
+            let id = match &inner["ids"] {
+                StorageEntry::Array(ids) => ids[0].clone(),
+                _ => unreachable!(),
+            };
+            [&id, &inner["short"], &section["blob"]]
+                .iter()
+                .map(|entry| match entry {
+                    StorageEntry::Buf(buf) => buf.is_shared(),
+                    _ => unreachable!(),
+                })
+                .collect()
+        }
+
+        let data = write_to_vec(&section);
+        let decoded = read_shared(&mut &data[..]).unwrap();
+        assert_eq!(decoded, section);
+        assert_eq!(shared(&decoded), [true, false, true]);
+
+        let copy = section.clone();
+        section.share_bufs();
+        assert_eq!(section, copy);
+        assert_eq!(shared(&section), [true, false, true]);
+        match (&section["blob"], &section.clone()["blob"]) {
+            (StorageEntry::Buf(a), StorageEntry::Buf(b)) => assert_eq!(a.as_ptr(), b.as_ptr()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut blob = Vec::with_capacity(256);
+        blob.extend_from_slice(&[1; 40]);
+        let mut ids = Array::with_capacity(64);
+        ids.push(StorageEntry::Buf(blob.into())).unwrap();
+        let mut inner = Section::with_capacity(32);
+        inner.insert("ids".to_owned(), StorageEntry::Array(ids));
+        let mut section = Section::with_capacity(32);
+        section.insert("inner".to_owned(), StorageEntry::Section(inner));
+
+        let before = section.memory_usage();
+        let copy = section.clone();
+        section.shrink_to_fit();
+        assert_eq!(section, copy);
+        assert!(section.memory_usage() < before - 256);
+        match &section["inner"] {
+            StorageEntry::Section(inner) => match &inner["ids"] {
+                StorageEntry::Array(ids) => assert_eq!(ids.array.capacity(), 1),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn slice_conversions() {
+        let mut section = Section::new();
+        section.insert("height".to_owned(), StorageEntry::U64(1337));
+
+        let mut data = Vec::from(&section);
+        assert_eq!(data, write_to_vec(&section));
+        assert_eq!(Section::try_from(&data[..]).unwrap(), section);
+
+        data.push(0);
+        assert!(matches!(
+            Section::try_from(&data[..]),
+            Err(Error::Conversion(_))
+        ));
+        assert!(Section::try_from(&data[..4]).unwrap_err().is_eof());
+    }
+
+    #[test]
+    fn numeric_accessors() {
+        assert_eq!(StorageEntry::U8(7).as_u64().unwrap(), 7);
+        assert_eq!(StorageEntry::I32(7).as_u64().unwrap(), 7);
+        assert!(matches!(
+            StorageEntry::I8(-1).as_u64(),
+            Err(Error::Conversion(_))
+        ));
+        assert_eq!(StorageEntry::I16(-3).as_i64().unwrap(), -3);
+        assert!(matches!(
+            StorageEntry::U64(u64::MAX).as_i64(),
+            Err(Error::Conversion(_))
+        ));
+
+        assert_eq!(StorageEntry::U32(u32::MAX).as_f64().unwrap(), 4294967295.0);
+        assert_eq!(
+            StorageEntry::I64(-(1 << 53)).as_f64().unwrap(),
+            -9007199254740992.0
+        );
+        assert!(matches!(
+            StorageEntry::U64((1 << 53) + 1).as_f64(),
+            Err(Error::Conversion(_))
+        ));
+
+        assert!(StorageEntry::Bool(true).as_bool().unwrap());
+        assert_eq!(
+            StorageEntry::Buf(b"ok".to_vec().into()).as_bytes().unwrap(),
+            b"ok"
+        );
+        assert!(matches!(
+            StorageEntry::Double(1.0).as_u64(),
+            Err(Error::UnexpectedType {
+                expected: SERIALIZE_TYPE_UINT64,
+                found: SERIALIZE_TYPE_DOUBLE,
+            })
+        ));
+        assert!(matches!(
+            StorageEntry::U8(1).as_bool(),
+            Err(Error::UnexpectedType {
+                expected: SERIALIZE_TYPE_BOOL,
+                found: SERIALIZE_TYPE_UINT8,
+            })
+        ));
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut section = Section::new();
+        assert_eq!(section.memory_usage(), 0);
+
+        section.insert("id".to_owned(), StorageEntry::U64(1));
+        let small = section.memory_usage();
+        assert!(small > mem::size_of::<(String, StorageEntry)>());
+
+        // Short buffers are stored inline, longer ones on the heap.
+        section.insert("hash".to_owned(), StorageEntry::Buf(vec![0; 32].into()));
+        let inline = section.memory_usage();
+        section.insert("hash".to_owned(), StorageEntry::Buf(vec![0; 4096].into()));
+        assert!(section.memory_usage() >= inline + 4096);
+
+        let mut peers = Array::new();
+        for _ in 0..100 {
+            peers.push(StorageEntry::Section(section.clone())).unwrap();
+        }
+        let mut outer = Section::new();
+        outer.insert("peers".to_owned(), StorageEntry::Array(peers));
+        assert!(outer.memory_usage() > 100 * section.memory_usage());
+    }
+
+    #[test]
+    fn filtered_keys() {
+        let mut section = Section::new();
+        section.insert("m_height".to_owned(), StorageEntry::U64(1));
+        section.insert("status".to_owned(), StorageEntry::U8(0));
+        section.insert("m_top_id".to_owned(), StorageEntry::Buf(vec![0; 32].into()));
+
+        let keys: Vec<&str> = section.keys_with_prefix("m_").collect();
+        assert_eq!(keys, ["m_height", "m_top_id"]);
+        assert_eq!(section.keys_with_prefix("x").count(), 0);
+
+        let integers: Vec<&str> = section
+            .iter_matching(|_, entry| entry.as_u64().is_ok())
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(integers, ["m_height", "status"]);
+    }
+
+    #[test]
+    fn split_arrays() {
+        let mut ids = Array::new();
+        for id in 0..5 {
+            ids.push(StorageEntry::U32(id)).unwrap();
+        }
+
+        let chunks: Vec<Array> = ids.chunks(2).collect();
+        assert_eq!(chunks.iter().map(Array::len).collect::<Vec<_>>(), [2, 2, 1]);
+        assert_eq!(chunks[2][0], StorageEntry::U32(4));
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.element_kind() == Some(SerializeType::U32)));
+
+        let rest = ids.split_off(5);
+        assert!(rest.is_empty());
+        assert_eq!(rest.element_kind(), Some(SerializeType::U32));
+        let rest = ids.split_off(3);
+        assert_eq!((ids.len(), rest.len()), (3, 2));
+        assert_eq!(rest[0], StorageEntry::U32(3));
+    }
+
+    #[test]
+    fn batch() {
+        let mut first = Section::new();
+        first.insert("height".to_owned(), StorageEntry::U64(1));
+        let mut second = Section::new();
+        second.insert("id".to_owned(), StorageEntry::Buf(vec![1; 40].into()));
+        let sections = [first, second];
+
+        let mut buf = BytesMut::new();
+        write_batch(&mut buf, &sections);
+        assert_eq!(buf.capacity(), buf.len());
+
+        let mut rest = &buf[..];
+        for section in &sections {
+            assert_eq!(&read(&mut rest).unwrap(), section);
+        }
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn embedded_not_a_buf() {
+        assert!(matches!(
+            StorageEntry::U8(1).read_embedded_section(),
+            Err(Error::UnexpectedType {
+                expected: SERIALIZE_TYPE_STRING,
+                found: SERIALIZE_TYPE_UINT8,
+            })
+        ));
+    }
+
+    #[test]
+    fn error_categories() {
+        let mut section = Section::new();
+        section.insert("id".to_owned(), StorageEntry::U8(1));
+        let blob = write_to_vec(&section);
+
+        let truncated = read(&mut &blob[..blob.len() - 1]).unwrap_err();
+        assert_eq!(truncated.category(), ErrorCategory::Eof);
+        assert!(truncated.is_eof());
+
+        let mut invalid = blob.clone();
+        invalid[blob.len() - 2] = 0x7f;
+        let invalid = read(&mut &invalid[..]).unwrap_err();
+        assert_eq!(invalid.category(), ErrorCategory::Format);
+
+        let limits = limits::Limits {
+            max_fields: 0,
+            ..limits::Limits::monero_default()
+        };
+        let limited = read_with_limits(&blob, &limits).unwrap_err();
+        assert_eq!(limited.category(), ErrorCategory::Limits);
+    }
+
+    #[test]
+    fn eof_diagnostics() {
+        let mut peer = Section::new();
+        peer.insert("id".to_owned(), StorageEntry::U64(1));
+        let mut peers = Array::new();
+        peers.push(StorageEntry::Section(peer.clone())).unwrap();
+        peers.push(StorageEntry::Section(peer)).unwrap();
+        let mut section = Section::new();
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+        let blob = write_to_vec(&section);
+
+        let error = read(&mut &blob[..blob.len() - 3]).unwrap_err();
+        match &error {
+            Error::UnexpectedEof {
+                needed: 8,
+                available: 5,
+                offset: Some(offset),
+                path,
+            } => {
+                assert_eq!(*offset, blob.len() - 8);
+                assert_eq!(path, "peers[1].id");
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "reached EOF at offset {} while reading `peers[1].id`, needed 8 bytes, 5 available",
+                blob.len() - 8
+            )
+        );
+
+        let error = read_section(&mut &blob[9..blob.len() - 3]).unwrap_err();
+        assert!(
+            matches!(error, Error::UnexpectedEof { offset: Some(o), .. } if o == blob.len() - 17)
+        );
+        assert_eq!(
+            read(&mut &blob[..4]).unwrap_err().to_string(),
+            "reached EOF at offset 0, needed 9 bytes, 4 available"
+        );
+    }
+
+    #[test]
+    fn apply_defaults() {
+        let mut template_data = Section::new();
+        template_data.insert("my_port".to_owned(), StorageEntry::U32(0));
+        template_data.insert("peer_id".to_owned(), StorageEntry::U64(0));
+        let mut template = Section::new();
+        template.insert("node_data".to_owned(), StorageEntry::Section(template_data));
+        template.insert("flags".to_owned(), StorageEntry::U32(1));
+
+        let mut node_data = Section::new();
+        node_data.insert("my_port".to_owned(), StorageEntry::U32(18080));
+        let mut section = Section::new();
+        section.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+        section.insert("flags".to_owned(), StorageEntry::U8(3));
+        section.apply_defaults(&template);
+
+        let mut node_data = Section::new();
+        node_data.insert("my_port".to_owned(), StorageEntry::U32(18080));
+        node_data.insert("peer_id".to_owned(), StorageEntry::U64(0));
+        let mut expected = Section::new();
+        expected.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+        expected.insert("flags".to_owned(), StorageEntry::U8(3));
+        assert_eq!(section, expected);
+
+        let mut empty = Section::new();
+        empty.apply_defaults(&template);
+        assert_eq!(empty, template);
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Limits
+//!
+//! Bounds on what a peer may send, matching the ones monerod enforces, so
+//! oversized or deeply nested messages are rejected before being decoded.
+//!
+//! ```rust
+//! use portable_storage::{limits::Limits, Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("height".to_owned(), StorageEntry::U64(1));
+//! let blob = portable_storage::write_to_vec(&section);
+//!
+//! let limits = Limits::monero_default();
+//! assert_eq!(portable_storage::read_with_limits(&blob, &limits).unwrap(), section);
+//!
+//! let limits = Limits { max_fields: 0, ..limits };
+//! assert!(portable_storage::read_with_limits(&blob, &limits).is_err());
+//! ```
+
+use crate::{
+    header::StorageBlockHeader, raw_size, wire, Error, Result, SERIALIZE_TYPE_ARRAY,
+    SERIALIZE_TYPE_BOOL, SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32,
+    SERIALIZE_TYPE_INT64, SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING,
+    SERIALIZE_TYPE_UINT16, SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use bytes::Buf;
+use std::collections::HashSet;
+
+/// Largest Levin packet, epee's `LEVIN_DEFAULT_MAX_PACKET_SIZE`.
+pub const MAX_PACKET_SIZE: u64 = 100_000_000;
+/// Largest Levin packet before the handshake completes, monerod's
+/// `LEVIN_INITIAL_MAX_PACKET_SIZE`.
+pub const INITIAL_MAX_PACKET_SIZE: u64 = 256 * 1024;
+/// How deep sections and arrays may nest, epee's
+/// `EPEE_PORTABLE_STORAGE_RECURSION_LIMIT_INTERNAL`.
+pub const MAX_DEPTH: usize = 100;
+/// Sections per message, from epee's `default_levin_limits`.
+pub const MAX_OBJECTS: usize = 8192;
+/// Section fields per message, from epee's `default_levin_limits`.
+pub const MAX_FIELDS: usize = 16384;
+/// Strings per message, from epee's `default_levin_limits`.
+pub const MAX_STRINGS: usize = 16384;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Largest message, storage block header included.
+    pub max_packet_size: u64,
+    pub max_depth: usize,
+    /// Sections in the whole message, the root one included.
+    pub max_objects: usize,
+    /// Fields of all the sections of the message.
+    pub max_fields: usize,
+    /// Strings in the whole message, array elements included.
+    pub max_strings: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::monero_default()
+    }
+}
+
+impl Limits {
+    /// The limits monerod applies to P2P messages.
+    pub fn monero_default() -> Self {
+        Limits {
+            max_packet_size: MAX_PACKET_SIZE,
+            max_depth: MAX_DEPTH,
+            max_objects: MAX_OBJECTS,
+            max_fields: MAX_FIELDS,
+            max_strings: MAX_STRINGS,
+        }
+    }
+
+    /// The limits monerod applies to P2P messages before the handshake
+    /// completes: the default ones with a packet size of
+    /// [`INITIAL_MAX_PACKET_SIZE`].
+    pub fn monero_initial() -> Self {
+        Limits {
+            max_packet_size: INITIAL_MAX_PACKET_SIZE,
+            ..Limits::monero_default()
+        }
+    }
+
+    /// No limits at all, for trusted input.
+    pub fn unlimited() -> Self {
+        Limits {
+            max_packet_size: u64::MAX,
+            max_depth: usize::MAX,
+            max_objects: usize::MAX,
+            max_fields: usize::MAX,
+            max_strings: usize::MAX,
+        }
+    }
+
+    /// Checks a storage blob against the limits without decoding it.
+    pub fn check(&self, data: &[u8]) -> Result<()> {
+        if data.len() as u64 > self.max_packet_size {
+            return Err(Error::LimitExceeded {
+                limit: "packet size",
+                max: self.max_packet_size,
+            });
+        }
+
+        let mut buf = data;
+        StorageBlockHeader::read(&mut buf)?;
+        Checker::new(self).section(&mut buf, 0)
+    }
+}
+
+/// Walks over an encoded section counting what the limits bound, and
+/// optionally checking that sizes are canonical and keys unique.
+pub(crate) struct Checker<'a> {
+    limits: &'a Limits,
+    pub canonical_sizes: bool,
+    pub unique_keys: bool,
+    objects: usize,
+    fields: usize,
+    strings: usize,
+}
+
+fn count(counter: &mut usize, n: usize, max: usize, limit: &'static str) -> Result<()> {
+    *counter = counter.saturating_add(n);
+    if *counter > max {
+        return Err(Error::LimitExceeded {
+            limit,
+            max: max as u64,
+        });
+    }
+    Ok(())
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(limits: &'a Limits) -> Self {
+        Checker {
+            limits,
+            canonical_sizes: false,
+            unique_keys: false,
+            objects: 0,
+            fields: 0,
+            strings: 0,
+        }
+    }
+
+    fn size(&self, buf: &mut &[u8]) -> Result<usize> {
+        let length = buf.len();
+        let size = raw_size::read_usize(buf)?;
+        if self.canonical_sizes && length - buf.len() != raw_size::encoded_len(size as u64) {
+            return Err(Error::NonCanonicalSize(size as u64));
+        }
+        Ok(size)
+    }
+
+    fn depth(&self, depth: usize) -> Result<()> {
+        if depth > self.limits.max_depth {
+            return Err(Error::LimitExceeded {
+                limit: "depth",
+                max: self.limits.max_depth as u64,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn section(&mut self, buf: &mut &[u8], depth: usize) -> Result<()> {
+        self.depth(depth)?;
+        count(&mut self.objects, 1, self.limits.max_objects, "objects")?;
+        let len = self.size(buf)?;
+        count(&mut self.fields, len, self.limits.max_fields, "fields")?;
+
+        let mut keys = HashSet::new();
+        for _ in 0..len {
+            let key = wire::read_name_bytes(buf)?;
+            if self.unique_keys && !keys.insert(key) {
+                return Err(Error::DuplicateKey(
+                    String::from_utf8_lossy(key).into_owned(),
+                ));
+            }
+            let serialize_type = wire::read_type(buf)?;
+            if wire::is_array(serialize_type) {
+                self.array(buf, serialize_type, depth + 1)?;
+            } else {
+                self.value(buf, serialize_type, depth)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn array(&mut self, buf: &mut &[u8], serialize_type: u8, depth: usize) -> Result<()> {
+        self.depth(depth)?;
+        let serialize_type = wire::element_type(serialize_type);
+        let len = self.size(buf)?;
+        match serialize_type {
+            SERIALIZE_TYPE_STRING => {
+                count(&mut self.strings, len, self.limits.max_strings, "strings")?
+            }
+            SERIALIZE_TYPE_OBJECT => {
+                count(&mut self.objects, len, self.limits.max_objects, "objects")?;
+                // Each section counts itself again when read.
+                self.objects -= len;
+            }
+            _ => {}
+        }
+
+        for _ in 0..len {
+            self.element(buf, serialize_type, depth)?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, buf: &mut &[u8], serialize_type: u8, depth: usize) -> Result<()> {
+        if serialize_type == SERIALIZE_TYPE_STRING {
+            count(&mut self.strings, 1, self.limits.max_strings, "strings")?;
+        }
+        self.element(buf, serialize_type, depth)
+    }
+
+    /// Reads a value whose strings were already counted.
+    fn element(&mut self, buf: &mut &[u8], serialize_type: u8, depth: usize) -> Result<()> {
+        let size = match serialize_type {
+            SERIALIZE_TYPE_INT64 | SERIALIZE_TYPE_UINT64 | SERIALIZE_TYPE_DOUBLE => 8,
+            SERIALIZE_TYPE_INT32 | SERIALIZE_TYPE_UINT32 => 4,
+            SERIALIZE_TYPE_INT16 | SERIALIZE_TYPE_UINT16 => 2,
+            SERIALIZE_TYPE_INT8 | SERIALIZE_TYPE_UINT8 | SERIALIZE_TYPE_BOOL => 1,
+            SERIALIZE_TYPE_STRING => self.size(buf)?,
+            SERIALIZE_TYPE_OBJECT => return self.section(buf, depth + 1),
+            SERIALIZE_TYPE_ARRAY => {
+                let serialize_type = wire::read_array_type(buf)?;
+                return self.array(buf, serialize_type, depth + 1);
+            }
+            _ => return Err(Error::InvalidSerializeType(serialize_type)),
+        };
+        ensure_eof!(buf, size);
+        buf.advance(size);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Array, Section, StorageEntry};
+
+    fn nested(depth: usize) -> Section {
+        let mut section = Section::new();
+        section.insert("name".to_owned(), StorageEntry::Buf(b"x"[..].into()));
+        if depth > 0 {
+            section.insert("inner".to_owned(), StorageEntry::Section(nested(depth - 1)));
+        }
+        section
+    }
+
+    fn exceeded(result: Result<()>) -> &'static str {
+        match result {
+            Err(Error::LimitExceeded { limit, .. }) => limit,
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn counts() {
+        let blob = crate::write_to_vec(&nested(3));
+        let limits = Limits {
+            max_depth: 3,
+            max_objects: 4,
+            max_fields: 7,
+            max_strings: 4,
+            max_packet_size: blob.len() as u64,
+        };
+        limits.check(&blob).unwrap();
+
+        for (limits, limit) in [
+            (
+                Limits {
+                    max_depth: 2,
+                    ..limits
+                },
+                "depth",
+            ),
+            (
+                Limits {
+                    max_objects: 3,
+                    ..limits
+                },
+                "objects",
+            ),
+            (
+                Limits {
+                    max_fields: 6,
+                    ..limits
+                },
+                "fields",
+            ),
+            (
+                Limits {
+                    max_strings: 3,
+                    ..limits
+                },
+                "strings",
+            ),
+            (
+                Limits {
+                    max_packet_size: 10,
+                    ..limits
+                },
+                "packet size",
+            ),
+        ] {
+            assert_eq!(exceeded(limits.check(&blob)), limit);
+        }
+
+        let mut strings = Array::new();
+        let mut sections = Array::new();
+        for _ in 0..10 {
+            strings.push(StorageEntry::Buf(b"x"[..].into())).unwrap();
+            sections
+                .push(StorageEntry::Section(Section::new()))
+                .unwrap();
+        }
+        let mut section = Section::new();
+        section.insert("sections".to_owned(), StorageEntry::Array(sections));
+        section.insert("strings".to_owned(), StorageEntry::Array(strings));
+        let blob = crate::write_to_vec(&section);
+        let limits = Limits {
+            max_objects: 11,
+            max_strings: 10,
+            ..Limits::monero_default()
+        };
+        limits.check(&blob).unwrap();
+        assert_eq!(
+            exceeded(
+                Limits {
+                    max_objects: 10,
+                    ..limits
+                }
+                .check(&blob)
+            ),
+            "objects"
+        );
+        assert_eq!(
+            exceeded(
+                Limits {
+                    max_strings: 9,
+                    ..limits
+                }
+                .check(&blob)
+            ),
+            "strings"
+        );
+
+        assert!(matches!(
+            Limits::unlimited().check(&blob[..blob.len() - 1]),
+            Err(Error::UnexpectedEof { .. })
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Duplicate-preserving sections
+//!
+//! [`Section`] keeps a single value per key, so a blob repeating a key
+//! decodes to the last value only. A [`MultiSection`] keeps every entry in
+//! the order it was read instead, for tooling that needs to see exactly
+//! what a peer sent:
+//!
+//! ```rust
+//! use portable_storage::{multimap, Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("id".to_owned(), StorageEntry::U8(1));
+//! section.insert("di".to_owned(), StorageEntry::U8(2));
+//! let mut blob = portable_storage::write_to_vec(&section);
+//! // Rename `di` to `id`.
+//! let end = blob.len();
+//! blob[end - 4..end - 2].copy_from_slice(b"id");
+//!
+//! let multi = multimap::read(&blob).unwrap();
+//! assert_eq!(multi.get_all("id").count(), 2);
+//! assert_eq!(multi.duplicates().collect::<Vec<_>>(), ["id"]);
+//! assert_eq!(multi.write_to_vec(), blob);
+//! assert_eq!(multi.into_section().len(), 1);
+//! ```
+//!
+//! Blobs as epee writes them are written back byte for byte. Raw sizes
+//! wider than needed, arrays introduced by an unflagged
+//! `SERIALIZE_TYPE_ARRAY` and keys that aren't valid UTF-8 are normalized
+//! like when writing a [`Section`].
+
+use crate::{
+    header::StorageBlockHeader, raw_size, read_name, wire, with_offset, write_name, Array, Error,
+    FreshKeys, Result, Section, SerializeType, StorageEntry, SERIALIZE_FLAG_ARRAY,
+    SERIALIZE_TYPE_ARRAY, SERIALIZE_TYPE_OBJECT,
+};
+use bytes::{Buf, BufMut, BytesMut};
+use std::collections::HashSet;
+
+/// A section keeping every entry, duplicate keys included, in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MultiSection {
+    pub entries: Vec<(String, MultiEntry)>,
+}
+
+/// An entry of a [`MultiSection`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum MultiEntry {
+    /// Any value other than a section or an array.
+    Value(StorageEntry),
+    Array(MultiArray),
+    Section(MultiSection),
+}
+
+/// An array of [`MultiEntry`] elements, all of `element_kind`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiArray {
+    pub element_kind: SerializeType,
+    pub elements: Vec<MultiEntry>,
+}
+
+impl MultiSection {
+    pub fn new() -> MultiSection {
+        Default::default()
+    }
+
+    /// Number of entries, duplicates included.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Appends an entry, even if `name` is already present.
+    pub fn push(&mut self, name: String, entry: MultiEntry) {
+        self.entries.push((name, entry));
+    }
+
+    /// The last value of `name`, the one [`Section`] keeps.
+    pub fn get(&self, name: &str) -> Option<&MultiEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, entry)| entry)
+    }
+
+    /// Every value of `name`, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a MultiEntry> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, entry)| entry)
+    }
+
+    /// The keys appearing more than once in this section, not in the nested
+    /// ones, each reported once in the order of their second occurrence.
+    pub fn duplicates(&self) -> impl Iterator<Item = &str> {
+        let mut seen = HashSet::new();
+        let mut reported = HashSet::new();
+        self.entries.iter().filter_map(move |(name, _)| {
+            if !seen.insert(name.as_str()) && reported.insert(name.as_str()) {
+                Some(name.as_str())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Collapses duplicate keys the way reading a [`Section`] does: the last
+    /// value wins, at the position of the first.
+    pub fn into_section(self) -> Section {
+        let mut section = Section::with_capacity(self.entries.len());
+        for (name, entry) in self.entries {
+            section.entries.insert(name, entry.into_entry());
+        }
+        section
+    }
+
+    /// Writes the section as a storage blob, header included.
+    pub fn write(&self, buf: &mut BytesMut) {
+        StorageBlockHeader::write(buf);
+        self.write_section(buf);
+    }
+
+    pub fn write_to_vec(&self) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        self.write(&mut buf);
+        buf.to_vec()
+    }
+
+    fn read<B: Buf>(buf: &mut B) -> Result<MultiSection> {
+        let count = raw_size::read_usize(buf)?;
+        let mut section = MultiSection::new();
+        for _ in 0..count {
+            let name = read_name(buf)?;
+            let serialize_type = wire::read_type(buf)?;
+            let entry = MultiEntry::read(buf, serialize_type).map_err(|e| e.in_key(&name))?;
+            section.entries.push((name, entry));
+        }
+        Ok(section)
+    }
+
+    fn write_section(&self, buf: &mut BytesMut) {
+        raw_size::write(buf, self.entries.len() as u64);
+        for (name, entry) in self.entries.iter() {
+            write_name(buf, name);
+            match entry {
+                // Like epee, arrays are only prefixed with their flagged
+                // element type.
+                MultiEntry::Array(array) => array.write(buf),
+                MultiEntry::Value(entry) => StorageEntry::write(buf, entry),
+                MultiEntry::Section(section) => {
+                    buf.put_u8(SERIALIZE_TYPE_OBJECT);
+                    section.write_section(buf);
+                }
+            }
+        }
+    }
+}
+
+impl From<Section> for MultiSection {
+    fn from(section: Section) -> MultiSection {
+        MultiSection {
+            entries: section
+                .entries
+                .into_iter()
+                .map(|(name, entry)| (name, MultiEntry::from(entry)))
+                .collect(),
+        }
+    }
+}
+
+impl MultiEntry {
+    /// Converts the entry, collapsing the duplicate keys of the nested
+    /// sections like [`MultiSection::into_section`].
+    pub fn into_entry(self) -> StorageEntry {
+        match self {
+            MultiEntry::Value(entry) => entry,
+            MultiEntry::Section(section) => StorageEntry::Section(section.into_section()),
+            MultiEntry::Array(array) => {
+                let mut converted = Array::new_typed(array.element_kind);
+                converted.array = array
+                    .elements
+                    .into_iter()
+                    .map(MultiEntry::into_entry)
+                    .collect();
+                StorageEntry::Array(converted)
+            }
+        }
+    }
+
+    fn read<B: Buf>(buf: &mut B, serialize_type: u8) -> Result<MultiEntry> {
+        if wire::is_array(serialize_type) {
+            return MultiArray::read(buf, serialize_type).map(MultiEntry::Array);
+        }
+
+        match serialize_type {
+            SERIALIZE_TYPE_OBJECT => MultiSection::read(buf).map(MultiEntry::Section),
+            SERIALIZE_TYPE_ARRAY => {
+                let serialize_type = wire::read_array_type(buf)?;
+                MultiArray::read(buf, serialize_type).map(MultiEntry::Array)
+            }
+            _ => StorageEntry::read_entry_raw(buf, serialize_type, &mut FreshKeys)
+                .map(MultiEntry::Value),
+        }
+    }
+
+    fn write_raw(&self, buf: &mut BytesMut) {
+        match self {
+            MultiEntry::Value(entry) => StorageEntry::write_raw(buf, entry),
+            MultiEntry::Array(array) => array.write(buf),
+            MultiEntry::Section(section) => section.write_section(buf),
+        }
+    }
+}
+
+impl From<StorageEntry> for MultiEntry {
+    fn from(entry: StorageEntry) -> MultiEntry {
+        match entry {
+            StorageEntry::Section(section) => MultiEntry::Section(section.into()),
+            StorageEntry::Array(array) => match array.element_kind() {
+                Some(element_kind) => MultiEntry::Array(MultiArray {
+                    element_kind,
+                    elements: array.into_iter().map(MultiEntry::from).collect(),
+                }),
+                // Untyped arrays are empty, keep them as they are.
+                None => MultiEntry::Value(StorageEntry::Array(array)),
+            },
+            entry => MultiEntry::Value(entry),
+        }
+    }
+}
+
+impl MultiArray {
+    fn read<B: Buf>(buf: &mut B, serialize_type: u8) -> Result<MultiArray> {
+        let element_type = wire::element_type(serialize_type);
+        let element_kind =
+            SerializeType::from_u8(element_type).ok_or(Error::InvalidArrayType(serialize_type))?;
+        let size = raw_size::read_usize(buf)?;
+
+        let mut elements = Vec::new();
+        for i in 0..size {
+            elements.push(MultiEntry::read(buf, element_type).map_err(|e| e.in_element(i))?);
+        }
+        Ok(MultiArray {
+            element_kind,
+            elements,
+        })
+    }
+
+    fn write(&self, buf: &mut BytesMut) {
+        buf.put_u8(self.element_kind as u8 | SERIALIZE_FLAG_ARRAY);
+        raw_size::write(buf, self.elements.len() as u64);
+        for element in self.elements.iter() {
+            element.write_raw(buf);
+        }
+    }
+}
+
+/// Reads a storage blob, header included, keeping duplicate keys.
+pub fn read(data: &[u8]) -> Result<MultiSection> {
+    with_offset(&mut &data[..], |buf| {
+        StorageBlockHeader::read(buf)?;
+        MultiSection::read(buf)
+    })
+}
+
+/// Reads a section that isn't preceded by the storage block header, keeping
+/// duplicate keys.
+pub fn read_section(data: &[u8]) -> Result<MultiSection> {
+    with_offset(&mut &data[..], MultiSection::read)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn value(entry: StorageEntry) -> MultiEntry {
+        MultiEntry::Value(entry)
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut peer = MultiSection::new();
+        peer.push("id".to_owned(), value(StorageEntry::U64(1)));
+        peer.push("port".to_owned(), value(StorageEntry::U32(18080)));
+        peer.push("id".to_owned(), value(StorageEntry::U64(2)));
+
+        let mut multi = MultiSection::new();
+        multi.push("height".to_owned(), value(StorageEntry::U64(7)));
+        multi.push(
+            "peers".to_owned(),
+            MultiEntry::Array(MultiArray {
+                element_kind: SerializeType::Section,
+                elements: vec![MultiEntry::Section(peer)],
+            }),
+        );
+        multi.push("height".to_owned(), value(StorageEntry::U8(8)));
+        multi.push("height".to_owned(), value(StorageEntry::U8(9)));
+
+        let blob = multi.write_to_vec();
+        let decoded = read(&blob).unwrap();
+        assert_eq!(decoded, multi);
+        assert_eq!(decoded.write_to_vec(), blob);
+        assert_eq!(read_section(&blob[9..]).unwrap(), multi);
+
+        assert_eq!(decoded.len(), 4);
+        assert_eq!(decoded.get_all("height").count(), 3);
+        assert_eq!(decoded.get("height"), Some(&value(StorageEntry::U8(9))));
+        assert_eq!(decoded.duplicates().collect::<Vec<_>>(), ["height"]);
+        match decoded.get("peers") {
+            Some(MultiEntry::Array(peers)) => match &peers.elements[0] {
+                MultiEntry::Section(peer) => {
+                    assert_eq!(peer.duplicates().collect::<Vec<_>>(), ["id"])
+                }
+                element => panic!("unexpected element {:?}", element),
+            },
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+
+        let section = crate::read(&mut &blob[..]).unwrap();
+        assert_eq!(decoded.into_section(), section);
+        let collapsed = MultiSection::from(section.clone());
+        assert!(collapsed.duplicates().next().is_none());
+        assert_eq!(collapsed.write_to_vec(), crate::write_to_vec(&section));
+
+        let error = read(&blob[..blob.len() - 1]).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::UnexpectedEof { offset: Some(_), ref path, .. } if path == "height"
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Numeric slices
+//!
+//! Moves numbers between slices and [`Array`]s without going through
+//! [`StorageEntry`] one element at a time, and writes numeric arrays straight
+//! from slices as one little-endian run.
+//!
+//! ```rust
+//! use bytes::BytesMut;
+//! use portable_storage::{numeric, wire, Array, StorageEntry};
+//!
+//! let heights = [1u64, 2, 3];
+//! let array = Array::from_slice(&heights);
+//!
+//! let mut copy = [0u64; 3];
+//! array.copy_to_slice(&mut copy).unwrap();
+//! assert_eq!(copy, heights);
+//!
+//! let mut buf = BytesMut::new();
+//! numeric::write_array(&mut buf, &heights);
+//! assert_eq!(wire::read_entry(&mut &buf[..]).unwrap(), StorageEntry::Array(array));
+//! ```
+
+use crate::{raw_size, Array, Error, SerializeType, StorageEntry, SERIALIZE_FLAG_ARRAY};
+use bytes::{BufMut, BytesMut};
+use std::mem;
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The numbers stored as fixed-width little-endian values.
+pub trait Numeric: Copy + private::Sealed {
+    /// The storage type of the values.
+    const KIND: SerializeType;
+
+    fn into_entry(self) -> StorageEntry;
+
+    /// The value of `entry`, `None` if it has another type.
+    fn from_entry(entry: &StorageEntry) -> Option<Self>;
+
+    /// Writes the value to `out`, which is exactly as long as the type.
+    fn write_le(self, out: &mut [u8]);
+}
+
+macro_rules! numeric {
+    ($($ty:ty => $variant:ident,)+) => {
+        $(
+        impl private::Sealed for $ty {}
+
+        impl Numeric for $ty {
+            const KIND: SerializeType = SerializeType::$variant;
+
+            fn into_entry(self) -> StorageEntry {
+                StorageEntry::$variant(self)
+            }
+
+            fn from_entry(entry: &StorageEntry) -> Option<Self> {
+                match entry {
+                    StorageEntry::$variant(v) => Some(*v),
+                    _ => None,
+                }
+            }
+
+            fn write_le(self, out: &mut [u8]) {
+                out.copy_from_slice(&self.to_le_bytes());
+            }
+        }
+        )+
+    };
+}
+
+numeric! {
+    u64 => U64,
+    u32 => U32,
+    u16 => U16,
+    u8 => U8,
+    i64 => I64,
+    i32 => I32,
+    i16 => I16,
+    i8 => I8,
+    f64 => Double,
+}
+
+impl Array {
+    /// Creates an array of the values of `values`, typed even when empty.
+    pub fn from_slice<T: Numeric>(values: &[T]) -> Array {
+        Array {
+            array: values.iter().map(|v| v.into_entry()).collect(),
+            serialize_type: Some(T::KIND as u8 | SERIALIZE_FLAG_ARRAY),
+        }
+    }
+
+    /// Copies the elements into `dst`. Fails with
+    /// [`Error::UnexpectedType`] if they aren't `T`s, in which case `dst` is
+    /// left untouched.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dst` isn't as long as the array.
+    pub fn copy_to_slice<T: Numeric>(&self, dst: &mut [T]) -> Result<(), Error> {
+        assert_eq!(
+            dst.len(),
+            self.array.len(),
+            "destination and array lengths differ"
+        );
+        let expected = T::KIND as u8;
+        match self.serialize_type {
+            Some(t) if t & !SERIALIZE_FLAG_ARRAY != expected => {
+                return Err(Error::UnexpectedType {
+                    expected,
+                    found: t & !SERIALIZE_FLAG_ARRAY,
+                })
+            }
+            _ => {}
+        }
+
+        // Every element is checked before the first one is copied.
+        if let Some(entry) = self.array.iter().find(|e| T::from_entry(e).is_none()) {
+            return Err(Error::UnexpectedType {
+                expected,
+                found: entry.serialize_type(),
+            });
+        }
+        for (value, entry) in dst.iter_mut().zip(&self.array) {
+            if let Some(v) = T::from_entry(entry) {
+                *value = v;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `values` as an array entry: its flagged type, the element count
+/// and the elements, without building an [`Array`].
+pub fn write_array<T: Numeric>(buf: &mut BytesMut, values: &[T]) {
+    buf.put_u8(T::KIND as u8 | SERIALIZE_FLAG_ARRAY);
+    raw_size::write(buf, values.len() as u64);
+    for (chunk, value) in extend(buf, mem::size_of::<T>(), values.len()).zip(values) {
+        value.write_le(chunk);
+    }
+}
+
+/// Writes the elements of a fixed-width array as one run, returns `false`
+/// without writing anything for other arrays, or if an element doesn't have
+/// the type of the array.
+pub(crate) fn write_elements(buf: &mut BytesMut, array: &Array) -> bool {
+    let width = match array.element_kind() {
+        Some(SerializeType::U64 | SerializeType::I64 | SerializeType::Double) => 8,
+        Some(SerializeType::U32 | SerializeType::I32) => 4,
+        Some(SerializeType::U16 | SerializeType::I16) => 2,
+        Some(SerializeType::U8 | SerializeType::I8 | SerializeType::Bool) => 1,
+        _ => return false,
+    };
+
+    let start = buf.len();
+    let mut written = true;
+    for (chunk, entry) in extend(buf, width, array.array.len()).zip(&array.array) {
+        match *entry {
+            StorageEntry::U64(v) => v.write_le(chunk),
+            StorageEntry::U32(v) => v.write_le(chunk),
+            StorageEntry::U16(v) => v.write_le(chunk),
+            StorageEntry::U8(v) => v.write_le(chunk),
+            StorageEntry::I64(v) => v.write_le(chunk),
+            StorageEntry::I32(v) => v.write_le(chunk),
+            StorageEntry::I16(v) => v.write_le(chunk),
+            StorageEntry::I8(v) => v.write_le(chunk),
+            StorageEntry::Double(v) => v.write_le(chunk),
+            StorageEntry::Bool(v) => chunk[0] = v as u8,
+            _ => {
+                written = false;
+                break;
+            }
+        }
+    }
+    if !written {
+        buf.truncate(start);
+    }
+    written
+}
+
+/// Grows `buf` by `count` values `width` bytes long, returning the chunks to
+/// write them to.
+fn extend(buf: &mut BytesMut, width: usize, count: usize) -> impl Iterator<Item = &mut [u8]> {
+    let start = buf.len();
+    buf.resize(start + width * count, 0);
+    buf[start..].chunks_exact_mut(width)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Section, SerializeType};
+
+    #[test]
+    fn slices() {
+        let values = [-1i16, 0, i16::MAX];
+        let array = Array::from_slice(&values);
+        assert_eq!(array.element_kind(), Some(SerializeType::I16));
+        assert_eq!(array[2], StorageEntry::I16(i16::MAX));
+
+        let mut copy = [0; 3];
+        array.copy_to_slice(&mut copy).unwrap();
+        assert_eq!(copy, values);
+
+        let mut wrong = [0u16; 3];
+        assert!(matches!(
+            array.copy_to_slice(&mut wrong),
+            Err(Error::UnexpectedType { .. })
+        ));
+        assert_eq!(wrong, [0; 3]);
+
+        let empty = Array::from_slice::<f64>(&[]);
+        assert_eq!(empty.element_kind(), Some(SerializeType::Double));
+        empty.copy_to_slice::<f64>(&mut []).unwrap();
+        Array::new().copy_to_slice::<u8>(&mut []).unwrap();
+
+        // An untyped array whose later element doesn't match.
+        let mixed = Array {
+            array: vec![StorageEntry::U8(1), StorageEntry::U16(2)],
+            serialize_type: None,
+        };
+        let mut dst = [7u8; 2];
+        assert!(matches!(
+            mixed.copy_to_slice(&mut dst),
+            Err(Error::UnexpectedType {
+                found: crate::SERIALIZE_TYPE_UINT16,
+                ..
+            })
+        ));
+        assert_eq!(dst, [7; 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "lengths differ")]
+    fn length_mismatch() {
+        let _ = Array::from_slice(&[1u8, 2]).copy_to_slice(&mut [0u8; 3]);
+    }
+
+    #[test]
+    fn runs() {
+        let mut section = Section::new();
+        section.insert(
+            "ids".to_owned(),
+            StorageEntry::Array(Array::from_slice(&[1u64, u64::MAX])),
+        );
+        section.insert(
+            "rates".to_owned(),
+            StorageEntry::Array(Array::from_slice(&[0.5f64, -2.0])),
+        );
+        section.insert(
+            "offsets".to_owned(),
+            StorageEntry::Array(Array::from_slice(&[-7i8, 7])),
+        );
+        let mut flags = Array::new();
+        flags.push(StorageEntry::Bool(true)).unwrap();
+        flags.push(StorageEntry::Bool(false)).unwrap();
+        section.insert("flags".to_owned(), StorageEntry::Array(flags));
+
+        let data = crate::write_to_vec(&section);
+        assert_eq!(crate::read(&mut &data[..]).unwrap(), section);
+
+        let mut buf = BytesMut::new();
+        write_array(&mut buf, &[1u64, u64::MAX]);
+        let mut expected = BytesMut::new();
+        crate::wire::write_entry(&mut expected, &section["ids"]);
+        assert_eq!(buf, expected);
+    }
+}
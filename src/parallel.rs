@@ -0,0 +1,241 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Parallel decoding and encoding
+//!
+//! Arrays of at least [`THRESHOLD`] elements read from a contiguous buffer
+//! are decoded on the rayon thread pool: a sizing pass walks the elements
+//! without allocating to find where each range of elements starts, then the
+//! ranges are decoded concurrently and joined in order. The result is the
+//! same as decoding sequentially, it only changes how long it takes for
+//! large hash and peer lists. Arrays that fail to decode are decoded again
+//! sequentially, so errors carry the same offset and path too.
+//!
+//! Only [`read`](crate::read) and [`read_shared`](crate::read_shared) decode
+//! in parallel. The [`Interner`](crate::interner::Interner) of
+//! [`read_interned`](crate::read_interned) and the
+//! [`Dedup`](crate::dedup::Dedup) of [`read_dedup`](crate::read_dedup) keep
+//! state that can't be shared across threads, so they decode every array
+//! sequentially.
+//!
+//! Batches of independent sections, like the frames a node broadcasts to
+//! its peers, can be encoded concurrently too:
+//!
+//! ```rust
+//! use portable_storage::{parallel, Section, StorageEntry};
+//!
+//! let sections: Vec<Section> = (0..4u64)
+//!     .map(|height| {
+//!         let mut section = Section::new();
+//!         section.insert("height".to_owned(), StorageEntry::U64(height));
+//!         section
+//!     })
+//!     .collect();
+//!
+//! let blobs = parallel::write_to_vecs(&sections);
+//! assert_eq!(blobs[2], portable_storage::write_to_vec(&sections[2]));
+//! ```
+
+use crate::{skip::skip_raw, FreshKeys, Keys, Section, SharedBufs, StorageBuf, StorageEntry};
+use bytes::{Buf, BufMut, BytesMut};
+use rayon::prelude::*;
+
+/// The number of elements from which arrays are decoded in parallel.
+pub const THRESHOLD: usize = 4096;
+
+/// How many ranges each thread gets, to even out uneven elements.
+const RANGES_PER_THREAD: usize = 4;
+
+/// The [`Keys`] without state, which every thread can use.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum StatelessKeys {
+    Fresh,
+    SharedBufs,
+}
+
+impl Keys for StatelessKeys {
+    fn key(&mut self, name: &[u8]) -> String {
+        FreshKeys.key(name)
+    }
+
+    fn buf(&mut self, data: &[u8]) -> StorageBuf {
+        match self {
+            StatelessKeys::Fresh => FreshKeys.buf(data),
+            StatelessKeys::SharedBufs => SharedBufs.buf(data),
+        }
+    }
+}
+
+/// Decodes `count` elements of `serialize_type` (without the array flag),
+/// `buf` must be contiguous. Returns `None` without consuming anything if
+/// the elements are invalid.
+pub(crate) fn read_elements<B: Buf>(
+    buf: &mut B,
+    serialize_type: u8,
+    count: usize,
+    keys: StatelessKeys,
+) -> Option<Vec<StorageEntry>> {
+    let per_range = (count / (rayon::current_num_threads() * RANGES_PER_THREAD)).max(1);
+
+    let (ranges, consumed) = {
+        let data = buf.bytes();
+        let mut cursor = data;
+        let mut starts = Vec::with_capacity(count / per_range + 1);
+        for i in 0..count {
+            if i % per_range == 0 {
+                starts.push(data.len() - cursor.len());
+            }
+            skip_raw(&mut cursor, serialize_type).ok()?;
+        }
+        let end = data.len() - cursor.len();
+
+        let ranges = starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let next = starts.get(i + 1).copied().unwrap_or(end);
+                let len = per_range.min(count - i * per_range);
+                (&data[start..next], len)
+            })
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|(mut range, len)| {
+                let mut keys = keys;
+                (0..len)
+                    .map(|_| StorageEntry::read_entry_raw(&mut range, serialize_type, &mut keys))
+                    .collect::<crate::Result<Vec<_>>>()
+            })
+            .collect::<crate::Result<Vec<_>>>()
+            .ok()?;
+        (ranges, end)
+    };
+    buf.advance(consumed);
+
+    let mut elements = Vec::with_capacity(count);
+    for range in ranges {
+        elements.extend(range);
+    }
+    Some(elements)
+}
+
+/// Writes each section as a storage blob into its own vector, in parallel.
+/// The vectors are in the order of `sections`.
+pub fn write_to_vecs(sections: &[Section]) -> Vec<Vec<u8>> {
+    sections.par_iter().map(crate::write_to_vec).collect()
+}
+
+/// Writes each section as a storage blob, back to back, like
+/// [`write_batch`](crate::write_batch) but encoding them in parallel before
+/// gathering them into `buf`.
+pub fn write_batch(buf: &mut BytesMut, sections: &[Section]) {
+    let blobs = write_to_vecs(sections);
+    buf.reserve(blobs.iter().map(Vec::len).sum());
+    for blob in blobs {
+        buf.put_slice(&blob);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{dedup::Dedup, Array, Error, Section};
+
+    fn peer(i: u64) -> Section {
+        let mut adr = Section::new();
+        adr.insert("m_ip".to_owned(), StorageEntry::U32(i as u32));
+        adr.insert("tags".to_owned(), {
+            let mut tags = Array::new();
+            for _ in 0..1 + i % 3 {
+                tags.push(StorageEntry::Buf(vec![i as u8; i as usize % 40].into()))
+                    .unwrap();
+            }
+            StorageEntry::Array(tags)
+        });
+        let mut peer = Section::new();
+        peer.insert("adr".to_owned(), StorageEntry::Section(adr));
+        peer.insert("id".to_owned(), StorageEntry::U64(i));
+        peer
+    }
+
+    #[test]
+    fn matches_sequential() {
+        let (mut peers, mut hashes) = (Array::new(), Array::new());
+        for i in 0..THRESHOLD as u64 * 3 + 7 {
+            peers.push(StorageEntry::Section(peer(i))).unwrap();
+            hashes
+                .push(StorageEntry::Buf(vec![i as u8; 32].into()))
+                .unwrap();
+        }
+        let mut section = Section::new();
+        section.insert("hashes".to_owned(), StorageEntry::Array(hashes));
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+
+        let blob = crate::write_to_vec(&section);
+        assert_eq!(crate::read(&mut &blob[..]).unwrap(), section);
+
+        // The sizing pass catches truncated elements, and the errors are the
+        // sequential ones.
+        let error = crate::read(&mut &blob[..blob.len() - 1]).unwrap_err();
+        assert!(matches!(error, Error::UnexpectedEof { .. }));
+        let sequential = crate::read_dedup(&mut &blob[..blob.len() - 1], &mut Dedup::new());
+        assert_eq!(error.to_string(), sequential.unwrap_err().to_string());
+        assert!(error.to_string().contains("peers["), "{}", error);
+    }
+
+    #[test]
+    fn stateful_keys() {
+        let mut hashes = Array::new();
+        for i in 0..THRESHOLD as u64 * 2 {
+            hashes
+                .push(StorageEntry::Buf(vec![(i % 4) as u8; 64].into()))
+                .unwrap();
+        }
+        let mut section = Section::new();
+        section.insert("hashes".to_owned(), StorageEntry::Array(hashes));
+        let blob = crate::write_to_vec(&section);
+
+        let is_shared = |section: &Section| match &section["hashes"] {
+            StorageEntry::Array(hashes) => hashes.array.iter().all(|hash| match hash {
+                StorageEntry::Buf(buf) => buf.is_shared(),
+                _ => false,
+            }),
+            _ => false,
+        };
+        let shared = crate::read_shared(&mut &blob[..]).unwrap();
+        assert_eq!(shared, section);
+        assert!(is_shared(&shared));
+        assert!(!is_shared(&crate::read(&mut &blob[..]).unwrap()));
+
+        let mut dedup = Dedup::new();
+        let deduped = crate::read_dedup(&mut &blob[..], &mut dedup).unwrap();
+        assert_eq!(deduped, section);
+        assert_eq!(dedup.len(), 4);
+    }
+
+    #[test]
+    fn batches() {
+        let sections: Vec<Section> = (0..100).map(peer).collect();
+        let blobs = write_to_vecs(&sections);
+        assert_eq!(blobs.len(), sections.len());
+        for (blob, section) in blobs.iter().zip(&sections) {
+            assert_eq!(blob, &crate::write_to_vec(section));
+        }
+
+        let (mut parallel, mut sequential) = (BytesMut::new(), BytesMut::new());
+        write_batch(&mut parallel, &sections);
+        crate::write_batch(&mut sequential, &sections);
+        assert_eq!(parallel, sequential);
+    }
+}
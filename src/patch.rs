@@ -0,0 +1,284 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Patching
+//!
+//! Overwrites fixed-width scalars directly in an encoded blob, without
+//! decoding and re-serializing the whole message. Useful to mutate inputs in
+//! fuzz harnesses or to rewrite timestamps in replayed captures.
+//!
+//! ```rust
+//! use portable_storage::{patch, Section, StorageEntry};
+//!
+//! let mut node_data = Section::new();
+//! node_data.insert("local_time".to_owned(), StorageEntry::U64(1_600_000_000));
+//! let mut section = Section::new();
+//! section.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+//! let mut blob = portable_storage::write_to_vec(&section);
+//!
+//! patch::patch(&mut blob, "node_data.local_time", &StorageEntry::U64(1_700_000_000)).unwrap();
+//!
+//! let section = portable_storage::read(&mut &blob[..]).unwrap();
+//! match &section["node_data"] {
+//!     StorageEntry::Section(node_data) => {
+//!         assert_eq!(node_data["local_time"], StorageEntry::U64(1_700_000_000))
+//!     }
+//!     _ => unreachable!(),
+//! }
+//! ```
+//!
+//! Paths are keys joined with `.`, array elements are selected by their
+//! index, as in `peers.3.last_seen`. When a key is repeated the last entry
+//! is used, the one decoding keeps.
+
+use crate::{
+    explain::type_name, header::StorageBlockHeader, raw_size, skip, wire, Error, Result,
+    StorageEntry, SERIALIZE_TYPE_ARRAY, SERIALIZE_TYPE_OBJECT,
+};
+
+/// Where a value starts in a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// Absolute offset of the value, past its serialize type.
+    pub offset: usize,
+    /// Serialize type of the value, with the array flag for arrays.
+    pub serialize_type: u8,
+}
+
+impl Location {
+    /// Overwrites the value at this location with `value`, which must be a
+    /// fixed-width scalar of the same type.
+    pub fn write(&self, blob: &mut [u8], value: &StorageEntry) -> Result<()> {
+        let found = value.serialize_type();
+        if found != self.serialize_type {
+            return Err(Error::UnexpectedType {
+                expected: self.serialize_type,
+                found,
+            });
+        }
+        let size = skip::fixed_size(found)
+            .ok_or_else(|| Error::Unsupported(format!("patching {} values", type_name(found))))?;
+        let available = blob.len().saturating_sub(self.offset);
+        let dst = blob
+            .get_mut(self.offset..self.offset + size)
+            .ok_or_else(|| Error::eof(size, available))?;
+
+        match value {
+            StorageEntry::U64(v) => dst.copy_from_slice(&v.to_le_bytes()),
+            StorageEntry::U32(v) => dst.copy_from_slice(&v.to_le_bytes()),
+            StorageEntry::U16(v) => dst.copy_from_slice(&v.to_le_bytes()),
+            StorageEntry::U8(v) => dst[0] = *v,
+            StorageEntry::I64(v) => dst.copy_from_slice(&v.to_le_bytes()),
+            StorageEntry::I32(v) => dst.copy_from_slice(&v.to_le_bytes()),
+            StorageEntry::I16(v) => dst.copy_from_slice(&v.to_le_bytes()),
+            StorageEntry::I8(v) => dst.copy_from_slice(&v.to_le_bytes()),
+            StorageEntry::Double(v) => dst.copy_from_slice(&v.to_le_bytes()),
+            StorageEntry::Bool(v) => dst[0] = *v as u8,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+/// Finds the value at `path` in a blob, including the storage block header.
+pub fn locate(blob: &[u8], path: &str) -> Result<Location> {
+    let mut buf = blob;
+    StorageBlockHeader::read(&mut buf)?;
+    Locator { len: blob.len() }.section(&mut buf, path, &split(path))
+}
+
+/// Finds the value at `path` in a section that isn't preceded by the storage
+/// block header.
+pub fn locate_section(buf: &[u8], path: &str) -> Result<Location> {
+    Locator { len: buf.len() }.section(&mut &buf[..], path, &split(path))
+}
+
+/// Overwrites the value at `path` in a blob, including the storage block
+/// header. See [`Location::write`].
+pub fn patch(blob: &mut [u8], path: &str, value: &StorageEntry) -> Result<()> {
+    locate(blob, path)?.write(blob, value)
+}
+
+/// Overwrites the value at `path` in a section that isn't preceded by the
+/// storage block header. See [`Location::write`].
+pub fn patch_section(buf: &mut [u8], path: &str, value: &StorageEntry) -> Result<()> {
+    locate_section(buf, path)?.write(buf, value)
+}
+
+fn split(path: &str) -> Vec<&str> {
+    if path.is_empty() {
+        Vec::new()
+    } else {
+        path.split('.').collect()
+    }
+}
+
+struct Locator {
+    len: usize,
+}
+
+impl Locator {
+    fn section(&self, buf: &mut &[u8], path: &str, segments: &[&str]) -> Result<Location> {
+        let (first, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => return Err(Error::KeyNotFound(path.to_owned())),
+        };
+
+        let mut found = None;
+        for _ in 0..raw_size::read_usize(buf)? {
+            let name = wire::read_name_bytes(buf)?;
+            let serialize_type = wire::read_type(buf)?;
+
+            if name == first.as_bytes() {
+                found = Some(self.value(&mut &buf[..], serialize_type, path, rest));
+            }
+            if wire::is_array(serialize_type) {
+                skip::skip_array(buf, serialize_type)?;
+            } else {
+                skip::skip_raw(buf, serialize_type)?;
+            }
+        }
+
+        found.unwrap_or_else(|| Err(Error::KeyNotFound(path.to_owned())))
+    }
+
+    fn value(
+        &self,
+        buf: &mut &[u8],
+        serialize_type: u8,
+        path: &str,
+        segments: &[&str],
+    ) -> Result<Location> {
+        if segments.is_empty() {
+            return Ok(Location {
+                offset: self.len - buf.len(),
+                serialize_type,
+            });
+        }
+
+        if wire::is_array(serialize_type) {
+            let serialize_type = wire::element_type(serialize_type);
+            let index: usize = segments[0]
+                .parse()
+                .map_err(|_| Error::KeyNotFound(path.to_owned()))?;
+            if index >= raw_size::read_usize(buf)? {
+                return Err(Error::KeyNotFound(path.to_owned()));
+            }
+            for _ in 0..index {
+                skip::skip_raw(buf, serialize_type)?;
+            }
+            return self.value(buf, serialize_type, path, &segments[1..]);
+        }
+
+        match serialize_type {
+            SERIALIZE_TYPE_OBJECT => self.section(buf, path, segments),
+            SERIALIZE_TYPE_ARRAY => {
+                let serialize_type = wire::read_array_type(buf)?;
+                self.value(buf, serialize_type, path, segments)
+            }
+            _ => Err(Error::KeyNotFound(path.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Array, Section};
+
+    fn sample() -> Section {
+        let mut peer = Section::new();
+        peer.insert("last_seen".to_owned(), StorageEntry::I64(10));
+        peer.insert("pruned".to_owned(), StorageEntry::Bool(false));
+        let mut peers = Array::new();
+        peers.push(StorageEntry::Section(peer.clone())).unwrap();
+        peers.push(StorageEntry::Section(peer)).unwrap();
+
+        let mut ports = Array::new();
+        ports.push(StorageEntry::U32(18080)).unwrap();
+        ports.push(StorageEntry::U32(18081)).unwrap();
+
+        let mut section = Section::new();
+        section.insert("id".to_owned(), StorageEntry::Buf(b"abc"[..].into()));
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+        section.insert("ports".to_owned(), StorageEntry::Array(ports));
+        section
+    }
+
+    #[test]
+    fn patches_in_place() {
+        let mut expected = sample();
+        let mut blob = crate::write_to_vec(&expected);
+        let len = blob.len();
+
+        patch(&mut blob, "peers.1.last_seen", &StorageEntry::I64(-5)).unwrap();
+        patch(&mut blob, "peers.1.pruned", &StorageEntry::Bool(true)).unwrap();
+        patch(&mut blob, "ports.1", &StorageEntry::U32(28080)).unwrap();
+        assert_eq!(blob.len(), len);
+
+        if let StorageEntry::Array(peers) = &mut expected.entries["peers"] {
+            let mut peer = Section::new();
+            peer.insert("last_seen".to_owned(), StorageEntry::I64(-5));
+            peer.insert("pruned".to_owned(), StorageEntry::Bool(true));
+            peers.array[1] = StorageEntry::Section(peer);
+        }
+        if let StorageEntry::Array(ports) = &mut expected.entries["ports"] {
+            ports.array[1] = StorageEntry::U32(28080);
+        }
+        assert_eq!(crate::read(&mut &blob[..]).unwrap(), expected);
+
+        let section = &blob[crate::header::PORTABLE_STORAGE_BLOCK_HEADER_LENGTH..];
+        let mut section = section.to_vec();
+        patch_section(&mut section, "ports.0", &StorageEntry::U32(1)).unwrap();
+        assert_eq!(
+            locate_section(&section, "ports.0").unwrap().serialize_type,
+            6
+        );
+
+        let location = locate(&blob, "peers.0.last_seen").unwrap();
+        location.write(&mut blob, &StorageEntry::I64(1)).unwrap();
+        location.write(&mut blob, &StorageEntry::I64(2)).unwrap();
+        assert_eq!(
+            crate::read(&mut &blob[..])
+                .unwrap()
+                .select("peers[0].last_seen")
+                .unwrap()
+                .next(),
+            Some(&StorageEntry::I64(2))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_paths_and_types() {
+        let mut blob = crate::write_to_vec(&sample());
+        let original = blob.clone();
+
+        for path in &["missing", "ports.2", "ports.x", "id.0", "peers.0.port", ""] {
+            assert!(matches!(
+                patch(&mut blob, path, &StorageEntry::U8(0)),
+                Err(Error::KeyNotFound(_))
+            ));
+        }
+        assert!(matches!(
+            patch(&mut blob, "ports.0", &StorageEntry::U64(0)),
+            Err(Error::UnexpectedType { .. })
+        ));
+        assert!(matches!(
+            patch(&mut blob, "id", &StorageEntry::Buf(b"xyz"[..].into())),
+            Err(Error::Unsupported(_))
+        ));
+        assert_eq!(blob, original);
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Pooling
+//!
+//! Servers decoding and encoding the same kinds of messages all day can keep
+//! the storage of past ones around instead of allocating it again for every
+//! request. A [`SectionPool`] hands out cleared sections to decode into with
+//! [`read_into`](crate::read_into), and a [`BufferPool`] hands out cleared
+//! buffers to encode into with [`to_bytes_into`](crate::to_bytes_into):
+//!
+//! ```rust
+//! use portable_storage::pool::{BufferPool, SectionPool};
+//!
+//! let (sections, buffers) = (SectionPool::new(16), BufferPool::new(16));
+//! # let request = portable_storage::write_to_vec(&Default::default());
+//!
+//! let mut section = sections.get();
+//! portable_storage::read_into(&mut &request[..], &mut section).unwrap();
+//!
+//! let mut buf = buffers.get();
+//! portable_storage::to_bytes_into(&section, &mut buf);
+//! // Send `buf`…
+//!
+//! sections.put(section);
+//! buffers.put(buf);
+//! ```
+//!
+//! Only the storage of the top-level section is reused, nested sections,
+//! keys and values are allocated as usual. Both pools are thread-safe and
+//! keep at most the given number of items, the rest is dropped when put
+//! back.
+
+use crate::Section;
+use bytes::BytesMut;
+use std::sync::Mutex;
+
+/// A pool of items recycled across requests.
+#[derive(Debug)]
+struct Pool<T> {
+    items: Mutex<Vec<T>>,
+    max: usize,
+}
+
+impl<T> Pool<T> {
+    fn new(max: usize) -> Pool<T> {
+        Pool {
+            items: Mutex::new(Vec::new()),
+            max,
+        }
+    }
+
+    fn get(&self) -> Option<T> {
+        self.items.lock().unwrap_or_else(|e| e.into_inner()).pop()
+    }
+
+    fn put(&self, item: T) {
+        let mut items = self.items.lock().unwrap_or_else(|e| e.into_inner());
+        if items.len() < self.max {
+            items.push(item);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+/// Recycles decoded sections.
+#[derive(Debug)]
+pub struct SectionPool(Pool<Section>);
+
+impl SectionPool {
+    /// Creates a pool keeping at most `max` sections.
+    pub fn new(max: usize) -> SectionPool {
+        SectionPool(Pool::new(max))
+    }
+
+    /// Takes an empty section out of the pool, or creates one.
+    pub fn get(&self) -> Section {
+        self.0.get().unwrap_or_default()
+    }
+
+    /// Gives `section` back to the pool.
+    pub fn put(&self, mut section: Section) {
+        section.clear();
+        self.0.put(section);
+    }
+
+    /// Number of sections waiting in the pool.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Recycles output buffers.
+#[derive(Debug)]
+pub struct BufferPool(Pool<BytesMut>);
+
+impl BufferPool {
+    /// Creates a pool keeping at most `max` buffers.
+    pub fn new(max: usize) -> BufferPool {
+        BufferPool(Pool::new(max))
+    }
+
+    /// Takes an empty buffer out of the pool, or creates one.
+    pub fn get(&self) -> BytesMut {
+        self.0.get().unwrap_or_default()
+    }
+
+    /// Gives `buf` back to the pool.
+    pub fn put(&self, mut buf: BytesMut) {
+        buf.clear();
+        self.0.put(buf);
+    }
+
+    /// Number of buffers waiting in the pool.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::StorageEntry;
+
+    #[test]
+    fn recycles() {
+        let mut request = Section::new();
+        request.insert("height".to_owned(), StorageEntry::U64(1));
+        let blob = crate::write_to_vec(&request);
+
+        let (sections, buffers) = (SectionPool::new(1), BufferPool::new(1));
+        for _ in 0..3 {
+            let mut section = sections.get();
+            assert!(section.is_empty());
+            crate::read_into(&mut &blob[..], &mut section).unwrap();
+            assert_eq!(section, request);
+
+            let mut buf = buffers.get();
+            assert!(buf.is_empty());
+            crate::to_bytes_into(&section, &mut buf);
+            assert_eq!(buf[..], blob[..]);
+
+            let capacity = buf.capacity();
+            buffers.put(buf);
+            let buf = buffers.get();
+            assert_eq!(buf.capacity(), capacity);
+            buffers.put(buf);
+            sections.put(section);
+        }
+
+        sections.put(Section::new());
+        assert_eq!(sections.len(), 1);
+    }
+}
@@ -0,0 +1,270 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Profiles
+//!
+//! Coherent sets of reading and writing behaviors, so applications pick one
+//! instead of tuning each knob.
+//!
+//! ```rust
+//! use portable_storage::{profile::Profile, Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("top_version".to_owned(), StorageEntry::U8(14));
+//! section.insert("current_height".to_owned(), StorageEntry::U64(1));
+//!
+//! let options = Profile::MoneroCompat.options();
+//! let blob = options.write_to_vec(&section);
+//! let decoded = options.read(&blob).unwrap();
+//! assert_eq!(decoded.entries.keys().next().unwrap(), "current_height");
+//! ```
+
+#[cfg(feature = "serde")]
+use crate::de::TypeMatching;
+use crate::{
+    header::{HeaderValidation, StorageBlockHeader},
+    limits::{Checker, Limits},
+    Result, Section,
+};
+use bytes::BytesMut;
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// What to do with a key appearing twice in a section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// The last value replaces the previous ones, keeping the position of
+    /// the first.
+    Overwrite,
+    /// Fail with an `Error::DuplicateKey` error.
+    Reject,
+}
+
+/// The reading and writing knobs.
+///
+/// The default options are the behavior of [`read`](crate::read) and
+/// [`write`](crate::write).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    pub header: HeaderValidation,
+    pub duplicate_keys: DuplicateKeys,
+    /// Reject raw sizes not written in their shortest form.
+    pub canonical_sizes: bool,
+    pub limits: Limits,
+    /// Write keys in byte order, as epee does, rather than in insertion
+    /// order.
+    pub sort_keys: bool,
+    /// Deserialize with [`TypeMatching::Exact`](crate::de::TypeMatching),
+    /// used by [`Options::from_bytes`].
+    pub exact_types: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            header: HeaderValidation::default(),
+            duplicate_keys: DuplicateKeys::Overwrite,
+            canonical_sizes: false,
+            limits: Limits::unlimited(),
+            sort_keys: false,
+            exact_types: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Accepts what monerod accepts and writes what it writes: monerod's
+    /// limits, any raw size width, keys in byte order.
+    MoneroCompat,
+    /// Additionally rejects anything monerod wouldn't write itself:
+    /// duplicate keys, raw sizes wider than needed and, when deserializing,
+    /// entries of another type than the field they're read into.
+    Strict,
+}
+
+impl Profile {
+    pub fn options(self) -> Options {
+        let monero = Options {
+            header: HeaderValidation::Strict,
+            limits: Limits::monero_default(),
+            sort_keys: true,
+            ..Options::default()
+        };
+
+        match self {
+            Profile::MoneroCompat => monero,
+            Profile::Strict => Options {
+                duplicate_keys: DuplicateKeys::Reject,
+                canonical_sizes: true,
+                exact_types: true,
+                ..monero
+            },
+        }
+    }
+}
+
+impl From<Profile> for Options {
+    fn from(profile: Profile) -> Options {
+        profile.options()
+    }
+}
+
+impl Options {
+    /// Reads a storage blob, checking it first when the options ask for
+    /// more than decoding does.
+    pub fn read(&self, data: &[u8]) -> Result<Section> {
+        self.check(data)?;
+        crate::read_with(&mut &data[..], self.header)
+    }
+
+    /// Deserializes a storage blob straight from its bytes, see
+    /// [`from_bytes`](crate::from_bytes), after the same checks as
+    /// [`read`](Options::read).
+    #[cfg(feature = "serde")]
+    pub fn from_bytes<'de, T: Deserialize<'de>>(
+        &self,
+        data: &'de [u8],
+    ) -> std::result::Result<T, serde::de::value::Error> {
+        use serde::de::Error;
+
+        self.check(data).map_err(Error::custom)?;
+        let mut buf = data;
+        StorageBlockHeader::read_with(&mut buf, self.header).map_err(Error::custom)?;
+        let matching = if self.exact_types {
+            TypeMatching::Exact
+        } else {
+            TypeMatching::Lenient
+        };
+        crate::de::from_section_bytes(buf, matching)
+    }
+
+    fn check(&self, data: &[u8]) -> Result<()> {
+        if *self != Options::default() {
+            if data.len() as u64 > self.limits.max_packet_size {
+                return Err(crate::Error::LimitExceeded {
+                    limit: "packet size",
+                    max: self.limits.max_packet_size,
+                });
+            }
+
+            let mut buf = data;
+            StorageBlockHeader::read_with(&mut buf, self.header)?;
+            let mut checker = Checker::new(&self.limits);
+            checker.canonical_sizes = self.canonical_sizes;
+            checker.unique_keys = self.duplicate_keys == DuplicateKeys::Reject;
+            checker.section(&mut buf, 0)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, buf: &mut BytesMut, section: &Section) {
+        let section = if self.sort_keys {
+            let mut section = section.clone();
+            section.sort_keys();
+            Cow::Owned(section)
+        } else {
+            Cow::Borrowed(section)
+        };
+        crate::write(buf, &section);
+    }
+
+    pub fn write_to_vec(&self, section: &Section) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        self.write(&mut buf, section);
+        buf.to_vec()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Error, StorageEntry};
+
+    #[test]
+    fn profiles() {
+        let mut inner = Section::new();
+        inner.insert("b".to_owned(), StorageEntry::U8(1));
+        inner.insert("a".to_owned(), StorageEntry::U8(2));
+        let mut section = Section::new();
+        section.insert("z".to_owned(), StorageEntry::Section(inner));
+        section.insert("y".to_owned(), StorageEntry::Bool(true));
+
+        let blob = Options::default().write_to_vec(&section);
+        assert_eq!(blob, crate::write_to_vec(&section));
+        let sorted = Profile::MoneroCompat.options().write_to_vec(&section);
+        let mut expected = section.clone();
+        expected.sort_keys();
+        assert_eq!(sorted, crate::write_to_vec(&expected));
+        assert_eq!(expected.entries.keys().collect::<Vec<_>>(), ["y", "z"]);
+
+        // The count of entries of the inner section, 2 written on two bytes.
+        let mut wide = blob.clone();
+        let offset = wide.windows(2).position(|w| w == [1, b'z']).unwrap() + 3;
+        wide.splice(offset..offset + 1, [0x09, 0x00]);
+        // The same key twice.
+        let mut duplicated = blob.clone();
+        let offset = duplicated.windows(2).position(|w| w == [1, b'y']).unwrap() + 1;
+        duplicated[offset] = b'z';
+
+        for blob in [&wide, &duplicated] {
+            Profile::MoneroCompat.options().read(blob).unwrap();
+        }
+        assert!(matches!(
+            Profile::Strict.options().read(&wide),
+            Err(Error::NonCanonicalSize(2))
+        ));
+        assert!(matches!(
+            Profile::Strict.options().read(&duplicated),
+            Err(Error::DuplicateKey(key)) if key == "z"
+        ));
+    }
+
+    #[test]
+    fn default_options() {
+        let mut section = Section::new();
+        section.insert("a".to_owned(), StorageEntry::U8(1));
+        let mut blob = crate::write_to_vec(&section);
+        // Corrupt signature_b, which the default header validation allows.
+        blob[4] ^= 0xff;
+
+        assert_eq!(
+            Options::default().read(&blob).unwrap(),
+            crate::read(&mut &blob[..]).unwrap()
+        );
+        assert!(Profile::MoneroCompat.options().read(&blob).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn exact_types() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Height {
+            height: u64,
+        }
+
+        let mut section = Section::new();
+        section.insert("height".to_owned(), StorageEntry::U32(1));
+        let blob = crate::write_to_vec(&section);
+
+        let height = Profile::MoneroCompat.options().from_bytes(&blob).unwrap();
+        assert_eq!(Height { height: 1 }, height);
+        assert!(Profile::Strict
+            .options()
+            .from_bytes::<Height>(&blob)
+            .is_err());
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kani proof harnesses for the security critical primitives.
+//!
+//! Only compiled by `cargo kani`, which sets `cfg(kani)`:
+//!
+//! ```text
+//! cargo kani --harness raw_size_roundtrip
+//! ```
+
+use crate::{
+    header::{self, HeaderValidation},
+    raw_size,
+};
+use bytes::{Buf, BytesMut};
+
+/// Every encodable value reads back unchanged, from exactly the bytes that
+/// were written.
+#[kani::proof]
+fn raw_size_roundtrip() {
+    let value: u64 = kani::any();
+    kani::assume(value <= raw_size::U64_MAX);
+
+    let mut buf = BytesMut::new();
+    raw_size::write(&mut buf, value);
+    let written = buf.len();
+    assert!(written == 1 || written == 2 || written == 4 || written == 8);
+
+    let mut bytes = &buf[..];
+    assert_eq!(raw_size::read(&mut bytes).unwrap(), value);
+    assert_eq!(bytes.remaining(), 0);
+}
+
+/// Reading never panics, consumes exactly the width announced by the mark on
+/// success and never more than the input.
+#[kani::proof]
+fn raw_size_consumption() {
+    let input: [u8; 9] = kani::any();
+    let len: usize = kani::any();
+    kani::assume(len <= input.len());
+
+    let mut buf = &input[..len];
+    match raw_size::read(&mut buf) {
+        Ok(_) => {
+            let consumed = len - buf.remaining();
+            let expected = 1 << (input[0] & raw_size::MARK_MASK);
+            assert_eq!(consumed, expected);
+        }
+        Err(_) => assert!(buf.remaining() <= len),
+    }
+}
+
+/// The header parser doesn't panic on any input, and only accepts what it
+/// validates.
+#[kani::proof]
+fn header_never_panics() {
+    let input: [u8; header::PORTABLE_STORAGE_BLOCK_HEADER_LENGTH + 1] = kani::any();
+    let len: usize = kani::any();
+    kani::assume(len <= input.len());
+
+    for validation in [HeaderValidation::Strict, HeaderValidation::Lenient] {
+        let mut buf = &input[..len];
+        if let Ok(header) = header::StorageBlockHeader::read_with(&mut buf, validation) {
+            assert!(len >= header::PORTABLE_STORAGE_BLOCK_HEADER_LENGTH);
+            assert!(header.is_valid(validation));
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Proptest strategies
+//!
+//! Composable strategies generating valid storage documents, so message
+//! handling can be property-tested against arbitrary input.
+//!
+//! ```rust
+//! use bytes::BytesMut;
+//! use portable_storage::proptest::any_section;
+//! use proptest::{prop_assert_eq, test_runner::TestRunner};
+//!
+//! let mut runner = TestRunner::default();
+//! runner
+//!     .run(&any_section(2, 8), |section| {
+//!         let mut buf = BytesMut::new();
+//!         portable_storage::write(&mut buf, &section);
+//!         prop_assert_eq!(portable_storage::read(&mut &buf[..]).unwrap(), section);
+//!         Ok(())
+//!     })
+//!     .unwrap();
+//! ```
+//!
+//! Generated arrays are homogeneous and typed even when empty, and doubles
+//! are never NaN so documents compare equal to themselves.
+
+use crate::{Array, Section, StorageEntry, SERIALIZE_FLAG_ARRAY, SERIALIZE_TYPE_OBJECT};
+use ::proptest::{collection::vec, num::f64, prelude::*};
+
+/// How many elements generated arrays hold at most.
+const MAX_ELEMENTS: usize = 8;
+
+/// Strategy for sections nested at most `depth` levels, each with up to
+/// `size` entries.
+pub fn any_section(depth: u32, size: usize) -> BoxedStrategy<Section> {
+    vec((any_name(), entry(depth, size)), 0..=size)
+        .prop_map(|entries| {
+            let mut section = Section::with_capacity(entries.len());
+            for (name, entry) in entries {
+                section.insert(name, entry);
+            }
+            section
+        })
+        .boxed()
+}
+
+/// Strategy for any entry, including arrays and small nested sections.
+pub fn any_entry() -> BoxedStrategy<StorageEntry> {
+    entry(2, 4)
+}
+
+/// Strategy for entries that are neither sections nor arrays.
+pub fn any_scalar() -> BoxedStrategy<StorageEntry> {
+    (0..SCALAR_KINDS).prop_flat_map(scalar).boxed()
+}
+
+/// Strategy for homogeneous arrays of `element`s.
+///
+/// Every value produced by `element` must have the same storage type, which
+/// empty arrays are typed with too.
+pub fn array_of(element: BoxedStrategy<StorageEntry>) -> BoxedStrategy<Array> {
+    // The extra element only carries the type, for when there are no others.
+    (element.clone(), vec(element, 0..=MAX_ELEMENTS))
+        .prop_map(|(sample, entries)| {
+            let mut array = typed(sample.serialize_type());
+            for entry in entries {
+                array
+                    .push(entry)
+                    .expect("array elements must have the same type");
+            }
+            array
+        })
+        .boxed()
+}
+
+/// Strategy for keys, as found in monero messages.
+pub fn any_name() -> BoxedStrategy<String> {
+    "[a-z][a-z0-9_]{0,23}".boxed()
+}
+
+const SCALAR_KINDS: u8 = 11;
+
+fn entry(depth: u32, size: usize) -> BoxedStrategy<StorageEntry> {
+    if depth == 0 {
+        return prop_oneof![
+            3 => any_scalar(),
+            1 => any_scalar_array(),
+        ]
+        .boxed();
+    }
+
+    let section = any_section(depth - 1, size);
+    prop_oneof![
+        6 => any_scalar(),
+        2 => any_scalar_array(),
+        1 => section.clone().prop_map(StorageEntry::Section),
+        1 => vec(section, 0..=MAX_ELEMENTS).prop_map(|sections| {
+            let mut array = typed(SERIALIZE_TYPE_OBJECT);
+            for section in sections {
+                array.push(StorageEntry::Section(section)).unwrap();
+            }
+            StorageEntry::Array(array)
+        }),
+    ]
+    .boxed()
+}
+
+fn any_scalar_array() -> BoxedStrategy<StorageEntry> {
+    (0..SCALAR_KINDS)
+        .prop_flat_map(|kind| {
+            vec(scalar(kind), 0..=MAX_ELEMENTS).prop_map(move |entries| {
+                let mut array = typed(serialize_type(kind));
+                for entry in entries {
+                    array.push(entry).unwrap();
+                }
+                StorageEntry::Array(array)
+            })
+        })
+        .boxed()
+}
+
+/// An empty array that already knows its element type.
+fn typed(serialize_type: u8) -> Array {
+    let mut array = Array::new();
+    array.serialize_type = Some(serialize_type | SERIALIZE_FLAG_ARRAY);
+    array
+}
+
+/// Scalar kinds follow the order of the serialize types, see `scalar`.
+fn serialize_type(kind: u8) -> u8 {
+    kind + 1
+}
+
+fn scalar(kind: u8) -> BoxedStrategy<StorageEntry> {
+    match kind {
+        0 => any::<i64>().prop_map(StorageEntry::I64).boxed(),
+        1 => any::<i32>().prop_map(StorageEntry::I32).boxed(),
+        2 => any::<i16>().prop_map(StorageEntry::I16).boxed(),
+        3 => any::<i8>().prop_map(StorageEntry::I8).boxed(),
+        4 => any::<u64>().prop_map(StorageEntry::U64).boxed(),
+        5 => any::<u32>().prop_map(StorageEntry::U32).boxed(),
+        6 => any::<u16>().prop_map(StorageEntry::U16).boxed(),
+        7 => any::<u8>().prop_map(StorageEntry::U8).boxed(),
+        8 => (f64::NORMAL | f64::SUBNORMAL | f64::ZERO | f64::INFINITE)
+            .prop_map(StorageEntry::Double)
+            .boxed(),
+        9 => vec(any::<u8>(), 0..64)
+            .prop_map(|v| StorageEntry::Buf(v.into()))
+            .boxed(),
+        _ => any::<bool>().prop_map(StorageEntry::Bool).boxed(),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    proptest! {
+        #[test]
+        fn roundtrip(section in any_section(3, 6)) {
+            let mut buf = BytesMut::new();
+            crate::write(&mut buf, &section);
+            prop_assert_eq!(crate::read(&mut &buf[..]).unwrap(), section);
+        }
+
+        #[test]
+        fn scalar_arrays(array in array_of(scalar(5))) {
+            prop_assert!(array.len() <= MAX_ELEMENTS);
+            prop_assert_eq!(array.element_kind(), Some(crate::SerializeType::U32));
+        }
+    }
+}
@@ -45,6 +45,7 @@
 
 use crate::Error;
 use bytes::{Buf, BufMut, BytesMut};
+use std::convert::TryFrom;
 
 /// The size in bits of the raw size marker.
 pub const MARK_BIT_SIZE: usize = 2;
@@ -98,13 +99,50 @@ pub fn read<B: Buf>(buf: &mut B) -> Result<u64, Error> {
     }
 }
 
+/// The number of bytes `val` takes as a "raw size" integer.
+pub fn encoded_len(val: u64) -> usize {
+    if val <= U8_MAX {
+        1
+    } else if val <= U16_MAX {
+        2
+    } else if val <= U32_MAX {
+        4
+    } else {
+        8
+    }
+}
+
+/// Reads a "raw size" value used as a length or a count.
+///
+/// # Errors
+///
+/// Besides the errors of [`read`], returns an `Error::StorageEntryTooBig`
+/// error if the value doesn't fit in a `usize`, as on 32-bit targets.
+pub fn read_usize<B: Buf>(buf: &mut B) -> Result<usize, Error> {
+    let val = read::<B>(buf)?;
+    usize::try_from(val).map_err(|_| Error::StorageEntryTooBig(val))
+}
+
 /// Writes the value onto `buf` as a "raw size" integer.
 ///
 /// # Panics
 ///
-/// This function will panic if the provided `val` value is higher or equal to
-/// `U64_MAX` which is the maximum value that can be stored.
+/// This function will panic if the provided `val` value is higher than
+/// `U64_MAX` which is the maximum value that can be stored, see
+/// [`try_write`] for a fallible version.
 pub fn write(buf: &mut BytesMut, val: u64) {
+    if let Err(e) = try_write(buf, val) {
+        panic!("{}", e);
+    }
+}
+
+/// Writes the value onto `buf` as a "raw size" integer.
+///
+/// # Errors
+///
+/// Returns an `Error::LengthOverflow` error if `val` is higher than
+/// `U64_MAX`, nothing is written then.
+pub fn try_write(buf: &mut BytesMut, val: u64) -> Result<(), Error> {
     if val <= U8_MAX {
         buf.reserve(1);
         buf.put_u8(((val as u8) << 2) | MARK_U8);
@@ -116,10 +154,12 @@ pub fn write(buf: &mut BytesMut, val: u64) {
         buf.put_u32_le(((val as u32) << 2) | MARK_U32 as u32);
     } else if val <= U64_MAX {
         buf.reserve(8);
-        buf.put_u64_le(((val as u64) << 2) | MARK_U64 as u64);
+        buf.put_u64_le((val << 2) | MARK_U64 as u64);
     } else {
-        panic!("the value is too big to be stored on a raw size variable integer");
+        return Err(Error::LengthOverflow(val));
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -134,6 +174,7 @@ pub mod tests {
             let mut buf = BytesMut::new();
             write(&mut buf, *value);
             assert_eq!(buf.len(), *size_in_bytes);
+            assert_eq!(encoded_len(*value), *size_in_bytes);
 
             let mut buf = buf.freeze();
             let readed_value = read(&mut buf).unwrap();
@@ -141,6 +182,29 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn boundaries() {
+        let mut buf = BytesMut::new();
+        assert!(matches!(
+            try_write(&mut buf, U64_MAX + 1),
+            Err(Error::LengthOverflow(v)) if v == U64_MAX + 1
+        ));
+        assert!(buf.is_empty());
+
+        write(&mut buf, U32_MAX);
+        assert_eq!(read_usize(&mut &buf[..]).unwrap(), U32_MAX as usize);
+
+        // The smallest size that doesn't fit on 32-bit targets.
+        let mut buf = BytesMut::new();
+        write(&mut buf, 1 << 32);
+        let result = read_usize(&mut &buf[..]);
+        if cfg!(target_pointer_width = "32") {
+            assert!(matches!(result, Err(Error::StorageEntryTooBig(v)) if v == 1 << 32));
+        } else {
+            assert_eq!(result.unwrap() as u64, 1 << 32);
+        }
+    }
+
     #[test]
     #[should_panic]
     fn too_big() {
@@ -0,0 +1,255 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Redaction
+//!
+//! Keeps sensitive values, such as keys and addresses, out of logs. A
+//! [`Redactor`] marks entries by key or by path, and the printers replace
+//! the values of marked entries with [`PLACEHOLDER`]:
+//! [`Section::to_text_redacted`], `Section::to_json_redacted` (behind the
+//! `json` feature) and the `Display` and `Debug` implementations of
+//! [`Redacted`].
+//!
+//! ```rust
+//! use portable_storage::{redact::Redactor, Section, StorageEntry};
+//!
+//! let mut node = Section::new();
+//! node.insert("peer_id".to_owned(), StorageEntry::U64(42));
+//! node.insert("my_port".to_owned(), StorageEntry::U32(18080));
+//! let mut section = Section::new();
+//! section.insert("node_data".to_owned(), StorageEntry::Section(node));
+//!
+//! let redactor = Redactor::new().path("node_data.peer_id");
+//! let text = redactor.display(&section).to_string();
+//! assert!(text.contains("peer_id: u64 <redacted>"));
+//! assert!(text.contains("my_port: u32 18080"));
+//! ```
+
+use crate::{text::KeyOrder, Section, StorageEntry};
+use std::{collections::HashSet, fmt};
+
+/// What the values of sensitive entries are replaced with.
+pub const PLACEHOLDER: &str = "<redacted>";
+
+/// The set of sensitive keys and paths.
+///
+/// Paths are the keys leading to an entry from the root section joined
+/// with `.`, array elements share the path of their array, so
+/// `local_peerlist_new.adr` names the address of every peer.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Redactor {
+    keys: HashSet<String>,
+    paths: HashSet<String>,
+}
+
+impl Redactor {
+    pub fn new() -> Redactor {
+        Default::default()
+    }
+
+    /// Marks the entries named `key` at any depth.
+    pub fn key<S: Into<String>>(mut self, key: S) -> Redactor {
+        self.keys.insert(key.into());
+        self
+    }
+
+    /// Marks the entries at `path`.
+    pub fn path<S: Into<String>>(mut self, path: S) -> Redactor {
+        self.paths.insert(path.into());
+        self
+    }
+
+    /// Whether the entry at `path` is marked.
+    pub fn is_sensitive(&self, path: &str) -> bool {
+        let key = path.rsplit('.').next().unwrap_or(path);
+        self.keys.contains(key) || self.paths.contains(path)
+    }
+
+    /// Wraps `section` to print it redacted.
+    pub fn display<'a>(&'a self, section: &'a Section) -> Redacted<'a> {
+        Redacted {
+            section,
+            redactor: self,
+        }
+    }
+}
+
+/// The path of the entry `key` of the section at `path`.
+pub(crate) fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Whether `path` is strictly inside the entry at `ancestor`.
+pub(crate) fn within(path: &str, ancestor: &str) -> bool {
+    path.len() > ancestor.len()
+        && path.starts_with(ancestor)
+        && path.as_bytes()[ancestor.len()] == b'.'
+}
+
+/// A section printed with its sensitive values replaced, in the stable
+/// text format by `Display` and as nested maps by `Debug`.
+#[derive(Clone, Copy)]
+pub struct Redacted<'a> {
+    section: &'a Section,
+    redactor: &'a Redactor,
+}
+
+impl<'a> fmt::Display for Redacted<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(
+            &self
+                .section
+                .to_text_redacted(KeyOrder::Insertion, self.redactor),
+        )
+    }
+}
+
+impl<'a> fmt::Debug for Redacted<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        DebugSection {
+            section: self.section,
+            redactor: self.redactor,
+            path: "",
+        }
+        .fmt(f)
+    }
+}
+
+struct Placeholder;
+
+impl fmt::Debug for Placeholder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(PLACEHOLDER)
+    }
+}
+
+struct DebugSection<'a> {
+    section: &'a Section,
+    redactor: &'a Redactor,
+    path: &'a str,
+}
+
+impl<'a> fmt::Debug for DebugSection<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (name, entry) in self.section.entries.iter() {
+            let path = join(self.path, name);
+            if self.redactor.is_sensitive(&path) {
+                map.entry(name, &Placeholder);
+            } else {
+                map.entry(
+                    name,
+                    &DebugEntry {
+                        entry,
+                        redactor: self.redactor,
+                        path: &path,
+                    },
+                );
+            }
+        }
+        map.finish()
+    }
+}
+
+struct DebugEntry<'a> {
+    entry: &'a StorageEntry,
+    redactor: &'a Redactor,
+    path: &'a str,
+}
+
+impl<'a> fmt::Debug for DebugEntry<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.entry {
+            StorageEntry::Section(section) => DebugSection {
+                section,
+                redactor: self.redactor,
+                path: self.path,
+            }
+            .fmt(f),
+            StorageEntry::Array(array) => f
+                .debug_list()
+                .entries(array.array.iter().map(|entry| DebugEntry {
+                    entry,
+                    redactor: self.redactor,
+                    path: self.path,
+                }))
+                .finish(),
+            entry => entry.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::Array;
+
+    fn section() -> Section {
+        let mut peer = Section::new();
+        peer.insert("host".to_owned(), StorageEntry::Buf(b"10.0.0.1"[..].into()));
+        peer.insert("id".to_owned(), StorageEntry::U64(7));
+        let mut peers = Array::new();
+        peers.push(StorageEntry::Section(peer.clone())).unwrap();
+        peers.push(StorageEntry::Section(peer)).unwrap();
+
+        let mut section = Section::new();
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+        section.insert(
+            "spend_key".to_owned(),
+            StorageEntry::Buf(vec![1; 32].into()),
+        );
+        section
+    }
+
+    #[test]
+    fn printers() {
+        let section = section();
+        let redactor = Redactor::new().key("spend_key").path("peers.host");
+        assert!(redactor.is_sensitive("a.b.spend_key"));
+        assert!(!redactor.is_sensitive("host"));
+
+        let text = redactor.display(&section).to_string();
+        assert_eq!(text.matches(PLACEHOLDER).count(), 3);
+        assert!(text.contains("id: u64 7"));
+        assert!(!text.contains("0x0101"));
+
+        let debug = format!("{:?}", redactor.display(&section));
+        assert_eq!(
+            debug,
+            "{\"peers\": [{\"host\": <redacted>, \"id\": U64(7)}, \
+             {\"host\": <redacted>, \"id\": U64(7)}], \"spend_key\": <redacted>}"
+        );
+
+        assert_eq!(
+            section.to_text_redacted(KeyOrder::Sorted, &Redactor::new()),
+            section.to_text(KeyOrder::Sorted)
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json() {
+        let config = crate::json::JsonConfig::default();
+        let redactor = Redactor::new().key("host");
+        let value = section().to_json_redacted(&config, &redactor);
+        assert_eq!(value["peers"][1]["host"], PLACEHOLDER);
+        assert_eq!(value["peers"][1]["id"]["$u64"], 7);
+        assert_eq!(value["spend_key"], "01".repeat(32));
+    }
+}
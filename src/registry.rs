@@ -0,0 +1,190 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Schema registry
+//!
+//! Maps protocol command identifiers to validators, so a dispatcher can
+//! decode and validate an incoming blob for its command with one call.
+//!
+//! ```rust
+//! use bytes::BytesMut;
+//! use portable_storage::{
+//!     registry::{Registry, Rejection},
+//!     schema::{Schema, SchemaType},
+//!     Section,
+//! };
+//!
+//! const COMMAND_TIMED_SYNC: u32 = 1002;
+//!
+//! let mut registry = Registry::new();
+//! registry.register_schema(
+//!     COMMAND_TIMED_SYNC,
+//!     Schema::new().required("payload_data", SchemaType::Section(Schema::new())),
+//! );
+//!
+//! let mut buf = BytesMut::new();
+//! portable_storage::write(&mut buf, &Section::new());
+//! assert!(matches!(
+//!     registry.decode(COMMAND_TIMED_SYNC, &buf),
+//!     Err(Rejection::Invalid(_))
+//! ));
+//! assert!(matches!(registry.decode(1, &buf), Err(Rejection::UnknownCommand(1))));
+//! ```
+
+#[cfg(feature = "serde")]
+use crate::{from_section, schema::ViolationKind};
+use crate::{
+    schema::{Schema, Violation},
+    Error, Section,
+};
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+
+type Validator = Box<dyn Fn(&Section) -> Vec<Violation> + Send + Sync>;
+
+/// Why a blob was rejected by a [`Registry`].
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Rejection {
+    #[error("no validator is registered for command {}", _0)]
+    UnknownCommand(u32),
+    #[error("the blob couldn't be decoded: {}", _0)]
+    Decode(Error),
+    #[error("the section has {} violation(s), first: {}", _0.len(), _0[0])]
+    Invalid(Vec<Violation>),
+}
+
+/// Validators keyed by command identifier.
+#[derive(Default)]
+pub struct Registry {
+    validators: HashMap<u32, Validator>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Default::default()
+    }
+
+    /// Validates sections of `command` against `schema`, replacing any
+    /// previously registered validator.
+    pub fn register_schema(&mut self, command: u32, schema: Schema) {
+        self.register_fn(command, move |section| schema.validate(section));
+    }
+
+    /// Validates sections of `command` by deserializing them into `T`.
+    #[cfg(feature = "serde")]
+    pub fn register_type<T: DeserializeOwned>(&mut self, command: u32) {
+        self.register_fn(command, |section| {
+            match from_section::<T>(section.clone()) {
+                Ok(_) => Vec::new(),
+                Err(e) => vec![Violation {
+                    path: String::new(),
+                    kind: ViolationKind::Custom(e.to_string()),
+                }],
+            }
+        });
+    }
+
+    /// Validates sections of `command` with a custom function returning the
+    /// violations found.
+    pub fn register_fn<F>(&mut self, command: u32, validator: F)
+    where
+        F: Fn(&Section) -> Vec<Violation> + Send + Sync + 'static,
+    {
+        self.validators.insert(command, Box::new(validator));
+    }
+
+    /// Returns `true` if a validator is registered for `command`.
+    pub fn contains(&self, command: u32) -> bool {
+        self.validators.contains_key(&command)
+    }
+
+    /// Validates a decoded section of `command`.
+    pub fn validate(&self, command: u32, section: &Section) -> Result<(), Rejection> {
+        let validator = self
+            .validators
+            .get(&command)
+            .ok_or(Rejection::UnknownCommand(command))?;
+
+        let violations = validator(section);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Rejection::Invalid(violations))
+        }
+    }
+
+    /// Decodes a storage blob of `command` and validates it.
+    pub fn decode(&self, command: u32, mut buf: &[u8]) -> Result<Section, Rejection> {
+        if !self.contains(command) {
+            return Err(Rejection::UnknownCommand(command));
+        }
+
+        let section = crate::read(&mut buf).map_err(Rejection::Decode)?;
+        self.validate(command, &section)?;
+        Ok(section)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{schema::SchemaType, StorageEntry};
+    use bytes::BytesMut;
+
+    fn status() -> BytesMut {
+        let mut section = Section::new();
+        section.insert("status".to_owned(), StorageEntry::U64(1));
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &section);
+        buf
+    }
+
+    #[test]
+    fn dispatch() {
+        let mut registry = Registry::new();
+        registry.register_schema(1002, Schema::new().required("status", SchemaType::U64));
+
+        let buf = status();
+        assert!(registry.decode(1002, &buf).is_ok());
+        assert!(matches!(
+            registry.decode(1002, &buf[..10]),
+            Err(Rejection::Decode(_))
+        ));
+        assert!(matches!(
+            registry.validate(1004, &Section::new()),
+            Err(Rejection::UnknownCommand(1004))
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn typed() {
+        #[derive(serde::Deserialize)]
+        #[allow(dead_code)]
+        struct Ping {
+            status: u64,
+        }
+
+        let mut registry = Registry::new();
+        registry.register_type::<Ping>(1003);
+
+        assert!(registry.decode(1003, &status()).is_ok());
+        let empty = Section::new();
+        assert!(
+            matches!(registry.validate(1003, &empty), Err(Rejection::Invalid(ref v)) if v.len() == 1)
+        );
+    }
+}
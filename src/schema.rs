@@ -0,0 +1,479 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Runtime schemas
+//!
+//! A [`Schema`] describes the keys a section is expected to have, their
+//! types, whether they're optional and how sections and arrays nest. Checking
+//! a decoded section against it yields every violation found, so malformed
+//! messages can be rejected before further processing.
+//!
+//! ```rust
+//! use portable_storage::{
+//!     schema::{Schema, SchemaType},
+//!     Section, StorageEntry,
+//! };
+//!
+//! let schema = Schema::new()
+//!     .required("height", SchemaType::U64)
+//!     .optional("top_id", SchemaType::String);
+//!
+//! let mut section = Section::new();
+//! section.insert("height".to_owned(), StorageEntry::U32(1));
+//!
+//! let violations = schema.validate(&section);
+//! assert_eq!(violations[0].to_string(), "height: expected u64, found u32");
+//! ```
+//!
+//! Schemas can also enforce the order of the keys, for consumers relying on
+//! byte-stable output. [`Schema::record`] captures the layout of a reference
+//! message, order included, to detect peers that reorder fields later on:
+//!
+//! ```rust
+//! use portable_storage::{schema::Schema, Section, StorageEntry};
+//!
+//! let mut reference = Section::new();
+//! reference.insert("current_height".to_owned(), StorageEntry::U64(1));
+//! reference.insert("top_version".to_owned(), StorageEntry::U8(14));
+//! let schema = Schema::record(&reference);
+//!
+//! let mut section = Section::new();
+//! section.insert("top_version".to_owned(), StorageEntry::U8(14));
+//! section.insert("current_height".to_owned(), StorageEntry::U64(2));
+//!
+//! let violations = schema.validate(&section);
+//! assert_eq!(
+//!     violations[0].to_string(),
+//!     "current_height: out of order, found after `top_version`"
+//! );
+//! ```
+
+use crate::{
+    explain::type_name, Section, StorageEntry, SERIALIZE_FLAG_ARRAY, SERIALIZE_TYPE_ARRAY,
+    SERIALIZE_TYPE_BOOL, SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32,
+    SERIALIZE_TYPE_INT64, SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING,
+    SERIALIZE_TYPE_UINT16, SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use linked_hash_map::LinkedHashMap;
+use std::fmt;
+
+/// The expected type of an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaType {
+    I64,
+    I32,
+    I16,
+    I8,
+    U64,
+    U32,
+    U16,
+    U8,
+    Double,
+    String,
+    Bool,
+    Section(Schema),
+    Array(Box<SchemaType>),
+}
+
+impl SchemaType {
+    /// Shorthand for `SchemaType::Array(Box::new(element))`.
+    pub fn array(element: SchemaType) -> SchemaType {
+        SchemaType::Array(Box::new(element))
+    }
+
+    fn serialize_type(&self) -> u8 {
+        match self {
+            SchemaType::I64 => SERIALIZE_TYPE_INT64,
+            SchemaType::I32 => SERIALIZE_TYPE_INT32,
+            SchemaType::I16 => SERIALIZE_TYPE_INT16,
+            SchemaType::I8 => SERIALIZE_TYPE_INT8,
+            SchemaType::U64 => SERIALIZE_TYPE_UINT64,
+            SchemaType::U32 => SERIALIZE_TYPE_UINT32,
+            SchemaType::U16 => SERIALIZE_TYPE_UINT16,
+            SchemaType::U8 => SERIALIZE_TYPE_UINT8,
+            SchemaType::Double => SERIALIZE_TYPE_DOUBLE,
+            SchemaType::String => SERIALIZE_TYPE_STRING,
+            SchemaType::Bool => SERIALIZE_TYPE_BOOL,
+            SchemaType::Section(_) => SERIALIZE_TYPE_OBJECT,
+            SchemaType::Array(_) => SERIALIZE_TYPE_ARRAY,
+        }
+    }
+}
+
+impl fmt::Display for SchemaType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaType::Array(element) => write!(f, "array of {}", element),
+            _ => f.write_str(type_name(self.serialize_type())),
+        }
+    }
+}
+
+/// A key of a [`Schema`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub ty: SchemaType,
+    pub optional: bool,
+}
+
+/// The expected layout of a section.
+///
+/// Keys not described by the schema are accepted unless
+/// [`deny_unknown`](Schema::deny_unknown) is set, matching how epee ignores
+/// fields it doesn't know about.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Schema {
+    pub fields: LinkedHashMap<String, Field>,
+    pub deny_unknown: bool,
+    /// Whether the keys must appear in the order of `fields`.
+    pub ordered: bool,
+}
+
+impl Schema {
+    pub fn new() -> Schema {
+        Default::default()
+    }
+
+    /// Adds a key that must be present.
+    pub fn required<N: Into<String>>(mut self, name: N, ty: SchemaType) -> Schema {
+        self.fields.insert(
+            name.into(),
+            Field {
+                ty,
+                optional: false,
+            },
+        );
+        self
+    }
+
+    /// Adds a key that may be missing.
+    pub fn optional<N: Into<String>>(mut self, name: N, ty: SchemaType) -> Schema {
+        self.fields
+            .insert(name.into(), Field { ty, optional: true });
+        self
+    }
+
+    /// Reports keys that aren't described by the schema as violations.
+    pub fn deny_unknown(mut self) -> Schema {
+        self.deny_unknown = true;
+        self
+    }
+
+    /// Reports keys of the schema appearing in another order than the one
+    /// they were added in. Unknown keys may appear anywhere.
+    pub fn ordered(mut self) -> Schema {
+        self.ordered = true;
+        self
+    }
+
+    /// Records the layout of `section` as an ordered schema: every key is
+    /// required, with the type and at the position it has in `section`.
+    ///
+    /// Array elements are described by the first element, or by the type of
+    /// the array when it's empty. Empty arrays without a type are recorded
+    /// as arrays of sections.
+    pub fn record(section: &Section) -> Schema {
+        let mut schema = Schema::new().ordered();
+        for (name, entry) in section.entries.iter() {
+            schema = schema.required(name.clone(), record_type(entry));
+        }
+        schema
+    }
+
+    /// Checks `section` against this schema and returns every violation
+    /// found, in key order. An empty list means the section is valid.
+    pub fn validate(&self, section: &Section) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        self.validate_section("", section, &mut violations);
+        violations
+    }
+
+    /// Returns `true` if `section` has no violations.
+    pub fn is_valid(&self, section: &Section) -> bool {
+        self.validate(section).is_empty()
+    }
+
+    fn validate_section(&self, prefix: &str, section: &Section, out: &mut Vec<Violation>) {
+        for (name, field) in self.fields.iter() {
+            let path = join(prefix, name);
+            match section.entries.get(name) {
+                Some(entry) => validate_entry(&path, &field.ty, entry, out),
+                None if !field.optional => out.push(Violation {
+                    path,
+                    kind: ViolationKind::Missing,
+                }),
+                None => (),
+            }
+        }
+
+        if self.ordered {
+            let mut last: Option<(usize, &str)> = None;
+            for name in section.entries.keys() {
+                let position = match self.fields.keys().position(|field| field == name) {
+                    Some(position) => position,
+                    None => continue,
+                };
+                match last {
+                    Some((previous, after)) if position < previous => out.push(Violation {
+                        path: join(prefix, name),
+                        kind: ViolationKind::OutOfOrder {
+                            after: after.to_owned(),
+                        },
+                    }),
+                    _ => last = Some((position, name)),
+                }
+            }
+        }
+
+        if self.deny_unknown {
+            for name in section.entries.keys() {
+                if !self.fields.contains_key(name) {
+                    out.push(Violation {
+                        path: join(prefix, name),
+                        kind: ViolationKind::Unknown,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn validate_entry(path: &str, ty: &SchemaType, entry: &StorageEntry, out: &mut Vec<Violation>) {
+    let mismatch = |out: &mut Vec<Violation>| {
+        out.push(Violation {
+            path: path.to_owned(),
+            kind: ViolationKind::WrongType {
+                expected: ty.to_string(),
+                found: describe(entry),
+            },
+        })
+    };
+
+    match (ty, entry) {
+        (SchemaType::Section(schema), StorageEntry::Section(section)) => {
+            schema.validate_section(path, section, out)
+        }
+        (SchemaType::Array(element), StorageEntry::Array(array)) => {
+            if let Some(serialize_type) = array.serialize_type {
+                if serialize_type & !SERIALIZE_FLAG_ARRAY != element.serialize_type() {
+                    return mismatch(out);
+                }
+            }
+
+            for (i, entry) in array.array.iter().enumerate() {
+                validate_entry(&format!("{}[{}]", path, i), element, entry, out);
+            }
+        }
+        _ if ty.serialize_type() == entry.serialize_type() => (),
+        _ => mismatch(out),
+    }
+}
+
+fn record_type(entry: &StorageEntry) -> SchemaType {
+    match entry {
+        StorageEntry::Section(section) => SchemaType::Section(Schema::record(section)),
+        StorageEntry::Array(array) => {
+            let element = match (array.array.first(), array.serialize_type) {
+                (Some(first), _) => record_type(first),
+                (None, Some(t)) => scalar_type(t & !SERIALIZE_FLAG_ARRAY)
+                    .unwrap_or_else(|| SchemaType::Section(Schema::new())),
+                (None, None) => SchemaType::Section(Schema::new()),
+            };
+            SchemaType::array(element)
+        }
+        entry => scalar_type(entry.serialize_type()).unwrap(),
+    }
+}
+
+fn scalar_type(serialize_type: u8) -> Option<SchemaType> {
+    Some(match serialize_type {
+        SERIALIZE_TYPE_INT64 => SchemaType::I64,
+        SERIALIZE_TYPE_INT32 => SchemaType::I32,
+        SERIALIZE_TYPE_INT16 => SchemaType::I16,
+        SERIALIZE_TYPE_INT8 => SchemaType::I8,
+        SERIALIZE_TYPE_UINT64 => SchemaType::U64,
+        SERIALIZE_TYPE_UINT32 => SchemaType::U32,
+        SERIALIZE_TYPE_UINT16 => SchemaType::U16,
+        SERIALIZE_TYPE_UINT8 => SchemaType::U8,
+        SERIALIZE_TYPE_DOUBLE => SchemaType::Double,
+        SERIALIZE_TYPE_STRING => SchemaType::String,
+        SERIALIZE_TYPE_BOOL => SchemaType::Bool,
+        _ => return None,
+    })
+}
+
+fn describe(entry: &StorageEntry) -> String {
+    match entry {
+        StorageEntry::Array(array) => match (array.array.first(), array.serialize_type) {
+            (Some(first), _) => format!("array of {}", describe(first)),
+            (None, Some(t)) => format!("array of {}", type_name(t & !SERIALIZE_FLAG_ARRAY)),
+            (None, None) => "array".to_owned(),
+        },
+        _ => type_name(entry.serialize_type()).to_owned(),
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+/// A mismatch between a section and a [`Schema`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Path of the offending entry, e.g. `payload_data.top_id`.
+    pub path: String,
+    pub kind: ViolationKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// A required key is missing.
+    Missing,
+    /// The key isn't described by a schema that denies unknown keys.
+    Unknown,
+    /// The entry has a different type than expected.
+    WrongType { expected: String, found: String },
+    /// The key of an ordered schema comes after `after`, which the schema
+    /// puts later.
+    OutOfOrder { after: String },
+    /// Reported by a custom validator, e.g. a failed deserialization.
+    Custom(String),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ViolationKind::Missing => write!(f, "{}: missing required key", self.path),
+            ViolationKind::Unknown => write!(f, "{}: unknown key", self.path),
+            ViolationKind::WrongType { expected, found } => {
+                write!(f, "{}: expected {}, found {}", self.path, expected, found)
+            }
+            ViolationKind::OutOfOrder { after } => {
+                write!(f, "{}: out of order, found after `{}`", self.path, after)
+            }
+            ViolationKind::Custom(message) if self.path.is_empty() => f.write_str(message),
+            ViolationKind::Custom(message) => write!(f, "{}: {}", self.path, message),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::Array;
+
+    #[test]
+    fn violations() {
+        let schema = Schema::new()
+            .required(
+                "node_data",
+                SchemaType::Section(
+                    Schema::new()
+                        .required("my_port", SchemaType::U32)
+                        .required("peer_id", SchemaType::U64)
+                        .deny_unknown(),
+                ),
+            )
+            .required("ids", SchemaType::array(SchemaType::U32))
+            .optional("support_flags", SchemaType::U32);
+
+        let mut node_data = Section::new();
+        node_data.insert("my_port".to_owned(), StorageEntry::U16(18080));
+        node_data.insert("extra".to_owned(), StorageEntry::Bool(true));
+
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U64(1)).unwrap();
+
+        let mut section = Section::new();
+        section.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+        section.insert("ids".to_owned(), StorageEntry::Array(ids));
+
+        let violations: Vec<String> = schema
+            .validate(&section)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                "node_data.my_port: expected u32, found u16",
+                "node_data.peer_id: missing required key",
+                "node_data.extra: unknown key",
+                "ids: expected array of u32, found array of u64",
+            ]
+        );
+
+        let mut node_data = Section::new();
+        node_data.insert("my_port".to_owned(), StorageEntry::U32(18080));
+        node_data.insert("peer_id".to_owned(), StorageEntry::U64(1));
+        let mut section = Section::new();
+        section.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+        section.insert("ids".to_owned(), StorageEntry::Array(Array::new()));
+        section.insert("unknown".to_owned(), StorageEntry::U8(0));
+        assert!(schema.is_valid(&section));
+    }
+
+    #[test]
+    fn order() {
+        let mut node_data = Section::new();
+        node_data.insert("my_port".to_owned(), StorageEntry::U32(18080));
+        node_data.insert("peer_id".to_owned(), StorageEntry::U64(1));
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U32(1)).unwrap();
+
+        let mut reference = Section::new();
+        reference.insert("ids".to_owned(), StorageEntry::Array(ids));
+        reference.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+        reference.insert("top".to_owned(), StorageEntry::Bool(true));
+
+        let schema = Schema::record(&reference);
+        assert_eq!(schema.fields["ids"].ty, SchemaType::array(SchemaType::U32));
+        assert!(schema.is_valid(&reference));
+
+        let mut node_data = Section::new();
+        node_data.insert("peer_id".to_owned(), StorageEntry::U64(1));
+        node_data.insert("extra".to_owned(), StorageEntry::U8(0));
+        node_data.insert("my_port".to_owned(), StorageEntry::U32(18080));
+        let mut section = Section::new();
+        section.insert("top".to_owned(), StorageEntry::Bool(true));
+        section.insert("ids".to_owned(), StorageEntry::Array(Array::new()));
+        section.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+
+        let violations: Vec<String> = schema
+            .validate(&section)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                "node_data.my_port: out of order, found after `peer_id`",
+                "ids: out of order, found after `top`",
+                "node_data: out of order, found after `top`",
+            ]
+        );
+
+        let unordered = Schema {
+            ordered: false,
+            ..schema
+        };
+        // The nested schema is still ordered.
+        assert_eq!(unordered.validate(&section).len(), 1);
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Selectors
+//!
+//! A small jq-like query language to pick values out of a section without
+//! hand-rolling the traversal:
+//!
+//! ```rust
+//! use portable_storage::{Array, Section, StorageEntry};
+//!
+//! let mut peers = Array::new();
+//! for port in [18080u32, 18081].iter() {
+//!     let mut peer = Section::new();
+//!     peer.insert("port".to_owned(), StorageEntry::U32(*port));
+//!     peers.push(StorageEntry::Section(peer)).unwrap();
+//! }
+//! let mut section = Section::new();
+//! section.insert("peers".to_owned(), StorageEntry::Array(peers));
+//!
+//! let ports: Vec<_> = section.select("peers[*].port").unwrap().collect();
+//! assert_eq!(ports, [&StorageEntry::U32(18080), &StorageEntry::U32(18081)]);
+//! ```
+//!
+//! A selector is a list of keys separated by `.`, each optionally followed by
+//! array subscripts:
+//!
+//! - `name` selects the entry `name` of a section,
+//! - `*` selects every entry of a section,
+//! - `[3]` selects the fourth element of an array,
+//! - `[*]` selects every element of an array.
+//!
+//! Steps that don't apply to a value, like a key on an array, select
+//! nothing. Matches are produced lazily in the order they appear.
+
+use crate::{Error, Result, Section, StorageEntry};
+use std::{borrow::Cow, fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Key(String),
+    AnyKey,
+    Index(usize),
+    AnyIndex,
+}
+
+/// A parsed selector, reusable across sections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Selector> {
+        let invalid = || Error::InvalidSelector(selector.to_owned());
+        let mut steps = Vec::new();
+
+        for segment in selector.split('.') {
+            let (name, mut subscripts) = match segment.find('[') {
+                Some(i) => segment.split_at(i),
+                None => (segment, ""),
+            };
+            match name {
+                "" if subscripts.is_empty() => return Err(invalid()),
+                "" => {}
+                "*" => steps.push(Step::AnyKey),
+                name => steps.push(Step::Key(name.to_owned())),
+            }
+
+            while !subscripts.is_empty() {
+                let end = subscripts.find(']').ok_or_else(invalid)?;
+                let step = match &subscripts[1..end] {
+                    "*" => Step::AnyIndex,
+                    index => Step::Index(index.parse().map_err(|_| invalid())?),
+                };
+                steps.push(step);
+                subscripts = &subscripts[end + 1..];
+                if !subscripts.is_empty() && !subscripts.starts_with('[') {
+                    return Err(invalid());
+                }
+            }
+        }
+
+        Ok(Selector { steps })
+    }
+}
+
+impl FromStr for Selector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Selector> {
+        Selector::parse(s)
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            match step {
+                Step::Key(_) | Step::AnyKey if i > 0 => f.write_str(".")?,
+                _ => {}
+            }
+            match step {
+                Step::Key(name) => f.write_str(name)?,
+                Step::AnyKey => f.write_str("*")?,
+                Step::Index(index) => write!(f, "[{}]", index)?,
+                Step::AnyIndex => f.write_str("[*]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Section {
+    /// Iterates over the values matching `selector`, see the
+    /// [module documentation](crate::select).
+    pub fn select(&self, selector: &str) -> Result<Select<'_>> {
+        Ok(Select::new(
+            self,
+            Cow::Owned(Selector::parse(selector)?.steps),
+        ))
+    }
+
+    /// Iterates over the values matching an already parsed selector.
+    pub fn select_with<'a>(&'a self, selector: &'a Selector) -> Select<'a> {
+        Select::new(self, Cow::Borrowed(&selector.steps))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Node<'a> {
+    Section(&'a Section),
+    Entry(&'a StorageEntry),
+}
+
+/// Iterator over the values matching a selector.
+pub struct Select<'a> {
+    steps: Cow<'a, [Step]>,
+    stack: Vec<(Node<'a>, usize)>,
+}
+
+impl<'a> Select<'a> {
+    fn new(section: &'a Section, steps: Cow<'a, [Step]>) -> Select<'a> {
+        Select {
+            steps,
+            stack: vec![(Node::Section(section), 0)],
+        }
+    }
+}
+
+impl<'a> Iterator for Select<'a> {
+    type Item = &'a StorageEntry;
+
+    fn next(&mut self) -> Option<&'a StorageEntry> {
+        while let Some((node, step)) = self.stack.pop() {
+            let step = match (self.steps.get(step), node) {
+                (None, Node::Entry(entry)) => return Some(entry),
+                (None, Node::Section(_)) => continue,
+                (Some(s), _) => (s, step + 1),
+            };
+
+            let section = match node {
+                Node::Section(section) | Node::Entry(StorageEntry::Section(section)) => {
+                    Some(section)
+                }
+                _ => None,
+            };
+            let array = match node {
+                Node::Entry(StorageEntry::Array(array)) => Some(&array.array),
+                _ => None,
+            };
+
+            match (step.0, section, array) {
+                (Step::Key(name), Some(section), _) => {
+                    if let Some(entry) = section.entries.get(name) {
+                        self.stack.push((Node::Entry(entry), step.1));
+                    }
+                }
+                (Step::AnyKey, Some(section), _) => self.stack.extend(
+                    section
+                        .entries
+                        .values()
+                        .rev()
+                        .map(|entry| (Node::Entry(entry), step.1)),
+                ),
+                (Step::Index(index), _, Some(array)) => {
+                    if let Some(entry) = array.get(*index) {
+                        self.stack.push((Node::Entry(entry), step.1));
+                    }
+                }
+                (Step::AnyIndex, _, Some(array)) => self
+                    .stack
+                    .extend(array.iter().rev().map(|entry| (Node::Entry(entry), step.1))),
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::Array;
+
+    #[test]
+    fn parse() {
+        for selector in &[
+            "a", "a.b", "a[0]", "a[*].b", "*", "a[1][*]", "[0]", "*.x[2]",
+        ] {
+            assert_eq!(Selector::parse(selector).unwrap().to_string(), *selector);
+        }
+        for selector in &["", "a.", ".a", "a[", "a[x]", "a[0]b", "a..b", "a[-1]"] {
+            assert!(matches!(
+                Selector::parse(selector),
+                Err(Error::InvalidSelector(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn select() {
+        let mut grid = Array::new();
+        for row in 0..2u8 {
+            let mut cells = Array::new();
+            cells.push(StorageEntry::U8(row * 10)).unwrap();
+            cells.push(StorageEntry::U8(row * 10 + 1)).unwrap();
+            grid.push(StorageEntry::Array(cells)).unwrap();
+        }
+        let mut section = Section::new();
+        section.insert("a".to_owned(), StorageEntry::U8(1));
+        section.insert("b".to_owned(), StorageEntry::U8(2));
+        section.insert("grid".to_owned(), StorageEntry::Array(grid));
+
+        let select = |selector| {
+            section
+                .select(selector)
+                .unwrap()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select("a"), [StorageEntry::U8(1)]);
+        assert_eq!(select("grid[1][0]"), [StorageEntry::U8(10)]);
+        assert_eq!(
+            select("grid[*][1]"),
+            [StorageEntry::U8(1), StorageEntry::U8(11)]
+        );
+        assert_eq!(select("*").len(), 3);
+        assert!(select("a.b").is_empty());
+        assert!(select("grid[2][0]").is_empty());
+        assert!(select("missing[*]").is_empty());
+
+        let mut peers = Array::new();
+        for _ in 0..250 {
+            let mut adr = Section::new();
+            adr.insert("addr".to_owned(), StorageEntry::U32(0));
+            let mut peer = Section::new();
+            peer.insert("adr".to_owned(), StorageEntry::Section(adr));
+            peers.push(StorageEntry::Section(peer)).unwrap();
+        }
+        let mut response = Section::new();
+        response.insert("local_peerlist_new".to_owned(), StorageEntry::Array(peers));
+        let selector = "local_peerlist_new[*].adr.addr"
+            .parse::<Selector>()
+            .unwrap();
+        assert_eq!(response.select_with(&selector).count(), 250);
+    }
+}
@@ -13,15 +13,160 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{Array, Section, StorageEntry};
+use crate::{
+    redact::{join, within},
+    Array, Section, StorageEntry,
+};
+use bytes::BytesMut;
 use serde::{
     de::value::Error,
     ser::{Error as ErrorTrait, Impossible, SerializeSeq, SerializeStruct},
     Serialize, Serializer,
 };
+use std::collections::HashSet;
+
+/// What serializers report from `is_human_readable`, which types with both a
+/// textual and a binary form (identifiers, hashes, addresses) use to pick
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    /// The binary form, the one other epee implementations expect on the
+    /// wire. This is the default.
+    Binary,
+    /// The textual form, for sections meant to be exported, e.g. with
+    /// `Section::to_json`. Strings are stored as their UTF-8 bytes.
+    HumanReadable,
+}
+
+impl Default for Representation {
+    fn default() -> Self {
+        Representation::Binary
+    }
+}
 
-pub fn to_section<T: Serialize>(v: &T) -> Result<Section, Error> {
-    v.serialize(RootSectionSerializer)
+/// Which fields of the serialized structures are written, to trim values
+/// without changing their `Serialize` implementations.
+///
+/// Fields are named by path: the field names leading to them from the root
+/// joined with `.`. Elements of sequences share the path of the sequence,
+/// so `peers.id` names the `id` field of every peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldFilter {
+    /// Every field. This is the default.
+    All,
+    /// Only the fields at these paths, with the structures leading to them.
+    /// All the fields of a listed structure are written.
+    Only(HashSet<String>),
+    /// Every field but those at these paths.
+    Except(HashSet<String>),
+}
+
+impl Default for FieldFilter {
+    fn default() -> Self {
+        FieldFilter::All
+    }
+}
+
+impl FieldFilter {
+    pub fn only<I: IntoIterator<Item = S>, S: Into<String>>(paths: I) -> FieldFilter {
+        FieldFilter::Only(paths.into_iter().map(Into::into).collect())
+    }
+
+    pub fn except<I: IntoIterator<Item = S>, S: Into<String>>(paths: I) -> FieldFilter {
+        FieldFilter::Except(paths.into_iter().map(Into::into).collect())
+    }
+
+    /// Whether the field at `path` is written. Filters are applied from the
+    /// root, the fields of a skipped structure are never checked.
+    pub fn keeps(&self, path: &str) -> bool {
+        match self {
+            FieldFilter::All => true,
+            FieldFilter::Only(paths) => {
+                paths.contains(path) || paths.iter().any(|p| within(path, p) || within(p, path))
+            }
+            FieldFilter::Except(paths) => !paths.contains(path),
+        }
+    }
+}
+
+/// Serializer options.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SerializerConfig {
+    pub representation: Representation,
+    pub fields: FieldFilter,
+}
+
+impl SerializerConfig {
+    pub fn to_section<T: ?Sized + Serialize>(&self, v: &T) -> Result<Section, Error> {
+        v.serialize(RootSectionSerializer(Context {
+            config: self,
+            path: String::new(),
+        }))
+    }
+}
+
+pub fn to_section<T: ?Sized + Serialize>(v: &T) -> Result<Section, Error> {
+    to_section_with(v, Representation::default())
+}
+
+/// Like [`to_section`], serializing values in the given representation.
+pub fn to_section_with<T: ?Sized + Serialize>(
+    v: &T,
+    representation: Representation,
+) -> Result<Section, Error> {
+    SerializerConfig {
+        representation,
+        ..SerializerConfig::default()
+    }
+    .to_section(v)
+}
+
+/// The configuration and the path of the value being serialized.
+#[derive(Clone)]
+struct Context<'a> {
+    config: &'a SerializerConfig,
+    path: String,
+}
+
+impl<'a> Context<'a> {
+    /// The context of the field `key`, `None` if it's filtered out.
+    fn field(&self, key: &str) -> Option<Context<'a>> {
+        let path = join(&self.path, key);
+        if self.config.fields.keeps(&path) {
+            Some(Context {
+                config: self.config,
+                path,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Serializes `v` into a storage blob, header included.
+pub fn to_storage_bytes<T: ?Sized + Serialize>(v: &T) -> Result<Vec<u8>, Error> {
+    Ok(crate::write_to_vec(&to_section(v)?))
+}
+
+/// Serializes each value and writes them as storage blobs, back to back,
+/// like [`write_batch`](crate::write_batch). Nothing is written on errors.
+pub fn write_batch<T: Serialize>(buf: &mut BytesMut, values: &[T]) -> Result<(), Error> {
+    let sections = values
+        .iter()
+        .map(to_section)
+        .collect::<Result<Vec<_>, _>>()?;
+    crate::write_batch(buf, &sections);
+    Ok(())
+}
+
+impl Section {
+    /// Inserts the keys of `T::default()` missing from this section, see
+    /// [`Section::apply_defaults`].
+    pub fn apply_defaults_from<T: Default + Serialize>(&mut self) -> Result<(), Error> {
+        let template = to_section(&T::default())?;
+        self.apply_defaults(&template);
+        Ok(())
+    }
 }
 
 macro_rules! unsupported {
@@ -36,9 +181,9 @@ macro_rules! unsupported {
     };
 }
 
-struct RootSectionSerializer;
+struct RootSectionSerializer<'a>(Context<'a>);
 
-impl Serializer for RootSectionSerializer {
+impl<'a> Serializer for RootSectionSerializer<'a> {
     type Ok = Section;
     type Error = Error;
     type SerializeSeq = Impossible<Self::Ok, Self::Error>;
@@ -46,7 +191,7 @@ impl Serializer for RootSectionSerializer {
     type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
     type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
     type SerializeMap = Impossible<Self::Ok, Self::Error>;
-    type SerializeStruct = KvSerializer;
+    type SerializeStruct = KvSerializer<'a>;
     type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
 
     unsupported!(serialize_bool, bool);
@@ -68,9 +213,9 @@ impl Serializer for RootSectionSerializer {
         Err(Error::custom("serializing `None` isn't supported"))
     }
 
-    fn serialize_some<T: ?Sized>(self, _value: &T) -> Result<Self::Ok, Self::Error>
+    fn serialize_some<T>(self, _value: &T) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         Err(Error::custom("serializing `Some(_)` isn't supported"))
     }
@@ -92,20 +237,20 @@ impl Serializer for RootSectionSerializer {
         Err(Error::custom("serializing `()` isn't supported"))
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
         _name: &'static str,
         _value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         Err(Error::custom(
             "serializing a newtype struct isn't supported",
         ))
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         _variant_index: u32,
@@ -113,7 +258,7 @@ impl Serializer for RootSectionSerializer {
         _value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         Err(Error::custom(
             "serializing a newtype variant isn't supported",
@@ -155,7 +300,7 @@ impl Serializer for RootSectionSerializer {
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        Ok(KvSerializer(Section::with_capacity(len)))
+        Ok(KvSerializer(Section::with_capacity(len), self.0))
     }
 
     fn serialize_struct_variant(
@@ -171,26 +316,25 @@ impl Serializer for RootSectionSerializer {
     }
 
     fn is_human_readable(&self) -> bool {
-        false
+        self.0.config.representation == Representation::HumanReadable
     }
 }
 
-struct KvSerializer(Section);
+struct KvSerializer<'a>(Section, Context<'a>);
 
-impl SerializeStruct for KvSerializer {
+impl<'a> SerializeStruct for KvSerializer<'a> {
     type Ok = Section;
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        let entry = value.serialize(StorageEntrySerializer)?;
-        self.0.insert(key.to_string(), entry);
+        if let Some(context) = self.1.field(key) {
+            crate::check_name(key).map_err(Error::custom)?;
+            let entry = value.serialize(StorageEntrySerializer(context))?;
+            self.0.insert(key.to_string(), entry);
+        }
         Ok(())
     }
 
@@ -203,9 +347,9 @@ impl SerializeStruct for KvSerializer {
     }
 }
 
-struct ArraySerializer(Array);
+struct ArraySerializer<'a>(Array, Context<'a>);
 
-impl ArraySerializer {
+impl<'a> ArraySerializer<'a> {
     fn push(&mut self, entry: StorageEntry) -> Result<(), Error> {
         self.0
             .push(entry)
@@ -213,15 +357,15 @@ impl ArraySerializer {
     }
 }
 
-impl SerializeSeq for ArraySerializer {
+impl<'a> SerializeSeq for ArraySerializer<'a> {
     type Ok = StorageEntry;
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        let entry = value.serialize(StorageEntrySerializer)?;
+        let entry = value.serialize(StorageEntrySerializer(self.1.clone()))?;
         self.push(entry)
     }
 
@@ -238,22 +382,21 @@ macro_rules! storage_entry {
     };
 }
 
-struct EntryKvSerializer(Section);
+struct EntryKvSerializer<'a>(Section, Context<'a>);
 
-impl SerializeStruct for EntryKvSerializer {
+impl<'a> SerializeStruct for EntryKvSerializer<'a> {
     type Ok = StorageEntry;
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        let entry = value.serialize(StorageEntrySerializer)?;
-        self.0.insert(key.to_string(), entry);
+        if let Some(context) = self.1.field(key) {
+            crate::check_name(key).map_err(Error::custom)?;
+            let entry = value.serialize(StorageEntrySerializer(context))?;
+            self.0.insert(key.to_string(), entry);
+        }
         Ok(())
     }
 
@@ -266,17 +409,17 @@ impl SerializeStruct for EntryKvSerializer {
     }
 }
 
-struct StorageEntrySerializer;
+struct StorageEntrySerializer<'a>(Context<'a>);
 
-impl Serializer for StorageEntrySerializer {
+impl<'a> Serializer for StorageEntrySerializer<'a> {
     type Ok = StorageEntry;
     type Error = Error;
-    type SerializeSeq = ArraySerializer;
+    type SerializeSeq = ArraySerializer<'a>;
     type SerializeTuple = Impossible<Self::Ok, Self::Error>;
     type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
     type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
     type SerializeMap = Impossible<Self::Ok, Self::Error>;
-    type SerializeStruct = EntryKvSerializer;
+    type SerializeStruct = EntryKvSerializer<'a>;
     type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
 
     storage_entry!(serialize_bool, bool, StorageEntry::Bool);
@@ -291,19 +434,25 @@ impl Serializer for StorageEntrySerializer {
     unsupported!(serialize_f32, f32);
     storage_entry!(serialize_f64, f64, StorageEntry::Double);
     unsupported!(serialize_char, char);
-    unsupported!(serialize_str, &str);
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        match self.0.config.representation {
+            Representation::Binary => Err(Error::custom("serializing a `&str` isn't supported")),
+            Representation::HumanReadable => Ok(StorageEntry::Buf(v.as_bytes().into())),
+        }
+    }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        Ok(StorageEntry::Buf(v.to_vec()))
+        Ok(StorageEntry::Buf(v.into()))
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
         Err(Error::custom("serializing `None` isn't supported"))
     }
 
-    fn serialize_some<T: ?Sized>(self, _value: &T) -> Result<Self::Ok, Self::Error>
+    fn serialize_some<T>(self, _value: &T) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         Err(Error::custom("serializing `Some(_)` isn't supported"))
     }
@@ -325,20 +474,20 @@ impl Serializer for StorageEntrySerializer {
         Err(Error::custom("serializing `()` isn't supported"))
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
         _name: &'static str,
         _value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         Err(Error::custom(
             "serializing a newtype struct isn't supported",
         ))
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         _variant_index: u32,
@@ -346,7 +495,7 @@ impl Serializer for StorageEntrySerializer {
         _value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         Err(Error::custom(
             "serializing a newtype variant isn't supported",
@@ -355,9 +504,9 @@ impl Serializer for StorageEntrySerializer {
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
         if let Some(len) = len {
-            Ok(ArraySerializer(Array::with_capacity(len)))
+            Ok(ArraySerializer(Array::with_capacity(len), self.0))
         } else {
-            Ok(ArraySerializer(Array::new()))
+            Ok(ArraySerializer(Array::new(), self.0))
         }
     }
 
@@ -392,7 +541,7 @@ impl Serializer for StorageEntrySerializer {
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        Ok(EntryKvSerializer(Section::with_capacity(len)))
+        Ok(EntryKvSerializer(Section::with_capacity(len), self.0))
     }
 
     fn serialize_struct_variant(
@@ -408,7 +557,7 @@ impl Serializer for StorageEntrySerializer {
     }
 
     fn is_human_readable(&self) -> bool {
-        false
+        self.0.config.representation == Representation::HumanReadable
     }
 }
 
@@ -437,4 +586,138 @@ pub mod tests {
             StorageEntry::U32(1337)
         ));
     }
+
+    #[test]
+    fn batch() {
+        let values = [
+            TestVector0 {
+                id: 1,
+                transaction_proof: 2,
+            },
+            TestVector0 {
+                id: 3,
+                transaction_proof: 4,
+            },
+        ];
+        let mut buf = BytesMut::new();
+        write_batch(&mut buf, &values).unwrap();
+
+        let mut rest = &buf[..];
+        for value in &values {
+            let section = crate::read(&mut rest).unwrap();
+            assert_eq!(section, to_section(value).unwrap());
+        }
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn representation() {
+        /// A hash written as hex in human-readable formats.
+        struct Hash([u8; 2]);
+
+        impl Serialize for Hash {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if serializer.is_human_readable() {
+                    serializer.serialize_str(&format!("{:02x}{:02x}", self.0[0], self.0[1]))
+                } else {
+                    serializer.serialize_bytes(&self.0)
+                }
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Block {
+            hashes: Vec<Hash>,
+        }
+
+        let block = Block {
+            hashes: vec![Hash([0xab, 0x01])],
+        };
+        let section = to_section(&block).unwrap();
+        let hashes = match &section["hashes"] {
+            StorageEntry::Array(hashes) => hashes,
+            entry => panic!("unexpected entry {:?}", entry),
+        };
+        assert_eq!(hashes[0], StorageEntry::Buf(vec![0xab, 0x01].into()));
+
+        let section = to_section_with(&block, Representation::HumanReadable).unwrap();
+        let hashes = match &section["hashes"] {
+            StorageEntry::Array(hashes) => hashes,
+            entry => panic!("unexpected entry {:?}", entry),
+        };
+        assert_eq!(hashes[0], StorageEntry::Buf(b"ab01".to_vec().into()));
+    }
+
+    #[test]
+    fn field_filter() {
+        #[derive(Serialize)]
+        struct Peer {
+            id: u64,
+            port: u32,
+            last_seen: i64,
+        }
+
+        #[derive(Serialize)]
+        struct Response {
+            height: u64,
+            peers: Vec<Peer>,
+        }
+
+        let response = Response {
+            height: 10,
+            peers: vec![Peer {
+                id: 1,
+                port: 18080,
+                last_seen: 7,
+            }],
+        };
+        let keys = |section: &Section| -> Vec<String> {
+            let mut keys: Vec<String> = section.entries.keys().cloned().collect();
+            if let Some(StorageEntry::Array(peers)) = section.entries.get("peers") {
+                if let StorageEntry::Section(peer) = &peers[0] {
+                    keys.extend(peer.entries.keys().map(|key| format!("peers.{}", key)));
+                }
+            }
+            keys
+        };
+
+        let config = SerializerConfig {
+            fields: FieldFilter::only(vec!["peers.id", "peers.port"]),
+            ..SerializerConfig::default()
+        };
+        let section = config.to_section(&response).unwrap();
+        assert_eq!(keys(&section), ["peers", "peers.id", "peers.port"]);
+
+        let config = SerializerConfig {
+            fields: FieldFilter::only(vec!["height", "peers"]),
+            ..SerializerConfig::default()
+        };
+        let section = config.to_section(&response).unwrap();
+        assert_eq!(section, to_section(&response).unwrap());
+
+        let config = SerializerConfig {
+            fields: FieldFilter::except(vec!["peers.last_seen"]),
+            ..SerializerConfig::default()
+        };
+        let section = config.to_section(&response).unwrap();
+        assert_eq!(
+            keys(&section),
+            ["height", "peers", "peers.id", "peers.port"]
+        );
+    }
+
+    #[test]
+    fn apply_defaults_from() {
+        #[derive(Default, Serialize)]
+        struct SupportFlags {
+            support_flags: u32,
+            rpc_port: u16,
+        }
+
+        let mut section = Section::new();
+        section.insert("support_flags".to_owned(), StorageEntry::U32(1));
+        section.apply_defaults_from::<SupportFlags>().unwrap();
+        assert_eq!(section["support_flags"], StorageEntry::U32(1));
+        assert_eq!(section["rpc_port"], StorageEntry::U16(0));
+    }
 }
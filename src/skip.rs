@@ -0,0 +1,133 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Skipping over encoded values without decoding them, for values nobody
+//! asked for and for sizing passes.
+
+use crate::{
+    raw_size, wire, Error, Result, SERIALIZE_TYPE_ARRAY, SERIALIZE_TYPE_BOOL,
+    SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32, SERIALIZE_TYPE_INT64,
+    SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING, SERIALIZE_TYPE_UINT16,
+    SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use bytes::Buf;
+
+/// Size of the values of fixed size types.
+pub(crate) fn fixed_size(serialize_type: u8) -> Option<usize> {
+    match serialize_type {
+        SERIALIZE_TYPE_INT64 | SERIALIZE_TYPE_UINT64 | SERIALIZE_TYPE_DOUBLE => Some(8),
+        SERIALIZE_TYPE_INT32 | SERIALIZE_TYPE_UINT32 => Some(4),
+        SERIALIZE_TYPE_INT16 | SERIALIZE_TYPE_UINT16 => Some(2),
+        SERIALIZE_TYPE_INT8 | SERIALIZE_TYPE_UINT8 | SERIALIZE_TYPE_BOOL => Some(1),
+        _ => None,
+    }
+}
+
+fn advance<B: Buf>(buf: &mut B, length: usize) -> Result<()> {
+    ensure_eof!(buf, length);
+    buf.advance(length);
+    Ok(())
+}
+
+/// Skips a value without its serialize type.
+pub(crate) fn skip_raw<B: Buf>(buf: &mut B, serialize_type: u8) -> Result<()> {
+    if let Some(size) = fixed_size(serialize_type) {
+        return advance(buf, size);
+    }
+
+    match serialize_type {
+        SERIALIZE_TYPE_STRING => {
+            let length = raw_size::read_usize(buf)?;
+            advance(buf, length)
+        }
+        SERIALIZE_TYPE_OBJECT => {
+            for _ in 0..raw_size::read_usize(buf)? {
+                let length = wire::read_name_len(buf)?;
+                buf.advance(length);
+                skip(buf)?;
+            }
+            Ok(())
+        }
+        SERIALIZE_TYPE_ARRAY => {
+            let serialize_type = wire::read_array_type(buf)?;
+            skip_array(buf, serialize_type)
+        }
+        _ => Err(Error::InvalidSerializeType(serialize_type)),
+    }
+}
+
+/// Skips a value with its serialize type.
+pub(crate) fn skip<B: Buf>(buf: &mut B) -> Result<()> {
+    let serialize_type = wire::read_type(buf)?;
+    if wire::is_array(serialize_type) {
+        skip_array(buf, serialize_type)
+    } else {
+        skip_raw(buf, serialize_type)
+    }
+}
+
+/// Skips an array after its flagged serialize type.
+pub(crate) fn skip_array<B: Buf>(buf: &mut B, serialize_type: u8) -> Result<()> {
+    let serialize_type = wire::element_type(serialize_type);
+    let count = raw_size::read_usize(buf)?;
+
+    // Arrays of numbers are skipped at once.
+    if let Some(size) = fixed_size(serialize_type) {
+        let length = count
+            .checked_mul(size)
+            .ok_or(Error::LengthOverflow(count as u64))?;
+        return advance(buf, length);
+    }
+
+    for _ in 0..count {
+        skip_raw(buf, serialize_type)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Array, Section, StorageEntry};
+
+    #[test]
+    fn skips() {
+        let mut ids = Array::new();
+        for i in 0..100 {
+            ids.push(StorageEntry::U64(i)).unwrap();
+        }
+        let mut inner = Section::new();
+        inner.insert("host".to_owned(), StorageEntry::Buf(b"node"[..].into()));
+        let mut section = Section::new();
+        section.insert("ids".to_owned(), StorageEntry::Array(ids));
+        section.insert("inner".to_owned(), StorageEntry::Section(inner));
+        let mut blob = Vec::new();
+        blob.extend_from_slice(&crate::write_to_vec(&section)[9..]);
+        blob.push(0);
+
+        let mut cursor = &blob[..];
+        skip_raw(&mut cursor, SERIALIZE_TYPE_OBJECT).unwrap();
+        assert_eq!(cursor, &[0]);
+
+        assert!(matches!(
+            skip_raw(&mut &blob[..blob.len() - 2], SERIALIZE_TYPE_OBJECT),
+            Err(Error::UnexpectedEof { .. })
+        ));
+        assert!(matches!(
+            skip_raw(&mut &[0u8][..], 42),
+            Err(Error::InvalidSerializeType(42))
+        ));
+    }
+}
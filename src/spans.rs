@@ -0,0 +1,193 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Byte spans
+//!
+//! Decodes a blob along with the position of every value in it, keyed by
+//! path. Dissectors, patchers and error reports in higher layers can point at
+//! the exact bytes a field came from.
+//!
+//! ```rust
+//! use portable_storage::{spans, Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("height".to_owned(), StorageEntry::U64(1));
+//! let blob = portable_storage::write_to_vec(&section);
+//!
+//! let (decoded, spans) = spans::read_with_spans(&blob).unwrap();
+//! assert_eq!(decoded, section);
+//! let span = spans["height"];
+//! assert_eq!(&blob[span.offset..span.end()], &1u64.to_le_bytes());
+//! ```
+//!
+//! Paths are built like in [`patch`](crate::patch): keys joined with `.`,
+//! array elements by their index. A span covers the value only, not its key
+//! nor its serialize type, and sections and arrays span all of their
+//! contents, including the number of entries.
+
+use crate::{
+    header::StorageBlockHeader, raw_size, redact::join, skip, wire, Result, Section,
+    SERIALIZE_TYPE_ARRAY, SERIALIZE_TYPE_OBJECT,
+};
+use linked_hash_map::LinkedHashMap;
+
+/// A range of bytes of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub offset: usize,
+    pub length: usize,
+}
+
+impl Span {
+    /// Offset just past the span.
+    pub fn end(&self) -> usize {
+        self.offset + self.length
+    }
+}
+
+/// Spans by path, in the order the values appear.
+pub type Spans = LinkedHashMap<String, Span>;
+
+/// Reads a blob, including the storage block header, and returns the span
+/// of each value alongside the section.
+pub fn read_with_spans(data: &[u8]) -> Result<(Section, Spans)> {
+    let section = crate::read(&mut &data[..])?;
+    let mut buf = data;
+    StorageBlockHeader::read(&mut buf)?;
+    let spans = spans_of(data, buf)?;
+    Ok((section, spans))
+}
+
+/// Reads a section that isn't preceded by the storage block header, and
+/// returns the span of each value alongside it.
+pub fn read_section_with_spans(data: &[u8]) -> Result<(Section, Spans)> {
+    let section = crate::read_section(&mut &data[..])?;
+    let spans = spans_of(data, data)?;
+    Ok((section, spans))
+}
+
+fn spans_of(data: &[u8], mut buf: &[u8]) -> Result<Spans> {
+    let mut collector = Collector {
+        len: data.len(),
+        spans: Spans::new(),
+    };
+    collector.section(&mut buf, "")?;
+    Ok(collector.spans)
+}
+
+struct Collector {
+    len: usize,
+    spans: Spans,
+}
+
+impl Collector {
+    fn offset(&self, buf: &[u8]) -> usize {
+        self.len - buf.len()
+    }
+
+    fn section(&mut self, buf: &mut &[u8], path: &str) -> Result<()> {
+        for _ in 0..raw_size::read_usize(buf)? {
+            let name = String::from_utf8_lossy(wire::read_name_bytes(buf)?).into_owned();
+            let serialize_type = wire::read_type(buf)?;
+            self.value(buf, serialize_type, join(path, &name))?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, buf: &mut &[u8], serialize_type: u8, path: String) -> Result<()> {
+        let offset = self.offset(buf);
+        self.spans.insert(path.clone(), Span { offset, length: 0 });
+
+        if wire::is_array(serialize_type) {
+            self.elements(buf, serialize_type, &path)?;
+        } else {
+            match serialize_type {
+                SERIALIZE_TYPE_OBJECT => self.section(buf, &path)?,
+                SERIALIZE_TYPE_ARRAY => {
+                    let serialize_type = wire::read_array_type(buf)?;
+                    self.elements(buf, serialize_type, &path)?;
+                }
+                _ => skip::skip_raw(buf, serialize_type)?,
+            }
+        }
+
+        let length = self.offset(buf) - offset;
+        if let Some(span) = self.spans.get_mut(&path) {
+            span.length = length;
+        }
+        Ok(())
+    }
+
+    fn elements(&mut self, buf: &mut &[u8], serialize_type: u8, path: &str) -> Result<()> {
+        let serialize_type = wire::element_type(serialize_type);
+        for i in 0..raw_size::read_usize(buf)? {
+            self.value(buf, serialize_type, join(path, &i.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{patch, Array, StorageEntry};
+
+    #[test]
+    fn spans() {
+        let mut inner = Array::new();
+        inner.push(StorageEntry::U16(7)).unwrap();
+        let mut nested = Array::new();
+        nested.push(StorageEntry::Array(inner)).unwrap();
+
+        let mut node_data = Section::new();
+        node_data.insert("my_port".to_owned(), StorageEntry::U32(18080));
+
+        let mut section = Section::new();
+        section.insert("id".to_owned(), StorageEntry::Buf(b"abc"[..].into()));
+        section.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+        section.insert("nested".to_owned(), StorageEntry::Array(nested));
+        let blob = crate::write_to_vec(&section);
+
+        let (decoded, spans) = read_with_spans(&blob).unwrap();
+        assert_eq!(decoded, section);
+        let paths: Vec<&str> = spans.keys().map(String::as_str).collect();
+        assert_eq!(
+            paths,
+            [
+                "id",
+                "node_data",
+                "node_data.my_port",
+                "nested",
+                "nested.0",
+                "nested.0.0"
+            ]
+        );
+
+        let span = spans["id"];
+        assert_eq!(&blob[span.offset..span.end()], b"\x0cabc");
+        let span = spans["node_data.my_port"];
+        assert_eq!(&blob[span.offset..span.end()], &18080u32.to_le_bytes());
+        assert_eq!(spans["nested"].end(), blob.len());
+        assert_eq!(spans["nested.0.0"].length, 2);
+
+        let (_, headerless) = read_section_with_spans(&blob[9..]).unwrap();
+        assert_eq!(headerless["id"].offset + 9, spans["id"].offset);
+
+        let location = patch::locate(&blob, "nested.0.0").unwrap();
+        assert_eq!(spans["nested.0.0"].offset, location.offset);
+
+        assert!(read_with_spans(&blob[..blob.len() - 1]).is_err());
+    }
+}
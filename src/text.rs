@@ -0,0 +1,557 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Stable text format
+//!
+//! A deterministic text rendering of sections meant for snapshot tests, and
+//! a parser so snapshots can be loaded back as fixtures. Every entry is on
+//! its own line as `key: type value`, strings are written in hex and arrays
+//! name their element type:
+//!
+//! ```text
+//! node_data: section {
+//!   my_port: u32 18080
+//!   network_id: string 0x1230f171
+//! }
+//! ids: array of u64 [1 2 3]
+//! peers: array of section [
+//!   {
+//!     id: u64 1
+//!   }
+//! ]
+//! "odd key": bool true
+//! ```
+//!
+//! Keys with characters other than ASCII letters, digits, `_`, `-`, `.` and
+//! `$` are quoted. Doubles are written with the shortest representation that
+//! reads back to the same value.
+//!
+//! ```rust
+//! use portable_storage::{text::KeyOrder, Section, StorageEntry};
+//!
+//! let mut section = Section::new();
+//! section.insert("b".to_owned(), StorageEntry::U8(1));
+//! section.insert("a".to_owned(), StorageEntry::Buf(vec![0xab].into()));
+//!
+//! let text = section.to_text(KeyOrder::Sorted);
+//! assert_eq!(text, "a: string 0xab\nb: u8 1\n");
+//! let parsed = Section::from_text(&text).unwrap();
+//! assert_eq!(parsed.to_text(KeyOrder::Sorted), text);
+//! ```
+
+use crate::{
+    explain::type_name,
+    redact::{self, Redactor},
+    Array, Error, Result, Section, StorageEntry, SERIALIZE_FLAG_ARRAY, SERIALIZE_TYPE_ARRAY,
+    SERIALIZE_TYPE_BOOL, SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32,
+    SERIALIZE_TYPE_INT64, SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING,
+    SERIALIZE_TYPE_UINT16, SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use std::fmt::Write;
+
+/// The order in which keys are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyOrder {
+    /// The order of the section, i.e. the order on the wire.
+    Insertion,
+    /// Byte order, so snapshots don't depend on how a section was built.
+    Sorted,
+}
+
+impl Default for KeyOrder {
+    fn default() -> Self {
+        KeyOrder::Insertion
+    }
+}
+
+const INDENT: &str = "  ";
+
+#[derive(Clone, Copy)]
+struct Style<'a> {
+    order: KeyOrder,
+    redactor: Option<&'a Redactor>,
+}
+
+impl Section {
+    /// Renders this section in the stable text format.
+    pub fn to_text(&self, order: KeyOrder) -> String {
+        let style = Style {
+            order,
+            redactor: None,
+        };
+        let mut out = String::new();
+        write_entries(&mut out, self, 0, style, "");
+        out
+    }
+
+    /// Renders this section in the stable text format, with the values of
+    /// the entries marked by `redactor` replaced by a placeholder. The
+    /// result can't be parsed back.
+    pub fn to_text_redacted(&self, order: KeyOrder, redactor: &Redactor) -> String {
+        let style = Style {
+            order,
+            redactor: Some(redactor),
+        };
+        let mut out = String::new();
+        write_entries(&mut out, self, 0, style, "");
+        out
+    }
+
+    /// Parses a section rendered in the stable text format.
+    pub fn from_text(text: &str) -> Result<Section> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let section = parser.entries(false)?;
+        match parser.tokens.get(parser.position) {
+            Some((line, token)) => Err(parse_error(*line, format!("unexpected {:?}", token))),
+            None => Ok(section),
+        }
+    }
+}
+
+fn write_entries(out: &mut String, section: &Section, depth: usize, style: Style, path: &str) {
+    let mut entries: Vec<_> = section.entries.iter().collect();
+    if style.order == KeyOrder::Sorted {
+        entries.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+    }
+
+    for (name, entry) in entries {
+        let path = redact::join(path, name);
+        out.push_str(&INDENT.repeat(depth));
+        write_key(out, name);
+        write!(out, ": {} ", type_name(entry.serialize_type())).unwrap();
+        match style.redactor {
+            Some(redactor) if redactor.is_sensitive(&path) => out.push_str(redact::PLACEHOLDER),
+            _ => write_entry(out, entry, depth, style, &path),
+        }
+        out.push('\n');
+    }
+}
+
+fn write_key(out: &mut String, name: &str) {
+    if !name.is_empty() && name.chars().all(is_bare) {
+        out.push_str(name);
+        return;
+    }
+
+    out.push('"');
+    for c in name.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => write!(out, "\\u{{{:x}}}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn is_bare(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' || c == '$'
+}
+
+pub(crate) fn write_value(out: &mut String, entry: &StorageEntry, depth: usize, order: KeyOrder) {
+    let style = Style {
+        order,
+        redactor: None,
+    };
+    write_entry(out, entry, depth, style, "")
+}
+
+fn write_entry(out: &mut String, entry: &StorageEntry, depth: usize, style: Style, path: &str) {
+    match entry {
+        StorageEntry::U64(v) => write!(out, "{}", v).unwrap(),
+        StorageEntry::U32(v) => write!(out, "{}", v).unwrap(),
+        StorageEntry::U16(v) => write!(out, "{}", v).unwrap(),
+        StorageEntry::U8(v) => write!(out, "{}", v).unwrap(),
+        StorageEntry::I64(v) => write!(out, "{}", v).unwrap(),
+        StorageEntry::I32(v) => write!(out, "{}", v).unwrap(),
+        StorageEntry::I16(v) => write!(out, "{}", v).unwrap(),
+        StorageEntry::I8(v) => write!(out, "{}", v).unwrap(),
+        StorageEntry::Double(v) => write!(out, "{:?}", v).unwrap(),
+        StorageEntry::Bool(v) => write!(out, "{}", v).unwrap(),
+        StorageEntry::Buf(v) => {
+            out.push_str("0x");
+            for b in v.iter() {
+                write!(out, "{:02x}", b).unwrap();
+            }
+        }
+        StorageEntry::Section(v) if v.is_empty() => out.push_str("{}"),
+        StorageEntry::Section(v) => {
+            out.push_str("{\n");
+            write_entries(out, v, depth + 1, style, path);
+            out.push_str(&INDENT.repeat(depth));
+            out.push('}');
+        }
+        StorageEntry::Array(v) => match v.serialize_type {
+            None => out.push_str("[]"),
+            Some(t) => {
+                let element_type = t & !SERIALIZE_FLAG_ARRAY;
+                write!(out, "of {} [", type_name(element_type)).unwrap();
+                let multiline =
+                    element_type == SERIALIZE_TYPE_OBJECT || element_type == SERIALIZE_TYPE_ARRAY;
+
+                for (i, element) in v.array.iter().enumerate() {
+                    if multiline {
+                        out.push('\n');
+                        out.push_str(&INDENT.repeat(depth + 1));
+                    } else if i != 0 {
+                        out.push(' ');
+                    }
+                    write_entry(out, element, depth + 1, style, path);
+                }
+
+                if multiline && !v.is_empty() {
+                    out.push('\n');
+                    out.push_str(&INDENT.repeat(depth));
+                }
+                out.push(']');
+            }
+        },
+    }
+}
+
+fn serialize_type(name: &str) -> Option<u8> {
+    Some(match name {
+        "i64" => SERIALIZE_TYPE_INT64,
+        "i32" => SERIALIZE_TYPE_INT32,
+        "i16" => SERIALIZE_TYPE_INT16,
+        "i8" => SERIALIZE_TYPE_INT8,
+        "u64" => SERIALIZE_TYPE_UINT64,
+        "u32" => SERIALIZE_TYPE_UINT32,
+        "u16" => SERIALIZE_TYPE_UINT16,
+        "u8" => SERIALIZE_TYPE_UINT8,
+        "double" => SERIALIZE_TYPE_DOUBLE,
+        "string" => SERIALIZE_TYPE_STRING,
+        "bool" => SERIALIZE_TYPE_BOOL,
+        "section" => SERIALIZE_TYPE_OBJECT,
+        "array" => SERIALIZE_TYPE_ARRAY,
+        _ => return None,
+    })
+}
+
+fn parse_error(line: usize, message: String) -> Error {
+    Error::Conversion(format!("line {}: {}", line, message))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Colon,
+    OpenBrace,
+    CloseBrace,
+    OpenBracket,
+    CloseBracket,
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            ':' => Token::Colon,
+            '{' => Token::OpenBrace,
+            '}' => Token::CloseBrace,
+            '[' => Token::OpenBracket,
+            ']' => Token::CloseBracket,
+            '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('u') => {
+                                let mut code = String::new();
+                                if chars.next() != Some('{') {
+                                    return Err(parse_error(line, "invalid escape".to_owned()));
+                                }
+                                for c in chars.by_ref() {
+                                    if c == '}' {
+                                        break;
+                                    }
+                                    code.push(c);
+                                }
+                                let c = u32::from_str_radix(&code, 16)
+                                    .ok()
+                                    .and_then(std::char::from_u32)
+                                    .ok_or_else(|| {
+                                        parse_error(line, format!("invalid escape `{}`", code))
+                                    })?;
+                                quoted.push(c);
+                            }
+                            Some(c @ '"') | Some(c @ '\\') => quoted.push(c),
+                            _ => return Err(parse_error(line, "invalid escape".to_owned())),
+                        },
+                        Some(c) => quoted.push(c),
+                        None => return Err(parse_error(line, "unterminated key".to_owned())),
+                    }
+                }
+                Token::Quoted(quoted)
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || ":{}[]\"".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push((line, token));
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or_else(|| self.tokens.last())
+            .map(|(line, _)| *line)
+            .unwrap_or(1)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let line = self.line();
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| parse_error(line, "unexpected end of input".to_owned()))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let line = self.line();
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(parse_error(
+                line,
+                format!("expected {:?}, found {:?}", expected, token),
+            )),
+        }
+    }
+
+    fn word(&mut self) -> Result<String> {
+        let line = self.line();
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            token => Err(parse_error(
+                line,
+                format!("expected a value, found {:?}", token),
+            )),
+        }
+    }
+
+    /// Parses `key: type value` lines up to a closing brace, if `nested`, or
+    /// the end of input.
+    fn entries(&mut self, nested: bool) -> Result<Section> {
+        let mut section = Section::new();
+        loop {
+            match self.peek() {
+                None if !nested => return Ok(section),
+                Some(Token::CloseBrace) if nested => {
+                    self.position += 1;
+                    return Ok(section);
+                }
+                _ => (),
+            }
+
+            let line = self.line();
+            let name = match self.next()? {
+                Token::Word(name) | Token::Quoted(name) => name,
+                token => {
+                    return Err(parse_error(
+                        line,
+                        format!("expected a key, found {:?}", token),
+                    ))
+                }
+            };
+            if name.len() > 255 {
+                return Err(parse_error(line, "keys can't exceed 255 bytes".to_owned()));
+            }
+            self.expect(Token::Colon)?;
+
+            let type_name = self.word()?;
+            let serialize_type = serialize_type(&type_name)
+                .ok_or_else(|| parse_error(line, format!("unknown type `{}`", type_name)))?;
+            let entry = self.value(serialize_type)?;
+            section.insert(name, entry);
+        }
+    }
+
+    fn value(&mut self, serialize_type: u8) -> Result<StorageEntry> {
+        let line = self.line();
+
+        macro_rules! number {
+            ($variant:path) => {{
+                let word = self.word()?;
+                word.parse()
+                    .map($variant)
+                    .map_err(|e| parse_error(line, format!("invalid number `{}`: {}", word, e)))?
+            }};
+        }
+
+        Ok(match serialize_type {
+            SERIALIZE_TYPE_INT64 => number!(StorageEntry::I64),
+            SERIALIZE_TYPE_INT32 => number!(StorageEntry::I32),
+            SERIALIZE_TYPE_INT16 => number!(StorageEntry::I16),
+            SERIALIZE_TYPE_INT8 => number!(StorageEntry::I8),
+            SERIALIZE_TYPE_UINT64 => number!(StorageEntry::U64),
+            SERIALIZE_TYPE_UINT32 => number!(StorageEntry::U32),
+            SERIALIZE_TYPE_UINT16 => number!(StorageEntry::U16),
+            SERIALIZE_TYPE_UINT8 => number!(StorageEntry::U8),
+            SERIALIZE_TYPE_DOUBLE => number!(StorageEntry::Double),
+            SERIALIZE_TYPE_BOOL => match self.word()?.as_str() {
+                "true" => StorageEntry::Bool(true),
+                "false" => StorageEntry::Bool(false),
+                word => return Err(parse_error(line, format!("invalid bool `{}`", word))),
+            },
+            SERIALIZE_TYPE_STRING => {
+                let word = self.word()?;
+                StorageEntry::Buf(
+                    decode_hex(&word)
+                        .ok_or_else(|| parse_error(line, format!("invalid string `{}`", word)))?
+                        .into(),
+                )
+            }
+            SERIALIZE_TYPE_OBJECT => {
+                self.expect(Token::OpenBrace)?;
+                StorageEntry::Section(self.entries(true)?)
+            }
+            _ => StorageEntry::Array(self.array()?),
+        })
+    }
+
+    /// Parses `[]` or `of type [values]`.
+    fn array(&mut self) -> Result<Array> {
+        let line = self.line();
+        if self.peek() == Some(&Token::OpenBracket) {
+            self.position += 1;
+            self.expect(Token::CloseBracket)?;
+            return Ok(Array::new());
+        }
+
+        if self.word()? != "of" {
+            return Err(parse_error(line, "expected `of` or `[]`".to_owned()));
+        }
+        let type_name = self.word()?;
+        let element_type = serialize_type(&type_name)
+            .ok_or_else(|| parse_error(line, format!("unknown type `{}`", type_name)))?;
+        self.expect(Token::OpenBracket)?;
+
+        let mut array = Array::new();
+        array.serialize_type = Some(element_type | SERIALIZE_FLAG_ARRAY);
+        while self.peek() != Some(&Token::CloseBracket) {
+            array.push(self.value(element_type)?)?;
+        }
+        self.position += 1;
+
+        Ok(array)
+    }
+}
+
+fn decode_hex(word: &str) -> Option<Vec<u8>> {
+    let digits = word.strip_prefix("0x")?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"node_data: section {
+  my_port: u32 18080
+  network_id: string 0x1230f171
+  ratio: double 0.1
+  inf: double -inf
+  flags: bool false
+}
+ids: array of i16 [-1 2 3]
+peers: array of section [
+  {
+    id: u64 1
+  }
+  {}
+]
+nested: array of array [
+  of u8 [1 2]
+  []
+]
+empty: array []
+empty_typed: array of string []
+"odd \"key\"\u{a}": i8 -128
+"#;
+
+    #[test]
+    fn roundtrip() {
+        let section = Section::from_text(TEXT).unwrap();
+        assert_eq!(section.to_text(KeyOrder::Insertion), TEXT);
+        assert!(matches!(section["node_data"], StorageEntry::Section(ref s) if s.len() == 5));
+        assert!(
+            matches!(section["empty_typed"], StorageEntry::Array(ref a) if a.serialize_type.is_some())
+        );
+
+        let sorted = section.to_text(KeyOrder::Sorted);
+        assert!(sorted.starts_with("empty: array []\nempty_typed: "));
+        assert!(crate::diff::diff(&Section::from_text(&sorted).unwrap(), &section).is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("a: u8 256", "line 1: invalid number `256`"),
+            ("a: u8 1\nb: string 0xabc", "line 2: invalid string `0xabc`"),
+            ("a: array of u8 [1 -1]", "line 1: invalid number `-1`"),
+            ("a: section {\n b: bool 1", "line 2: invalid bool `1`"),
+            ("a: section {", "line 1: unexpected end of input"),
+            ("a: float 1", "line 1: unknown type `float`"),
+        ];
+        for (text, message) in cases.iter() {
+            let error = Section::from_text(text).unwrap_err().to_string();
+            assert!(error.contains(message), "{}", error);
+        }
+    }
+}
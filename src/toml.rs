@@ -0,0 +1,85 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # TOML conversion
+//!
+//! Converts sections to and from TOML documents, going through the JSON
+//! value model so the same [`JsonConfig`] policies apply.
+//!
+//! TOML integers are signed 64-bit values, so `U64` entries above
+//! `i64::MAX` can't be converted.
+
+use crate::{json::JsonConfig, Error, Result, Section};
+
+impl Section {
+    /// Converts this section into a TOML document.
+    pub fn to_toml(&self, config: &JsonConfig) -> Result<String> {
+        ::toml::Value::try_from(self.to_json(config))
+            .and_then(|value| ::toml::to_string_pretty(&value))
+            .map_err(|e| Error::Conversion(e.to_string()))
+    }
+
+    /// Creates a section from a TOML document.
+    pub fn from_toml(s: &str, config: &JsonConfig) -> Result<Section> {
+        let value: serde_json::Value =
+            ::toml::from_str(s).map_err(|e| Error::Conversion(e.to_string()))?;
+        Section::from_json(&value, config)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{
+        json::{ByteEncoding, IntegerWidth},
+        StorageEntry,
+    };
+
+    #[test]
+    fn roundtrip() {
+        const DOC: &str = r#"
+            network_id = "1230f171610441611731008216a1a110"
+
+            [node_data]
+            my_port = { "$u32" = 18080 }
+            peer_id = { "$u64" = 1337 }
+        "#;
+
+        let config = JsonConfig::default();
+        let section = Section::from_toml(DOC, &config).unwrap();
+        assert!(matches!(section["network_id"], StorageEntry::Buf(ref v) if v.len() == 16));
+
+        let section = Section::from_toml(&section.to_toml(&config).unwrap(), &config).unwrap();
+        match &section["node_data"] {
+            StorageEntry::Section(node_data) => {
+                assert!(matches!(node_data["my_port"], StorageEntry::U32(18080)));
+                assert!(matches!(node_data["peer_id"], StorageEntry::U64(1337)));
+            }
+            _ => panic!("expected a section"),
+        }
+    }
+
+    #[test]
+    fn u64_out_of_range() {
+        let mut section = Section::new();
+        section.insert("big".to_owned(), StorageEntry::U64(u64::MAX));
+
+        let config = JsonConfig::new(ByteEncoding::Hex, IntegerWidth::Plain);
+        assert!(matches!(
+            section.to_toml(&config),
+            Err(Error::Conversion(_))
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Incremental re-encoding
+//!
+//! A [`Tracked`] section remembers the blob it was decoded from and which
+//! of its values were modified since. Writing it back copies the bytes of
+//! the unmodified values from the original blob, and only serializes the
+//! modified ones and the sections and arrays enclosing them. Relays that
+//! rewrite a field or two of each message skip most of the encoding work.
+//!
+//! ```rust
+//! use portable_storage::{tracked::Tracked, Section, StorageEntry};
+//!
+//! let mut node_data = Section::new();
+//! node_data.insert("local_time".to_owned(), StorageEntry::U64(1_600_000_000));
+//! node_data.insert("my_port".to_owned(), StorageEntry::U32(18080));
+//! let mut section = Section::new();
+//! section.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+//! let blob = portable_storage::write_to_vec(&section);
+//!
+//! let mut tracked = Tracked::read(&blob).unwrap();
+//! *tracked.get_mut("node_data.my_port").unwrap() = StorageEntry::U32(28080);
+//! let rewritten = tracked.write_to_vec();
+//!
+//! let section = portable_storage::read(&mut &rewritten[..]).unwrap();
+//! match &section["node_data"] {
+//!     StorageEntry::Section(node_data) => {
+//!         assert_eq!(node_data["my_port"], StorageEntry::U32(28080))
+//!     }
+//!     _ => unreachable!(),
+//! }
+//! ```
+//!
+//! Paths are built like in [`patch`](crate::patch): keys joined with `.`,
+//! array elements by their index.
+
+use crate::{
+    header::StorageBlockHeader,
+    raw_size,
+    redact::{join, within},
+    spans::{read_with_spans, Span, Spans},
+    write_name, Array, Result, Section, StorageEntry, SERIALIZE_FLAG_ARRAY, SERIALIZE_TYPE_OBJECT,
+    SERIALIZE_TYPE_STRING,
+};
+use bytes::{BufMut, BytesMut};
+
+/// A section decoded from a blob, tracking its modifications.
+#[derive(Debug, Clone)]
+pub struct Tracked<'a> {
+    data: &'a [u8],
+    section: Section,
+    spans: Spans,
+    /// Paths of the modified values, the empty path for the whole section.
+    dirty: Vec<String>,
+}
+
+impl<'a> Tracked<'a> {
+    /// Reads a blob, including the storage block header.
+    pub fn read(data: &'a [u8]) -> Result<Tracked<'a>> {
+        let (section, spans) = read_with_spans(data)?;
+        Ok(Tracked {
+            data,
+            section,
+            spans,
+            dirty: Vec::new(),
+        })
+    }
+
+    pub fn section(&self) -> &Section {
+        &self.section
+    }
+
+    pub fn into_section(self) -> Section {
+        self.section
+    }
+
+    /// Whether anything was modified.
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Returns the entry at `path`, marking it as modified.
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut StorageEntry> {
+        let mut segments = path.split('.');
+        let mut entry = self.section.entries.get_mut(segments.next()?)?;
+        for segment in segments {
+            entry = match entry {
+                StorageEntry::Section(section) => section.entries.get_mut(segment)?,
+                StorageEntry::Array(array) => {
+                    array.array.get_mut(segment.parse::<usize>().ok()?)?
+                }
+                _ => return None,
+            };
+        }
+
+        self.dirty.push(path.to_owned());
+        Some(entry)
+    }
+
+    /// Returns the whole section, marking all of it as modified.
+    pub fn section_mut(&mut self) -> &mut Section {
+        self.dirty.push(String::new());
+        &mut self.section
+    }
+
+    /// Writes the section as a storage blob, header included.
+    pub fn write(&self, buf: &mut BytesMut) {
+        StorageBlockHeader::write(buf);
+        self.write_section(buf, &self.section, "");
+    }
+
+    pub fn write_to_vec(&self) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        self.write(&mut buf);
+        buf.to_vec()
+    }
+
+    /// The original bytes of the value at `path`, when it wasn't modified,
+    /// nor anything in it or enclosing it.
+    fn clean_span(&self, path: &str) -> Option<Span> {
+        let affected = self.dirty.iter().any(|dirty| {
+            dirty.is_empty() || dirty == path || within(path, dirty) || within(dirty, path)
+        });
+        if affected {
+            None
+        } else {
+            self.spans.get(path).copied()
+        }
+    }
+
+    fn write_section(&self, buf: &mut BytesMut, section: &Section, path: &str) {
+        raw_size::write(buf, section.entries.len() as u64);
+        for (name, entry) in section.entries.iter() {
+            write_name(buf, name);
+            let path = join(path, name);
+            match self.clean_span(&path) {
+                // Spans start past the serialize type, copy it as well.
+                Some(span) => buf.put_slice(&self.data[span.offset - 1..span.end()]),
+                None => match entry {
+                    StorageEntry::Section(section) => {
+                        buf.put_u8(SERIALIZE_TYPE_OBJECT);
+                        self.write_section(buf, section, &path);
+                    }
+                    StorageEntry::Array(array) => self.write_array(buf, array, &path),
+                    entry => StorageEntry::write(buf, entry),
+                },
+            }
+        }
+    }
+
+    fn write_array(&self, buf: &mut BytesMut, array: &Array, path: &str) {
+        buf.put_u8(
+            array
+                .serialize_type
+                .unwrap_or(SERIALIZE_FLAG_ARRAY | SERIALIZE_TYPE_STRING),
+        );
+        raw_size::write(buf, array.array.len() as u64);
+        for (i, element) in array.array.iter().enumerate() {
+            let path = join(path, &i.to_string());
+            match self.clean_span(&path) {
+                Some(span) => buf.put_slice(&self.data[span.offset..span.end()]),
+                None => match element {
+                    StorageEntry::Section(section) => self.write_section(buf, section, &path),
+                    StorageEntry::Array(array) => self.write_array(buf, array, &path),
+                    element => StorageEntry::write_raw(buf, element),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn peer(id: u64) -> Section {
+        let mut peer = Section::new();
+        peer.insert("id".to_owned(), StorageEntry::U64(id));
+        peer.insert(
+            "addr".to_owned(),
+            StorageEntry::Buf(vec![id as u8; 40].into()),
+        );
+        peer
+    }
+
+    #[test]
+    fn splices() {
+        let mut peers = Array::new();
+        for id in 0..3 {
+            peers.push(StorageEntry::Section(peer(id))).unwrap();
+        }
+        let mut section = Section::new();
+        section.insert("node_data".to_owned(), StorageEntry::Section(peer(9)));
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+        section.insert("flags".to_owned(), StorageEntry::U32(1));
+        let blob = crate::write_to_vec(&section);
+
+        let tracked = Tracked::read(&blob).unwrap();
+        assert!(!tracked.is_dirty());
+        assert_eq!(tracked.write_to_vec(), blob);
+
+        let mut tracked = Tracked::read(&blob).unwrap();
+        *tracked.get_mut("peers.1.addr").unwrap() = StorageEntry::Buf(b"short"[..].into());
+        *tracked.get_mut("flags").unwrap() = StorageEntry::U32(2);
+        assert!(tracked.get_mut("peers.7").is_none());
+        assert!(tracked.is_dirty());
+
+        let rewritten = tracked.write_to_vec();
+        assert_eq!(rewritten, crate::write_to_vec(tracked.section()));
+        assert!(rewritten.len() < blob.len());
+
+        let mut tracked = Tracked::read(&blob).unwrap();
+        tracked
+            .section_mut()
+            .insert("extra".to_owned(), StorageEntry::Bool(true));
+        assert_eq!(
+            tracked.write_to_vec(),
+            crate::write_to_vec(tracked.section())
+        );
+    }
+
+    #[test]
+    fn unflagged_arrays() {
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U8(1)).unwrap();
+        let mut section = Section::new();
+        section.insert("ids".to_owned(), StorageEntry::Array(ids));
+        section.insert("flags".to_owned(), StorageEntry::U32(1));
+        let mut blob = crate::write_to_vec(&section);
+        // An array introduced by an unflagged `SERIALIZE_TYPE_ARRAY` is kept
+        // as it was.
+        blob.insert(14, crate::SERIALIZE_TYPE_ARRAY);
+
+        let mut tracked = Tracked::read(&blob).unwrap();
+        *tracked.get_mut("flags").unwrap() = StorageEntry::U32(1);
+        assert_eq!(tracked.write_to_vec(), blob);
+    }
+}
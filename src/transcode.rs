@@ -0,0 +1,364 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Transcoding
+//!
+//! Converts sections to and from CBOR (`cbor` feature) and MessagePack
+//! (`msgpack` feature), for archiving captures in more widely supported
+//! binary formats.
+//!
+//! The conversion is lossless: a section is encoded as a map from key to a
+//! `[serialize type, value]` pair, arrays as `[array serialize type,
+//! [values...]]` and `Buf` values as native byte strings, so decoding yields
+//! exactly the same entries, including integer widths and the element type
+//! of empty arrays.
+
+use crate::{
+    Array, Error, Result, Section, StorageEntry, SERIALIZE_FLAG_ARRAY, SERIALIZE_TYPE_ARRAY,
+    SERIALIZE_TYPE_BOOL, SERIALIZE_TYPE_DOUBLE, SERIALIZE_TYPE_INT16, SERIALIZE_TYPE_INT32,
+    SERIALIZE_TYPE_INT64, SERIALIZE_TYPE_INT8, SERIALIZE_TYPE_OBJECT, SERIALIZE_TYPE_STRING,
+    SERIALIZE_TYPE_UINT16, SERIALIZE_TYPE_UINT32, SERIALIZE_TYPE_UINT64, SERIALIZE_TYPE_UINT8,
+};
+use serde::{
+    de::{DeserializeSeed, Deserializer, Error as DeError, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq, SerializeTuple, Serializer},
+    Deserialize, Serialize,
+};
+use std::fmt;
+
+/// Encodes `section` as CBOR.
+#[cfg(feature = "cbor")]
+pub fn to_cbor(section: &Section) -> Result<Vec<u8>> {
+    serde_cbor::to_vec(&SectionRef(section)).map_err(|e| Error::Conversion(e.to_string()))
+}
+
+/// Decodes a section previously encoded with [`to_cbor`].
+#[cfg(feature = "cbor")]
+pub fn from_cbor(buf: &[u8]) -> Result<Section> {
+    serde_cbor::from_slice::<OwnedSection>(buf)
+        .map(|s| s.0)
+        .map_err(|e| Error::Conversion(e.to_string()))
+}
+
+/// Encodes `section` as MessagePack.
+#[cfg(feature = "msgpack")]
+pub fn to_msgpack(section: &Section) -> Result<Vec<u8>> {
+    rmp_serde::to_vec(&SectionRef(section)).map_err(|e| Error::Conversion(e.to_string()))
+}
+
+/// Decodes a section previously encoded with [`to_msgpack`].
+#[cfg(feature = "msgpack")]
+pub fn from_msgpack(buf: &[u8]) -> Result<Section> {
+    rmp_serde::from_slice::<OwnedSection>(buf)
+        .map(|s| s.0)
+        .map_err(|e| Error::Conversion(e.to_string()))
+}
+
+struct SectionRef<'a>(&'a Section);
+
+impl<'a> Serialize for SectionRef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, entry) in self.0.entries.iter() {
+            map.serialize_entry(name, &EntryRef(entry))?;
+        }
+        map.end()
+    }
+}
+
+struct EntryRef<'a>(&'a StorageEntry);
+
+impl<'a> Serialize for EntryRef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&self.0.serialize_type())?;
+        tuple.serialize_element(&ValueRef(self.0))?;
+        tuple.end()
+    }
+}
+
+struct ValueRef<'a>(&'a StorageEntry);
+
+impl<'a> Serialize for ValueRef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            StorageEntry::U64(v) => serializer.serialize_u64(*v),
+            StorageEntry::U32(v) => serializer.serialize_u32(*v),
+            StorageEntry::U16(v) => serializer.serialize_u16(*v),
+            StorageEntry::U8(v) => serializer.serialize_u8(*v),
+            StorageEntry::I64(v) => serializer.serialize_i64(*v),
+            StorageEntry::I32(v) => serializer.serialize_i32(*v),
+            StorageEntry::I16(v) => serializer.serialize_i16(*v),
+            StorageEntry::I8(v) => serializer.serialize_i8(*v),
+            StorageEntry::Double(v) => serializer.serialize_f64(*v),
+            StorageEntry::Bool(v) => serializer.serialize_bool(*v),
+            StorageEntry::Buf(v) => serializer.serialize_bytes(v),
+            StorageEntry::Array(v) => {
+                let mut tuple = serializer.serialize_tuple(2)?;
+                tuple.serialize_element(&v.serialize_type.unwrap_or(0))?;
+                tuple.serialize_element(&ValuesRef(v))?;
+                tuple.end()
+            }
+            StorageEntry::Section(v) => SectionRef(v).serialize(serializer),
+        }
+    }
+}
+
+struct ValuesRef<'a>(&'a Array);
+
+impl<'a> Serialize for ValuesRef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for entry in self.0.array.iter() {
+            seq.serialize_element(&ValueRef(entry))?;
+        }
+        seq.end()
+    }
+}
+
+struct OwnedSection(Section);
+
+impl<'de> Deserialize<'de> for OwnedSection {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer
+            .deserialize_map(SectionVisitor)
+            .map(OwnedSection)
+    }
+}
+
+struct SectionVisitor;
+
+impl<'de> Visitor<'de> for SectionVisitor {
+    type Value = Section;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a section")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Section, A::Error> {
+        let mut section = Section::new();
+        while let Some((name, entry)) = map.next_entry::<String, OwnedEntry>()? {
+            crate::check_name(&name).map_err(A::Error::custom)?;
+            section.insert(name, entry.0);
+        }
+        Ok(section)
+    }
+}
+
+struct OwnedEntry(StorageEntry);
+
+impl<'de> Deserialize<'de> for OwnedEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = OwnedEntry;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a [serialize type, value] pair")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> std::result::Result<OwnedEntry, A::Error> {
+                let serialize_type: u8 = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                seq.next_element_seed(ValueSeed(serialize_type))?
+                    .map(OwnedEntry)
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))
+            }
+        }
+
+        deserializer.deserialize_tuple(2, EntryVisitor)
+    }
+}
+
+struct ValueSeed(u8);
+
+impl<'de> DeserializeSeed<'de> for ValueSeed {
+    type Value = StorageEntry;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        d: D,
+    ) -> std::result::Result<StorageEntry, D::Error> {
+        let entry = match self.0 {
+            SERIALIZE_TYPE_UINT64 => StorageEntry::U64(u64::deserialize(d)?),
+            SERIALIZE_TYPE_UINT32 => StorageEntry::U32(u32::deserialize(d)?),
+            SERIALIZE_TYPE_UINT16 => StorageEntry::U16(u16::deserialize(d)?),
+            SERIALIZE_TYPE_UINT8 => StorageEntry::U8(u8::deserialize(d)?),
+            SERIALIZE_TYPE_INT64 => StorageEntry::I64(i64::deserialize(d)?),
+            SERIALIZE_TYPE_INT32 => StorageEntry::I32(i32::deserialize(d)?),
+            SERIALIZE_TYPE_INT16 => StorageEntry::I16(i16::deserialize(d)?),
+            SERIALIZE_TYPE_INT8 => StorageEntry::I8(i8::deserialize(d)?),
+            SERIALIZE_TYPE_DOUBLE => StorageEntry::Double(f64::deserialize(d)?),
+            SERIALIZE_TYPE_BOOL => StorageEntry::Bool(bool::deserialize(d)?),
+            SERIALIZE_TYPE_STRING => StorageEntry::Buf(d.deserialize_byte_buf(BufVisitor)?.into()),
+            SERIALIZE_TYPE_ARRAY => StorageEntry::Array(d.deserialize_tuple(2, ArrayVisitor)?),
+            SERIALIZE_TYPE_OBJECT => StorageEntry::Section(d.deserialize_map(SectionVisitor)?),
+            serialize_type => {
+                return Err(D::Error::custom(format!(
+                    "invalid serialize type ({:X})",
+                    serialize_type
+                )))
+            }
+        };
+
+        Ok(entry)
+    }
+}
+
+struct BufVisitor;
+
+impl<'de> Visitor<'de> for BufVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a byte string")
+    }
+
+    fn visit_bytes<E: DeError>(self, v: &[u8]) -> std::result::Result<Vec<u8>, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: DeError>(self, v: Vec<u8>) -> std::result::Result<Vec<u8>, E> {
+        Ok(v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Vec<u8>, A::Error> {
+        let mut v = Vec::new();
+        while let Some(b) = seq.next_element()? {
+            v.push(b);
+        }
+        Ok(v)
+    }
+}
+
+struct ArrayVisitor;
+
+impl<'de> Visitor<'de> for ArrayVisitor {
+    type Value = Array;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an [array serialize type, values] pair")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Array, A::Error> {
+        let serialize_type: u8 = seq
+            .next_element()?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        let serialize_type = match serialize_type {
+            0 => None,
+            t if t & SERIALIZE_FLAG_ARRAY == SERIALIZE_FLAG_ARRAY => Some(t),
+            t => {
+                return Err(A::Error::custom(format!(
+                    "invalid array serialize type ({:X})",
+                    t
+                )))
+            }
+        };
+
+        seq.next_element_seed(ValuesSeed(serialize_type))?
+            .ok_or_else(|| A::Error::invalid_length(1, &self))
+    }
+}
+
+struct ValuesSeed(Option<u8>);
+
+impl<'de> DeserializeSeed<'de> for ValuesSeed {
+    type Value = Array;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Array, D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ValuesSeed {
+    type Value = Array;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a sequence of array values")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Array, A::Error> {
+        let mut array = Array {
+            array: Vec::new(),
+            serialize_type: self.0,
+        };
+
+        if let Some(serialize_type) = self.0 {
+            let seed = || ValueSeed(serialize_type & !SERIALIZE_FLAG_ARRAY);
+            while let Some(entry) = seq.next_element_seed(seed())? {
+                array.array.push(entry);
+            }
+        } else if seq.next_element::<serde::de::IgnoredAny>()?.is_some() {
+            return Err(A::Error::custom("untyped arrays must be empty"));
+        }
+
+        Ok(array)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn section() -> Section {
+        let mut hashes = Array::new();
+        hashes.push(StorageEntry::Buf(vec![1; 32].into())).unwrap();
+        hashes.push(StorageEntry::Buf(vec![2; 32].into())).unwrap();
+
+        let mut inner = Section::new();
+        inner.insert("port".to_owned(), StorageEntry::U16(18080));
+        inner.insert("offset".to_owned(), StorageEntry::I32(-5));
+
+        let mut section = Section::new();
+        section.insert("hashes".to_owned(), StorageEntry::Array(hashes));
+        section.insert("node_data".to_owned(), StorageEntry::Section(inner));
+        section.insert("pruned".to_owned(), StorageEntry::Bool(true));
+        section.insert("ratio".to_owned(), StorageEntry::Double(0.25));
+        section
+    }
+
+    fn check(section: &Section) {
+        match (&section["hashes"], &section["node_data"]) {
+            (StorageEntry::Array(hashes), StorageEntry::Section(inner)) => {
+                assert_eq!(hashes.len(), 2);
+                assert!(matches!(hashes[1], StorageEntry::Buf(ref v) if v[..] == [2; 32]));
+                assert!(matches!(inner["port"], StorageEntry::U16(18080)));
+                assert!(matches!(inner["offset"], StorageEntry::I32(-5)));
+            }
+            _ => panic!("unexpected entries"),
+        }
+        assert!(matches!(section["pruned"], StorageEntry::Bool(true)));
+        assert!(matches!(section["ratio"], StorageEntry::Double(v) if v == 0.25));
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_roundtrip() {
+        let buf = to_cbor(&section()).unwrap();
+        check(&from_cbor(&buf).unwrap());
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_roundtrip() {
+        let buf = to_msgpack(&section()).unwrap();
+        check(&from_msgpack(&buf).unwrap());
+    }
+}
@@ -0,0 +1,810 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Dry-run validation
+//!
+//! Checks whether a section would deserialize into a type with
+//! [`from_section`](crate::from_section), and reports every problem found
+//! with its path instead of stopping at the first one:
+//!
+//! ```rust
+//! use portable_storage::{Section, StorageEntry};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct NodeData {
+//!     my_port: u32,
+//!     peer_id: u64,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Handshake {
+//!     node_data: NodeData,
+//!     network_id: Vec<u8>,
+//! }
+//!
+//! let mut node_data = Section::new();
+//! node_data.insert("my_port".to_owned(), StorageEntry::Bool(true));
+//! let mut section = Section::new();
+//! section.insert("node_data".to_owned(), StorageEntry::Section(node_data));
+//!
+//! let violations: Vec<String> = section
+//!     .validate_against::<Handshake>()
+//!     .iter()
+//!     .map(ToString::to_string)
+//!     .collect();
+//! assert_eq!(
+//!     violations,
+//!     [
+//!         "node_data.my_port: expected u32, found boolean `true`",
+//!         "node_data.peer_id: missing required key",
+//!         "network_id: missing required key",
+//!     ]
+//! );
+//! ```
+//!
+//! Values are borrowed from the section rather than cloned out of it. After
+//! a problem is found the offending value is replaced by a placeholder of
+//! the expected shape and deserialization starts over, so a type whose
+//! `Deserialize` implementation rejects the placeholder stops the report
+//! early.
+
+use crate::{
+    redact::join,
+    schema::{Violation, ViolationKind},
+    Array, Section, StorageEntry,
+};
+use serde::{
+    de::{
+        self, value::BorrowedStrDeserializer, DeserializeOwned, DeserializeSeed, Deserializer,
+        MapAccess, SeqAccess, Visitor,
+    },
+    forward_to_deserialize_any,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+impl Section {
+    /// Checks whether this section would deserialize into `T` and returns
+    /// every violation found, see the [module documentation](crate::validate).
+    /// An empty list means [`from_section`](crate::from_section) succeeds.
+    pub fn validate_against<T: DeserializeOwned>(&self) -> Vec<Violation> {
+        let mut run = Run::default();
+        let mut violations: Vec<Violation> = Vec::new();
+
+        while let Err(failure) = T::deserialize(Root {
+            section: self,
+            run: &run,
+        }) {
+            let base = failure.path.unwrap_or_default();
+            let path = match failure.key {
+                Some(ref key) => join(&base, key),
+                None => base.clone(),
+            };
+            let repeated = violations.iter().any(|v| v.path == path);
+            if !repeated {
+                violations.push(Violation {
+                    path: path.clone(),
+                    kind: failure.kind.clone(),
+                });
+            }
+            if repeated || path.is_empty() {
+                break;
+            }
+
+            match (failure.kind, failure.key) {
+                (ViolationKind::Missing, Some(key)) => {
+                    run.missing.entry(base).or_default().push(key)
+                }
+                (ViolationKind::Unknown, Some(_)) => {
+                    run.skipped.insert(path);
+                }
+                _ => {
+                    run.substitutes.insert(path);
+                }
+            }
+        }
+
+        violations
+    }
+}
+
+/// Where placeholders are used in a deserialization attempt.
+#[derive(Default)]
+struct Run {
+    /// Paths of values replaced by a placeholder.
+    substitutes: HashSet<String>,
+    /// Paths of keys left out.
+    skipped: HashSet<String>,
+    /// Keys added with a placeholder value, by section path.
+    missing: HashMap<String, Vec<String>>,
+}
+
+/// The first problem of a deserialization attempt.
+#[derive(Debug)]
+struct Failure {
+    /// Path of the innermost value being deserialized when it happened.
+    path: Option<String>,
+    /// Key of that section the problem is about, for missing and unknown
+    /// keys.
+    key: Option<String>,
+    kind: ViolationKind,
+}
+
+impl Failure {
+    fn at(mut self, path: &str) -> Failure {
+        if self.path.is_none() {
+            self.path = Some(path.to_owned());
+        }
+        self
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.kind, f)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+impl de::Error for Failure {
+    fn custom<T: fmt::Display>(msg: T) -> Failure {
+        Failure {
+            path: None,
+            key: None,
+            kind: ViolationKind::Custom(msg.to_string()),
+        }
+    }
+
+    fn invalid_type(unexp: de::Unexpected, exp: &dyn de::Expected) -> Failure {
+        Failure {
+            path: None,
+            key: None,
+            kind: ViolationKind::WrongType {
+                expected: exp.to_string(),
+                found: unexp.to_string(),
+            },
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Failure {
+        Failure {
+            path: None,
+            key: Some(field.to_owned()),
+            kind: ViolationKind::Missing,
+        }
+    }
+
+    fn unknown_field(field: &str, _expected: &'static [&'static str]) -> Failure {
+        Failure {
+            path: None,
+            key: Some(field.to_owned()),
+            kind: ViolationKind::Unknown,
+        }
+    }
+}
+
+/// The root section, which like in `from_section` can only be a structure.
+struct Root<'a> {
+    section: &'a Section,
+    run: &'a Run,
+}
+
+impl<'de, 'a> Deserializer<'de> for Root<'a> {
+    type Error = Failure;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Failure> {
+        Err(de::Error::custom(
+            "only structures can be deserialized from a section",
+        ))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        visitor
+            .visit_map(Entries::new(self.section, "", fields, self.run))
+            .map_err(|e| e.at(""))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+        byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Deserializes an entry the way `from_section` does, unless it's replaced
+/// by a placeholder.
+struct Entry<'a> {
+    entry: &'a StorageEntry,
+    path: String,
+    run: &'a Run,
+}
+
+impl<'a> Entry<'a> {
+    fn substituted(&self) -> bool {
+        self.run.substitutes.contains(&self.path)
+    }
+}
+
+macro_rules! forward_or_substitute {
+    ($($method:ident)*) => {
+        $(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+            if self.substituted() {
+                Placeholder.$method(visitor).map_err(|e| e.at(&self.path))
+            } else {
+                self.deserialize_any(visitor)
+            }
+        }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for Entry<'a> {
+    type Error = Failure;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        if self.substituted() {
+            return Placeholder
+                .deserialize_any(visitor)
+                .map_err(|e| e.at(&self.path));
+        }
+
+        let result = match self.entry {
+            StorageEntry::U64(v) => visitor.visit_u64(*v),
+            StorageEntry::U32(v) => visitor.visit_u32(*v),
+            StorageEntry::U16(v) => visitor.visit_u16(*v),
+            StorageEntry::U8(v) => visitor.visit_u8(*v),
+            StorageEntry::I64(v) => visitor.visit_i64(*v),
+            StorageEntry::I32(v) => visitor.visit_i32(*v),
+            StorageEntry::I16(v) => visitor.visit_i16(*v),
+            StorageEntry::I8(v) => visitor.visit_i8(*v),
+            StorageEntry::Double(v) => visitor.visit_f64(*v),
+            StorageEntry::Bool(v) => visitor.visit_bool(*v),
+            StorageEntry::Buf(v) => visitor.visit_bytes(v),
+            StorageEntry::Array(v) => visitor.visit_seq(Elements::new(v, &self.path, self.run)),
+            StorageEntry::Section(v) => {
+                visitor.visit_map(Entries::new(v, &self.path, &[], self.run))
+            }
+        };
+        result.map_err(|e| e.at(&self.path))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        if self.substituted() {
+            return Placeholder
+                .deserialize_struct(name, fields, visitor)
+                .map_err(|e| e.at(&self.path));
+        }
+
+        match self.entry {
+            StorageEntry::Section(v) => visitor
+                .visit_map(Entries::new(v, &self.path, fields, self.run))
+                .map_err(|e| e.at(&self.path)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        if self.substituted() {
+            Placeholder
+                .deserialize_unit_struct(name, visitor)
+                .map_err(|e| e.at(&self.path))
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        if self.substituted() {
+            Placeholder
+                .deserialize_newtype_struct(name, visitor)
+                .map_err(|e| e.at(&self.path))
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        if self.substituted() {
+            Placeholder
+                .deserialize_tuple(len, visitor)
+                .map_err(|e| e.at(&self.path))
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        if self.substituted() {
+            Placeholder
+                .deserialize_tuple_struct(name, len, visitor)
+                .map_err(|e| e.at(&self.path))
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        if self.substituted() {
+            Placeholder
+                .deserialize_enum(name, variants, visitor)
+                .map_err(|e| e.at(&self.path))
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    forward_or_substitute! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_u8 deserialize_u16 deserialize_u32
+        deserialize_u64 deserialize_f32 deserialize_f64 deserialize_char
+        deserialize_str deserialize_string deserialize_bytes deserialize_byte_buf
+        deserialize_option deserialize_unit deserialize_seq deserialize_map
+        deserialize_identifier deserialize_ignored_any
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+struct Elements<'a> {
+    iter: std::iter::Enumerate<std::slice::Iter<'a, StorageEntry>>,
+    path: &'a str,
+    run: &'a Run,
+}
+
+impl<'a> Elements<'a> {
+    fn new(array: &'a Array, path: &'a str, run: &'a Run) -> Elements<'a> {
+        Elements {
+            iter: array.array.iter().enumerate(),
+            path,
+            run,
+        }
+    }
+}
+
+impl<'de, 'a> SeqAccess<'de> for Elements<'a> {
+    type Error = Failure;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Failure> {
+        match self.iter.next() {
+            Some((i, entry)) => seed
+                .deserialize(Entry {
+                    entry,
+                    path: format!("{}[{}]", self.path, i),
+                    run: self.run,
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// The entries of a section, without the skipped ones and followed by the
+/// missing ones.
+struct Entries<'a> {
+    iter: linked_hash_map::Iter<'a, String, StorageEntry>,
+    missing: std::slice::Iter<'a, String>,
+    path: &'a str,
+    fields: &'static [&'static str],
+    run: &'a Run,
+    value: Option<(Option<&'a StorageEntry>, String)>,
+}
+
+impl<'a> Entries<'a> {
+    fn new(
+        section: &'a Section,
+        path: &'a str,
+        fields: &'static [&'static str],
+        run: &'a Run,
+    ) -> Entries<'a> {
+        let missing = match run.missing.get(path) {
+            Some(missing) => missing.iter(),
+            None => [].iter(),
+        };
+        Entries {
+            iter: section.entries.iter(),
+            missing,
+            path,
+            fields,
+            run,
+            value: None,
+        }
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for Entries<'a> {
+    type Error = Failure;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Failure> {
+        let (key, entry) = loop {
+            match self.iter.next() {
+                Some((key, entry)) => {
+                    if !self.run.skipped.contains(&join(self.path, key)) {
+                        break (key, Some(entry));
+                    }
+                }
+                None => match self.missing.next() {
+                    Some(key) => break (key, None),
+                    None => return Ok(None),
+                },
+            }
+        };
+
+        self.value = Some((entry, join(self.path, key)));
+        seed.deserialize(Key {
+            key,
+            fields: self.fields,
+        })
+        .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Failure> {
+        match self.value.take() {
+            Some((Some(entry), path)) => seed.deserialize(Entry {
+                entry,
+                path,
+                run: self.run,
+            }),
+            Some((None, path)) => seed.deserialize(Placeholder).map_err(|e| e.at(&path)),
+            None => Err(de::Error::custom("seed value is missing")),
+        }
+    }
+}
+
+/// Deserializes keys like `from_section`, as the index of the structure
+/// field they name when there's one.
+struct Key<'a> {
+    key: &'a str,
+    fields: &'static [&'static str],
+}
+
+impl<'de, 'a> Deserializer<'de> for Key<'a> {
+    type Error = Failure;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_str(self.key)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        match self.fields.iter().position(|field| *field == self.key) {
+            Some(index) => visitor.visit_u64(index as u64),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+        byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum ignored_any
+    }
+}
+
+/// Produces an empty value of whatever shape is asked for.
+struct Placeholder;
+
+impl<'de> Deserializer<'de> for Placeholder {
+    type Error = Failure;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_i8(0)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_i16(0)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_i32(0)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_u8(0)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_u16(0)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_u32(0)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_f32(0.0)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_char('\0')
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_borrowed_str("")
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_borrowed_str("")
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_borrowed_bytes(&[])
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_borrowed_bytes(&[])
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_none()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_seq(Placeholders(&[]))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        visitor.visit_seq(PlaceholderTuple(len))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        visitor.visit_seq(PlaceholderTuple(len))
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_map(Placeholders(&[]))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        visitor.visit_map(Placeholders(fields))
+    }
+
+    forward_to_deserialize_any! {
+        unit unit_struct enum identifier ignored_any
+    }
+}
+
+/// Placeholder fields of a structure.
+struct Placeholders(&'static [&'static str]);
+
+impl<'de> MapAccess<'de> for Placeholders {
+    type Error = Failure;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Failure> {
+        match self.0.split_first() {
+            Some((field, rest)) => {
+                self.0 = rest;
+                seed.deserialize(BorrowedStrDeserializer::new(field))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Failure> {
+        seed.deserialize(Placeholder)
+    }
+}
+
+impl<'de> SeqAccess<'de> for Placeholders {
+    type Error = Failure;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        _seed: T,
+    ) -> Result<Option<T::Value>, Failure> {
+        Ok(None)
+    }
+}
+
+/// Placeholder elements of a tuple.
+struct PlaceholderTuple(usize);
+
+impl<'de> SeqAccess<'de> for PlaceholderTuple {
+    type Error = Failure;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Failure> {
+        if self.0 == 0 {
+            return Ok(None);
+        }
+        self.0 -= 1;
+        seed.deserialize(Placeholder).map(Some)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Peer {
+        id: u64,
+        #[serde(default)]
+        port: u16,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    struct Message {
+        peers: Vec<Peer>,
+        name: String,
+        flags: HashMap<String, bool>,
+        top: [u8; 2],
+    }
+
+    fn peer(id: StorageEntry) -> StorageEntry {
+        let mut peer = Section::new();
+        peer.insert("id".to_owned(), id);
+        StorageEntry::Section(peer)
+    }
+
+    #[test]
+    fn valid() {
+        let mut peers = Array::new();
+        peers.push(peer(StorageEntry::U32(1))).unwrap();
+        let mut flags = Section::new();
+        flags.insert("a".to_owned(), StorageEntry::Bool(true));
+        let mut top = Array::new();
+        top.push(StorageEntry::U8(1)).unwrap();
+        top.push(StorageEntry::U8(2)).unwrap();
+
+        let mut section = Section::new();
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+        section.insert("name".to_owned(), StorageEntry::Buf(b"x"[..].into()));
+        section.insert("flags".to_owned(), StorageEntry::Section(flags));
+        section.insert("top".to_owned(), StorageEntry::Array(top));
+
+        assert!(section.validate_against::<Message>().is_empty());
+        assert!(crate::from_section::<Message>(section).is_ok());
+    }
+
+    #[test]
+    fn every_violation() {
+        let mut bad_peer = Section::new();
+        bad_peer.insert("id".to_owned(), StorageEntry::I8(-1));
+        bad_peer.insert("extra".to_owned(), StorageEntry::U8(0));
+
+        let mut peers = Array::new();
+        peers.push(peer(StorageEntry::U8(1))).unwrap();
+        peers.push(StorageEntry::Section(bad_peer)).unwrap();
+        peers.push(StorageEntry::Section(Section::new())).unwrap();
+        let mut flags = Section::new();
+        flags.insert("a".to_owned(), StorageEntry::U8(1));
+
+        let mut section = Section::new();
+        section.insert("peers".to_owned(), StorageEntry::Array(peers));
+        section.insert("name".to_owned(), StorageEntry::Buf(b"\xff"[..].into()));
+        section.insert("flags".to_owned(), StorageEntry::Section(flags));
+
+        let violations: Vec<String> = section
+            .validate_against::<Message>()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                "peers[1].id: invalid value: integer `-1`, expected u64",
+                "peers[1].extra: unknown key",
+                "peers[2].id: missing required key",
+                "name: invalid value: byte array, expected a string",
+                "flags.a: expected a boolean, found integer `1`",
+                "top: missing required key",
+            ]
+        );
+        assert!(crate::from_section::<Message>(section).is_err());
+
+        let section = Section::new();
+        assert_eq!(section.validate_against::<u32>()[0].path, "");
+    }
+}
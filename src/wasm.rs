@@ -0,0 +1,116 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # WebAssembly entry points
+//!
+//! `wasm-bindgen` exports for browser-based blob explorers. Blobs are passed
+//! as `Uint8Array`s and errors are thrown as strings.
+//!
+//! ```js
+//! import { explain, toJson, fromJson } from "portable-storage";
+//!
+//! console.log(explain(blob));
+//! const json = toJson(blob, false);
+//! const again = fromJson(json);
+//! ```
+
+use crate::{
+    json::{ByteEncoding, IntegerWidth, JsonConfig},
+    text::KeyOrder,
+    Section,
+};
+use bytes::BytesMut;
+use wasm_bindgen::prelude::*;
+
+/// Annotates every byte of `blob`, see [`crate::explain`].
+#[wasm_bindgen]
+pub fn explain(blob: &[u8]) -> String {
+    crate::explain::explain(blob).to_string()
+}
+
+/// Decodes `blob` to JSON with tagged integers and hex strings. Pretty prints
+/// when `pretty` is set.
+#[wasm_bindgen(js_name = toJson)]
+pub fn to_json(blob: &[u8], pretty: bool) -> Result<String, JsValue> {
+    to_json_string(blob, pretty).map_err(|e| JsValue::from_str(&e))
+}
+
+/// Encodes JSON produced by [`to_json`] back to a blob.
+#[wasm_bindgen(js_name = fromJson)]
+pub fn from_json(json: &str) -> Result<Vec<u8>, JsValue> {
+    from_json_string(json).map_err(|e| JsValue::from_str(&e))
+}
+
+/// Decodes `blob` to the stable text format, keys in byte order when
+/// `sorted` is set.
+#[wasm_bindgen(js_name = toText)]
+pub fn to_text(blob: &[u8], sorted: bool) -> Result<String, JsValue> {
+    to_text_string(blob, sorted).map_err(|e| JsValue::from_str(&e))
+}
+
+fn config() -> JsonConfig {
+    JsonConfig::new(ByteEncoding::Hex, IntegerWidth::Tagged)
+}
+
+fn to_json_string(blob: &[u8], pretty: bool) -> Result<String, String> {
+    let section = crate::read(&mut &blob[..]).map_err(|e| e.to_string())?;
+    let value = section.to_json(&config());
+    let json = if pretty {
+        serde_json::to_string_pretty(&value)
+    } else {
+        serde_json::to_string(&value)
+    };
+    json.map_err(|e| e.to_string())
+}
+
+fn from_json_string(json: &str) -> Result<Vec<u8>, String> {
+    let value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let section = Section::from_json(&value, &config()).map_err(|e| e.to_string())?;
+    let mut buf = BytesMut::new();
+    crate::write(&mut buf, &section);
+    Ok(buf.to_vec())
+}
+
+fn to_text_string(blob: &[u8], sorted: bool) -> Result<String, String> {
+    let section = crate::read(&mut &blob[..]).map_err(|e| e.to_string())?;
+    let order = if sorted {
+        KeyOrder::Sorted
+    } else {
+        KeyOrder::Insertion
+    };
+    Ok(section.to_text(order))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::StorageEntry;
+
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    fn entry_points() {
+        let mut section = Section::new();
+        section.insert("id".to_owned(), StorageEntry::U32(5));
+        let mut buf = BytesMut::new();
+        crate::write(&mut buf, &section);
+
+        let json = to_json_string(&buf, false).unwrap();
+        assert_eq!(json, r#"{"id":{"$u32":5}}"#);
+        assert_eq!(from_json_string(&json).unwrap(), buf.to_vec());
+        assert_eq!(to_text_string(&buf, true).unwrap(), "id: u32 5\n");
+        assert!(explain(&buf).contains("\"id\""));
+        assert!(to_json_string(&buf[1..], false).is_err());
+    }
+}
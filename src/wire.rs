@@ -0,0 +1,231 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Wire primitives
+//!
+//! The building blocks of the encoding, for consumers that walk or produce
+//! blobs by hand (dissectors, custom codecs) instead of going through a
+//! [`Section`](crate::Section):
+//!
+//! ```rust
+//! use bytes::BytesMut;
+//! use portable_storage::{raw_size, wire, StorageEntry};
+//!
+//! let mut buf = BytesMut::new();
+//! raw_size::write(&mut buf, 1);
+//! wire::write_name(&mut buf, "height").unwrap();
+//! wire::write_entry(&mut buf, &StorageEntry::U64(1337));
+//!
+//! let mut data = &buf[..];
+//! assert_eq!(raw_size::read_usize(&mut data).unwrap(), 1);
+//! assert_eq!(wire::read_name(&mut data).unwrap(), "height");
+//! assert_eq!(wire::read_entry(&mut data).unwrap(), StorageEntry::U64(1337));
+//! assert!(data.is_empty());
+//! ```
+//!
+//! A section is its entry count as a raw size followed by each entry's name
+//! and entry. The readers consume exactly the bytes of the item they return
+//! and fail with [`Error::UnexpectedEof`] when `buf` ends early, in which
+//! case the position of `buf` is unspecified. The writers never fail, the
+//! buffer grows as needed.
+//!
+//! The walkers of this crate (the skipper, the checkers, the handlers) read
+//! the framing through the same functions: [`read_type`], [`read_array_type`]
+//! and [`read_name_len`].
+
+use crate::{Error, Result, StorageBuf, StorageEntry, SERIALIZE_FLAG_ARRAY};
+use bytes::{Buf, BytesMut};
+
+/// Reads the serialize type that starts an entry.
+pub fn read_type<B: Buf>(buf: &mut B) -> Result<u8> {
+    ensure_eof!(buf, 1);
+    Ok(buf.get_u8())
+}
+
+/// Whether `serialize_type` is the flagged element type of an array.
+pub fn is_array(serialize_type: u8) -> bool {
+    serialize_type & SERIALIZE_FLAG_ARRAY == SERIALIZE_FLAG_ARRAY
+}
+
+/// The element type of a flagged array type.
+pub fn element_type(serialize_type: u8) -> u8 {
+    serialize_type & !SERIALIZE_FLAG_ARRAY
+}
+
+/// Reads the flagged element type that follows `SERIALIZE_TYPE_ARRAY`,
+/// failing with [`Error::WrongTypeSequence`] if it isn't flagged.
+pub fn read_array_type<B: Buf>(buf: &mut B) -> Result<u8> {
+    let serialize_type = read_type(buf)?;
+    if !is_array(serialize_type) {
+        return Err(Error::WrongTypeSequence);
+    }
+    Ok(serialize_type)
+}
+
+/// Reads the length byte of an entry name and checks that the name follows,
+/// leaving `buf` at its first byte.
+pub fn read_name_len<B: Buf>(buf: &mut B) -> Result<usize> {
+    ensure_eof!(buf, 1);
+    let length = buf.get_u8() as usize;
+    ensure_eof!(buf, length);
+    Ok(length)
+}
+
+/// Reads an entry name as the bytes it's encoded with.
+pub fn read_name_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let length = read_name_len(buf)?;
+    let (name, rest) = buf.split_at(length);
+    *buf = rest;
+    Ok(name)
+}
+
+/// Reads an entry name: a length byte followed by the name. Names that
+/// aren't valid UTF-8 are decoded lossily.
+pub fn read_name<B: Buf>(buf: &mut B) -> Result<String> {
+    crate::read_name(buf)
+}
+
+/// Writes an entry name, failing with [`Error::NameTooLong`] if it's longer
+/// than the 255 bytes its length byte can count.
+pub fn write_name(buf: &mut BytesMut, name: &str) -> Result<()> {
+    crate::check_name(name)?;
+    crate::write_name(buf, name);
+    Ok(())
+}
+
+/// Reads a string: its length as a raw size followed by the bytes.
+pub fn read_buf<B: Buf>(buf: &mut B) -> Result<StorageBuf> {
+    crate::read_buf(buf, &mut crate::FreshKeys)
+}
+
+/// Writes a string, see [`read_buf`].
+pub fn write_buf(buf: &mut BytesMut, b: &[u8]) {
+    crate::write_buf(buf, b);
+}
+
+/// Reads an entry: its serialize type followed by the value. Arrays are
+/// prefixed with their flagged element type, or with
+/// `SERIALIZE_TYPE_ARRAY` and then the flagged element type as epee also
+/// accepts.
+pub fn read_entry<B: Buf>(buf: &mut B) -> Result<StorageEntry> {
+    StorageEntry::read(buf, &mut crate::FreshKeys)
+}
+
+/// Writes an entry, see [`read_entry`]. Arrays are written with their
+/// flagged element type only, like epee does.
+pub fn write_entry(buf: &mut BytesMut, entry: &StorageEntry) {
+    StorageEntry::write(buf, entry);
+}
+
+/// Reads a value of the given serialize type that isn't preceded by it, as
+/// array elements are laid out. `serialize_type` must not carry
+/// `SERIALIZE_FLAG_ARRAY`, values of `SERIALIZE_TYPE_ARRAY` are read with
+/// their flagged element type like array elements of an array of arrays.
+pub fn read_entry_raw<B: Buf>(buf: &mut B, serialize_type: u8) -> Result<StorageEntry> {
+    StorageEntry::read_entry_raw(buf, serialize_type, &mut crate::FreshKeys)
+}
+
+/// Writes a value without its serialize type, see [`read_entry_raw`].
+/// Arrays still carry their flagged element type.
+pub fn write_entry_raw(buf: &mut BytesMut, entry: &StorageEntry) {
+    StorageEntry::write_raw(buf, entry);
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{Array, Section, SERIALIZE_TYPE_ARRAY, SERIALIZE_TYPE_UINT32};
+
+    #[test]
+    fn entries() {
+        let mut ids = Array::new();
+        ids.push(StorageEntry::U32(7)).unwrap();
+        let mut section = Section::new();
+        section.insert("ids".to_owned(), StorageEntry::Array(ids.clone()));
+
+        let entries = [
+            StorageEntry::Buf(b"abc".to_vec().into()),
+            StorageEntry::Array(ids),
+            StorageEntry::Section(section),
+        ];
+        for entry in entries.iter() {
+            let mut buf = BytesMut::new();
+            write_entry(&mut buf, entry);
+            assert_eq!(buf.len(), entry.encoded_len());
+            assert_eq!(&read_entry(&mut &buf[..]).unwrap(), entry);
+
+            let mut raw = BytesMut::new();
+            write_entry_raw(&mut raw, entry);
+            let serialize_type = match entry {
+                StorageEntry::Array(_) => SERIALIZE_TYPE_ARRAY,
+                _ => buf[0],
+            };
+            assert_eq!(
+                &read_entry_raw(&mut &raw[..], serialize_type).unwrap(),
+                entry
+            );
+        }
+
+        assert!(matches!(
+            read_entry_raw(&mut &[1, 0][..], SERIALIZE_TYPE_UINT32),
+            Err(Error::UnexpectedEof { .. })
+        ));
+    }
+
+    #[test]
+    fn names_and_bufs() {
+        let mut buf = BytesMut::new();
+        write_name(&mut buf, "m_ip").unwrap();
+        write_buf(&mut buf, &[0xff; 70]);
+        assert_eq!(buf[..5], b"\x04m_ip"[..]);
+
+        let mut data = &buf[..];
+        assert_eq!(read_name(&mut data).unwrap(), "m_ip");
+        assert_eq!(read_buf(&mut data).unwrap()[..], [0xff; 70][..]);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn framing() {
+        let mut data = &b"\x02id\x8d\x85"[..];
+        assert_eq!(read_name_bytes(&mut data).unwrap(), b"id");
+        let serialize_type = read_type(&mut data).unwrap();
+        assert!(is_array(serialize_type));
+        assert_eq!(element_type(serialize_type), SERIALIZE_TYPE_ARRAY);
+        assert_eq!(read_array_type(&mut data).unwrap(), 0x85);
+        assert!(data.is_empty());
+
+        assert!(matches!(
+            read_array_type(&mut &[SERIALIZE_TYPE_UINT32][..]),
+            Err(Error::WrongTypeSequence)
+        ));
+        assert!(matches!(
+            read_name_len(&mut &b"\x03ab"[..]),
+            Err(Error::UnexpectedEof { .. })
+        ));
+    }
+
+    #[test]
+    fn long_name() {
+        let mut buf = BytesMut::new();
+        assert!(matches!(
+            write_name(&mut buf, &"a".repeat(256)),
+            Err(Error::NameTooLong(256))
+        ));
+        assert!(buf.is_empty());
+        write_name(&mut buf, &"a".repeat(255)).unwrap();
+        assert_eq!(buf.len(), 256);
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # YAML conversion
+//!
+//! Converts sections to and from YAML documents, going through the JSON
+//! value model so the same [`JsonConfig`] policies apply.
+
+use crate::{json::JsonConfig, Error, Result, Section};
+
+impl Section {
+    /// Converts this section into a YAML document.
+    pub fn to_yaml(&self, config: &JsonConfig) -> Result<String> {
+        serde_yaml::to_string(&self.to_json(config)).map_err(|e| Error::Conversion(e.to_string()))
+    }
+
+    /// Creates a section from a YAML document.
+    pub fn from_yaml(s: &str, config: &JsonConfig) -> Result<Section> {
+        let value: serde_json::Value =
+            serde_yaml::from_str(s).map_err(|e| Error::Conversion(e.to_string()))?;
+        Section::from_json(&value, config)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::StorageEntry;
+
+    #[test]
+    fn roundtrip() {
+        const DOC: &str = r#"
+payload_data:
+  current_height: { "$u64": 2000000 }
+  cumulative_difficulty: { "$u64": 1234 }
+  top_id: "00ff"
+"#;
+
+        let config = JsonConfig::default();
+        let section = Section::from_yaml(DOC, &config).unwrap();
+        let section = Section::from_yaml(&section.to_yaml(&config).unwrap(), &config).unwrap();
+
+        match &section["payload_data"] {
+            StorageEntry::Section(payload) => {
+                assert!(matches!(
+                    payload["current_height"],
+                    StorageEntry::U64(2_000_000)
+                ));
+                assert!(
+                    matches!(payload["top_id"], StorageEntry::Buf(ref v) if v[..] == [0x00, 0xff])
+                );
+            }
+            _ => panic!("expected a section"),
+        }
+    }
+}
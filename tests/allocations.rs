@@ -0,0 +1,94 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Allocation budgets of decoding and encoding the test vectors, run with
+//! `cargo test --features alloc-counter`.
+
+#![cfg(feature = "alloc-counter")]
+
+use bytes::BytesMut;
+use portable_storage::{
+    alloc_counter::{self, CountingAllocator},
+    pool::SectionPool,
+    testvectors::{HANDSHAKE_REQUEST, TIMED_SYNC_REQUEST},
+};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn encoding_into_a_buffer_doesnt_allocate() {
+    let section = HANDSHAKE_REQUEST.section();
+    let mut buf = BytesMut::new();
+    portable_storage::to_bytes_into(&section, &mut buf);
+
+    alloc_counter::assert_max_allocations(0, || {
+        portable_storage::to_bytes_into(&section, &mut buf)
+    });
+    assert_eq!(&buf[..], HANDSHAKE_REQUEST.bytes);
+}
+
+#[test]
+fn encoding_reserves_once() {
+    let section = HANDSHAKE_REQUEST.section();
+    let buf = alloc_counter::assert_max_allocations(1, || {
+        let mut buf = BytesMut::new();
+        portable_storage::write(&mut buf, &section);
+        buf
+    });
+    assert_eq!(&buf[..], HANDSHAKE_REQUEST.bytes);
+}
+
+#[test]
+fn decoding() {
+    // `timed_sync_request` holds two sections and seven keys: a string and
+    // a map node per key, a sentinel node and a table per map, and one more
+    // table as `payload_data` grows.
+    let section = alloc_counter::assert_max_allocations(19, || {
+        portable_storage::read(&mut &TIMED_SYNC_REQUEST.bytes[..]).unwrap()
+    });
+    assert_eq!(section, TIMED_SYNC_REQUEST.section());
+
+    // A recycled top-level section keeps its node, sentinel and table.
+    let pool = SectionPool::new(1);
+    pool.put(section);
+    alloc_counter::assert_max_allocations(16, || {
+        let mut section = pool.get();
+        portable_storage::read_into(&mut &TIMED_SYNC_REQUEST.bytes[..], &mut section).unwrap();
+        pool.put(section);
+    });
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn deserializing_from_bytes_doesnt_allocate() {
+    #[derive(serde::Deserialize)]
+    struct CoreSyncData<'a> {
+        current_height: u64,
+        top_id: &'a [u8],
+    }
+
+    #[derive(serde::Deserialize)]
+    struct TimedSyncRequest<'a> {
+        #[serde(borrow)]
+        payload_data: CoreSyncData<'a>,
+    }
+
+    let request = alloc_counter::assert_max_allocations(0, || {
+        portable_storage::de::from_bytes::<TimedSyncRequest>(TIMED_SYNC_REQUEST.bytes).unwrap()
+    });
+    assert_eq!(request.payload_data.current_height, 2_200_000);
+    assert_eq!(request.payload_data.top_id.len(), 32);
+}
@@ -0,0 +1,113 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `#[serde(with)]` adapter for `bool` fields that some peers send as `u8`.
+//!
+//! Accepts a bool or an integer of value 0 or 1 and always writes a bool.
+//!
+//! ```rust
+//! use portable_storage_utils::bool_or_u8;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Response {
+//!     #[serde(with = "bool_or_u8")]
+//!     untrusted: bool,
+//! }
+//! ```
+
+use serde::{
+    de::{Deserializer, Error, Unexpected, Visitor},
+    ser::Serializer,
+};
+use std::fmt;
+
+pub fn serialize<S: Serializer>(value: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bool(*value)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    struct BoolVisitor;
+
+    impl<'de> Visitor<'de> for BoolVisitor {
+        type Value = bool;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a bool, 0 or 1")
+        }
+
+        fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            Ok(v)
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            match v {
+                0 => Ok(false),
+                1 => Ok(true),
+                v => Err(E::invalid_value(Unexpected::Unsigned(v), &self)),
+            }
+        }
+
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            match v {
+                0 | 1 => self.visit_u64(v as u64),
+                v => Err(E::invalid_value(Unexpected::Signed(v), &self)),
+            }
+        }
+    }
+
+    deserializer.deserialize_any(BoolVisitor)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use portable_storage::{from_section, to_section, Section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Response {
+        #[serde(with = "super")]
+        untrusted: bool,
+    }
+
+    fn read(entry: StorageEntry) -> Result<bool, String> {
+        let mut section = Section::new();
+        section.insert("untrusted".to_owned(), entry);
+        from_section::<Response>(section)
+            .map(|r| r.untrusted)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn lenient() {
+        assert_eq!(read(StorageEntry::Bool(true)), Ok(true));
+        assert_eq!(read(StorageEntry::U8(0)), Ok(false));
+        assert_eq!(read(StorageEntry::U8(1)), Ok(true));
+        assert_eq!(read(StorageEntry::I32(1)), Ok(true));
+        assert!(read(StorageEntry::U8(2)).is_err());
+
+        let section = to_section(&Response { untrusted: true }).unwrap();
+        assert_eq!(section["untrusted"], StorageEntry::Bool(true));
+    }
+}
@@ -17,8 +17,10 @@ use serde::{
     de::{Deserialize, Deserializer, Error, Visitor},
     ser::{Serialize, Serializer},
 };
-use std::fmt;
+use std::{fmt, str::FromStr};
 
+/// A UUID serialized as its 16 bytes, or as a hyphenated string when the
+/// serializer is human-readable.
 #[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
 pub struct BytesUuid(pub uuid::Uuid);
 
@@ -28,6 +30,26 @@ impl From<uuid::Uuid> for BytesUuid {
     }
 }
 
+impl AsRef<uuid::Uuid> for BytesUuid {
+    fn as_ref(&self) -> &uuid::Uuid {
+        &self.0
+    }
+}
+
+impl fmt::Display for BytesUuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0.to_hyphenated_ref(), f)
+    }
+}
+
+impl FromStr for BytesUuid {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<BytesUuid, uuid::Error> {
+        uuid::Uuid::parse_str(s).map(BytesUuid)
+    }
+}
+
 impl<'de> Deserialize<'de> for BytesUuid {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -50,9 +72,20 @@ impl<'de> Deserialize<'de> for BytesUuid {
                     .map(BytesUuid::from)
                     .map_err(E::custom)
             }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                v.parse().map_err(E::custom)
+            }
         }
 
-        deserializer.deserialize_bytes(UuidVisitor)
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(UuidVisitor)
+        } else {
+            deserializer.deserialize_bytes(UuidVisitor)
+        }
     }
 }
 
@@ -61,6 +94,49 @@ impl Serialize for BytesUuid {
     where
         S: Serializer,
     {
-        serializer.serialize_bytes(self.0.as_bytes())
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_bytes(self.0.as_bytes())
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use portable_storage::{from_section, to_section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    const NETWORK_ID: &str = "1230f171-6104-4161-1731-008216a1a110";
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct NodeData {
+        network_id: BytesUuid,
+    }
+
+    #[test]
+    fn text() {
+        let id: BytesUuid = NETWORK_ID.parse().unwrap();
+        assert_eq!(id.to_string(), NETWORK_ID);
+        assert_eq!(id.as_ref().as_bytes()[0], 0x12);
+        assert!("1230f171".parse::<BytesUuid>().is_err());
+
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, format!("\"{}\"", NETWORK_ID));
+        assert_eq!(serde_json::from_str::<BytesUuid>(&json).unwrap(), id);
+    }
+
+    #[test]
+    fn binary_on_the_wire() {
+        let data = NodeData {
+            network_id: NETWORK_ID.parse().unwrap(),
+        };
+        let section = to_section(&data).unwrap();
+        assert_eq!(
+            section["network_id"],
+            StorageEntry::Buf(data.network_id.0.as_bytes()[..].into())
+        );
+        assert_eq!(from_section::<NodeData>(section).unwrap(), data);
     }
 }
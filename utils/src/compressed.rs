@@ -0,0 +1,285 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Binary blobs stored compressed, for archiving message captures.
+//!
+//! A [`Compressed`] blob is written as a string starting with a tag byte:
+//! `0` when the rest is the plain blob, the codec's tag when it's
+//! compressed. Blobs shorter than [`THRESHOLD`], or which don't shrink, are
+//! stored plain. The result isn't understood by monerod, keep it to storage
+//! formats of your own.
+//!
+//! ```rust
+//! # #[cfg(feature = "zstd")] {
+//! use portable_storage_utils::compressed::ZstdBlob;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Capture {
+//!     message: ZstdBlob,
+//! }
+//!
+//! let capture = Capture { message: ZstdBlob::new(vec![0; 4096]) };
+//! let section = portable_storage::to_section(&capture).unwrap();
+//! let capture: Capture = portable_storage::from_section(section).unwrap();
+//! assert_eq!(capture.message.bytes, vec![0; 4096]);
+//! # }
+//! ```
+
+use serde::{
+    de::{Deserialize, Deserializer, Error, Visitor},
+    ser::{Serialize, Serializer},
+};
+#[cfg(feature = "zstd")]
+use std::io::Read;
+use std::{fmt, marker::PhantomData};
+
+/// Blobs shorter than this are never compressed.
+pub const THRESHOLD: usize = 256;
+
+/// Largest decompressed blob, epee's largest packet, so corrupted or
+/// hostile blobs can't exhaust the memory.
+pub const MAX_DECOMPRESSED_SIZE: usize = 100_000_000;
+
+const TAG_PLAIN: u8 = 0;
+
+/// A compression algorithm.
+pub trait Codec {
+    /// The tag byte of compressed blobs, never `0`.
+    const TAG: u8;
+
+    fn compress(bytes: &[u8]) -> Vec<u8>;
+
+    /// Decompresses at most `max` bytes.
+    fn decompress(bytes: &[u8], max: usize) -> Result<Vec<u8>, String>;
+}
+
+/// Zstandard, at its default level.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zstd {}
+
+#[cfg(feature = "zstd")]
+impl Codec for Zstd {
+    const TAG: u8 = 1;
+
+    fn compress(bytes: &[u8]) -> Vec<u8> {
+        zstd::bulk::compress(bytes, 0).expect("compressing into a vector can't fail")
+    }
+
+    fn decompress(bytes: &[u8], max: usize) -> Result<Vec<u8>, String> {
+        let decoder = zstd::stream::read::Decoder::new(bytes).map_err(|e| e.to_string())?;
+        read_at_most(decoder, max)
+    }
+}
+
+/// LZ4 block format, prefixed with the decompressed length.
+#[cfg(feature = "lz4")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lz4 {}
+
+#[cfg(feature = "lz4")]
+impl Codec for Lz4 {
+    const TAG: u8 = 2;
+
+    fn compress(bytes: &[u8]) -> Vec<u8> {
+        lz4_flex::compress_prepend_size(bytes)
+    }
+
+    fn decompress(bytes: &[u8], max: usize) -> Result<Vec<u8>, String> {
+        // Check the announced length before it's allocated.
+        match bytes.get(..4) {
+            Some(&[a, b, c, d]) if u32::from_le_bytes([a, b, c, d]) as usize > max => {
+                Err(format!("the blob decompresses to more than {} bytes", max))
+            }
+            _ => lz4_flex::decompress_size_prepended(bytes).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn read_at_most<R: Read>(reader: R, max: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    reader
+        .take(max as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    if bytes.len() > max {
+        return Err(format!("the blob decompresses to more than {} bytes", max));
+    }
+    Ok(bytes)
+}
+
+/// A binary blob stored compressed with `C` when it's worth it.
+pub struct Compressed<C> {
+    pub bytes: Vec<u8>,
+    codec: PhantomData<C>,
+}
+
+/// A blob compressed with Zstandard.
+#[cfg(feature = "zstd")]
+pub type ZstdBlob = Compressed<Zstd>;
+
+/// A blob compressed with LZ4.
+#[cfg(feature = "lz4")]
+pub type Lz4Blob = Compressed<Lz4>;
+
+impl<C> Compressed<C> {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Compressed {
+            bytes,
+            codec: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl<C: Codec> Compressed<C> {
+    /// The blob as written: its tag and its bytes, compressed or not.
+    pub fn encode(&self) -> Vec<u8> {
+        if self.bytes.len() >= THRESHOLD {
+            let compressed = C::compress(&self.bytes);
+            if compressed.len() < self.bytes.len() {
+                let mut encoded = Vec::with_capacity(compressed.len() + 1);
+                encoded.push(C::TAG);
+                encoded.extend_from_slice(&compressed);
+                return encoded;
+            }
+        }
+
+        let mut encoded = Vec::with_capacity(self.bytes.len() + 1);
+        encoded.push(TAG_PLAIN);
+        encoded.extend_from_slice(&self.bytes);
+        encoded
+    }
+
+    /// Reads a blob written by [`encode`](Self::encode).
+    pub fn decode(encoded: &[u8]) -> Result<Self, String> {
+        match encoded.split_first() {
+            Some((&TAG_PLAIN, bytes)) => Ok(Compressed::new(bytes.to_vec())),
+            Some((&tag, bytes)) if tag == C::TAG => {
+                C::decompress(bytes, MAX_DECOMPRESSED_SIZE).map(Compressed::new)
+            }
+            Some((tag, _)) => Err(format!("unknown compression tag {}", tag)),
+            None => Err("the compressed blob has no tag".to_owned()),
+        }
+    }
+}
+
+impl<C> From<Vec<u8>> for Compressed<C> {
+    fn from(bytes: Vec<u8>) -> Self {
+        Compressed::new(bytes)
+    }
+}
+
+impl<C> fmt::Debug for Compressed<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Compressed").field(&self.bytes).finish()
+    }
+}
+
+impl<C> Clone for Compressed<C> {
+    fn clone(&self) -> Self {
+        Compressed::new(self.bytes.clone())
+    }
+}
+
+impl<C> Default for Compressed<C> {
+    fn default() -> Self {
+        Compressed::new(Vec::new())
+    }
+}
+
+impl<C> PartialEq for Compressed<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl<C> Eq for Compressed<C> {}
+
+impl<'de, C: Codec> Deserialize<'de> for Compressed<C> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct CompressedVisitor<C>(PhantomData<C>);
+
+        impl<'de, C: Codec> Visitor<'de> for CompressedVisitor<C> {
+            type Value = Compressed<C>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a tagged, possibly compressed, binary blob")
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                Compressed::decode(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_bytes(CompressedVisitor(PhantomData))
+    }
+}
+
+impl<C: Codec> Serialize for Compressed<C> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.encode())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn roundtrip<C: Codec>() {
+        let small = Compressed::<C>::new(vec![7; 10]);
+        assert_eq!(small.encode()[0], TAG_PLAIN);
+        let random: Vec<u8> = (0..1000u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let random = Compressed::<C>::new(random);
+        let large = Compressed::<C>::new(vec![7; 100_000]);
+        let encoded = large.encode();
+        assert_eq!(encoded[0], C::TAG);
+        assert!(encoded.len() < 1000);
+
+        for blob in [small, random, large] {
+            assert_eq!(Compressed::<C>::decode(&blob.encode()).unwrap(), blob);
+        }
+
+        assert!(C::decompress(&C::compress(&[0; 1000]), 999).is_err());
+        assert!(Compressed::<C>::decode(&[]).is_err());
+        assert!(Compressed::<C>::decode(&[0xff, 1]).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd() {
+        roundtrip::<Zstd>();
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn lz4() {
+        roundtrip::<Lz4>();
+    }
+}
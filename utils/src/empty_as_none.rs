@@ -0,0 +1,88 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `#[serde(with)]` adapter mapping an empty string to `None`.
+//!
+//! monerod writes absent hashes as empty strings in several payloads. Any
+//! non-empty string is deserialized as the inner type, which validates its
+//! length.
+//!
+//! ```rust
+//! use portable_storage_utils::{empty_as_none, BytesH256};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Request {
+//!     #[serde(with = "empty_as_none")]
+//!     prev_id: Option<BytesH256>,
+//! }
+//! ```
+
+use crate::Blob;
+use serde::{
+    de::{value::BytesDeserializer, Deserialize, Deserializer},
+    ser::{Serialize, Serializer},
+};
+
+pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    match value {
+        Some(value) => value.serialize(serializer),
+        None => serializer.serialize_bytes(&[]),
+    }
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    let blob = Blob::deserialize(deserializer)?;
+    if blob.0.is_empty() {
+        Ok(None)
+    } else {
+        T::deserialize(BytesDeserializer::<D::Error>::new(&blob.0)).map(Some)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::BytesH256;
+    use portable_storage::{from_section, to_section, Section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Request {
+        #[serde(with = "super")]
+        prev_id: Option<BytesH256>,
+    }
+
+    #[test]
+    fn roundtrip() {
+        for (prev_id, bytes) in &[(None, vec![]), (Some(BytesH256([3; 32])), vec![3; 32])] {
+            let request = Request { prev_id: *prev_id };
+            let section = to_section(&request).unwrap();
+            assert_eq!(section["prev_id"], StorageEntry::Buf(bytes[..].into()));
+            assert_eq!(from_section::<Request>(section).unwrap(), request);
+        }
+
+        let mut section = Section::new();
+        section.insert("prev_id".to_owned(), StorageEntry::Buf(vec![3; 31].into()));
+        assert!(from_section::<Request>(section).is_err());
+    }
+}
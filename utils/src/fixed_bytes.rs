@@ -0,0 +1,121 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{
+    de::{Deserialize, Deserializer, Error, Visitor},
+    ser::{Serialize, Serializer},
+};
+use std::{fmt, marker::PhantomData};
+
+/// Exactly `N` bytes, serialized as a string of that length.
+///
+/// Deserializing a string of any other length fails, which makes it suitable
+/// for hashes, keys and signatures.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct FixedBytes<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Default for FixedBytes<N> {
+    fn default() -> Self {
+        FixedBytes([0; N])
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for FixedBytes<N> {
+    fn from(v: [u8; N]) -> Self {
+        FixedBytes(v)
+    }
+}
+
+impl<const N: usize> From<FixedBytes<N>> for [u8; N] {
+    fn from(v: FixedBytes<N>) -> Self {
+        v.0
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for FixedBytes<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for FixedBytes<N> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct FixedBytesVisitor<const N: usize>(PhantomData<[u8; N]>);
+
+        impl<'de, const N: usize> Visitor<'de> for FixedBytesVisitor<N> {
+            type Value = FixedBytes<N>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a binary blob of {} bytes", N)
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                let mut bytes = [0; N];
+                if v.len() != N {
+                    return Err(E::invalid_length(v.len(), &self));
+                }
+                bytes.copy_from_slice(v);
+                Ok(FixedBytes(bytes))
+            }
+        }
+
+        deserializer.deserialize_bytes(FixedBytesVisitor(PhantomData))
+    }
+}
+
+impl<const N: usize> Serialize for FixedBytes<N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use portable_storage::{from_section, to_section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Block {
+        hash: FixedBytes<32>,
+    }
+
+    #[test]
+    fn roundtrip() {
+        let block = Block {
+            hash: FixedBytes([7; 32]),
+        };
+        let section = to_section(&block).unwrap();
+        assert_eq!(section["hash"], StorageEntry::Buf(vec![7; 32].into()));
+        assert_eq!(from_section::<Block>(section).unwrap(), block);
+    }
+
+    #[test]
+    fn wrong_length() {
+        let mut section = portable_storage::Section::new();
+        section.insert("hash".to_owned(), StorageEntry::Buf(vec![7; 31].into()));
+        let error = from_section::<Block>(section).unwrap_err().to_string();
+        assert!(error.contains("invalid length 31"), "{}", error);
+    }
+}
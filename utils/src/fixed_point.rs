@@ -0,0 +1,283 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{
+    de::{Deserialize, Deserializer, Error, Unexpected, Visitor},
+    ser::{Serialize, Serializer},
+};
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+/// A decimal amount with `EXP` fractional digits, stored as an integer
+/// number of units of 10^-`EXP`, e.g. `FixedPoint<12>` for monero amounts in
+/// piconero.
+///
+/// Serialized as an unsigned 64-bit integer, or as decimal text when the
+/// serializer is human-readable, so amounts never go through a double.
+/// Doubles are still accepted when deserializing, as long as the shortest
+/// decimal representing them has at most `EXP` fractional digits, same for
+/// integers and decimal strings.
+///
+/// ```rust
+/// use portable_storage_utils::FixedPoint;
+///
+/// let amount: FixedPoint<12> = "0.35".parse().unwrap();
+/// assert_eq!(amount.0, 350_000_000_000);
+/// assert_eq!(amount.to_string(), "0.350000000000");
+/// ```
+///
+/// `EXP` can't be over 19, `10^EXP` has to fit a `u64`.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct FixedPoint<const EXP: u32>(pub u64);
+
+/// Why a decimal amount couldn't be parsed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParseFixedPointError {
+    /// Not a plain decimal number.
+    Invalid,
+    /// More fractional digits than the exponent allows.
+    TooPrecise,
+    /// Doesn't fit the units.
+    Overflow,
+}
+
+impl fmt::Display for ParseFixedPointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ParseFixedPointError::Invalid => "invalid decimal number",
+            ParseFixedPointError::TooPrecise => "too many fractional digits",
+            ParseFixedPointError::Overflow => "decimal number out of range",
+        })
+    }
+}
+
+impl std::error::Error for ParseFixedPointError {}
+
+impl<const EXP: u32> FixedPoint<EXP> {
+    /// Units in one.
+    pub const SCALE: u64 = 10u64.pow(EXP);
+}
+
+impl<const EXP: u32> From<u64> for FixedPoint<EXP> {
+    fn from(units: u64) -> Self {
+        FixedPoint(units)
+    }
+}
+
+impl<const EXP: u32> From<FixedPoint<EXP>> for u64 {
+    fn from(v: FixedPoint<EXP>) -> u64 {
+        v.0
+    }
+}
+
+impl<const EXP: u32> fmt::Display for FixedPoint<EXP> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let whole = self.0 / Self::SCALE;
+        if EXP == 0 {
+            return write!(f, "{}", whole);
+        }
+        write!(
+            f,
+            "{}.{:0width$}",
+            whole,
+            self.0 % Self::SCALE,
+            width = EXP as usize
+        )
+    }
+}
+
+impl<const EXP: u32> FromStr for FixedPoint<EXP> {
+    type Err = ParseFixedPointError;
+
+    fn from_str(s: &str) -> Result<Self, ParseFixedPointError> {
+        let (whole, fraction) = match s.find('.') {
+            Some(dot) => (&s[..dot], &s[dot + 1..]),
+            None => (s, "0"),
+        };
+        let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+        if !digits(whole) || !digits(fraction) {
+            return Err(ParseFixedPointError::Invalid);
+        }
+
+        // Zeros past the exponent don't change the value.
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > EXP as usize {
+            return Err(ParseFixedPointError::TooPrecise);
+        }
+
+        let mut units = 0u64;
+        let padding = EXP as usize - fraction.len();
+        for digit in whole
+            .bytes()
+            .chain(fraction.bytes())
+            .chain(std::iter::repeat(b'0').take(padding))
+        {
+            units = units
+                .checked_mul(10)
+                .and_then(|units| units.checked_add(u64::from(digit - b'0')))
+                .ok_or(ParseFixedPointError::Overflow)?;
+        }
+        Ok(FixedPoint(units))
+    }
+}
+
+impl<'de, const EXP: u32> Deserialize<'de> for FixedPoint<EXP> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct FixedPointVisitor<const EXP: u32>;
+
+        impl<'de, const EXP: u32> Visitor<'de> for FixedPointVisitor<EXP> {
+            type Value = FixedPoint<EXP>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    formatter,
+                    "an amount in units of 10^-{} or a decimal number",
+                    EXP
+                )
+            }
+
+            fn visit_u64<E: Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(FixedPoint(v))
+            }
+
+            fn visit_i64<E: Error>(self, v: i64) -> Result<Self::Value, E> {
+                u64::try_from(v)
+                    .map(FixedPoint)
+                    .map_err(|_| E::invalid_value(Unexpected::Signed(v), &self))
+            }
+
+            fn visit_f64<E: Error>(self, v: f64) -> Result<Self::Value, E> {
+                // `Display` gives the shortest decimal reading back as `v`,
+                // without an exponent.
+                if !v.is_finite() || v < 0.0 {
+                    return Err(E::invalid_value(Unexpected::Float(v), &self));
+                }
+                v.to_string().parse().map_err(E::custom)
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                let v = std::str::from_utf8(v)
+                    .map_err(|_| E::invalid_value(Unexpected::Bytes(v), &self))?;
+                self.visit_str(v)
+            }
+        }
+
+        deserializer.deserialize_any(FixedPointVisitor)
+    }
+}
+
+impl<const EXP: u32> Serialize for FixedPoint<EXP> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u64(self.0)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use portable_storage::{from_section, to_section, Section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    type Xmr = FixedPoint<12>;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Transfer {
+        amount: Xmr,
+        fee: Xmr,
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("1".parse::<Xmr>(), Ok(FixedPoint(1_000_000_000_000)));
+        assert_eq!("0.000000000001".parse::<Xmr>(), Ok(FixedPoint(1)));
+        assert_eq!(
+            "2.50000000000000".parse::<Xmr>(),
+            Ok(FixedPoint(2_500_000_000_000))
+        );
+        assert_eq!(
+            "18446744.073709551615".parse::<Xmr>(),
+            Ok(FixedPoint(u64::MAX))
+        );
+        assert_eq!(
+            "18446744.073709551616".parse::<Xmr>(),
+            Err(ParseFixedPointError::Overflow)
+        );
+        assert_eq!(
+            "0.0000000000001".parse::<Xmr>(),
+            Err(ParseFixedPointError::TooPrecise)
+        );
+        for invalid in &["", ".5", "1.", "-1", "1e3", "1.2.3"] {
+            assert_eq!(
+                invalid.parse::<Xmr>(),
+                Err(ParseFixedPointError::Invalid),
+                "{}",
+                invalid
+            );
+        }
+
+        assert_eq!(
+            FixedPoint::<12>(u64::MAX).to_string(),
+            "18446744.073709551615"
+        );
+        assert_eq!(FixedPoint::<0>(42).to_string(), "42");
+        assert_eq!("42".parse::<FixedPoint<0>>(), Ok(FixedPoint(42)));
+    }
+
+    #[test]
+    fn roundtrip() {
+        let transfer = Transfer {
+            amount: FixedPoint(123_456_789_012_345),
+            fee: FixedPoint(30_000_000),
+        };
+        let section = to_section(&transfer).unwrap();
+        assert_eq!(section["amount"], StorageEntry::U64(123_456_789_012_345));
+        assert_eq!(from_section::<Transfer>(section).unwrap(), transfer);
+
+        let json = serde_json::to_string(&transfer).unwrap();
+        assert_eq!(
+            json,
+            r#"{"amount":"123.456789012345","fee":"0.000030000000"}"#
+        );
+        assert_eq!(serde_json::from_str::<Transfer>(&json).unwrap(), transfer);
+    }
+
+    #[test]
+    fn doubles() {
+        let mut section = Section::new();
+        section.insert("amount".to_owned(), StorageEntry::Double(0.1));
+        section.insert("fee".to_owned(), StorageEntry::Double(0.0003));
+        let transfer: Transfer = from_section(section.clone()).unwrap();
+        assert_eq!(transfer.amount, FixedPoint(100_000_000_000));
+        assert_eq!(transfer.fee, FixedPoint(300_000_000));
+
+        section.insert("fee".to_owned(), StorageEntry::Double(1e-13));
+        assert!(from_section::<Transfer>(section.clone()).is_err());
+        section.insert("fee".to_owned(), StorageEntry::Double(-1.0));
+        assert!(from_section::<Transfer>(section).is_err());
+    }
+}
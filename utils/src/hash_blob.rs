@@ -0,0 +1,109 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `#[serde(with)]` adapter storing a list of 32-byte hashes as a single
+//! string of their bytes, epee's `KV_SERIALIZE_CONTAINER_POD_AS_BLOB`.
+//!
+//! ```rust
+//! use portable_storage_utils::{hash_blob, BytesH256};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct GetHashesRequest {
+//!     #[serde(with = "hash_blob")]
+//!     block_ids: Vec<BytesH256>,
+//!     start_height: u64,
+//! }
+//! ```
+
+use serde::{
+    de::{Deserializer, Error, Visitor},
+    ser::Serializer,
+};
+use std::{convert::TryInto, fmt, marker::PhantomData};
+
+pub fn serialize<T, S>(hashes: &[T], serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: AsRef<[u8]>,
+    S: Serializer,
+{
+    let bytes: Vec<u8> = hashes
+        .iter()
+        .flat_map(|hash| hash.as_ref().iter().copied())
+        .collect();
+    serializer.serialize_bytes(&bytes)
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    T: From<[u8; 32]>,
+    D: Deserializer<'de>,
+{
+    struct HashesVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: From<[u8; 32]>> Visitor<'de> for HashesVisitor<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a binary blob of 32-byte hashes")
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            if v.len() % 32 != 0 {
+                return Err(E::invalid_length(v.len(), &self));
+            }
+            Ok(v.chunks_exact(32)
+                .map(|hash| T::from(hash.try_into().unwrap()))
+                .collect())
+        }
+    }
+
+    deserializer.deserialize_bytes(HashesVisitor(PhantomData))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::BytesH256;
+    use portable_storage::{from_section, to_section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Hashes {
+        #[serde(with = "super")]
+        block_ids: Vec<BytesH256>,
+    }
+
+    #[test]
+    fn roundtrip() {
+        let hashes = Hashes {
+            block_ids: vec![BytesH256([1; 32]), BytesH256([2; 32])],
+        };
+        let mut section = to_section(&hashes).unwrap();
+        match &section["block_ids"] {
+            StorageEntry::Buf(bytes) => assert_eq!(bytes.len(), 64),
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+        assert_eq!(from_section::<Hashes>(section.clone()).unwrap(), hashes);
+
+        section.insert(
+            "block_ids".to_owned(),
+            StorageEntry::Buf(vec![0; 33].into()),
+        );
+        assert!(from_section::<Hashes>(section).is_err());
+    }
+}
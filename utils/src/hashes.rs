@@ -0,0 +1,144 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::FixedBytes;
+use serde::{
+    de::{Deserialize, Deserializer},
+    ser::{Serialize, Serializer},
+};
+use std::{
+    array::TryFromSliceError,
+    convert::{TryFrom, TryInto},
+    fmt,
+};
+
+macro_rules! bytes32 {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        #[derive(Default, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+        pub struct $name(pub [u8; 32]);
+
+        impl $name {
+            pub fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+        }
+
+        impl From<[u8; 32]> for $name {
+            fn from(v: [u8; 32]) -> $name {
+                $name(v)
+            }
+        }
+
+        impl From<$name> for [u8; 32] {
+            fn from(v: $name) -> [u8; 32] {
+                v.0
+            }
+        }
+
+        impl TryFrom<&[u8]> for $name {
+            type Error = TryFromSliceError;
+
+            fn try_from(v: &[u8]) -> Result<$name, TryFromSliceError> {
+                v.try_into().map($name)
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                FixedBytes::<32>::deserialize(deserializer).map(|v| $name(v.0))
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                serializer.serialize_bytes(&self.0)
+            }
+        }
+    };
+}
+
+bytes32! {
+    /// A 32-byte hash, such as a block or transaction ID.
+    BytesH256
+}
+
+bytes32! {
+    /// A 32-byte public or private key.
+    BytesKey
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use portable_storage::{from_section, to_section, Section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct CoreSyncData {
+        top_id: BytesH256,
+    }
+
+    #[test]
+    fn hex() {
+        let mut bytes = [0; 32];
+        bytes[0] = 0x41;
+        bytes[31] = 0xe3;
+        let hash = BytesH256::from(bytes);
+        let hex = format!("41{}e3", "00".repeat(30));
+        assert_eq!(hash.to_string(), hex);
+        assert_eq!(format!("{:?}", hash), format!("BytesH256({})", hex));
+        assert_eq!(<[u8; 32]>::from(hash), bytes);
+        assert!(BytesKey::try_from(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let data = CoreSyncData {
+            top_id: BytesH256([9; 32]),
+        };
+        let section = to_section(&data).unwrap();
+        assert_eq!(section["top_id"], StorageEntry::Buf(vec![9; 32].into()));
+        assert_eq!(from_section::<CoreSyncData>(section).unwrap(), data);
+
+        let mut section = Section::new();
+        section.insert("top_id".to_owned(), StorageEntry::Buf(vec![9; 33].into()));
+        assert!(from_section::<CoreSyncData>(section).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `#[serde(with)]` adapter storing bytes as lowercase hex text.
+//!
+//! Works with any type convertible from a `Vec<u8>`, such as `Vec<u8>`,
+//! `Blob` or `[u8; N]`, in which case the length is validated too.
+//!
+//! ```rust
+//! use portable_storage_utils::hex;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Block {
+//!     #[serde(with = "hex")]
+//!     prev_hash: [u8; 32],
+//!     #[serde(with = "hex")]
+//!     blob: Vec<u8>,
+//! }
+//! ```
+
+use serde::{
+    de::{Deserializer, Error, Unexpected, Visitor},
+    ser::Serializer,
+};
+use std::{convert::TryFrom, fmt, marker::PhantomData};
+
+pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: AsRef<[u8]>,
+    S: Serializer,
+{
+    let text: String = bytes
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&text)
+    } else {
+        serializer.serialize_bytes(text.as_bytes())
+    }
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: TryFrom<Vec<u8>>,
+    D: Deserializer<'de>,
+{
+    struct HexVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: TryFrom<Vec<u8>>> Visitor<'de> for HexVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a hex string")
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            let bytes = decode(v).ok_or_else(|| E::invalid_value(Unexpected::Bytes(v), &self))?;
+            let len = bytes.len();
+            T::try_from(bytes)
+                .map_err(|_| E::invalid_length(len, &"a hex string of the right length"))
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            self.visit_bytes(v.as_bytes())
+        }
+    }
+
+    deserializer.deserialize_str(HexVisitor(PhantomData))
+}
+
+fn decode(text: &[u8]) -> Option<Vec<u8>> {
+    let pairs = text.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    pairs
+        .map(|pair| Some(digit(pair[0])? << 4 | digit(pair[1])?))
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use portable_storage::{from_section, to_section, Section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Block {
+        #[serde(with = "super")]
+        prev_hash: [u8; 4],
+        #[serde(with = "super")]
+        blob: Vec<u8>,
+    }
+
+    #[test]
+    fn roundtrip() {
+        let block = Block {
+            prev_hash: [0xde, 0xad, 0xbe, 0xef],
+            blob: vec![0x0a],
+        };
+        let section = to_section(&block).unwrap();
+        assert_eq!(
+            section["prev_hash"],
+            StorageEntry::Buf(b"deadbeef"[..].into())
+        );
+        assert_eq!(section["blob"], StorageEntry::Buf(b"0a"[..].into()));
+        assert_eq!(from_section::<Block>(section).unwrap(), block);
+    }
+
+    #[test]
+    fn invalid() {
+        for (prev_hash, error) in &[
+            (&b"DEADBEEF"[..], None),
+            (b"deadbee", Some("invalid value")),
+            (b"deadbeeg", Some("invalid value")),
+            (b"deadbeef00", Some("invalid length 5")),
+        ] {
+            let mut section = Section::new();
+            section.insert(
+                "prev_hash".to_owned(),
+                StorageEntry::Buf(prev_hash[..].into()),
+            );
+            section.insert("blob".to_owned(), StorageEntry::Buf(Default::default()));
+            match (from_section::<Block>(section), error) {
+                (Ok(_), None) => {}
+                (Err(e), Some(error)) => assert!(e.to_string().contains(error), "{}", e),
+                (result, _) => panic!("unexpected {:?}", result),
+            }
+        }
+    }
+}
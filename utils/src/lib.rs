@@ -14,7 +14,32 @@
 // limitations under the License.
 
 mod blob;
+pub mod bool_or_u8;
 mod bytes_uuid;
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+pub mod compressed;
+pub mod empty_as_none;
+mod fixed_bytes;
+mod fixed_point;
+pub mod hash_blob;
+mod hashes;
+pub mod hex;
+#[cfg(feature = "monero")]
+pub mod monero;
+pub mod net;
+pub mod numeric;
+#[cfg(feature = "monero")]
+pub mod p2p;
+#[cfg(feature = "monero")]
+pub mod rpc;
+#[cfg(feature = "serde_with")]
+pub mod serde_as;
+pub mod time;
+mod var_bytes;
 
 pub use blob::Blob;
 pub use bytes_uuid::BytesUuid;
+pub use fixed_bytes::FixedBytes;
+pub use fixed_point::{FixedPoint, ParseFixedPointError};
+pub use hashes::{BytesH256, BytesKey};
+pub use var_bytes::VarBytes;
@@ -0,0 +1,328 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Peer-to-peer structures laid out the way monerod writes them.
+//!
+//! Fields are declared in byte order, like epee writes section keys, so
+//! serializing these structures reproduces monerod's encoding exactly.
+//!
+//! The envelope helpers merge the fields shared by every binary RPC call
+//! with the payload of each call:
+//!
+//! ```rust
+//! use portable_storage_utils::{monero, Blob};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct GetHeightResponse {
+//!     height: u64,
+//! }
+//!
+//! let base = monero::AccessResponseBase {
+//!     status: Blob::from(monero::STATUS_OK),
+//!     ..Default::default()
+//! };
+//! let section = monero::wrap(&base, &GetHeightResponse { height: 3 }).unwrap();
+//!
+//! let (base, response) = monero::unwrap_response::<GetHeightResponse>(section).unwrap();
+//! assert_eq!(response.height, 3);
+//! assert!(!base.untrusted);
+//! ```
+
+use crate::{net::NetworkAddress, Blob, BytesH256, BytesUuid};
+use portable_storage::{from_section, to_section, Section};
+use serde::{
+    de::{value, Deserialize, DeserializeOwned, Deserializer, Error},
+    ser::{Serialize, Serializer},
+};
+use std::{convert::TryFrom, fmt, net::SocketAddr};
+
+/// `status` of successful calls.
+pub const STATUS_OK: &[u8] = b"OK";
+/// `status` of calls refused because the daemon is syncing.
+pub const STATUS_BUSY: &[u8] = b"BUSY";
+/// `status` of calls refused because the client has no credits left.
+pub const STATUS_PAYMENT_REQUIRED: &[u8] = b"PAYMENT REQUIRED";
+
+/// The `type` tag of a `network_address`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AddressType {
+    Invalid = 0,
+    Ipv4 = 1,
+    Ipv6 = 2,
+    Tor = 3,
+    I2p = 4,
+}
+
+impl TryFrom<u8> for AddressType {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<AddressType, u8> {
+        Ok(match v {
+            0 => AddressType::Invalid,
+            1 => AddressType::Ipv4,
+            2 => AddressType::Ipv6,
+            3 => AddressType::Tor,
+            4 => AddressType::I2p,
+            v => return Err(v),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for AddressType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v = u8::deserialize(deserializer)?;
+        AddressType::try_from(v)
+            .map_err(|v| D::Error::custom(format!("unknown network address type {}", v)))
+    }
+}
+
+impl Serialize for AddressType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+impl NetworkAddress {
+    pub fn address_type(&self) -> AddressType {
+        match self.0 {
+            SocketAddr::V4(_) => AddressType::Ipv4,
+            SocketAddr::V6(_) => AddressType::Ipv6,
+        }
+    }
+}
+
+/// An entry of `local_peerlist_new`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PeerlistEntry {
+    pub adr: NetworkAddress,
+    pub id: u64,
+    pub last_seen: i64,
+    #[serde(default)]
+    pub pruning_seed: u32,
+    #[serde(default)]
+    pub rpc_credits_per_hash: u32,
+    #[serde(default)]
+    pub rpc_port: u16,
+}
+
+/// An entry of the anchor peer list.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AnchorPeerlistEntry {
+    pub adr: NetworkAddress,
+    pub first_seen: i64,
+    pub id: u64,
+}
+
+/// `node_data` of handshakes.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BasicNodeData {
+    pub local_time: u64,
+    pub my_port: u32,
+    pub network_id: BytesUuid,
+    pub peer_id: u64,
+    #[serde(default)]
+    pub rpc_credits_per_hash: u32,
+    #[serde(default)]
+    pub rpc_port: u16,
+    #[serde(default)]
+    pub support_flags: u32,
+}
+
+/// `payload_data` of handshakes and timed syncs.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CoreSyncData {
+    pub cumulative_difficulty: u64,
+    #[serde(default)]
+    pub cumulative_difficulty_top64: u64,
+    pub current_height: u64,
+    #[serde(default)]
+    pub pruning_seed: u32,
+    pub top_id: BytesH256,
+    pub top_version: u8,
+}
+
+/// Fields shared by binary RPC responses.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ResponseBase {
+    pub status: Blob,
+    #[serde(default)]
+    pub untrusted: bool,
+}
+
+/// Fields shared by binary RPC requests to paid endpoints.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AccessRequestBase {
+    /// The client's signature, empty when not paying.
+    #[serde(default)]
+    pub client: Blob,
+}
+
+/// Fields shared by binary RPC responses of paid endpoints, a superset of
+/// [`ResponseBase`].
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AccessResponseBase {
+    #[serde(default)]
+    pub credits: u64,
+    pub status: Blob,
+    /// The top block hash, written as hex text.
+    #[serde(default, with = "crate::hex")]
+    pub top_hash: Vec<u8>,
+    #[serde(default)]
+    pub untrusted: bool,
+}
+
+/// An error wrapping or unwrapping an RPC payload.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcError {
+    /// The envelope or the payload couldn't be serialized or deserialized.
+    Serde(value::Error),
+    /// The call failed with this status.
+    Status(String),
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RpcError::Serde(e) => write!(f, "{}", e),
+            RpcError::Status(status) => write!(f, "the call failed with status `{}`", status),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<value::Error> for RpcError {
+    fn from(e: value::Error) -> RpcError {
+        RpcError::Serde(e)
+    }
+}
+
+/// Merges the fields of `base` and `payload` into a section, keys in byte
+/// order.
+pub fn wrap<B: Serialize, T: Serialize>(base: &B, payload: &T) -> Result<Section, RpcError> {
+    let mut entries: Vec<_> = to_section(base)?
+        .into_iter()
+        .chain(to_section(payload)?)
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut section = Section::with_capacity(entries.len());
+    for (name, entry) in entries {
+        section.insert(name, entry);
+    }
+    Ok(section)
+}
+
+/// Splits a request into its envelope and payload.
+pub fn unwrap_request<T: DeserializeOwned>(
+    section: Section,
+) -> Result<(AccessRequestBase, T), RpcError> {
+    let base = from_section(section.clone())?;
+    Ok((base, from_section(section)?))
+}
+
+/// Splits a response into its envelope and payload, failing when its status
+/// isn't [`STATUS_OK`].
+pub fn unwrap_response<T: DeserializeOwned>(
+    section: Section,
+) -> Result<(AccessResponseBase, T), RpcError> {
+    let base: AccessResponseBase = from_section(section.clone())?;
+    if base.status.0 != STATUS_OK {
+        return Err(RpcError::Status(
+            String::from_utf8_lossy(&base.status.0).into_owned(),
+        ));
+    }
+    Ok((base, from_section(section)?))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use portable_storage::testvectors::{self, HANDSHAKE_REQUEST, HANDSHAKE_RESPONSE};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct HandshakeRequest {
+        node_data: BasicNodeData,
+        payload_data: CoreSyncData,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct HandshakeResponse {
+        local_peerlist_new: Vec<PeerlistEntry>,
+        node_data: BasicNodeData,
+        payload_data: CoreSyncData,
+    }
+
+    #[test]
+    fn handshake() {
+        let request: HandshakeRequest =
+            portable_storage::from_section(HANDSHAKE_REQUEST.section()).unwrap();
+        assert_eq!(request.node_data.my_port, 18080);
+        assert_eq!(request.payload_data.top_version, 14);
+        testvectors::assert_encodes(&HANDSHAKE_REQUEST, &request);
+
+        let response: HandshakeResponse =
+            portable_storage::from_section(HANDSHAKE_RESPONSE.section()).unwrap();
+        let peer = &response.local_peerlist_new[0];
+        assert_eq!(peer.adr.address_type(), AddressType::Ipv4);
+        assert_eq!(peer.adr.0, "127.0.0.2:18080".parse().unwrap());
+        testvectors::assert_encodes(&HANDSHAKE_RESPONSE, &response);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct GetHashesRequest {
+        start_height: u64,
+    }
+
+    #[test]
+    fn envelope() {
+        let base = AccessRequestBase {
+            client: Blob(b"signature".to_vec()),
+        };
+        let request = GetHashesRequest { start_height: 7 };
+        let section = wrap(&base, &request).unwrap();
+        let keys: Vec<_> = section.entries.keys().cloned().collect();
+        assert_eq!(keys, ["client", "start_height"]);
+        assert_eq!(unwrap_request(section).unwrap(), (base, request));
+
+        let base = AccessResponseBase {
+            credits: 100,
+            status: Blob::from(STATUS_OK),
+            top_hash: vec![0xab; 32],
+            untrusted: true,
+        };
+        let section = wrap(&base, &GetHashesRequest { start_height: 1 }).unwrap();
+        let (unwrapped, _) = unwrap_response::<GetHashesRequest>(section).unwrap();
+        assert_eq!(unwrapped, base);
+
+        let base = ResponseBase {
+            status: Blob::from(STATUS_BUSY),
+            untrusted: false,
+        };
+        let section = wrap(&base, &GetHashesRequest { start_height: 1 }).unwrap();
+        assert_eq!(
+            unwrap_response::<GetHashesRequest>(section).unwrap_err(),
+            RpcError::Status("BUSY".to_owned())
+        );
+    }
+}
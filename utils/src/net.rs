@@ -0,0 +1,243 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Adapters between `std::net` addresses and monerod's `network_address`
+//! sections.
+//!
+//! A `network_address` is a section with a `type` tag and an `addr` section,
+//! `{ m_ip: u32, m_port: u16 }` for IPv4 and `{ addr: [u8; 16], m_port: u16 }`
+//! for IPv6. `m_ip` holds the address in network byte order, so its
+//! little-endian bytes are the octets.
+//!
+//! ```rust
+//! use portable_storage_utils::net;
+//! use serde::{Deserialize, Serialize};
+//! use std::net::{Ipv4Addr, SocketAddr};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Peer {
+//!     #[serde(with = "net::network_address")]
+//!     adr: SocketAddr,
+//!     #[serde(with = "net::ipv4")]
+//!     my_ip: Ipv4Addr,
+//! }
+//! ```
+
+use crate::FixedBytes;
+use serde::{
+    de::{Deserialize, Deserializer, Error},
+    ser::{Serialize, Serializer},
+};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+/// The `type` of IPv4 addresses.
+pub const IPV4: u8 = 1;
+/// The `type` of IPv6 addresses.
+pub const IPV6: u8 = 2;
+/// The `type` of Tor onion addresses.
+pub const TOR: u8 = 3;
+/// The `type` of I2P addresses.
+pub const I2P: u8 = 4;
+
+/// A socket address serialized as a `network_address` section.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct NetworkAddress(pub SocketAddr);
+
+impl From<SocketAddr> for NetworkAddress {
+    fn from(v: SocketAddr) -> NetworkAddress {
+        NetworkAddress(v)
+    }
+}
+
+impl From<NetworkAddress> for SocketAddr {
+    fn from(v: NetworkAddress) -> SocketAddr {
+        v.0
+    }
+}
+
+impl<'de> Deserialize<'de> for NetworkAddress {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        network_address::deserialize(deserializer).map(NetworkAddress)
+    }
+}
+
+impl Serialize for NetworkAddress {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        network_address::serialize(&self.0, serializer)
+    }
+}
+
+/// `Ipv4Addr` as an `m_ip` `u32`.
+pub mod ipv4 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(addr: &Ipv4Addr, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(u32::from_le_bytes(addr.octets()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Ipv4Addr, D::Error> {
+        u32::deserialize(deserializer).map(|ip| Ipv4Addr::from(ip.to_le_bytes()))
+    }
+}
+
+/// `Ipv6Addr` as a 16 bytes string.
+pub mod ipv6 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(addr: &Ipv6Addr, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&addr.octets())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Ipv6Addr, D::Error> {
+        FixedBytes::<16>::deserialize(deserializer).map(|addr| Ipv6Addr::from(addr.0))
+    }
+}
+
+/// `SocketAddr` as a `network_address` section.
+///
+/// Tor and I2P addresses can't be represented and fail to deserialize.
+pub mod network_address {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize)]
+    struct Ipv4Ser<'a> {
+        #[serde(with = "ipv4")]
+        m_ip: &'a Ipv4Addr,
+        m_port: u16,
+    }
+
+    #[derive(Serialize)]
+    struct Ipv6Ser<'a> {
+        #[serde(with = "ipv6")]
+        addr: &'a Ipv6Addr,
+        m_port: u16,
+    }
+
+    #[derive(Serialize)]
+    struct Ser<A> {
+        addr: A,
+        #[serde(rename = "type")]
+        ty: u8,
+    }
+
+    #[derive(Deserialize)]
+    struct RawAddr {
+        #[serde(default)]
+        m_ip: u32,
+        #[serde(default)]
+        addr: crate::Blob,
+        m_port: u16,
+    }
+
+    #[derive(Deserialize)]
+    struct Raw {
+        addr: RawAddr,
+        #[serde(rename = "type")]
+        ty: u8,
+    }
+
+    pub fn serialize<S: Serializer>(addr: &SocketAddr, serializer: S) -> Result<S::Ok, S::Error> {
+        match addr {
+            SocketAddr::V4(addr) => Ser {
+                addr: Ipv4Ser {
+                    m_ip: addr.ip(),
+                    m_port: addr.port(),
+                },
+                ty: IPV4,
+            }
+            .serialize(serializer),
+            SocketAddr::V6(addr) => Ser {
+                addr: Ipv6Ser {
+                    addr: addr.ip(),
+                    m_port: addr.port(),
+                },
+                ty: IPV6,
+            }
+            .serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SocketAddr, D::Error> {
+        let raw = Raw::deserialize(deserializer)?;
+        match raw.ty {
+            IPV4 => {
+                let ip = Ipv4Addr::from(raw.addr.m_ip.to_le_bytes());
+                Ok(SocketAddrV4::new(ip, raw.addr.m_port).into())
+            }
+            IPV6 => {
+                let addr = raw.addr.addr.0;
+                if addr.len() != 16 {
+                    return Err(D::Error::invalid_length(addr.len(), &"an IPv6 address"));
+                }
+                let mut octets = [0; 16];
+                octets.copy_from_slice(&addr);
+                Ok(SocketAddrV6::new(octets.into(), raw.addr.m_port, 0, 0).into())
+            }
+            ty => Err(D::Error::custom(format!(
+                "network address type {} isn't supported",
+                ty
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use portable_storage::{from_section, to_section, Section, StorageEntry};
+
+    #[test]
+    fn ipv4() {
+        let addr = NetworkAddress("1.2.3.4:18080".parse().unwrap());
+        let section = to_section(&addr).unwrap();
+        assert_eq!(section["type"], StorageEntry::U8(IPV4));
+        match &section["addr"] {
+            StorageEntry::Section(addr) => {
+                assert_eq!(addr["m_ip"], StorageEntry::U32(0x0403_0201));
+                assert_eq!(addr["m_port"], StorageEntry::U16(18080));
+            }
+            entry => panic!("unexpected {:?}", entry),
+        }
+        assert_eq!(from_section::<NetworkAddress>(section).unwrap(), addr);
+    }
+
+    #[test]
+    fn ipv6() {
+        let addr = NetworkAddress("[2001:db8::1]:18080".parse().unwrap());
+        let section = to_section(&addr).unwrap();
+        assert_eq!(section["type"], StorageEntry::U8(IPV6));
+        assert_eq!(from_section::<NetworkAddress>(section).unwrap(), addr);
+    }
+
+    #[test]
+    fn unsupported() {
+        let mut addr = Section::new();
+        addr.insert("host".to_owned(), StorageEntry::Buf(b"x.onion"[..].into()));
+        addr.insert("m_port".to_owned(), StorageEntry::U16(0));
+        let mut section = Section::new();
+        section.insert("addr".to_owned(), StorageEntry::Section(addr));
+        section.insert("type".to_owned(), StorageEntry::U8(TOR));
+
+        let error = from_section::<NetworkAddress>(section).unwrap_err();
+        assert!(error.to_string().contains("type 3 isn't supported"));
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `#[serde(with)]` adapters for numbers that changed between integer and
+//! double representations across daemon versions.
+//!
+//! Every adapter accepts any integer or double on read and writes its own
+//! type. Integer adapters come in three flavours deciding what happens to
+//! doubles with a fractional part: [`exact`] rejects them, [`round`] rounds
+//! them to the nearest integer and [`truncate`] drops the fraction. Values
+//! that don't fit the target type are always rejected.
+//!
+//! ```rust
+//! use portable_storage_utils::numeric;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Info {
+//!     #[serde(with = "numeric::exact::u64")]
+//!     difficulty: u64,
+//!     #[serde(with = "numeric::f64")]
+//!     block_size_median: f64,
+//! }
+//! ```
+
+use serde::{
+    de::{Deserializer, Error, Unexpected, Visitor},
+    ser::Serializer,
+};
+use std::{convert::TryFrom, fmt};
+
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Unsigned(u64),
+    Signed(i64),
+    Double(f64),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Rounding {
+    Exact,
+    Round,
+    Truncate,
+}
+
+struct NumberVisitor;
+
+impl<'de> Visitor<'de> for NumberVisitor {
+    type Value = Number;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an integer or a double")
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<Number, E> {
+        Ok(Number::Unsigned(v))
+    }
+
+    fn visit_i64<E: Error>(self, v: i64) -> Result<Number, E> {
+        Ok(Number::Signed(v))
+    }
+
+    fn visit_f64<E: Error>(self, v: f64) -> Result<Number, E> {
+        Ok(Number::Double(v))
+    }
+}
+
+fn integer<'de, D, T>(deserializer: D, rounding: Rounding) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64> + TryFrom<i64>,
+{
+    let number = deserializer.deserialize_any(NumberVisitor)?;
+    let unexpected = match number {
+        Number::Unsigned(v) => Unexpected::Unsigned(v),
+        Number::Signed(v) => Unexpected::Signed(v),
+        Number::Double(v) => Unexpected::Float(v),
+    };
+    let out_of_range = || D::Error::invalid_value(unexpected, &"a number in range");
+
+    let number = match number {
+        Number::Double(v) => {
+            let v = match rounding {
+                Rounding::Exact if v.fract() != 0.0 => {
+                    return Err(D::Error::invalid_value(unexpected, &"an integral number"))
+                }
+                Rounding::Exact => v,
+                Rounding::Round => v.round(),
+                Rounding::Truncate => v.trunc(),
+            };
+            // 2^64, the first double past `u64::MAX`.
+            if v.is_nan() || v < i64::MIN as f64 || v >= 18_446_744_073_709_551_616.0 {
+                return Err(out_of_range());
+            }
+            if v < 0.0 {
+                Number::Signed(v as i64)
+            } else {
+                Number::Unsigned(v as u64)
+            }
+        }
+        number => number,
+    };
+
+    match number {
+        Number::Unsigned(v) => T::try_from(v).map_err(|_| out_of_range()),
+        Number::Signed(v) => T::try_from(v).map_err(|_| out_of_range()),
+        Number::Double(_) => unreachable!(),
+    }
+}
+
+macro_rules! integers {
+    ($rounding:expr, $($ty:ident $serialize:ident),+) => {
+        $(
+        #[doc = concat!("`", stringify!($ty), "` from any number.")]
+        pub mod $ty {
+            use super::super::*;
+
+            pub fn serialize<S: Serializer>(
+                v: &::core::primitive::$ty,
+                serializer: S,
+            ) -> Result<S::Ok, S::Error> {
+                serializer.$serialize(*v)
+            }
+
+            pub fn deserialize<'de, D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<::core::primitive::$ty, D::Error> {
+                integer(deserializer, $rounding)
+            }
+        }
+        )+
+    };
+}
+
+/// Integers, rejecting doubles with a fractional part.
+pub mod exact {
+    integers! {
+        Rounding::Exact,
+        u64 serialize_u64, u32 serialize_u32, u16 serialize_u16, u8 serialize_u8,
+        i64 serialize_i64, i32 serialize_i32, i16 serialize_i16, i8 serialize_i8
+    }
+}
+
+/// Integers, rounding doubles to the nearest integer, half away from zero.
+pub mod round {
+    integers! {
+        Rounding::Round,
+        u64 serialize_u64, u32 serialize_u32, u16 serialize_u16, u8 serialize_u8,
+        i64 serialize_i64, i32 serialize_i32, i16 serialize_i16, i8 serialize_i8
+    }
+}
+
+/// Integers, truncating doubles towards zero.
+pub mod truncate {
+    integers! {
+        Rounding::Truncate,
+        u64 serialize_u64, u32 serialize_u32, u16 serialize_u16, u8 serialize_u8,
+        i64 serialize_i64, i32 serialize_i32, i16 serialize_i16, i8 serialize_i8
+    }
+}
+
+/// `f64` from any number. Integers beyond 2^53 lose precision.
+pub mod f64 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        v: &::core::primitive::f64,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(*v)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<::core::primitive::f64, D::Error> {
+        Ok(match deserializer.deserialize_any(NumberVisitor)? {
+            Number::Unsigned(v) => v as ::core::primitive::f64,
+            Number::Signed(v) => v as ::core::primitive::f64,
+            Number::Double(v) => v,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use portable_storage::{from_section, to_section, Section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Info {
+        #[serde(with = "super::exact::u32")]
+        exact: u32,
+        #[serde(with = "super::round::i8")]
+        round: i8,
+        #[serde(with = "super::truncate::u64")]
+        truncate: u64,
+        #[serde(with = "super::f64")]
+        double: f64,
+    }
+
+    fn read(exact: StorageEntry, round: StorageEntry) -> Result<Info, String> {
+        let mut section = Section::new();
+        section.insert("exact".to_owned(), exact);
+        section.insert("round".to_owned(), round);
+        section.insert("truncate".to_owned(), StorageEntry::Double(2.9));
+        section.insert("double".to_owned(), StorageEntry::U64(7));
+        from_section(section).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn conversions() {
+        let info = read(StorageEntry::Double(3.0), StorageEntry::Double(-1.5)).unwrap();
+        assert_eq!(
+            info,
+            Info {
+                exact: 3,
+                round: -2,
+                truncate: 2,
+                double: 7.0
+            }
+        );
+
+        let section = to_section(&info).unwrap();
+        assert_eq!(section["exact"], StorageEntry::U32(3));
+        assert_eq!(section["round"], StorageEntry::I8(-2));
+        assert_eq!(section["double"], StorageEntry::Double(7.0));
+    }
+
+    #[test]
+    fn rejected() {
+        let errors = [
+            read(StorageEntry::Double(3.5), StorageEntry::I8(0)),
+            read(StorageEntry::I64(-1), StorageEntry::I8(0)),
+            read(StorageEntry::U8(0), StorageEntry::Double(127.5)),
+            read(StorageEntry::Double(f64::NAN), StorageEntry::I8(0)),
+        ];
+        for error in errors.iter() {
+            assert!(error.is_err(), "{:?}", error);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Payloads of monerod's peer-to-peer commands and notifications.
+//!
+//! Like the structures of [`monero`](crate::monero), fields are declared in
+//! byte order so serializing a payload reproduces monerod's encoding. Each
+//! payload carries its command ID as [`Message::COMMAND`], the one written
+//! in the Levin bucket header.
+//!
+//! ```rust
+//! use portable_storage_utils::p2p::{Message, SupportFlagsResponse};
+//!
+//! let response = SupportFlagsResponse { support_flags: 1 };
+//! let section = portable_storage::to_section(&response).unwrap();
+//! assert_eq!(portable_storage::from_section::<SupportFlagsResponse>(section).unwrap(), response);
+//! assert_eq!(SupportFlagsResponse::COMMAND, 1007);
+//! ```
+
+use crate::{
+    monero::{BasicNodeData, CoreSyncData, PeerlistEntry},
+    Blob,
+};
+use serde::{Deserialize, Serialize};
+
+pub const COMMAND_HANDSHAKE: u32 = 1001;
+pub const COMMAND_TIMED_SYNC: u32 = 1002;
+pub const COMMAND_PING: u32 = 1003;
+pub const COMMAND_REQUEST_SUPPORT_FLAGS: u32 = 1007;
+pub const NOTIFY_NEW_BLOCK: u32 = 2001;
+pub const NOTIFY_NEW_TRANSACTIONS: u32 = 2002;
+pub const NOTIFY_NEW_FLUFFY_BLOCK: u32 = 2008;
+
+/// `status` of ping responses.
+pub const PING_OK_RESPONSE_STATUS: &[u8] = b"OK";
+
+/// A payload sent under a given command.
+pub trait Message {
+    const COMMAND: u32;
+}
+
+macro_rules! messages {
+    ($($ty:ty => $command:ident,)+) => {
+        $(
+        impl Message for $ty {
+            const COMMAND: u32 = $command;
+        }
+        )+
+    };
+}
+
+messages! {
+    HandshakeRequest => COMMAND_HANDSHAKE,
+    HandshakeResponse => COMMAND_HANDSHAKE,
+    TimedSyncRequest => COMMAND_TIMED_SYNC,
+    TimedSyncResponse => COMMAND_TIMED_SYNC,
+    PingRequest => COMMAND_PING,
+    PingResponse => COMMAND_PING,
+    SupportFlagsRequest => COMMAND_REQUEST_SUPPORT_FLAGS,
+    SupportFlagsResponse => COMMAND_REQUEST_SUPPORT_FLAGS,
+    NewBlock => NOTIFY_NEW_BLOCK,
+    NewTransactions => NOTIFY_NEW_TRANSACTIONS,
+    NewFluffyBlock => NOTIFY_NEW_FLUFFY_BLOCK,
+}
+
+/// `COMMAND_HANDSHAKE` request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HandshakeRequest {
+    pub node_data: BasicNodeData,
+    pub payload_data: CoreSyncData,
+}
+
+/// `COMMAND_HANDSHAKE` response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HandshakeResponse {
+    #[serde(default)]
+    pub local_peerlist_new: Vec<PeerlistEntry>,
+    pub node_data: BasicNodeData,
+    pub payload_data: CoreSyncData,
+}
+
+/// `COMMAND_TIMED_SYNC` request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimedSyncRequest {
+    pub payload_data: CoreSyncData,
+}
+
+/// `COMMAND_TIMED_SYNC` response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimedSyncResponse {
+    #[serde(default)]
+    pub local_peerlist_new: Vec<PeerlistEntry>,
+    pub payload_data: CoreSyncData,
+}
+
+/// `COMMAND_PING` request, it has no fields.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PingRequest {}
+
+/// `COMMAND_PING` response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PingResponse {
+    pub peer_id: u64,
+    /// [`PING_OK_RESPONSE_STATUS`] on success.
+    pub status: Blob,
+}
+
+/// `COMMAND_REQUEST_SUPPORT_FLAGS` request, it has no fields.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SupportFlagsRequest {}
+
+/// `COMMAND_REQUEST_SUPPORT_FLAGS` response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SupportFlagsResponse {
+    pub support_flags: u32,
+}
+
+/// A block with its transactions, as relayed and synced.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockCompleteEntry {
+    pub block: Blob,
+    #[serde(default)]
+    pub block_weight: u64,
+    #[serde(default)]
+    pub pruned: bool,
+    #[serde(default)]
+    pub txs: Vec<Blob>,
+}
+
+/// `NOTIFY_NEW_BLOCK` notification.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewBlock {
+    pub b: BlockCompleteEntry,
+    pub current_blockchain_height: u64,
+}
+
+/// `NOTIFY_NEW_FLUFFY_BLOCK` notification, the block carries only the
+/// transactions the receiver may not have.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewFluffyBlock {
+    pub b: BlockCompleteEntry,
+    pub current_blockchain_height: u64,
+}
+
+/// `NOTIFY_NEW_TRANSACTIONS` notification.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewTransactions {
+    /// Random bytes hiding the size of the transactions.
+    #[serde(rename = "_", default)]
+    pub padding: Blob,
+    #[serde(default)]
+    pub dandelionpp_fluff: bool,
+    pub txs: Vec<Blob>,
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use portable_storage::{
+        from_section,
+        testvectors::{self, HANDSHAKE_REQUEST, HANDSHAKE_RESPONSE, TIMED_SYNC_REQUEST},
+        to_section,
+    };
+    use serde::de::DeserializeOwned;
+
+    fn roundtrip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(value: &T) {
+        let bytes = portable_storage::write_to_vec(&to_section(value).unwrap());
+        let (section, _) = portable_storage::read_from_slice(&bytes).unwrap();
+        assert_eq!(&from_section::<T>(section).unwrap(), value);
+    }
+
+    #[test]
+    fn test_vectors() {
+        let request: HandshakeRequest = from_section(HANDSHAKE_REQUEST.section()).unwrap();
+        assert_eq!(request.node_data.my_port, 18080);
+        testvectors::assert_encodes(&HANDSHAKE_REQUEST, &request);
+
+        let response: HandshakeResponse = from_section(HANDSHAKE_RESPONSE.section()).unwrap();
+        assert_eq!(response.local_peerlist_new.len(), 250);
+        testvectors::assert_encodes(&HANDSHAKE_RESPONSE, &response);
+
+        let request: TimedSyncRequest = from_section(TIMED_SYNC_REQUEST.section()).unwrap();
+        assert_eq!(request.payload_data.current_height, 2_200_000);
+        testvectors::assert_encodes(&TIMED_SYNC_REQUEST, &request);
+    }
+
+    #[test]
+    fn notifications() {
+        let block = BlockCompleteEntry {
+            block: Blob(vec![0x0e; 80]),
+            txs: vec![Blob(vec![1; 40]), Blob(vec![2; 50])],
+            ..Default::default()
+        };
+        roundtrip(&NewBlock {
+            b: block.clone(),
+            current_blockchain_height: 2_200_001,
+        });
+        roundtrip(&NewFluffyBlock {
+            b: block,
+            current_blockchain_height: 2_200_001,
+        });
+        roundtrip(&NewTransactions {
+            padding: Blob(vec![0; 7]),
+            dandelionpp_fluff: true,
+            txs: vec![Blob(vec![3; 60])],
+        });
+
+        roundtrip(&PingRequest {});
+        roundtrip(&PingResponse {
+            peer_id: 42,
+            status: Blob::from(PING_OK_RESPONSE_STATUS),
+        });
+        roundtrip(&SupportFlagsRequest {});
+        roundtrip(&SupportFlagsResponse { support_flags: 1 });
+    }
+}
@@ -0,0 +1,440 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Requests and responses of the binary RPC endpoints used to sync,
+//! `/get_blocks.bin` and `/get_hashes.bin`.
+//!
+//! Sync responses run into hundreds of megabytes, so they aren't decoded as
+//! a whole: [`GetBlocksResponse::read`] hands each block to a callback as
+//! it's reached, with its blobs borrowed from the response bytes, and
+//! [`GetHashesResponse`] borrows its hashes. Only the small fields of the
+//! responses are kept.
+//!
+//! ```rust
+//! use portable_storage_utils::rpc::GetBlocksResponse;
+//!
+//! # fn fetch() -> Vec<u8> {
+//! #     let mut section = portable_storage::Section::new();
+//! #     section.insert("status".to_owned(), portable_storage::StorageEntry::Buf(b"OK"[..].into()));
+//! #     portable_storage::write_to_vec(&section)
+//! # }
+//! let body = fetch();
+//! let mut weight = 0;
+//! let response = GetBlocksResponse::read(&body, |block| weight += block.block.len()).unwrap();
+//! assert_eq!(response.status.0, b"OK");
+//! ```
+//!
+//! The borrowing types only deserialize with
+//! [`from_bytes`](portable_storage::from_bytes).
+
+use crate::{Blob, BytesH256};
+use portable_storage::from_bytes_seed;
+use serde::{
+    de::{value, DeserializeSeed, Deserializer, Error, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Serialize,
+};
+use std::{convert::TryInto, fmt, marker::PhantomData};
+
+/// `/get_blocks.bin` request.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GetBlocksRequest {
+    /// Known block IDs, the first ten sequentially from the top, then
+    /// spaced out by powers of two, ending with the genesis block.
+    #[serde(with = "crate::hash_blob")]
+    pub block_ids: Vec<BytesH256>,
+    #[serde(default)]
+    pub client: Blob,
+    #[serde(default)]
+    pub no_miner_tx: bool,
+    #[serde(default)]
+    pub pool_info_since: u64,
+    pub prune: bool,
+    #[serde(default)]
+    pub requested_info: u8,
+    pub start_height: u64,
+}
+
+/// `/get_hashes.bin` request.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GetHashesRequest {
+    #[serde(with = "crate::hash_blob")]
+    pub block_ids: Vec<BytesH256>,
+    #[serde(default)]
+    pub client: Blob,
+    pub start_height: u64,
+}
+
+/// `/get_hashes.bin` response, borrowing the block IDs.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GetHashesResponse<'a> {
+    #[serde(default)]
+    pub credits: u64,
+    pub current_height: u64,
+    /// The block IDs back to back, see [`block_ids`](Self::block_ids).
+    #[serde(borrow, deserialize_with = "hashes")]
+    pub m_block_ids: &'a [u8],
+    pub start_height: u64,
+    pub status: Blob,
+    #[serde(default, with = "crate::hex")]
+    pub top_hash: Vec<u8>,
+    #[serde(default)]
+    pub untrusted: bool,
+}
+
+impl<'a> GetHashesResponse<'a> {
+    /// The block IDs, from `start_height` on.
+    pub fn block_ids(&self) -> impl Iterator<Item = BytesH256> + 'a {
+        self.m_block_ids
+            .chunks_exact(32)
+            .map(|id| BytesH256(id.try_into().unwrap()))
+    }
+}
+
+fn hashes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<&'de [u8], D::Error> {
+    let bytes = <&[u8]>::deserialize(deserializer)?;
+    if bytes.len() % 32 != 0 {
+        return Err(D::Error::invalid_length(
+            bytes.len(),
+            &"a binary blob of 32-byte hashes",
+        ));
+    }
+    Ok(bytes)
+}
+
+/// A block of a `/get_blocks.bin` response, borrowed from the response.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BlockEntry<'a> {
+    #[serde(borrow)]
+    pub block: &'a [u8],
+    #[serde(default)]
+    pub block_weight: u64,
+    #[serde(default)]
+    pub pruned: bool,
+    #[serde(borrow, default)]
+    pub txs: Vec<TxEntry<'a>>,
+}
+
+/// A transaction of a [`BlockEntry`], pruned blocks carry the hash of the
+/// prunable part along with the pruned transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TxEntry<'a> {
+    pub blob: &'a [u8],
+    pub prunable_hash: Option<BytesH256>,
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for TxEntry<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TxEntryVisitor<'a>(PhantomData<&'a ()>);
+
+        impl<'de: 'a, 'a> Visitor<'de> for TxEntryVisitor<'a> {
+            type Value = TxEntry<'a>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a transaction blob or a pruned transaction")
+            }
+
+            fn visit_borrowed_bytes<E: Error>(self, v: &'de [u8]) -> Result<Self::Value, E> {
+                Ok(TxEntry {
+                    blob: v,
+                    prunable_hash: None,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut blob = None;
+                let mut prunable_hash = None;
+                while let Some(key) = map.next_key::<&str>()? {
+                    match key {
+                        "blob" => blob = Some(map.next_value()?),
+                        "prunable_hash" => prunable_hash = Some(map.next_value()?),
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                Ok(TxEntry {
+                    blob: blob.ok_or_else(|| A::Error::missing_field("blob"))?,
+                    prunable_hash,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(TxEntryVisitor(PhantomData))
+    }
+}
+
+/// The output indices of the transactions of a block, miner transaction
+/// first.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockOutputIndices {
+    pub indices: Vec<TxOutputIndices>,
+}
+
+/// The global indices of the outputs of a transaction.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxOutputIndices {
+    pub indices: Vec<u64>,
+}
+
+/// `/get_blocks.bin` response without its blocks, see
+/// [`read`](Self::read).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GetBlocksResponse {
+    pub credits: u64,
+    pub current_height: u64,
+    pub output_indices: Vec<BlockOutputIndices>,
+    pub start_height: u64,
+    pub status: Blob,
+    pub top_hash: Vec<u8>,
+    pub untrusted: bool,
+}
+
+impl GetBlocksResponse {
+    /// Reads a response from its bytes, handing each block to `f` in order
+    /// as it's reached.
+    pub fn read<'de, F>(data: &'de [u8], f: F) -> Result<Self, value::Error>
+    where
+        F: FnMut(BlockEntry<'de>),
+    {
+        from_bytes_seed(data, ResponseSeed(f))
+    }
+}
+
+const FIELDS: &[&str] = &[
+    "blocks",
+    "credits",
+    "current_height",
+    "output_indices",
+    "start_height",
+    "status",
+    "top_hash",
+    "untrusted",
+];
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum Field {
+    Blocks,
+    Credits,
+    CurrentHeight,
+    OutputIndices,
+    StartHeight,
+    Status,
+    TopHash,
+    Untrusted,
+    #[serde(other)]
+    Other,
+}
+
+struct ResponseSeed<F>(F);
+
+impl<'de, F: FnMut(BlockEntry<'de>)> DeserializeSeed<'de> for ResponseSeed<F> {
+    type Value = GetBlocksResponse;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("GetBlocksResponse", FIELDS, self)
+    }
+}
+
+impl<'de, F: FnMut(BlockEntry<'de>)> Visitor<'de> for ResponseSeed<F> {
+    type Value = GetBlocksResponse;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a get_blocks.bin response")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut response = GetBlocksResponse::default();
+        let mut status = None;
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Blocks => map.next_value_seed(BlocksSeed(&mut self.0))?,
+                Field::Credits => response.credits = map.next_value()?,
+                Field::CurrentHeight => response.current_height = map.next_value()?,
+                Field::OutputIndices => response.output_indices = map.next_value()?,
+                Field::StartHeight => response.start_height = map.next_value()?,
+                Field::Status => status = Some(map.next_value()?),
+                Field::TopHash => response.top_hash = map.next_value_seed(HexSeed)?,
+                Field::Untrusted => response.untrusted = map.next_value()?,
+                Field::Other => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        response.status = status.ok_or_else(|| A::Error::missing_field("status"))?;
+        Ok(response)
+    }
+}
+
+struct BlocksSeed<'f, F>(&'f mut F);
+
+impl<'f, 'de, F: FnMut(BlockEntry<'de>)> DeserializeSeed<'de> for BlocksSeed<'f, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'f, 'de, F: FnMut(BlockEntry<'de>)> Visitor<'de> for BlocksSeed<'f, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an array of blocks")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(block) = seq.next_element()? {
+            (self.0)(block);
+        }
+        Ok(())
+    }
+}
+
+struct HexSeed;
+
+impl<'de> DeserializeSeed<'de> for HexSeed {
+    type Value = Vec<u8>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Vec<u8>, D::Error> {
+        crate::hex::deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::monero::STATUS_OK;
+    use portable_storage::{from_bytes, to_section, Array, Section, StorageEntry};
+
+    fn buf(bytes: &[u8]) -> StorageEntry {
+        StorageEntry::Buf(bytes.into())
+    }
+
+    fn array(entries: Vec<StorageEntry>) -> StorageEntry {
+        let mut array = Array::new();
+        for entry in entries {
+            array.push(entry).unwrap();
+        }
+        StorageEntry::Array(array)
+    }
+
+    fn block(pruned: bool) -> StorageEntry {
+        let tx = |i: u8| {
+            if pruned {
+                let mut tx = Section::new();
+                tx.insert("blob".to_owned(), buf(&[i; 40]));
+                tx.insert("prunable_hash".to_owned(), buf(&[i; 32]));
+                StorageEntry::Section(tx)
+            } else {
+                buf(&[i; 40])
+            }
+        };
+
+        let mut block = Section::new();
+        block.insert("block".to_owned(), buf(&[0x0e; 80]));
+        block.insert("block_weight".to_owned(), StorageEntry::U64(300));
+        block.insert("pruned".to_owned(), StorageEntry::Bool(pruned));
+        block.insert("txs".to_owned(), array(vec![tx(1), tx(2)]));
+        StorageEntry::Section(block)
+    }
+
+    fn indices(indices: &[u64]) -> StorageEntry {
+        let mut tx = Section::new();
+        tx.insert(
+            "indices".to_owned(),
+            array(indices.iter().map(|i| StorageEntry::U64(*i)).collect()),
+        );
+        let mut block = Section::new();
+        block.insert("indices".to_owned(), array(vec![StorageEntry::Section(tx)]));
+        StorageEntry::Section(block)
+    }
+
+    #[test]
+    fn get_blocks() {
+        let request = GetBlocksRequest {
+            block_ids: vec![BytesH256([7; 32])],
+            prune: true,
+            start_height: 100,
+            ..Default::default()
+        };
+        let section = to_section(&request).unwrap();
+        assert_eq!(section["block_ids"], buf(&[7; 32]));
+
+        for pruned in [false, true] {
+            let mut section = Section::new();
+            section.insert(
+                "blocks".to_owned(),
+                array(vec![block(pruned), block(pruned)]),
+            );
+            section.insert("current_height".to_owned(), StorageEntry::U64(102));
+            section.insert(
+                "output_indices".to_owned(),
+                array(vec![indices(&[5, 6]), indices(&[7])]),
+            );
+            section.insert("start_height".to_owned(), StorageEntry::U64(100));
+            section.insert("status".to_owned(), buf(STATUS_OK));
+            section.insert("top_hash".to_owned(), buf(b"abcd"));
+            let data = portable_storage::write_to_vec(&section);
+
+            let mut blocks = Vec::new();
+            let response = GetBlocksResponse::read(&data, |block| blocks.push(block)).unwrap();
+            assert_eq!(response.current_height, 102);
+            assert_eq!(response.output_indices[1].indices[0].indices, [7]);
+            assert_eq!(response.top_hash, [0xab, 0xcd]);
+            assert_eq!(response.status.0, STATUS_OK);
+
+            assert_eq!(blocks.len(), 2);
+            assert_eq!(blocks[0].block, &[0x0e; 80][..]);
+            assert_eq!(blocks[1].txs[1].blob, &[2; 40][..]);
+            assert_eq!(
+                blocks[1].txs[0].prunable_hash,
+                Some(BytesH256([1; 32])).filter(|_| pruned)
+            );
+            let range = data.as_ptr_range();
+            assert!(range.contains(&blocks[0].txs[0].blob.as_ptr()));
+
+            assert!(GetBlocksResponse::read(&data[..data.len() - 1], |_| {}).is_err());
+        }
+    }
+
+    #[test]
+    fn get_hashes() {
+        let ids: Vec<_> = (0..3).map(|i| BytesH256([i; 32])).collect();
+        let mut section = Section::new();
+        section.insert("current_height".to_owned(), StorageEntry::U64(3));
+        section.insert(
+            "m_block_ids".to_owned(),
+            to_section(&GetHashesRequest {
+                block_ids: ids.clone(),
+                ..Default::default()
+            })
+            .unwrap()["block_ids"]
+                .clone(),
+        );
+        section.insert("start_height".to_owned(), StorageEntry::U64(0));
+        section.insert("status".to_owned(), buf(STATUS_OK));
+        let data = portable_storage::write_to_vec(&section);
+
+        let response: GetHashesResponse = from_bytes(&data).unwrap();
+        assert_eq!(response.block_ids().collect::<Vec<_>>(), ids);
+
+        section.insert("m_block_ids".to_owned(), buf(&[0; 33]));
+        let data = portable_storage::write_to_vec(&section);
+        assert!(from_bytes::<GetHashesResponse>(&data).is_err());
+    }
+}
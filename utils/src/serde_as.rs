@@ -0,0 +1,220 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! [`serde_with`] adapters, behind the `serde_with` feature.
+//!
+//! The `#[serde(with)]` adapters of this crate only apply to the field
+//! itself, these apply inside `Vec`, maps, `Option` and the other types
+//! `serde_with` supports:
+//!
+//! ```rust
+//! use portable_storage_utils::{serde_as::{Hex, UnixU64}, FixedBytes};
+//! use serde::{Deserialize, Serialize};
+//! use serde_with::serde_as;
+//! use std::time::SystemTime;
+//!
+//! #[serde_as]
+//! #[derive(Serialize, Deserialize)]
+//! struct Chain {
+//!     #[serde_as(as = "Vec<FixedBytes<32>>")]
+//!     block_ids: Vec<[u8; 32]>,
+//!     #[serde_as(as = "Vec<Hex>")]
+//!     tx_hashes: Vec<Vec<u8>>,
+//!     #[serde_as(as = "Vec<UnixU64>")]
+//!     timestamps: Vec<SystemTime>,
+//! }
+//! ```
+//!
+//! Each adapter behaves like its `#[serde(with)]` counterpart. Storage
+//! sections have no representation for `Option` and maps, those only
+//! combine with the adapters in other formats. For optional values in
+//! sections, see [`empty_as_none`](crate::empty_as_none).
+
+use crate::{hash_blob, hex, time, FixedBytes};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::{DeserializeAs, SerializeAs};
+use std::{
+    convert::TryFrom,
+    time::{Duration, SystemTime},
+};
+
+/// Bytes as lowercase hex text, see [`hex`](crate::hex).
+pub struct Hex;
+
+/// A list of 32-byte hashes as a single string of their bytes, see
+/// [`hash_blob`](crate::hash_blob).
+pub struct HashBlob;
+
+/// `SystemTime` as unsigned seconds since the Unix epoch, see
+/// [`time::unix_u64`](crate::time::unix_u64).
+pub struct UnixU64;
+
+/// `SystemTime` as signed seconds since the Unix epoch, see
+/// [`time::unix_i64`](crate::time::unix_i64).
+pub struct UnixI64;
+
+/// `Duration` as unsigned seconds, see [`time::seconds`](crate::time::seconds).
+pub struct Seconds;
+
+impl<T: AsRef<[u8]>> SerializeAs<T> for Hex {
+    fn serialize_as<S: Serializer>(source: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        hex::serialize(source, serializer)
+    }
+}
+
+impl<'de, T: TryFrom<Vec<u8>>> DeserializeAs<'de, T> for Hex {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        hex::deserialize(deserializer)
+    }
+}
+
+impl<T: AsRef<[u8]>> SerializeAs<Vec<T>> for HashBlob {
+    fn serialize_as<S: Serializer>(source: &Vec<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        hash_blob::serialize(source, serializer)
+    }
+}
+
+impl<'de, T: From<[u8; 32]>> DeserializeAs<'de, Vec<T>> for HashBlob {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<Vec<T>, D::Error> {
+        hash_blob::deserialize(deserializer)
+    }
+}
+
+/// `[u8; N]` as a string of exactly `N` bytes.
+impl<const N: usize> SerializeAs<[u8; N]> for FixedBytes<N> {
+    fn serialize_as<S: Serializer>(source: &[u8; N], serializer: S) -> Result<S::Ok, S::Error> {
+        FixedBytes(*source).serialize(serializer)
+    }
+}
+
+impl<'de, const N: usize> DeserializeAs<'de, [u8; N]> for FixedBytes<N> {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<[u8; N], D::Error> {
+        FixedBytes::deserialize(deserializer).map(|bytes| bytes.0)
+    }
+}
+
+impl SerializeAs<SystemTime> for UnixU64 {
+    fn serialize_as<S: Serializer>(source: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        time::unix_u64::serialize(source, serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, SystemTime> for UnixU64 {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        time::unix_u64::deserialize(deserializer)
+    }
+}
+
+impl SerializeAs<SystemTime> for UnixI64 {
+    fn serialize_as<S: Serializer>(source: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        time::unix_i64::serialize(source, serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, SystemTime> for UnixI64 {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        time::unix_i64::deserialize(deserializer)
+    }
+}
+
+impl SerializeAs<Duration> for Seconds {
+    fn serialize_as<S: Serializer>(source: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        time::seconds::serialize(source, serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, Duration> for Seconds {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        time::seconds::deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::BytesH256;
+    use portable_storage::{from_section, to_section, StorageEntry};
+    use serde_with::serde_as;
+    use std::{collections::BTreeMap, time::UNIX_EPOCH};
+
+    #[serde_as]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Chain {
+        #[serde_as(as = "Vec<FixedBytes<32>>")]
+        block_ids: Vec<[u8; 32]>,
+        #[serde_as(as = "Vec<Hex>")]
+        blobs: Vec<Vec<u8>>,
+        #[serde_as(as = "HashBlob")]
+        hashes: Vec<BytesH256>,
+        #[serde_as(as = "Vec<UnixU64>")]
+        last_seen: Vec<SystemTime>,
+        #[serde_as(as = "Vec<UnixI64>")]
+        times: Vec<SystemTime>,
+        #[serde_as(as = "Vec<Seconds>")]
+        timeouts: Vec<Duration>,
+    }
+
+    #[serde_as]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Optional {
+        #[serde_as(as = "Option<Hex>")]
+        id: Option<Vec<u8>>,
+        #[serde_as(as = "BTreeMap<_, Option<Seconds>>")]
+        timeouts: BTreeMap<String, Option<Duration>>,
+    }
+
+    #[test]
+    fn roundtrip() {
+        let chain = Chain {
+            block_ids: vec![[7; 32]],
+            blobs: vec![vec![0xde, 0xad], vec![]],
+            hashes: vec![BytesH256([1; 32]), BytesH256([2; 32])],
+            last_seen: vec![UNIX_EPOCH + Duration::from_secs(1_600_000_000)],
+            times: vec![UNIX_EPOCH - Duration::from_secs(60)],
+            timeouts: vec![Duration::from_secs(30)],
+        };
+
+        let section = to_section(&chain).unwrap();
+        match &section["blobs"] {
+            StorageEntry::Array(blobs) => {
+                assert_eq!(blobs[0], StorageEntry::Buf(b"dead".to_vec().into()))
+            }
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+        match &section["hashes"] {
+            StorageEntry::Buf(bytes) => assert_eq!(bytes.len(), 64),
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+        assert_eq!(from_section::<Chain>(section).unwrap(), chain);
+    }
+
+    #[test]
+    fn optional() {
+        let mut timeouts = BTreeMap::new();
+        timeouts.insert("handshake".to_owned(), Some(Duration::from_secs(5)));
+        timeouts.insert("sync".to_owned(), None);
+        let value = Optional {
+            id: Some(vec![0xbe, 0xef]),
+            timeouts,
+        };
+
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(
+            json,
+            r#"{"id":"beef","timeouts":{"handshake":5,"sync":null}}"#
+        );
+        assert_eq!(serde_json::from_str::<Optional>(&json).unwrap(), value);
+    }
+}
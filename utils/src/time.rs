@@ -0,0 +1,160 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `#[serde(with)]` adapters storing times as whole seconds.
+//!
+//! Sub-second precision is truncated on serialization.
+//!
+//! ```rust
+//! use portable_storage_utils::time;
+//! use serde::{Deserialize, Serialize};
+//! use std::time::{Duration, SystemTime};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Peer {
+//!     #[serde(with = "time::unix_i64")]
+//!     last_seen: SystemTime,
+//!     #[serde(with = "time::unix_u64")]
+//!     local_time: SystemTime,
+//!     #[serde(with = "time::seconds")]
+//!     timeout: Duration,
+//! }
+//! ```
+
+use serde::{
+    de::{Deserialize, Deserializer, Error as _},
+    ser::{Error as _, Serializer},
+};
+use std::{
+    convert::TryFrom,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// `SystemTime` as unsigned seconds since the Unix epoch. Times before the
+/// epoch fail to serialize.
+pub mod unix_u64 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let since = time
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| S::Error::custom("time is before the Unix epoch"))?;
+        serializer.serialize_u64(since.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let secs = u64::deserialize(deserializer)?;
+        UNIX_EPOCH
+            .checked_add(Duration::from_secs(secs))
+            .ok_or_else(|| D::Error::custom(format!("timestamp {} is out of range", secs)))
+    }
+}
+
+/// `SystemTime` as signed seconds since the Unix epoch.
+pub mod unix_i64 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => i64::try_from(since.as_secs()).ok(),
+            Err(e) => i64::try_from(e.duration().as_secs()).ok().map(|s| -s),
+        };
+        let secs = secs.ok_or_else(|| S::Error::custom("time is out of range"))?;
+        serializer.serialize_i64(secs)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let secs = i64::deserialize(deserializer)?;
+        let offset = Duration::from_secs(secs.unsigned_abs());
+        if secs < 0 {
+            UNIX_EPOCH.checked_sub(offset)
+        } else {
+            UNIX_EPOCH.checked_add(offset)
+        }
+        .ok_or_else(|| D::Error::custom(format!("timestamp {} is out of range", secs)))
+    }
+}
+
+/// `Duration` as unsigned seconds.
+pub mod seconds {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+/// `chrono::DateTime<Utc>` as signed seconds since the Unix epoch.
+#[cfg(feature = "chrono")]
+pub mod chrono {
+    use super::*;
+    use ::chrono::{DateTime, Utc};
+
+    pub fn serialize<S: Serializer>(
+        time: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(time.timestamp())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        let secs = i64::deserialize(deserializer)?;
+        DateTime::from_timestamp(secs, 0)
+            .ok_or_else(|| D::Error::custom(format!("timestamp {} is out of range", secs)))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use portable_storage::{from_section, to_section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Peer {
+        #[serde(with = "unix_i64")]
+        last_seen: SystemTime,
+        #[serde(with = "unix_u64")]
+        local_time: SystemTime,
+        #[serde(with = "seconds")]
+        timeout: Duration,
+    }
+
+    #[test]
+    fn roundtrip() {
+        let peer = Peer {
+            last_seen: UNIX_EPOCH - Duration::from_secs(60),
+            local_time: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+            timeout: Duration::from_secs(90),
+        };
+        let section = to_section(&peer).unwrap();
+        assert_eq!(section["last_seen"], StorageEntry::I64(-60));
+        assert_eq!(section["local_time"], StorageEntry::U64(1_600_000_000));
+        assert_eq!(section["timeout"], StorageEntry::U64(90));
+        assert_eq!(from_section::<Peer>(section).unwrap(), peer);
+
+        let peer = Peer {
+            local_time: UNIX_EPOCH - Duration::from_secs(1),
+            ..peer
+        };
+        assert!(to_section(&peer).is_err());
+    }
+}
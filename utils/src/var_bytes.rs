@@ -0,0 +1,127 @@
+// Copyright 2018-2020 Jean Pierre Dudey <me@jeandudey.tech>
+// Copyright 2020 Artem Vorotnikov <artem@vorotnikov.me>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{
+    de::{Deserialize, Deserializer, Error, Visitor},
+    ser::{Error as _, Serialize, Serializer},
+};
+use std::{convert::TryFrom, fmt};
+
+/// A binary blob of at most `MAX` bytes.
+///
+/// Longer blobs fail to deserialize and to serialize, so message types can
+/// carry their own limits.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct VarBytes<const MAX: usize>(pub Vec<u8>);
+
+impl<const MAX: usize> TryFrom<Vec<u8>> for VarBytes<MAX> {
+    type Error = Vec<u8>;
+
+    /// Fails, giving the vector back, when it's longer than `MAX`.
+    fn try_from(v: Vec<u8>) -> Result<Self, Vec<u8>> {
+        if v.len() <= MAX {
+            Ok(VarBytes(v))
+        } else {
+            Err(v)
+        }
+    }
+}
+
+impl<const MAX: usize> AsRef<[u8]> for VarBytes<MAX> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<'de, const MAX: usize> Deserialize<'de> for VarBytes<MAX> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct VarBytesVisitor<const MAX: usize>;
+
+        impl<'de, const MAX: usize> Visitor<'de> for VarBytesVisitor<MAX> {
+            type Value = VarBytes<MAX>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a binary blob of at most {} bytes", MAX)
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                self.visit_byte_buf(v.to_vec())
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                VarBytes::try_from(v).map_err(|v| E::invalid_length(v.len(), &self))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(VarBytesVisitor)
+    }
+}
+
+impl<const MAX: usize> Serialize for VarBytes<MAX> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.0.len() > MAX {
+            return Err(S::Error::custom(format!(
+                "blob of {} bytes is longer than {} bytes",
+                self.0.len(),
+                MAX
+            )));
+        }
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use portable_storage::{from_section, to_section, Section, StorageEntry};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Transaction {
+        blob: VarBytes<4>,
+    }
+
+    #[test]
+    fn limits() {
+        let tx = Transaction {
+            blob: VarBytes::try_from(vec![1, 2, 3, 4]).unwrap(),
+        };
+        let section = to_section(&tx).unwrap();
+        assert_eq!(from_section::<Transaction>(section).unwrap(), tx);
+
+        assert!(VarBytes::<4>::try_from(vec![0; 5]).is_err());
+        let tx = Transaction {
+            blob: VarBytes(vec![0; 5]),
+        };
+        assert!(to_section(&tx).is_err());
+
+        let mut section = Section::new();
+        section.insert("blob".to_owned(), StorageEntry::Buf(vec![0; 5].into()));
+        let error = from_section::<Transaction>(section).unwrap_err();
+        assert!(error.to_string().contains("invalid length 5"), "{}", error);
+    }
+}